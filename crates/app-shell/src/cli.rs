@@ -0,0 +1,75 @@
+//! Command-line tools for script files (`.json`, `.yaml`, `.yml`).
+//!
+//! Exit codes follow `diff` and `git merge-file`: 0 when the files are the
+//! same, merged cleanly or passed their tests, 1 when they differ, the
+//! merge has conflicts or a test failed, 2 when a file could not be read or
+//! the arguments are wrong.
+
+use script_manager::{
+    diff_scripts, merge_scripts, parse_script, parse_suite, run_suite, write_script, ScriptFormat,
+};
+use std::path::Path;
+
+pub const USAGE: &str = "usage:
+  app-shell diff <base> <changed>
+  app-shell merge <base> <ours> <theirs> [-o <output>]
+  app-shell test <script> <suite>";
+
+/// Run the subcommand in `args` (without the program name) and return the
+/// exit code.
+pub async fn run(args: &[String]) -> anyhow::Result<i32> {
+    match args {
+        [command, base, changed] if command == "diff" => {
+            let diff = diff_scripts(&read(base)?, &read(changed)?);
+            print!("{diff}");
+            Ok(i32::from(!diff.is_empty()))
+        }
+        [command, base, ours, theirs, rest @ ..] if command == "merge" => {
+            let output = match rest {
+                [] => None,
+                [flag, path] if flag == "-o" || flag == "--output" => Some(Path::new(path)),
+                _ => return Err(anyhow::anyhow!(USAGE)),
+            };
+            let result = merge_scripts(&read(base)?, &read(ours)?, &read(theirs)?);
+            for conflict in &result.conflicts {
+                eprintln!(
+                    "conflict at {}:\n  base:   {}\n  ours:   {}\n  theirs: {}",
+                    conflict.location, conflict.base, conflict.ours, conflict.theirs
+                );
+            }
+            let format = format_of(output.unwrap_or(Path::new(ours)))?;
+            let text = write_script(&result.script, format)?;
+            match output {
+                Some(path) => std::fs::write(path, text)
+                    .map_err(|err| anyhow::anyhow!("failed to write {}: {err}", path.display()))?,
+                None => print!("{text}"),
+            }
+            Ok(i32::from(!result.is_clean()))
+        }
+        [command, script, suite] if command == "test" => {
+            let script = read(script)?;
+            let path = Path::new(suite);
+            let text = std::fs::read_to_string(path)
+                .map_err(|err| anyhow::anyhow!("failed to read {}: {err}", path.display()))?;
+            let suite = parse_suite(&text, format_of(path)?)
+                .map_err(|err| anyhow::anyhow!("{}: {err}", path.display()))?;
+            let report = run_suite(&script, &suite).await;
+            print!("{report}");
+            Ok(i32::from(!report.is_success()))
+        }
+        _ => Err(anyhow::anyhow!(USAGE)),
+    }
+}
+
+fn read(path: &str) -> anyhow::Result<automation_engine::LoginScript> {
+    let path = Path::new(path);
+    let format = format_of(path)?;
+    let text = std::fs::read_to_string(path)
+        .map_err(|err| anyhow::anyhow!("failed to read {}: {err}", path.display()))?;
+    parse_script(&text, format).map_err(|err| anyhow::anyhow!("{}: {err}", path.display()))
+}
+
+fn format_of(path: &Path) -> anyhow::Result<ScriptFormat> {
+    ScriptFormat::from_path(path)
+        .ok_or_else(|| anyhow::anyhow!("{} is not a .json, .yaml or .yml file", path.display()))
+}
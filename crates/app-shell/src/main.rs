@@ -1,9 +1,23 @@
 //! Entry point placeholder for the multi-crate workspace.
+//!
+//! With arguments it runs one of the script tools in [`cli`] instead.
 
-use automation_engine::AutomationEngine;
-use integration_ipc::IpcHandler;
+mod cli;
+
+use automation_engine::{AutomationEngine, PoolConfig, SessionPool};
+use integration_ipc::keepalive::SystemClock;
+use integration_ipc::{
+    forward_events, IpcHandler, KeepAliveConfig, KeepAliveScheduler, TracingEmitter, VaultSecrets,
+    WorkerConfig, WorkerSupervisor,
+};
 use script_manager::ScriptManager;
 use secure_vault::CredentialVault;
+use std::path::Path;
+use std::sync::Arc;
+
+/// Directory for persistent app data; without it everything stays in memory.
+const DATA_DIR_ENV: &str = "AI_HELPER_DATA_DIR";
+const SCRIPT_DATABASE: &str = "scripts.db";
 
 #[derive(Debug)]
 pub struct AppContext {
@@ -12,12 +26,34 @@ pub struct AppContext {
     pub vault: CredentialVault,
 }
 
+impl Default for AppContext {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl AppContext {
     pub fn new() -> Self {
+        Self::with_scripts(ScriptManager::default())
+    }
+
+    /// Context whose scripts persist in `data_dir`.
+    pub fn open(data_dir: &Path) -> anyhow::Result<Self> {
+        std::fs::create_dir_all(data_dir)?;
+        Ok(Self::with_scripts(ScriptManager::open(
+            data_dir.join(SCRIPT_DATABASE),
+        )?))
+    }
+
+    fn with_scripts(scripts: ScriptManager) -> Self {
+        let vault = CredentialVault::default();
+        let mut automation = AutomationEngine::with_defaults();
+        automation.set_secret_resolver(Arc::new(VaultSecrets::new(vault.clone())));
+        automation.set_session_pool(Arc::new(SessionPool::new(PoolConfig::default())));
         Self {
-            automation: AutomationEngine::with_defaults(),
-            scripts: ScriptManager::default(),
-            vault: CredentialVault::default(),
+            automation,
+            scripts,
+            vault,
         }
     }
 }
@@ -26,10 +62,54 @@ impl AppContext {
 async fn main() -> anyhow::Result<()> {
     tracing_subscriber::fmt::init();
 
-    let ctx = AppContext::new();
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if !args.is_empty() {
+        std::process::exit(cli::run(&args).await.unwrap_or_else(|err| {
+            eprintln!("{err:#}");
+            2
+        }));
+    }
+
+    let ctx = match std::env::var_os(DATA_DIR_ENV) {
+        Some(dir) => AppContext::open(Path::new(&dir))?,
+        None => AppContext::new(),
+    };
+    let ctx = Arc::new(ctx);
+    tokio::spawn(forward_events(ctx.automation.subscribe(), &TracingEmitter));
+    // Runs requested over IPC, and keep-alive re-logins, go to the
+    // sandboxed worker when it is installed.
+    let worker = WorkerConfig::beside_current_exe()
+        .ok()
+        .filter(|config| config.program.is_file())
+        .map(|config| {
+            Arc::new(WorkerSupervisor::new(
+                config,
+                Arc::new(VaultSecrets::new(ctx.vault.clone())),
+            ))
+        });
+    if let Some(worker) = &worker {
+        tokio::spawn(forward_events(worker.subscribe(), &TracingEmitter));
+    }
+    let keepalive = Arc::new(KeepAliveScheduler::new(
+        ctx.vault.clone(),
+        Arc::new(SystemClock),
+        Arc::new(TracingEmitter),
+        KeepAliveConfig::default(),
+    ));
+    tokio::spawn({
+        let (ctx, worker, keepalive) = (ctx.clone(), worker.clone(), keepalive.clone());
+        async move {
+            match &worker {
+                Some(worker) => keepalive.run(worker.as_ref()).await,
+                None => keepalive.run(&ctx.automation).await,
+            }
+        }
+    });
     let ipc = IpcHandler {
         vault: &ctx.vault,
         automation: &ctx.automation,
+        keepalive: Some(&keepalive),
+        worker: worker.as_deref(),
     };
 
     println!("app-shell initialized: {ctx:?}");
@@ -38,7 +118,8 @@ async fn main() -> anyhow::Result<()> {
         id: "demo".into(),
         username: "user".into(),
         secret: "pass".into(),
-    })?;
+    })
+    .await?;
 
     Ok(())
 }
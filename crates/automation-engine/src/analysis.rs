@@ -0,0 +1,393 @@
+//! Static checks over a `LoginScript` that run without opening a session.
+//!
+//! Every issue carries a `StepPath` so editors can underline the offending
+//! step. The analysis never resolves secrets; it only asks the resolver
+//! whether keys exist.
+
+use crate::{
+    CaptchaHandler, Condition, LoginScript, PathSegment, RetryPolicy, SecretResolver, Selector,
+    Step, StepPath, TargetAppKind, ValueRef,
+};
+use serde::{Deserialize, Serialize};
+
+/// Total iterations (nested loops and retry attempts multiply) above which
+/// a loop or retry block is treated as unbounded.
+pub const MAX_LOOP_ITERATIONS: u64 = 1_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    Info,
+    Warning,
+    Error,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IssueCode {
+    NoDriver,
+    UnreachableStep,
+    ConstantCondition,
+    UnboundedLoop,
+    EmptySelector,
+    InvalidSelector,
+    MissingVaultKey,
+    UnsupportedCaptcha,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AnalysisIssue {
+    pub severity: Severity,
+    pub code: IssueCode,
+    /// Empty for script-level issues.
+    pub path: StepPath,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AnalysisReport {
+    pub issues: Vec<AnalysisIssue>,
+}
+
+impl AnalysisReport {
+    /// Errors mean the script cannot succeed as written.
+    pub fn has_errors(&self) -> bool {
+        self.issues
+            .iter()
+            .any(|issue| issue.severity == Severity::Error)
+    }
+
+    pub fn is_clean(&self) -> bool {
+        self.issues.is_empty()
+    }
+
+    pub(crate) fn push(
+        &mut self,
+        severity: Severity,
+        code: IssueCode,
+        path: StepPath,
+        message: impl Into<String>,
+    ) {
+        self.issues.push(AnalysisIssue {
+            severity,
+            code,
+            path,
+            message: message.into(),
+        });
+    }
+}
+
+/// Analyze the script body. Driver availability is checked by
+/// `AutomationEngine::analyze`, which knows the registered drivers.
+pub fn analyze_script(
+    script: &LoginScript,
+    secrets: &dyn SecretResolver,
+    captcha: &dyn CaptchaHandler,
+) -> AnalysisReport {
+    let mut analyzer = Analyzer {
+        secrets,
+        captcha,
+        subset_xpath: script.target.kind == TargetAppKind::Android,
+        report: AnalysisReport::default(),
+    };
+    let root = StepPath::root();
+    // The script-level policy reruns every step.
+    let runs = match &script.retry {
+        Some(policy) => analyzer.retry(policy, &root, 1),
+        None => 1,
+    };
+    for (index, step) in script.steps.iter().enumerate() {
+        analyzer.step(step, &root.child(PathSegment::Steps(index)), runs);
+    }
+    for (handler, error_handler) in script.error_handlers.iter().enumerate() {
+        for (index, step) in error_handler.on_error.iter().enumerate() {
+            analyzer.step(
+                step,
+                &root.child(PathSegment::OnError { handler, index }),
+                1,
+            );
+        }
+    }
+    for (index, validation) in script.validations.iter().enumerate() {
+        let path = root.child(PathSegment::Validation(index));
+        analyzer.condition(&validation.condition, &path);
+        match constant(&validation.condition) {
+            Some(false) => analyzer.report.push(
+                Severity::Error,
+                IssueCode::ConstantCondition,
+                path,
+                format!("validation `{}` can never pass", validation.description),
+            ),
+            Some(true) => analyzer.report.push(
+                Severity::Warning,
+                IssueCode::ConstantCondition,
+                path,
+                format!("validation `{}` always passes", validation.description),
+            ),
+            None => {}
+        }
+    }
+    analyzer.report
+}
+
+struct Analyzer<'a> {
+    secrets: &'a dyn SecretResolver,
+    captcha: &'a dyn CaptchaHandler,
+    /// Native Android sessions evaluate XPath with the `dom` subset; other
+    /// drivers may support more of the language.
+    subset_xpath: bool,
+    report: AnalysisReport,
+}
+
+impl Analyzer<'_> {
+    /// `iterations` is how often the enclosing loops run this step.
+    fn step(&mut self, step: &Step, path: &StepPath, iterations: u64) {
+        match step {
+            Step::Click(selector) | Step::WaitFor(selector) => self.selector(selector, path),
+            Step::Input { selector, value } => {
+                self.selector(selector, path);
+                if let ValueRef::FromVault(key) = value {
+                    if self.secrets.contains(key) == Some(false) {
+                        self.report.push(
+                            Severity::Error,
+                            IssueCode::MissingVaultKey,
+                            path.clone(),
+                            format!("vault key `{key}` does not exist"),
+                        );
+                    }
+                }
+            }
+            Step::Swipe { from, to, .. } => {
+                self.selector(from, path);
+                self.selector(to, path);
+            }
+            Step::SleepMs(_) | Step::SwitchContext(_) => {}
+            Step::Conditional {
+                condition,
+                on_true,
+                on_false,
+            } => {
+                self.condition(condition, path);
+                let taken = constant(condition);
+                if taken.is_some() {
+                    self.report.push(
+                        Severity::Warning,
+                        IssueCode::ConstantCondition,
+                        path.clone(),
+                        "condition does not depend on the UI",
+                    );
+                }
+                self.branch(
+                    on_true,
+                    path,
+                    PathSegment::OnTrue,
+                    iterations,
+                    taken != Some(false),
+                );
+                self.branch(
+                    on_false,
+                    path,
+                    PathSegment::OnFalse,
+                    iterations,
+                    taken != Some(true),
+                );
+            }
+            Step::Loop { times, body } => {
+                let total = iterations.saturating_mul(u64::from(*times));
+                if total > MAX_LOOP_ITERATIONS {
+                    self.report.push(
+                        Severity::Error,
+                        IssueCode::UnboundedLoop,
+                        path.clone(),
+                        format!("loop body would run {total} times (limit {MAX_LOOP_ITERATIONS})"),
+                    );
+                }
+                self.branch(body, path, PathSegment::Body, total, *times > 0);
+            }
+            Step::Retry { policy, body } => {
+                let total = self.retry(policy, path, iterations);
+                self.branch(body, path, PathSegment::Body, total, true);
+            }
+            Step::Captcha {
+                kind,
+                challenge,
+                response,
+            } => {
+                self.selector(challenge, path);
+                self.selector(response, path);
+                if !self.captcha.supports(kind) {
+                    self.report.push(
+                        Severity::Error,
+                        IssueCode::UnsupportedCaptcha,
+                        path.clone(),
+                        format!(
+                            "captcha handler {} cannot solve {kind:?} challenges",
+                            self.captcha.label()
+                        ),
+                    );
+                }
+            }
+        }
+    }
+
+    /// Flag policies that could run their block more than
+    /// `MAX_LOOP_ITERATIONS` times; returns the iterations of the block.
+    fn retry(&mut self, policy: &RetryPolicy, path: &StepPath, iterations: u64) -> u64 {
+        let total = iterations.saturating_mul(u64::from(policy.max_attempts.max(1)));
+        if total > MAX_LOOP_ITERATIONS {
+            self.report.push(
+                Severity::Error,
+                IssueCode::UnboundedLoop,
+                path.clone(),
+                format!("retry block could run {total} times (limit {MAX_LOOP_ITERATIONS})"),
+            );
+        }
+        total
+    }
+
+    fn branch(
+        &mut self,
+        steps: &[Step],
+        parent: &StepPath,
+        segment: fn(usize) -> PathSegment,
+        iterations: u64,
+        reachable: bool,
+    ) {
+        for (index, step) in steps.iter().enumerate() {
+            let path = parent.child(segment(index));
+            if reachable {
+                self.step(step, &path, iterations);
+            } else {
+                self.report.push(
+                    Severity::Warning,
+                    IssueCode::UnreachableStep,
+                    path,
+                    "step can never run",
+                );
+            }
+        }
+    }
+
+    fn condition(&mut self, condition: &Condition, path: &StepPath) {
+        match condition {
+            Condition::Exists(selector) | Condition::TextEquals { selector, .. } => {
+                self.selector(selector, path)
+            }
+            Condition::And(conditions) | Condition::Or(conditions) => {
+                for condition in conditions {
+                    self.condition(condition, path);
+                }
+            }
+            Condition::Not(inner) => self.condition(inner, path),
+            Condition::UrlContains(_) => {}
+        }
+    }
+
+    fn selector(&mut self, selector: &Selector, path: &StepPath) {
+        let raw = match selector {
+            Selector::Css(raw)
+            | Selector::XPath(raw)
+            | Selector::AccessibilityId(raw)
+            | Selector::Image(raw) => raw,
+            Selector::Coordinates { .. } => return,
+            Selector::AnyOf(candidates) => {
+                if candidates.is_empty() {
+                    self.report.push(
+                        Severity::Error,
+                        IssueCode::EmptySelector,
+                        path.clone(),
+                        "AnyOf selector has no candidates",
+                    );
+                }
+                for candidate in candidates {
+                    self.selector(candidate, path);
+                }
+                return;
+            }
+        };
+        if raw.trim().is_empty() {
+            self.report.push(
+                Severity::Error,
+                IssueCode::EmptySelector,
+                path.clone(),
+                format!("empty selector {selector:?}"),
+            );
+            return;
+        }
+        match selector {
+            Selector::Css(css) if scraper::Selector::parse(css).is_err() => self.report.push(
+                Severity::Error,
+                IssueCode::InvalidSelector,
+                path.clone(),
+                format!("invalid selector {selector:?}: not valid CSS"),
+            ),
+            Selector::XPath(xpath) => {
+                let Err(err) = crate::dom::NodeTree::new().xpath(xpath) else {
+                    return;
+                };
+                if self.subset_xpath {
+                    self.report.push(
+                        Severity::Error,
+                        IssueCode::InvalidSelector,
+                        path.clone(),
+                        format!("invalid selector {selector:?}: {err}"),
+                    );
+                } else {
+                    // Browsers evaluate full XPath; only the subset is known here.
+                    self.report.push(
+                        Severity::Warning,
+                        IssueCode::InvalidSelector,
+                        path.clone(),
+                        format!("selector {selector:?} may not be valid XPath: {err}"),
+                    );
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Value of a condition that does not depend on the UI, if any: empty
+/// `AnyOf` selectors, the empty URL fragment, empty `And`/`Or` lists and
+/// lists holding a condition together with its negation.
+fn constant(condition: &Condition) -> Option<bool> {
+    match condition {
+        Condition::Exists(selector) | Condition::TextEquals { selector, .. } => {
+            matches_nothing(selector).then_some(false)
+        }
+        Condition::UrlContains(fragment) => fragment.is_empty().then_some(true),
+        Condition::Not(inner) => constant(inner).map(|value| !value),
+        Condition::And(conditions) => {
+            let values: Vec<Option<bool>> = conditions.iter().map(constant).collect();
+            if values.contains(&Some(false)) || contradicts(conditions) {
+                Some(false)
+            } else if values.iter().all(|value| *value == Some(true)) {
+                Some(true)
+            } else {
+                None
+            }
+        }
+        Condition::Or(conditions) => {
+            let values: Vec<Option<bool>> = conditions.iter().map(constant).collect();
+            if values.contains(&Some(true)) || contradicts(conditions) {
+                Some(true)
+            } else if values.iter().all(|value| *value == Some(false)) {
+                Some(false)
+            } else {
+                None
+            }
+        }
+    }
+}
+
+/// An `AnyOf` without candidates finds no element on any driver.
+fn matches_nothing(selector: &Selector) -> bool {
+    matches!(selector, Selector::AnyOf(candidates) if candidates.iter().all(matches_nothing))
+}
+
+/// Whether `conditions` hold some condition and its negation.
+fn contradicts(conditions: &[Condition]) -> bool {
+    conditions.iter().any(|condition| {
+        matches!(condition, Condition::Not(inner) if conditions.contains(inner.as_ref()))
+    })
+}
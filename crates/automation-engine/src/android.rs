@@ -0,0 +1,699 @@
+//! Android driver built on ADB and `uiautomator` hierarchy dumps.
+//!
+//! All device access goes through [`AdbTransport`], so the driver can run
+//! against a real device (`adb` binary) or replay canned dumps in tests.
+//! Element lookup follows Appium conventions: `Selector::AccessibilityId`
+//! matches `content-desc` (or the resource id), and XPath tags are widget
+//! class names such as `android.widget.EditText`.
+//!
+//! Hybrid screens are reached with `Step::SwitchContext`: the session lists
+//! the app's WebView DevTools sockets, forwards one over ADB and attaches a
+//! [`CdpSession`] to it. While a WebView context is active every selector and
+//! condition resolves against its DOM.
+
+use crate::cdp::CdpSession;
+use crate::dom::{NodeId, NodeTree};
+use crate::retry::StepError;
+use crate::runner::DriverSession;
+use crate::trace::{SnapshotFormat, UiSnapshot};
+use crate::{
+    AutomationContext, AutomationDriver, Selector, SessionToken, TargetApp, TargetAppKind,
+    TokenSource,
+};
+use async_trait::async_trait;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// Where `uiautomator dump` writes the hierarchy on the device.
+const DUMP_PATH: &str = "/sdcard/window_dump.xml";
+
+/// Raw access to one device.
+#[async_trait]
+pub trait AdbTransport: Send + Sync {
+    /// Run `adb shell <command>` and return its stdout.
+    async fn shell(&self, command: &str) -> anyhow::Result<String>;
+
+    /// Run `adb exec-out <command>` and return raw stdout bytes.
+    async fn exec_out(&self, command: &str) -> anyhow::Result<Vec<u8>>;
+
+    /// Forward a free local TCP port to a device abstract socket and return
+    /// the port.
+    async fn forward(&self, _abstract_socket: &str) -> anyhow::Result<u16> {
+        Err(anyhow::anyhow!(
+            "this transport does not support port forwarding"
+        ))
+    }
+
+    /// Undo a previous [`AdbTransport::forward`].
+    async fn remove_forward(&self, _port: u16) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+/// Transport shelling out to the `adb` binary.
+#[derive(Debug, Clone)]
+pub struct AdbCommand {
+    adb: PathBuf,
+    serial: Option<String>,
+}
+
+impl Default for AdbCommand {
+    fn default() -> Self {
+        Self {
+            adb: PathBuf::from("adb"),
+            serial: None,
+        }
+    }
+}
+
+impl AdbCommand {
+    pub fn new(adb: impl Into<PathBuf>) -> Self {
+        Self {
+            adb: adb.into(),
+            serial: None,
+        }
+    }
+
+    /// Target a specific device (`adb -s <serial>`).
+    pub fn with_serial(mut self, serial: impl Into<String>) -> Self {
+        self.serial = Some(serial.into());
+        self
+    }
+
+    async fn run(&self, args: &[&str]) -> anyhow::Result<Vec<u8>> {
+        let mut cmd = tokio::process::Command::new(&self.adb);
+        if let Some(serial) = &self.serial {
+            cmd.arg("-s").arg(serial);
+        }
+        let output = cmd
+            .args(args)
+            .output()
+            .await
+            .map_err(|err| anyhow::anyhow!("failed to spawn adb: {err}"))?;
+        if !output.status.success() {
+            return Err(anyhow::anyhow!(
+                "adb {} exited with {}: {}",
+                args.join(" "),
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+        Ok(output.stdout)
+    }
+}
+
+#[async_trait]
+impl AdbTransport for AdbCommand {
+    async fn shell(&self, command: &str) -> anyhow::Result<String> {
+        let stdout = self.run(&["shell", command]).await?;
+        Ok(String::from_utf8_lossy(&stdout).into_owned())
+    }
+
+    async fn exec_out(&self, command: &str) -> anyhow::Result<Vec<u8>> {
+        self.run(&["exec-out", command]).await
+    }
+
+    async fn forward(&self, abstract_socket: &str) -> anyhow::Result<u16> {
+        let remote = format!("localabstract:{abstract_socket}");
+        let stdout = self.run(&["forward", "tcp:0", &remote]).await?;
+        let port = String::from_utf8_lossy(&stdout);
+        port.trim()
+            .parse()
+            .map_err(|_| anyhow::anyhow!("adb forward returned no port: {port}"))
+    }
+
+    async fn remove_forward(&self, port: u16) -> anyhow::Result<()> {
+        self.run(&["forward", "--remove", &format!("tcp:{port}")])
+            .await?;
+        Ok(())
+    }
+}
+
+/// Accessibility-style driver for native Android apps.
+pub struct AndroidDriver {
+    transport: Arc<dyn AdbTransport>,
+}
+
+impl AndroidDriver {
+    pub fn new(transport: Arc<dyn AdbTransport>) -> Self {
+        Self { transport }
+    }
+}
+
+#[async_trait]
+impl AutomationDriver for AndroidDriver {
+    fn name(&self) -> &'static str {
+        "android-adb"
+    }
+
+    fn supports(&self, target: &TargetApp) -> bool {
+        matches!(target.kind, TargetAppKind::Android)
+    }
+
+    fn poolable(&self) -> bool {
+        true
+    }
+
+    async fn open_session(&self, target: &TargetApp) -> anyhow::Result<Box<dyn DriverSession>> {
+        let endpoint = target
+            .endpoint
+            .as_deref()
+            .ok_or_else(|| anyhow::anyhow!("android target {} has no package", target.name))?;
+        let mut session = AndroidSession::new(self.transport.clone());
+        session.launch(endpoint).await?;
+        Ok(Box::new(session))
+    }
+}
+
+/// Value of `key` in a SharedPreferences XML file. Strings carry the value
+/// as text, other types in a `value` attribute.
+pub fn shared_preference(xml: &str, key: &str) -> anyhow::Result<Option<String>> {
+    let Some(start) = xml.find('<') else {
+        return Ok(None);
+    };
+    let document = roxmltree::Document::parse(&xml[start..])
+        .map_err(|err| anyhow::anyhow!("invalid shared preferences file: {err}"))?;
+    Ok(document
+        .descendants()
+        .find(|node| node.is_element() && node.attribute("name") == Some(key))
+        .map(|node| {
+            node.attribute("value")
+                .map(str::to_string)
+                .unwrap_or_else(|| node.text().unwrap_or_default().to_string())
+        }))
+}
+
+/// Screen rectangle of a node, as reported in `bounds="[l,t][r,b]"`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Bounds {
+    pub left: i32,
+    pub top: i32,
+    pub right: i32,
+    pub bottom: i32,
+}
+
+impl Bounds {
+    pub fn center(&self) -> (i32, i32) {
+        ((self.left + self.right) / 2, (self.top + self.bottom) / 2)
+    }
+
+    /// Parse the uiautomator `[l,t][r,b]` notation.
+    pub fn parse(raw: &str) -> Option<Self> {
+        let numbers: Vec<i32> = raw
+            .split(|c: char| !(c.is_ascii_digit() || c == '-'))
+            .filter(|part| !part.is_empty())
+            .map(str::parse)
+            .collect::<Result<_, _>>()
+            .ok()?;
+        match numbers.as_slice() {
+            [left, top, right, bottom] => Some(Self {
+                left: *left,
+                top: *top,
+                right: *right,
+                bottom: *bottom,
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// Parse a `uiautomator dump` into a [`NodeTree`]. Each `<node>` is keyed by
+/// its `class` attribute so XPath can address widgets by class name.
+pub fn parse_hierarchy(xml: &str) -> anyhow::Result<NodeTree> {
+    // `uiautomator dump` may prefix the XML with a status line.
+    let start = xml
+        .find("<?xml")
+        .or_else(|| xml.find("<hierarchy"))
+        .ok_or_else(|| anyhow::anyhow!("no uiautomator hierarchy in dump output"))?;
+    let document = roxmltree::Document::parse(&xml[start..])
+        .map_err(|err| anyhow::anyhow!("invalid uiautomator dump: {err}"))?;
+
+    fn walk(tree: &mut NodeTree, parent: NodeId, element: roxmltree::Node<'_, '_>) {
+        for child in element.children().filter(|node| node.is_element()) {
+            let attributes: Vec<(String, String)> = child
+                .attributes()
+                .map(|attr| (attr.name().to_string(), attr.value().to_string()))
+                .collect();
+            let tag = child
+                .attribute("class")
+                .filter(|class| !class.is_empty())
+                .unwrap_or(child.tag_name().name())
+                .to_string();
+            let id = tree.push(parent, tag, attributes);
+            walk(tree, id, child);
+        }
+    }
+
+    let mut tree = NodeTree::new();
+    walk(&mut tree, NodeTree::ROOT, document.root());
+    Ok(tree)
+}
+
+/// Shell commands typing `text`: printable runs go through `input text`,
+/// newlines and tabs are sent as Enter and Tab key events. Other control
+/// characters cannot be typed and are rejected.
+fn input_commands(text: &str) -> anyhow::Result<Vec<String>> {
+    let mut commands = Vec::new();
+    let mut run = String::new();
+    for ch in text.chars() {
+        let keycode = match ch {
+            '\n' => 66,
+            '\t' => 61,
+            _ if ch.is_control() => {
+                anyhow::bail!("cannot type control character {ch:?} with adb input")
+            }
+            _ => {
+                run.push(ch);
+                continue;
+            }
+        };
+        if !run.is_empty() {
+            commands.push(format!("input text {}", escape_input_text(&run)));
+            run.clear();
+        }
+        commands.push(format!("input keyevent {keycode}"));
+    }
+    if !run.is_empty() {
+        commands.push(format!("input text {}", escape_input_text(&run)));
+    }
+    Ok(commands)
+}
+
+/// Escape text for `adb shell input text`, which treats `%s` as a space and
+/// goes through the device shell.
+fn escape_input_text(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for ch in text.chars() {
+        match ch {
+            ' ' => escaped.push_str("%s"),
+            '\'' | '"' | '\\' | '`' | '$' | '&' | '|' | ';' | '<' | '>' | '(' | ')' | '*' | '?'
+            | '~' | '#' | '!' | '[' | ']' | '{' | '}' | '%' => {
+                escaped.push('\\');
+                escaped.push(ch);
+            }
+            _ => escaped.push(ch),
+        }
+    }
+    escaped
+}
+
+/// Quote a package, component or file name for `adb shell`. Only the
+/// characters such names are made of are accepted; the single quotes keep
+/// `$` in inner-class activity names literal.
+fn shell_word(what: &str, value: &str) -> anyhow::Result<String> {
+    let valid = !value.is_empty()
+        && value
+            .chars()
+            .all(|ch| ch.is_ascii_alphanumeric() || matches!(ch, '.' | '_' | '/' | '$'));
+    if !valid {
+        anyhow::bail!("invalid {what} {value:?} for adb shell");
+    }
+    Ok(format!("'{value}'"))
+}
+
+/// Prefix of the abstract sockets WebViews open when debugging is enabled.
+const WEBVIEW_SOCKET_PREFIX: &str = "webview_devtools_remote_";
+
+/// A WebView attached over a forwarded DevTools socket.
+struct AttachedWebView {
+    socket: String,
+    port: u16,
+    cdp: CdpSession,
+}
+
+/// One app launched on a device.
+pub struct AndroidSession {
+    transport: Arc<dyn AdbTransport>,
+    package: Option<String>,
+    webview: Option<AttachedWebView>,
+    active: AutomationContext,
+}
+
+impl AndroidSession {
+    pub fn new(transport: Arc<dyn AdbTransport>) -> Self {
+        Self {
+            transport,
+            package: None,
+            webview: None,
+            active: AutomationContext::Native,
+        }
+    }
+
+    /// Start `package` (or `package/.Activity`) on the device.
+    pub async fn launch(&mut self, endpoint: &str) -> anyhow::Result<()> {
+        let component = shell_word("component", endpoint)?;
+        let command = if endpoint.contains('/') {
+            format!("am start -W -n {component}")
+        } else {
+            format!("monkey -p {component} -c android.intent.category.LAUNCHER 1")
+        };
+        self.transport.shell(&command).await?;
+        self.package = endpoint.split('/').next().map(str::to_string);
+        Ok(())
+    }
+
+    /// DevTools sockets of debuggable WebViews, as `(socket, owning package)`.
+    async fn webview_sockets(&self) -> anyhow::Result<Vec<(String, Option<String>)>> {
+        let table = self.transport.shell("cat /proc/net/unix").await?;
+        let mut sockets = Vec::new();
+        for line in table.lines() {
+            let Some(name) = line
+                .split_whitespace()
+                .last()
+                .and_then(|path| path.strip_prefix('@'))
+            else {
+                continue;
+            };
+            // Apps pick their socket names, so only a numeric pid goes
+            // into the shell command below.
+            let Some(pid) = name
+                .strip_prefix(WEBVIEW_SOCKET_PREFIX)
+                .and_then(|pid| pid.parse::<u32>().ok())
+            else {
+                continue;
+            };
+            if sockets.iter().any(|(known, _)| known == name) {
+                continue;
+            }
+            let owner = self
+                .transport
+                .shell(&format!("cat /proc/{pid}/cmdline"))
+                .await
+                .ok()
+                .map(|cmdline| cmdline.trim_matches(char::from(0)).trim().to_string())
+                .filter(|cmdline| !cmdline.is_empty());
+            sockets.push((name.to_string(), owner));
+        }
+        Ok(sockets)
+    }
+
+    /// Forward `socket` and attach to its first page.
+    async fn attach_webview(&mut self, socket: &str) -> anyhow::Result<()> {
+        self.detach_webview().await?;
+        let port = self.transport.forward(socket).await?;
+        let pages: Vec<serde_json::Value> = reqwest::get(format!("http://127.0.0.1:{port}/json"))
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|err| anyhow::anyhow!("failed to list pages of {socket}: {err}"))?
+            .json()
+            .await
+            .map_err(|err| anyhow::anyhow!("invalid page list from {socket}: {err}"))?;
+        let debugger_url = pages
+            .iter()
+            .filter(|page| page.get("type").and_then(|t| t.as_str()) == Some("page"))
+            .find_map(|page| {
+                page.get("webSocketDebuggerUrl")
+                    .and_then(|url| url.as_str())
+            })
+            .ok_or_else(|| anyhow::anyhow!("webview {socket} exposes no debuggable page"))?;
+        let cdp = CdpSession::connect(debugger_url).await?;
+        self.webview = Some(AttachedWebView {
+            socket: socket.to_string(),
+            port,
+            cdp,
+        });
+        Ok(())
+    }
+
+    async fn detach_webview(&mut self) -> anyhow::Result<()> {
+        if let Some(mut webview) = self.webview.take() {
+            if let Err(err) = webview.cdp.close().await {
+                tracing::debug!(socket = %webview.socket, error = %err, "webview close failed");
+            }
+            self.transport.remove_forward(webview.port).await?;
+        }
+        Ok(())
+    }
+
+    /// The attached WebView when it is the active context.
+    fn active_webview(&mut self) -> Option<&mut CdpSession> {
+        match self.active {
+            AutomationContext::WebView(_) => self.webview.as_mut().map(|webview| &mut webview.cdp),
+            AutomationContext::Native => None,
+        }
+    }
+
+    /// Dump and parse the current window hierarchy.
+    pub async fn hierarchy(&self) -> anyhow::Result<NodeTree> {
+        parse_hierarchy(&self.dump().await?)
+    }
+
+    async fn dump(&self) -> anyhow::Result<String> {
+        self.transport
+            .shell(&format!("uiautomator dump {DUMP_PATH} && cat {DUMP_PATH}"))
+            .await
+    }
+
+    fn find(tree: &NodeTree, selector: &Selector) -> anyhow::Result<Option<NodeId>> {
+        match selector {
+            Selector::AccessibilityId(id) => {
+                let suffix = format!(":id/{id}");
+                Ok(tree.elements().find(|node| {
+                    let node = tree.node(*node);
+                    node.attr("content-desc") == Some(id.as_str())
+                        || node
+                            .attr("resource-id")
+                            .is_some_and(|rid| rid == id || rid.ends_with(&suffix))
+                }))
+            }
+            Selector::XPath(xpath) => Ok(tree.xpath(xpath)?.first().copied()),
+            Selector::Css(_) | Selector::Image(_) => Err(anyhow::anyhow!(
+                "selector {selector:?} is not supported in the native android context"
+            )),
+            Selector::Coordinates { .. } => Ok(None),
+            Selector::AnyOf(candidates) => {
+                for candidate in candidates {
+                    if let Some(node) = Self::find(tree, candidate)? {
+                        return Ok(Some(node));
+                    }
+                }
+                Ok(None)
+            }
+        }
+    }
+
+    /// Screen point for a selector, `None` when nothing matches.
+    async fn locate(&self, selector: &Selector) -> anyhow::Result<Option<(i32, i32)>> {
+        if let Selector::Coordinates { x, y } = selector {
+            return Ok(Some((*x, *y)));
+        }
+        let tree = self.hierarchy().await?;
+        Ok(Self::find(&tree, selector)?
+            .and_then(|node| tree.node(node).attr("bounds").and_then(Bounds::parse))
+            .map(|bounds| bounds.center()))
+    }
+
+    async fn require(&self, selector: &Selector) -> anyhow::Result<(i32, i32)> {
+        self.locate(selector)
+            .await?
+            .ok_or_else(|| StepError::not_found(selector).into())
+    }
+}
+
+#[async_trait]
+impl DriverSession for AndroidSession {
+    async fn exists(&mut self, selector: &Selector) -> anyhow::Result<bool> {
+        if let Some(web) = self.active_webview() {
+            return web.exists(selector).await;
+        }
+        if matches!(selector, Selector::Coordinates { .. }) {
+            return Ok(true);
+        }
+        let tree = self.hierarchy().await?;
+        Ok(Self::find(&tree, selector)?.is_some())
+    }
+
+    async fn click(&mut self, selector: &Selector) -> anyhow::Result<()> {
+        if let Some(web) = self.active_webview() {
+            return web.click(selector).await;
+        }
+        let (x, y) = self.require(selector).await?;
+        self.transport.shell(&format!("input tap {x} {y}")).await?;
+        Ok(())
+    }
+
+    async fn input(&mut self, selector: &Selector, text: &str) -> anyhow::Result<()> {
+        if let Some(web) = self.active_webview() {
+            return web.input(selector, text).await;
+        }
+        let commands = input_commands(text)?;
+        self.click(selector).await?;
+        for command in commands {
+            self.transport.shell(&command).await?;
+        }
+        Ok(())
+    }
+
+    async fn swipe(
+        &mut self,
+        from: &Selector,
+        to: &Selector,
+        duration_ms: u64,
+    ) -> anyhow::Result<()> {
+        if let Some(web) = self.active_webview() {
+            return web.swipe(from, to, duration_ms).await;
+        }
+        let (x1, y1) = self.require(from).await?;
+        let (x2, y2) = self.require(to).await?;
+        self.transport
+            .shell(&format!("input swipe {x1} {y1} {x2} {y2} {duration_ms}"))
+            .await?;
+        Ok(())
+    }
+
+    async fn text(&mut self, selector: &Selector) -> anyhow::Result<Option<String>> {
+        if let Some(web) = self.active_webview() {
+            return web.text(selector).await;
+        }
+        let tree = self.hierarchy().await?;
+        Ok(Self::find(&tree, selector)?.map(|node| {
+            let node = tree.node(node);
+            match node.attr("text") {
+                Some(text) if !text.is_empty() => text.to_string(),
+                _ => node.attr("content-desc").unwrap_or_default().to_string(),
+            }
+        }))
+    }
+
+    /// The device cannot crop server-side, so captcha payloads are full
+    /// screenshots; the element must still be present.
+    async fn capture(&mut self, selector: &Selector) -> anyhow::Result<Vec<u8>> {
+        if let Some(web) = self.active_webview() {
+            return web.capture(selector).await;
+        }
+        self.require(selector).await?;
+        self.screenshot().await
+    }
+
+    async fn screenshot(&mut self) -> anyhow::Result<Vec<u8>> {
+        self.transport.exec_out("screencap -p").await
+    }
+
+    async fn snapshot(&mut self) -> anyhow::Result<Option<UiSnapshot>> {
+        if let Some(web) = self.active_webview() {
+            return web.snapshot().await;
+        }
+        let output = self.dump().await?;
+        // Drop the status line `uiautomator dump` prints before the XML.
+        let xml = output.find('<').map_or("", |start| &output[start..]);
+        Ok(Some(UiSnapshot {
+            format: SnapshotFormat::AccessibilityXml,
+            content: xml.to_string(),
+        }))
+    }
+
+    /// SharedPreferences are read with `run-as`, so the app must be
+    /// debuggable. Browser-style sources go to the attached WebView.
+    async fn extract_token(&mut self, source: &TokenSource) -> anyhow::Result<Option<SessionToken>> {
+        match source {
+            TokenSource::SharedPreference { file, key } => {
+                let package = self
+                    .package
+                    .clone()
+                    .ok_or_else(|| anyhow::anyhow!("no app launched in this session"))?;
+                if file.contains('/') {
+                    anyhow::bail!("shared preference file {file:?} must be a bare name");
+                }
+                let command = format!(
+                    "run-as {} cat {}",
+                    shell_word("package", &package)?,
+                    shell_word("shared preference file", &format!("shared_prefs/{file}.xml"))?
+                );
+                let xml = self.transport.shell(&command).await?;
+                Ok(shared_preference(&xml, key)?
+                    .map(|value| SessionToken::from_source(value, source, None)))
+            }
+            TokenSource::ElementText(selector) => Ok(self
+                .text(selector)
+                .await?
+                .map(|text| SessionToken::from_source(text, source, None))),
+            _ => match self.webview.as_mut() {
+                Some(webview) => webview.cdp.extract_token(source).await,
+                None => Err(anyhow::anyhow!(
+                    "{source:?} needs an attached WebView; switch context first"
+                )),
+            },
+        }
+    }
+
+    async fn current_url(&mut self) -> anyhow::Result<Option<String>> {
+        match self.active_webview() {
+            Some(web) => web.current_url().await,
+            None => Ok(None),
+        }
+    }
+
+    /// Only a WebView carries cookies; native logins have no token yet.
+    async fn session_token(&mut self) -> anyhow::Result<Option<String>> {
+        match self.webview.as_mut() {
+            Some(webview) => webview.cdp.session_token().await,
+            None => Ok(None),
+        }
+    }
+
+    /// `Native` plus one `WebView` per debuggable WebView socket, named after
+    /// the socket. WebViews of other apps are skipped.
+    async fn contexts(&mut self) -> anyhow::Result<Vec<AutomationContext>> {
+        let mut contexts = vec![AutomationContext::Native];
+        for (socket, owner) in self.webview_sockets().await? {
+            if owner.is_some() && owner != self.package {
+                continue;
+            }
+            contexts.push(AutomationContext::WebView(Some(socket)));
+        }
+        Ok(contexts)
+    }
+
+    async fn switch_context(&mut self, context: &AutomationContext) -> anyhow::Result<()> {
+        if let AutomationContext::WebView(name) = context {
+            let wanted = match name {
+                Some(name) => Some(name.clone()),
+                None => self
+                    .contexts()
+                    .await?
+                    .into_iter()
+                    .find_map(|context| match context {
+                        AutomationContext::WebView(socket) => socket,
+                        AutomationContext::Native => None,
+                    }),
+            }
+            .ok_or_else(|| anyhow::anyhow!("no debuggable webview found"))?;
+            let attached = self.webview.as_ref().map(|webview| webview.socket.as_str());
+            if attached != Some(wanted.as_str()) {
+                self.attach_webview(&wanted).await?;
+            }
+        }
+        tracing::debug!(from = ?self.active, to = ?context, "android context switch");
+        self.active = context.clone();
+        Ok(())
+    }
+
+    async fn health_check(&mut self) -> anyhow::Result<()> {
+        let reply = self.transport.shell("echo ok").await?;
+        if reply.trim() == "ok" {
+            Ok(())
+        } else {
+            Err(anyhow::anyhow!("unexpected adb reply: {reply}"))
+        }
+    }
+
+    /// `pm clear` wipes the app's data (cookies, preferences, databases)
+    /// before relaunching it.
+    async fn reset(&mut self, target: &TargetApp) -> anyhow::Result<()> {
+        self.detach_webview().await?;
+        self.active = AutomationContext::Native;
+        let endpoint = target
+            .endpoint
+            .as_deref()
+            .ok_or_else(|| anyhow::anyhow!("android target {} has no package", target.name))?;
+        let package = shell_word("package", endpoint.split('/').next().unwrap_or(endpoint))?;
+        self.transport
+            .shell(&format!("am force-stop {package} && pm clear {package}"))
+            .await?;
+        self.launch(endpoint).await
+    }
+
+    async fn close(&mut self) -> anyhow::Result<()> {
+        self.detach_webview().await
+    }
+}
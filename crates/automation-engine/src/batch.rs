@@ -0,0 +1,295 @@
+//! Running many (script, credential) pairs concurrently.
+//!
+//! Jobs run as futures on the caller's task, so the engine is only borrowed.
+//! The dispatcher starts a job only when the global limit, the limit of its
+//! driver and the rate limit of its target all allow it, and rotates between
+//! users so one user's long job list cannot starve the others.
+
+use crate::{AutomationEngine, ErrorKind, LoginOutcome, LoginScript, RunControl, SecretResolver};
+use futures::stream::{FuturesUnordered, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// One script run for one account.
+#[derive(Clone)]
+pub struct BatchJob {
+    /// Owner used for fair scheduling.
+    pub user: String,
+    /// Account label reported back in the results.
+    pub account: String,
+    /// Shared between all accounts running the same script.
+    pub script: Arc<LoginScript>,
+    /// Credentials for this account; falls back to the engine's resolver.
+    pub secrets: Option<Arc<dyn SecretResolver>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct BatchLimits {
+    /// Upper bound of concurrently running jobs.
+    pub max_concurrency: usize,
+    /// Per-driver caps keyed by `AutomationDriver::name`.
+    pub per_driver: HashMap<String, usize>,
+    /// Minimum spacing between two job starts against the same target
+    /// (keyed by endpoint, or name when there is none).
+    pub target_interval: Duration,
+}
+
+impl Default for BatchLimits {
+    fn default() -> Self {
+        Self {
+            max_concurrency: 4,
+            per_driver: HashMap::new(),
+            target_interval: Duration::ZERO,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchResult {
+    /// Position of the job in the submitted list.
+    pub index: usize,
+    pub user: String,
+    pub account: String,
+    pub script_id: String,
+    pub driver: Option<String>,
+    pub outcome: Option<LoginOutcome>,
+    /// Set when the run could not produce an outcome at all.
+    pub error: Option<String>,
+    pub duration_ms: u64,
+}
+
+impl BatchResult {
+    pub fn succeeded(&self) -> bool {
+        self.outcome.as_ref().is_some_and(|outcome| outcome.success)
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BatchReport {
+    /// Ordered by job index.
+    pub results: Vec<BatchResult>,
+    pub succeeded: usize,
+    pub failed: usize,
+    pub cancelled: usize,
+    pub duration_ms: u64,
+}
+
+struct Pending {
+    index: usize,
+    job: BatchJob,
+    driver: String,
+    target: String,
+}
+
+/// Round-robin over users, FIFO within a user.
+struct FairQueue {
+    users: Vec<VecDeque<Pending>>,
+    cursor: usize,
+}
+
+impl FairQueue {
+    fn is_empty(&self) -> bool {
+        self.users.iter().all(VecDeque::is_empty)
+    }
+
+    /// Take the head job of the next user whose head job may start.
+    fn pop(&mut self, ready: impl Fn(&Pending) -> bool) -> Option<Pending> {
+        let count = self.users.len();
+        for offset in 0..count {
+            let user = (self.cursor + offset) % count;
+            if self.users[user].front().is_some_and(&ready) {
+                self.cursor = (user + 1) % count;
+                return self.users[user].pop_front();
+            }
+        }
+        None
+    }
+}
+
+impl AutomationEngine {
+    /// Run all jobs under `limits` and aggregate the results. Cancelling
+    /// `control` stops running jobs and skips queued ones.
+    pub async fn run_batch(
+        &self,
+        jobs: Vec<BatchJob>,
+        limits: &BatchLimits,
+        control: RunControl,
+    ) -> BatchReport {
+        let started = Instant::now();
+        let mut results = Vec::with_capacity(jobs.len());
+        let mut queue = FairQueue {
+            users: Vec::new(),
+            cursor: 0,
+        };
+        let mut user_slots: HashMap<String, usize> = HashMap::new();
+        for (index, job) in jobs.into_iter().enumerate() {
+            let driver = match self.select_driver(&job.script.target) {
+                Ok(driver) => driver.name().to_string(),
+                Err(err) => {
+                    results.push(job_result(index, &job, None, Err(err), Duration::ZERO));
+                    continue;
+                }
+            };
+            if limits.per_driver.get(&driver) == Some(&0) {
+                let err = anyhow::anyhow!("driver {driver} has a limit of zero");
+                results.push(job_result(
+                    index,
+                    &job,
+                    Some(driver),
+                    Err(err),
+                    Duration::ZERO,
+                ));
+                continue;
+            }
+            let target = job
+                .script
+                .target
+                .endpoint
+                .clone()
+                .unwrap_or_else(|| job.script.target.name.clone());
+            let slot = *user_slots.entry(job.user.clone()).or_insert_with(|| {
+                queue.users.push(VecDeque::new());
+                queue.users.len() - 1
+            });
+            queue.users[slot].push_back(Pending {
+                index,
+                job,
+                driver,
+                target,
+            });
+        }
+
+        let max_concurrency = limits.max_concurrency.max(1);
+        let mut in_flight: HashMap<String, usize> = HashMap::new();
+        let mut next_start: HashMap<String, Instant> = HashMap::new();
+        let mut running = FuturesUnordered::new();
+
+        loop {
+            if control.is_cancelled() {
+                while let Some(pending) = queue.pop(|_| true) {
+                    results.push(job_result(
+                        pending.index,
+                        &pending.job,
+                        Some(pending.driver),
+                        Ok(cancelled_outcome()),
+                        Duration::ZERO,
+                    ));
+                }
+            }
+            // One reading of the clock for both starting jobs and picking the
+            // next wake-up, so a target cannot become ready in between.
+            let now = Instant::now();
+            while running.len() < max_concurrency {
+                let Some(pending) = queue.pop(|pending| {
+                    let driver_busy = limits.per_driver.get(&pending.driver).is_some_and(|limit| {
+                        in_flight.get(&pending.driver).copied().unwrap_or(0) >= *limit
+                    });
+                    let target_ready = next_start
+                        .get(&pending.target)
+                        .is_none_or(|next| *next <= now);
+                    !driver_busy && target_ready
+                }) else {
+                    break;
+                };
+                *in_flight.entry(pending.driver.clone()).or_default() += 1;
+                next_start.insert(pending.target.clone(), now + limits.target_interval);
+                running.push(self.run_job(pending, control.clone()));
+            }
+
+            if running.is_empty() && queue.is_empty() {
+                break;
+            }
+            // Nothing finished yet may be what unblocks the queue, but a
+            // target interval elapsing can as well.
+            let wake = next_start
+                .values()
+                .filter(|next| **next > now)
+                .min()
+                .copied();
+            let finished = match wake {
+                Some(wake) if !queue.is_empty() => tokio::select! {
+                    finished = running.next(), if !running.is_empty() => finished,
+                    _ = tokio::time::sleep_until(wake.into()) => None,
+                },
+                _ => running.next().await,
+            };
+            if let Some((driver, result)) = finished {
+                if let Some(count) = in_flight.get_mut(&driver) {
+                    *count = count.saturating_sub(1);
+                }
+                results.push(result);
+            }
+        }
+
+        results.sort_by_key(|result| result.index);
+        let mut report = BatchReport {
+            duration_ms: started.elapsed().as_millis() as u64,
+            ..BatchReport::default()
+        };
+        for result in &results {
+            match &result.outcome {
+                Some(outcome) if outcome.cancelled => report.cancelled += 1,
+                Some(outcome) if outcome.success => report.succeeded += 1,
+                _ => report.failed += 1,
+            }
+        }
+        report.results = results;
+        report
+    }
+
+    async fn run_job(&self, pending: Pending, control: RunControl) -> (String, BatchResult) {
+        let started = Instant::now();
+        let secrets = pending
+            .job
+            .secrets
+            .clone()
+            .unwrap_or_else(|| self.secrets.clone());
+        // Jobs share the batch control, so pausing or cancelling it affects
+        // every running job at once.
+        let outcome = self.run_inner(&pending.job.script, control, secrets).await;
+        let result = job_result(
+            pending.index,
+            &pending.job,
+            Some(pending.driver.clone()),
+            outcome,
+            started.elapsed(),
+        );
+        (pending.driver, result)
+    }
+}
+
+fn job_result(
+    index: usize,
+    job: &BatchJob,
+    driver: Option<String>,
+    outcome: anyhow::Result<LoginOutcome>,
+    duration: Duration,
+) -> BatchResult {
+    let (outcome, error) = match outcome {
+        Ok(outcome) => (Some(outcome), None),
+        Err(err) => (None, Some(err.to_string())),
+    };
+    BatchResult {
+        index,
+        user: job.user.clone(),
+        account: job.account.clone(),
+        script_id: job.script.meta.id.clone(),
+        driver,
+        outcome,
+        error,
+        duration_ms: duration.as_millis() as u64,
+    }
+}
+
+fn cancelled_outcome() -> LoginOutcome {
+    LoginOutcome {
+        success: false,
+        session_token: None,
+        error: Some(crate::Cancelled.to_string()),
+        cancelled: true,
+        error_kind: Some(ErrorKind::Cancelled),
+        attempts: Vec::new(),
+    }
+}
@@ -0,0 +1,662 @@
+//! Chrome DevTools Protocol driver talking to Chromium directly over
+//! WebSocket, without a Node/Playwright sidecar.
+//!
+//! The driver attaches to a page-level debugger endpoint
+//! (`ws://host:port/devtools/page/<id>`, as listed by `/json`) and uses the
+//! DOM, Input, Network and Page domains. Request/response headers seen on the
+//! Network domain are captured so the session token can come from either a
+//! cookie or a header.
+
+use crate::retry::{ErrorKind, StepError};
+use crate::runner::DriverSession;
+use crate::trace::{SnapshotFormat, UiSnapshot};
+use crate::{AutomationDriver, Selector, SessionToken, TargetApp, TargetAppKind, TokenSource};
+use async_trait::async_trait;
+use base64::Engine as _;
+use futures::{SinkExt, StreamExt};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+
+/// Upper bound for a single CDP command round-trip.
+const CALL_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Upper bound for `Page.navigate` to fire `Page.loadEventFired`.
+const NAVIGATION_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Web driver backed by a raw CDP connection.
+pub struct CdpDriver {
+    debugger_url: String,
+    session_cookie: Option<String>,
+    session_header: Option<String>,
+}
+
+impl CdpDriver {
+    /// `debugger_url` is the page WebSocket URL exposed by
+    /// `--remote-debugging-port`.
+    pub fn new(debugger_url: impl Into<String>) -> Self {
+        Self {
+            debugger_url: debugger_url.into(),
+            session_cookie: None,
+            session_header: None,
+        }
+    }
+
+    /// Use the value of this cookie as the session token.
+    pub fn with_session_cookie(mut self, name: impl Into<String>) -> Self {
+        self.session_cookie = Some(name.into());
+        self
+    }
+
+    /// Use the value of this request/response header (e.g. `authorization`)
+    /// as the session token. Ignored when a session cookie is configured.
+    pub fn with_session_header(mut self, name: impl Into<String>) -> Self {
+        self.session_header = Some(name.into().to_ascii_lowercase());
+        self
+    }
+}
+
+#[async_trait]
+impl AutomationDriver for CdpDriver {
+    fn name(&self) -> &'static str {
+        "cdp"
+    }
+
+    fn supports(&self, target: &TargetApp) -> bool {
+        matches!(target.kind, TargetAppKind::Web)
+    }
+
+    /// Every session attaches to the same page, and cookies can only be
+    /// cleared browser-wide, so pooled sessions would share one login.
+    fn poolable(&self) -> bool {
+        false
+    }
+
+    async fn open_session(&self, target: &TargetApp) -> anyhow::Result<Box<dyn DriverSession>> {
+        let mut session = CdpSession::connect(&self.debugger_url).await?;
+        session.session_cookie = self.session_cookie.clone();
+        session.session_header = self.session_header.clone();
+        if let Some(url) = &target.endpoint {
+            session.navigate(url).await?;
+        }
+        Ok(Box::new(session))
+    }
+}
+
+/// A cookie as reported by `Network.getCookies`.
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct CdpCookie {
+    pub name: String,
+    pub value: String,
+    #[serde(default)]
+    pub domain: String,
+    #[serde(default)]
+    pub path: String,
+    /// Expiry in seconds since the epoch; `-1` for session cookies.
+    #[serde(default)]
+    pub expires: f64,
+}
+
+/// Where a resolved selector points to.
+enum Target {
+    Node(i64),
+    Point(f64, f64),
+}
+
+/// One attached page.
+pub struct CdpSession {
+    socket: WebSocketStream<MaybeTlsStream<TcpStream>>,
+    next_id: u64,
+    loads_seen: u64,
+    /// Last value seen per lower-cased header name, requests and responses.
+    headers: HashMap<String, String>,
+    session_cookie: Option<String>,
+    session_header: Option<String>,
+}
+
+impl CdpSession {
+    /// Attach to a page debugger endpoint and enable the domains we rely on.
+    pub async fn connect(debugger_url: &str) -> anyhow::Result<Self> {
+        let (socket, _) = tokio_tungstenite::connect_async(debugger_url)
+            .await
+            .map_err(|err| {
+                StepError::new(
+                    ErrorKind::Network,
+                    format!("cdp connect to {debugger_url} failed: {err}"),
+                )
+            })?;
+        let mut session = Self {
+            socket,
+            next_id: 0,
+            loads_seen: 0,
+            headers: HashMap::new(),
+            session_cookie: None,
+            session_header: None,
+        };
+        for domain in ["Page", "Network", "DOM", "Runtime"] {
+            session.call(&format!("{domain}.enable"), json!({})).await?;
+        }
+        Ok(session)
+    }
+
+    /// Navigate the page and wait for its load event.
+    pub async fn navigate(&mut self, url: &str) -> anyhow::Result<()> {
+        let loads_before = self.loads_seen;
+        let result = self.call("Page.navigate", json!({ "url": url })).await?;
+        if let Some(error) = result.get("errorText").and_then(Value::as_str) {
+            return Err(anyhow::anyhow!("navigation to {url} failed: {error}"));
+        }
+        let deadline = tokio::time::Instant::now() + NAVIGATION_TIMEOUT;
+        while self.loads_seen == loads_before {
+            let remaining = deadline.saturating_duration_since(tokio::time::Instant::now());
+            if remaining.is_zero() {
+                return Err(StepError::new(
+                    ErrorKind::Timeout,
+                    format!("timed out waiting for {url} to load"),
+                )
+                .into());
+            }
+            self.read_frame(remaining).await?;
+        }
+        Ok(())
+    }
+
+    /// All cookies visible to the current page.
+    pub async fn cookies(&mut self) -> anyhow::Result<Vec<CdpCookie>> {
+        let result = self.call("Network.getCookies", json!({})).await?;
+        Ok(
+            serde_json::from_value(result.get("cookies").cloned().unwrap_or(Value::Null))
+                .unwrap_or_default(),
+        )
+    }
+
+    /// Headers captured from Network events so far, keyed by lower-cased name.
+    pub fn captured_headers(&self) -> &HashMap<String, String> {
+        &self.headers
+    }
+
+    /// Send a raw CDP command and wait for its result. Events received in the
+    /// meantime are folded into the session state.
+    pub async fn call(&mut self, method: &str, params: Value) -> anyhow::Result<Value> {
+        self.next_id += 1;
+        let id = self.next_id;
+        let frame = json!({ "id": id, "method": method, "params": params }).to_string();
+        self.socket
+            .send(Message::Text(frame.into()))
+            .await
+            .map_err(|err| {
+                StepError::new(
+                    ErrorKind::Network,
+                    format!("cdp send {method} failed: {err}"),
+                )
+            })?;
+
+        loop {
+            let Some(message) = self.read_frame(CALL_TIMEOUT).await? else {
+                continue;
+            };
+            if message.get("id").and_then(Value::as_u64) != Some(id) {
+                continue;
+            }
+            if let Some(error) = message.get("error") {
+                let text = error
+                    .get("message")
+                    .and_then(Value::as_str)
+                    .unwrap_or("unknown error");
+                return Err(anyhow::anyhow!("cdp {method} failed: {text}"));
+            }
+            return Ok(message.get("result").cloned().unwrap_or(Value::Null));
+        }
+    }
+
+    /// Read one frame; events are handled here, replies are returned.
+    async fn read_frame(&mut self, timeout: Duration) -> anyhow::Result<Option<Value>> {
+        let frame = tokio::time::timeout(timeout, self.socket.next())
+            .await
+            .map_err(|_| {
+                StepError::new(
+                    ErrorKind::Timeout,
+                    format!("cdp connection timed out after {timeout:?}"),
+                )
+            })?
+            .ok_or_else(|| StepError::new(ErrorKind::Network, "cdp connection closed"))?
+            .map_err(|err| {
+                StepError::new(ErrorKind::Network, format!("cdp receive failed: {err}"))
+            })?;
+        let text = match frame {
+            Message::Text(text) => text,
+            Message::Close(_) => {
+                return Err(StepError::new(ErrorKind::Network, "cdp connection closed").into())
+            }
+            _ => return Ok(None),
+        };
+        let message: Value = serde_json::from_str(&text)
+            .map_err(|err| anyhow::anyhow!("invalid cdp frame: {err}"))?;
+        if let Some(method) = message.get("method").and_then(Value::as_str) {
+            self.on_event(method, message.get("params").unwrap_or(&Value::Null));
+            return Ok(None);
+        }
+        Ok(Some(message))
+    }
+
+    fn on_event(&mut self, method: &str, params: &Value) {
+        let headers = match method {
+            "Page.loadEventFired" => {
+                self.loads_seen += 1;
+                return;
+            }
+            "Network.requestWillBeSent" => params.pointer("/request/headers"),
+            "Network.responseReceived" => params.pointer("/response/headers"),
+            _ => None,
+        };
+        if let Some(Value::Object(headers)) = headers {
+            for (name, value) in headers {
+                if let Some(value) = value.as_str() {
+                    self.headers
+                        .insert(name.to_ascii_lowercase(), value.to_string());
+                }
+            }
+        }
+    }
+
+    async fn resolve(&mut self, selector: &Selector) -> anyhow::Result<Option<Target>> {
+        match selector {
+            Selector::Css(css) => self.query_selector(css).await,
+            Selector::AccessibilityId(label) => {
+                let css = format!("[aria-label=\"{}\"]", label.replace('"', "\\\""));
+                self.query_selector(&css).await
+            }
+            Selector::XPath(xpath) => {
+                self.document_root().await?;
+                let search = self
+                    .call("DOM.performSearch", json!({ "query": xpath }))
+                    .await?;
+                let search_id = search
+                    .get("searchId")
+                    .cloned()
+                    .ok_or_else(|| anyhow::anyhow!("DOM.performSearch returned no searchId"))?;
+                let count = search
+                    .get("resultCount")
+                    .and_then(Value::as_u64)
+                    .unwrap_or(0);
+                let node = if count > 0 {
+                    let results = self
+                        .call(
+                            "DOM.getSearchResults",
+                            json!({ "searchId": search_id, "fromIndex": 0, "toIndex": 1 }),
+                        )
+                        .await?;
+                    results
+                        .pointer("/nodeIds/0")
+                        .and_then(Value::as_i64)
+                        .map(Target::Node)
+                } else {
+                    None
+                };
+                self.call("DOM.discardSearchResults", json!({ "searchId": search_id }))
+                    .await?;
+                Ok(node)
+            }
+            Selector::Coordinates { x, y } => Ok(Some(Target::Point(*x as f64, *y as f64))),
+            Selector::Image(_) => Err(anyhow::anyhow!(
+                "image selectors are not supported by the cdp driver"
+            )),
+            Selector::AnyOf(candidates) => {
+                for candidate in candidates {
+                    if let Some(target) = Box::pin(self.resolve(candidate)).await? {
+                        return Ok(Some(target));
+                    }
+                }
+                Ok(None)
+            }
+        }
+    }
+
+    async fn document_root(&mut self) -> anyhow::Result<i64> {
+        let document = self.call("DOM.getDocument", json!({ "depth": 0 })).await?;
+        document
+            .pointer("/root/nodeId")
+            .and_then(Value::as_i64)
+            .ok_or_else(|| anyhow::anyhow!("DOM.getDocument returned no root"))
+    }
+
+    async fn query_selector(&mut self, css: &str) -> anyhow::Result<Option<Target>> {
+        let root = self.document_root().await?;
+        let result = self
+            .call(
+                "DOM.querySelector",
+                json!({ "nodeId": root, "selector": css }),
+            )
+            .await?;
+        Ok(result
+            .get("nodeId")
+            .and_then(Value::as_i64)
+            .filter(|id| *id != 0)
+            .map(Target::Node))
+    }
+
+    async fn require(&mut self, selector: &Selector) -> anyhow::Result<Target> {
+        self.resolve(selector)
+            .await?
+            .ok_or_else(|| StepError::not_found(selector).into())
+    }
+
+    /// Border-box quad of a node as `(x, y, width, height)`.
+    async fn node_box(&mut self, node: i64) -> anyhow::Result<(f64, f64, f64, f64)> {
+        // Older Chromium builds lack this command; scrolling is best-effort.
+        let _ = self
+            .call("DOM.scrollIntoViewIfNeeded", json!({ "nodeId": node }))
+            .await;
+        let model = self
+            .call("DOM.getBoxModel", json!({ "nodeId": node }))
+            .await?;
+        let quad: Vec<f64> = model
+            .pointer("/model/border")
+            .and_then(Value::as_array)
+            .map(|points| points.iter().filter_map(Value::as_f64).collect())
+            .unwrap_or_default();
+        if quad.len() != 8 {
+            return Err(anyhow::anyhow!("node {node} has no box model"));
+        }
+        let xs = [quad[0], quad[2], quad[4], quad[6]];
+        let ys = [quad[1], quad[3], quad[5], quad[7]];
+        let min_x = xs.iter().cloned().fold(f64::INFINITY, f64::min);
+        let max_x = xs.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+        let min_y = ys.iter().cloned().fold(f64::INFINITY, f64::min);
+        let max_y = ys.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+        Ok((min_x, min_y, max_x - min_x, max_y - min_y))
+    }
+
+    async fn point_of(&mut self, target: Target) -> anyhow::Result<(f64, f64)> {
+        match target {
+            Target::Point(x, y) => Ok((x, y)),
+            Target::Node(node) => {
+                let (x, y, width, height) = self.node_box(node).await?;
+                Ok((x + width / 2.0, y + height / 2.0))
+            }
+        }
+    }
+
+    async fn dispatch_click(&mut self, x: f64, y: f64) -> anyhow::Result<()> {
+        self.call(
+            "Input.dispatchMouseEvent",
+            json!({ "type": "mouseMoved", "x": x, "y": y }),
+        )
+        .await?;
+        for kind in ["mousePressed", "mouseReleased"] {
+            self.call(
+                "Input.dispatchMouseEvent",
+                json!({ "type": kind, "x": x, "y": y, "button": "left", "clickCount": 1 }),
+            )
+            .await?;
+        }
+        Ok(())
+    }
+
+    async fn screenshot_with(&mut self, params: Value) -> anyhow::Result<Vec<u8>> {
+        let result = self.call("Page.captureScreenshot", params).await?;
+        let data = result
+            .get("data")
+            .and_then(Value::as_str)
+            .ok_or_else(|| anyhow::anyhow!("Page.captureScreenshot returned no data"))?;
+        base64::engine::general_purpose::STANDARD
+            .decode(data)
+            .map_err(|err| anyhow::anyhow!("invalid screenshot payload: {err}"))
+    }
+}
+
+#[async_trait]
+impl DriverSession for CdpSession {
+    async fn exists(&mut self, selector: &Selector) -> anyhow::Result<bool> {
+        Ok(self.resolve(selector).await?.is_some())
+    }
+
+    async fn click(&mut self, selector: &Selector) -> anyhow::Result<()> {
+        let target = self.require(selector).await?;
+        let (x, y) = self.point_of(target).await?;
+        self.dispatch_click(x, y).await
+    }
+
+    async fn input(&mut self, selector: &Selector, text: &str) -> anyhow::Result<()> {
+        match self.require(selector).await? {
+            Target::Node(node) => {
+                self.call("DOM.focus", json!({ "nodeId": node })).await?;
+            }
+            Target::Point(x, y) => self.dispatch_click(x, y).await?,
+        }
+        for ch in text.chars() {
+            let key = ch.to_string();
+            self.call(
+                "Input.dispatchKeyEvent",
+                json!({ "type": "keyDown", "key": key, "text": key, "unmodifiedText": key }),
+            )
+            .await?;
+            self.call(
+                "Input.dispatchKeyEvent",
+                json!({ "type": "keyUp", "key": key }),
+            )
+            .await?;
+        }
+        Ok(())
+    }
+
+    async fn swipe(
+        &mut self,
+        from: &Selector,
+        to: &Selector,
+        duration_ms: u64,
+    ) -> anyhow::Result<()> {
+        const MOVES: u64 = 10;
+        let start = self.require(from).await?;
+        let (x1, y1) = self.point_of(start).await?;
+        let end = self.require(to).await?;
+        let (x2, y2) = self.point_of(end).await?;
+        self.call(
+            "Input.dispatchMouseEvent",
+            json!({ "type": "mousePressed", "x": x1, "y": y1, "button": "left", "clickCount": 1 }),
+        )
+        .await?;
+        for i in 1..=MOVES {
+            let t = i as f64 / MOVES as f64;
+            self.call(
+                "Input.dispatchMouseEvent",
+                json!({
+                    "type": "mouseMoved",
+                    "x": x1 + (x2 - x1) * t,
+                    "y": y1 + (y2 - y1) * t,
+                    "button": "left",
+                }),
+            )
+            .await?;
+            tokio::time::sleep(Duration::from_millis(duration_ms / MOVES)).await;
+        }
+        self.call(
+            "Input.dispatchMouseEvent",
+            json!({ "type": "mouseReleased", "x": x2, "y": y2, "button": "left", "clickCount": 1 }),
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn text(&mut self, selector: &Selector) -> anyhow::Result<Option<String>> {
+        let node = match self.resolve(selector).await? {
+            Some(Target::Node(node)) => node,
+            Some(Target::Point(..)) | None => return Ok(None),
+        };
+        let object = self
+            .call("DOM.resolveNode", json!({ "nodeId": node }))
+            .await?;
+        let object_id = object
+            .pointer("/object/objectId")
+            .cloned()
+            .ok_or_else(|| anyhow::anyhow!("DOM.resolveNode returned no objectId"))?;
+        let result = self
+            .call(
+                "Runtime.callFunctionOn",
+                json!({
+                    "objectId": object_id,
+                    "functionDeclaration":
+                        "function() { return this.value ?? this.innerText ?? this.textContent; }",
+                    "returnByValue": true,
+                }),
+            )
+            .await?;
+        Ok(result
+            .pointer("/result/value")
+            .and_then(Value::as_str)
+            .map(|text| text.trim().to_string()))
+    }
+
+    async fn capture(&mut self, selector: &Selector) -> anyhow::Result<Vec<u8>> {
+        let clip = match self.require(selector).await? {
+            Target::Node(node) => {
+                let (x, y, width, height) = self.node_box(node).await?;
+                json!({ "x": x, "y": y, "width": width, "height": height, "scale": 1 })
+            }
+            Target::Point(..) => {
+                return Err(anyhow::anyhow!(
+                    "cannot capture an element addressed by coordinates"
+                ))
+            }
+        };
+        self.screenshot_with(json!({ "format": "png", "clip": clip }))
+            .await
+    }
+
+    async fn screenshot(&mut self) -> anyhow::Result<Vec<u8>> {
+        self.screenshot_with(json!({ "format": "png" })).await
+    }
+
+    async fn snapshot(&mut self) -> anyhow::Result<Option<UiSnapshot>> {
+        let result = self
+            .call(
+                "Runtime.evaluate",
+                json!({
+                    "expression": "document.documentElement.outerHTML",
+                    "returnByValue": true,
+                }),
+            )
+            .await?;
+        Ok(result
+            .pointer("/result/value")
+            .and_then(Value::as_str)
+            .map(|html| UiSnapshot {
+                format: SnapshotFormat::Html,
+                content: html.to_string(),
+            }))
+    }
+
+    async fn current_url(&mut self) -> anyhow::Result<Option<String>> {
+        Ok(self
+            .call(
+                "Runtime.evaluate",
+                json!({ "expression": "location.href", "returnByValue": true }),
+            )
+            .await?
+            .pointer("/result/value")
+            .and_then(Value::as_str)
+            .map(str::to_string))
+    }
+
+    async fn session_token(&mut self) -> anyhow::Result<Option<String>> {
+        if let Some(name) = self.session_cookie.clone() {
+            return Ok(self
+                .cookies()
+                .await?
+                .into_iter()
+                .find(|cookie| cookie.name == name)
+                .map(|cookie| cookie.value));
+        }
+        if let Some(name) = &self.session_header {
+            return Ok(self.headers.get(name).cloned());
+        }
+        let cookies = self.cookies().await?;
+        if cookies.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(
+            cookies
+                .iter()
+                .map(|cookie| format!("{}={}", cookie.name, cookie.value))
+                .collect::<Vec<_>>()
+                .join("; "),
+        ))
+    }
+
+    async fn extract_token(&mut self, source: &TokenSource) -> anyhow::Result<Option<SessionToken>> {
+        let found = match source {
+            TokenSource::Cookie(name) => self
+                .cookies()
+                .await?
+                .into_iter()
+                .find(|cookie| &cookie.name == name)
+                .map(|cookie| {
+                    // Session cookies report -1.
+                    let expires = (cookie.expires > 0.0).then_some(cookie.expires as u64);
+                    (cookie.value, expires)
+                }),
+            TokenSource::LocalStorage(key) => self
+                .call(
+                    "Runtime.evaluate",
+                    json!({
+                        "expression": format!("localStorage.getItem({})", json!(key)),
+                        "returnByValue": true,
+                    }),
+                )
+                .await?
+                .pointer("/result/value")
+                .and_then(Value::as_str)
+                .map(|value| (value.to_string(), None)),
+            TokenSource::ResponseHeader(name) => self
+                .headers
+                .get(&name.to_ascii_lowercase())
+                .map(|value| (value.clone(), None)),
+            TokenSource::ElementText(selector) => {
+                self.text(selector).await?.map(|text| (text, None))
+            }
+            TokenSource::SharedPreference { .. } => {
+                return Err(anyhow::anyhow!("browser sessions have no SharedPreferences"))
+            }
+        };
+        Ok(found.map(|(value, expires_at)| SessionToken::from_source(value, source, expires_at)))
+    }
+
+    async fn health_check(&mut self) -> anyhow::Result<()> {
+        self.call("Runtime.evaluate", json!({ "expression": "1" }))
+            .await
+            .map(drop)
+    }
+
+    async fn reset(&mut self, target: &TargetApp) -> anyhow::Result<()> {
+        let origin = self
+            .call(
+                "Runtime.evaluate",
+                json!({ "expression": "location.origin", "returnByValue": true }),
+            )
+            .await?
+            .pointer("/result/value")
+            .and_then(Value::as_str)
+            .map(str::to_string);
+        if let Some(origin) = origin.filter(|origin| origin != "null") {
+            self.call(
+                "Storage.clearDataForOrigin",
+                json!({ "origin": origin, "storageTypes": "all" }),
+            )
+            .await?;
+        }
+        self.call("Network.clearBrowserCookies", json!({})).await?;
+        self.headers.clear();
+        self.navigate(target.endpoint.as_deref().unwrap_or("about:blank"))
+            .await
+    }
+
+    async fn close(&mut self) -> anyhow::Result<()> {
+        self.socket
+            .close(None)
+            .await
+            .map_err(|err| anyhow::anyhow!("cdp close failed: {err}"))
+    }
+}
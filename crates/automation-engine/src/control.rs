@@ -0,0 +1,128 @@
+//! Cancellation and pause/resume for a running script.
+//!
+//! The runner checks the control between steps and while waiting or
+//! sleeping; it never interrupts a driver call half-way, so sessions stay in
+//! a consistent state and can always be closed.
+
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::watch;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunState {
+    Running,
+    Paused,
+    Cancelled,
+}
+
+/// Error returned by the runner once a run observes cancellation.
+#[derive(Debug, Clone, Copy)]
+pub struct Cancelled;
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("run cancelled")
+    }
+}
+
+impl std::error::Error for Cancelled {}
+
+/// Cloneable handle shared between the caller and the runner.
+#[derive(Clone)]
+pub struct RunControl {
+    state: Arc<watch::Sender<RunState>>,
+}
+
+impl Default for RunControl {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for RunControl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("RunControl").field(&self.state()).finish()
+    }
+}
+
+impl RunControl {
+    pub fn new() -> Self {
+        Self {
+            state: Arc::new(watch::channel(RunState::Running).0),
+        }
+    }
+
+    pub fn state(&self) -> RunState {
+        *self.state.borrow()
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.state() == RunState::Cancelled
+    }
+
+    /// Stop the run at the next checkpoint. Cancelling is final.
+    pub fn cancel(&self) {
+        self.state.send_replace(RunState::Cancelled);
+    }
+
+    /// Hold the run before its next step, e.g. while the user acts manually.
+    pub fn pause(&self) {
+        self.transition(RunState::Running, RunState::Paused);
+    }
+
+    pub fn resume(&self) {
+        self.transition(RunState::Paused, RunState::Running);
+    }
+
+    fn transition(&self, from: RunState, to: RunState) {
+        self.state.send_if_modified(|state| {
+            if *state == from {
+                *state = to;
+                true
+            } else {
+                false
+            }
+        });
+    }
+
+    /// Wait while paused; fail once cancelled.
+    pub async fn checkpoint(&self) -> Result<(), Cancelled> {
+        let mut state = self.state.subscribe();
+        // The sender lives in `self`, so the channel cannot close here.
+        let settled = state
+            .wait_for(|state| *state != RunState::Paused)
+            .await
+            .map(|state| *state);
+        match settled {
+            Ok(RunState::Cancelled) => Err(Cancelled),
+            _ => Ok(()),
+        }
+    }
+
+    /// Resolves once the run is cancelled.
+    pub async fn cancelled(&self) {
+        let mut state = self.state.subscribe();
+        let _ = state.wait_for(|state| *state == RunState::Cancelled).await;
+    }
+
+    /// Resolves with the new state once it is no longer `from`, e.g. to
+    /// relay pauses to a run in another process.
+    pub async fn changed_from(&self, from: RunState) -> RunState {
+        let mut state = self.state.subscribe();
+        // The sender lives in `self`, so the channel cannot close here.
+        state
+            .wait_for(|state| *state != from)
+            .await
+            .map_or(from, |state| *state)
+    }
+
+    /// Sleep that ends early when the run is cancelled.
+    pub async fn sleep(&self, duration: Duration) -> Result<(), Cancelled> {
+        let mut state = self.state.subscribe();
+        tokio::select! {
+            _ = tokio::time::sleep(duration) => Ok(()),
+            _ = state.wait_for(|state| *state == RunState::Cancelled) => Err(Cancelled),
+        }
+    }
+}
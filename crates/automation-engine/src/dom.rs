@@ -0,0 +1,568 @@
+//! Minimal element tree plus an XPath subset shared by drivers that parse
+//! markup themselves (uiautomator dumps, server-rendered HTML).
+//!
+//! Supported XPath: absolute and relative paths, `/` and `//`, `.`, `..`,
+//! name tests and `*`, and predicates built from positions, `last()`,
+//! `@attr`, `text()`, `=`/`!=`, `contains()`, `starts-with()`, `not()`,
+//! `and` and `or`. Unions (`|`) are accepted at the top level.
+
+use std::collections::BTreeSet;
+
+/// Index of a node inside a [`NodeTree`]. Index `0` is the document root.
+pub type NodeId = usize;
+
+#[derive(Debug, Clone, Default)]
+pub struct Node {
+    pub tag: String,
+    pub attributes: Vec<(String, String)>,
+    /// Text directly owned by this node (not its descendants).
+    pub text: String,
+    pub parent: Option<NodeId>,
+    pub children: Vec<NodeId>,
+}
+
+impl Node {
+    pub fn attr(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Flat arena of element nodes in document order.
+#[derive(Debug, Clone)]
+pub struct NodeTree {
+    nodes: Vec<Node>,
+}
+
+impl Default for NodeTree {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl NodeTree {
+    /// A tree holding only the synthetic document root.
+    pub fn new() -> Self {
+        Self {
+            nodes: vec![Node::default()],
+        }
+    }
+
+    pub const ROOT: NodeId = 0;
+
+    /// Append an element under `parent` and return its id.
+    pub fn push(
+        &mut self,
+        parent: NodeId,
+        tag: impl Into<String>,
+        attributes: Vec<(String, String)>,
+    ) -> NodeId {
+        let id = self.nodes.len();
+        self.nodes.push(Node {
+            tag: tag.into(),
+            attributes,
+            text: String::new(),
+            parent: Some(parent),
+            children: Vec::new(),
+        });
+        self.nodes[parent].children.push(id);
+        id
+    }
+
+    pub fn append_text(&mut self, id: NodeId, text: &str) {
+        self.nodes[id].text.push_str(text);
+    }
+
+    pub fn node(&self, id: NodeId) -> &Node {
+        &self.nodes[id]
+    }
+
+    /// All element ids in document order, excluding the root.
+    pub fn elements(&self) -> impl Iterator<Item = NodeId> + '_ {
+        1..self.nodes.len()
+    }
+
+    /// Concatenated text of a node and all of its descendants.
+    pub fn text_content(&self, id: NodeId) -> String {
+        let mut out = self.nodes[id].text.clone();
+        for child in &self.nodes[id].children {
+            out.push_str(&self.text_content(*child));
+        }
+        out
+    }
+
+    /// Ancestors of `id`, nearest first, excluding the root.
+    pub fn ancestors(&self, id: NodeId) -> impl Iterator<Item = NodeId> + '_ {
+        std::iter::successors(self.nodes[id].parent, |node| self.nodes[*node].parent)
+            .filter(|node| *node != Self::ROOT)
+    }
+
+    fn descendants(&self, id: NodeId, out: &mut Vec<NodeId>) {
+        for child in &self.nodes[id].children {
+            out.push(*child);
+            self.descendants(*child, out);
+        }
+    }
+
+    fn children_matching(&self, id: NodeId, test: &NameTest) -> Vec<NodeId> {
+        self.nodes[id]
+            .children
+            .iter()
+            .copied()
+            .filter(|child| test.matches(&self.nodes[*child].tag))
+            .collect()
+    }
+
+    /// Evaluate an XPath expression from the document root and return the
+    /// matching nodes in document order.
+    pub fn xpath(&self, expression: &str) -> anyhow::Result<Vec<NodeId>> {
+        let union = Parser::new(expression)?.parse_union()?;
+        let mut found = BTreeSet::new();
+        for path in &union {
+            found.extend(self.eval_path(path, &[Self::ROOT]));
+        }
+        Ok(found.into_iter().collect())
+    }
+
+    fn eval_path(&self, path: &Path, context: &[NodeId]) -> Vec<NodeId> {
+        let mut current: Vec<NodeId> = if path.absolute {
+            vec![Self::ROOT]
+        } else {
+            context.to_vec()
+        };
+        for step in &path.steps {
+            let mut next = BTreeSet::new();
+            for node in &current {
+                // Predicates see one parent's children at a time, so `//a[1]`
+                // is every `a` that comes first among its siblings.
+                let groups: Vec<Vec<NodeId>> = match step.axis {
+                    Axis::SelfNode => vec![vec![*node]],
+                    Axis::Parent => vec![self.nodes[*node].parent.into_iter().collect()],
+                    Axis::Child => vec![self.children_matching(*node, &step.test)],
+                    Axis::Descendant => {
+                        let mut parents = vec![*node];
+                        self.descendants(*node, &mut parents);
+                        parents
+                            .into_iter()
+                            .map(|parent| self.children_matching(parent, &step.test))
+                            .collect()
+                    }
+                };
+                for mut filtered in groups {
+                    for predicate in &step.predicates {
+                        let size = filtered.len();
+                        filtered = filtered
+                            .iter()
+                            .enumerate()
+                            .filter(|(index, id)| self.predicate(predicate, **id, index + 1, size))
+                            .map(|(_, id)| *id)
+                            .collect();
+                    }
+                    next.extend(filtered);
+                }
+            }
+            current = next.into_iter().collect();
+        }
+        current
+    }
+
+    fn predicate(&self, expr: &Expr, node: NodeId, position: usize, size: usize) -> bool {
+        match expr {
+            Expr::Number(n) => *n as usize == position,
+            Expr::Last => position == size,
+            other => self.truthy(other, node, position, size),
+        }
+    }
+
+    fn truthy(&self, expr: &Expr, node: NodeId, position: usize, size: usize) -> bool {
+        match expr {
+            Expr::Or(left, right) => {
+                self.truthy(left, node, position, size) || self.truthy(right, node, position, size)
+            }
+            Expr::And(left, right) => {
+                self.truthy(left, node, position, size) && self.truthy(right, node, position, size)
+            }
+            Expr::Not(inner) => !self.truthy(inner, node, position, size),
+            Expr::Eq(left, right) => self.value(left, node) == self.value(right, node),
+            Expr::Ne(left, right) => self.value(left, node) != self.value(right, node),
+            Expr::Contains(haystack, needle) => match self.value(haystack, node) {
+                Some(hay) => hay.contains(self.value(needle, node).unwrap_or_default().as_str()),
+                None => false,
+            },
+            Expr::StartsWith(haystack, prefix) => match self.value(haystack, node) {
+                Some(hay) => hay.starts_with(self.value(prefix, node).unwrap_or_default().as_str()),
+                None => false,
+            },
+            Expr::Number(n) => *n as usize == position,
+            Expr::Last => position == size,
+            Expr::Attr(_) | Expr::Text | Expr::Dot | Expr::Literal(_) => {
+                self.value(expr, node).is_some()
+            }
+            Expr::Path(path) => !self.eval_path(path, &[node]).is_empty(),
+        }
+    }
+
+    fn value(&self, expr: &Expr, node: NodeId) -> Option<String> {
+        match expr {
+            Expr::Attr(name) => self.nodes[node].attr(name).map(str::to_string),
+            Expr::Text => Some(self.nodes[node].text.trim().to_string()),
+            Expr::Dot => Some(self.text_content(node).trim().to_string()),
+            Expr::Literal(text) => Some(text.clone()),
+            Expr::Number(n) => Some(n.to_string()),
+            Expr::Path(path) => self
+                .eval_path(path, &[node])
+                .first()
+                .map(|id| self.text_content(*id).trim().to_string()),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Path {
+    absolute: bool,
+    steps: Vec<PathStep>,
+}
+
+#[derive(Debug)]
+struct PathStep {
+    axis: Axis,
+    test: NameTest,
+    predicates: Vec<Expr>,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Axis {
+    Child,
+    /// `//`: children of the context node or of any of its descendants.
+    Descendant,
+    SelfNode,
+    Parent,
+}
+
+#[derive(Debug)]
+enum NameTest {
+    Any,
+    Name(String),
+}
+
+impl NameTest {
+    fn matches(&self, tag: &str) -> bool {
+        match self {
+            NameTest::Any => true,
+            NameTest::Name(name) => name.eq_ignore_ascii_case(tag),
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Expr {
+    Or(Box<Expr>, Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Eq(Box<Expr>, Box<Expr>),
+    Ne(Box<Expr>, Box<Expr>),
+    Contains(Box<Expr>, Box<Expr>),
+    StartsWith(Box<Expr>, Box<Expr>),
+    Attr(String),
+    Text,
+    Dot,
+    Last,
+    Literal(String),
+    Number(f64),
+    Path(Path),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Slash,
+    DoubleSlash,
+    Dot,
+    DoubleDot,
+    At,
+    Star,
+    Pipe,
+    Comma,
+    LBracket,
+    RBracket,
+    LParen,
+    RParen,
+    Eq,
+    Ne,
+    Name(String),
+    Literal(String),
+    Number(f64),
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    source: String,
+}
+
+impl Parser {
+    fn new(source: &str) -> anyhow::Result<Self> {
+        Ok(Self {
+            tokens: tokenize(source)?,
+            pos: 0,
+            source: source.to_string(),
+        })
+    }
+
+    fn error(&self, message: &str) -> anyhow::Error {
+        anyhow::anyhow!("invalid xpath `{}`: {message}", self.source)
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn eat(&mut self, token: &Token) -> bool {
+        if self.peek() == Some(token) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, token: Token) -> anyhow::Result<()> {
+        if self.eat(&token) {
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected {token:?}")))
+        }
+    }
+
+    fn parse_union(mut self) -> anyhow::Result<Vec<Path>> {
+        let mut paths = vec![self.parse_path()?];
+        while self.eat(&Token::Pipe) {
+            paths.push(self.parse_path()?);
+        }
+        if self.pos != self.tokens.len() {
+            return Err(self.error("unexpected trailing input"));
+        }
+        Ok(paths)
+    }
+
+    fn parse_path(&mut self) -> anyhow::Result<Path> {
+        let mut steps = Vec::new();
+        let (absolute, mut axis) = match self.peek() {
+            Some(Token::Slash) => {
+                self.pos += 1;
+                (true, Axis::Child)
+            }
+            Some(Token::DoubleSlash) => {
+                self.pos += 1;
+                (true, Axis::Descendant)
+            }
+            _ => (false, Axis::Child),
+        };
+        loop {
+            steps.push(self.parse_step(axis)?);
+            axis = match self.peek() {
+                Some(Token::Slash) => Axis::Child,
+                Some(Token::DoubleSlash) => Axis::Descendant,
+                _ => break,
+            };
+            self.pos += 1;
+        }
+        Ok(Path { absolute, steps })
+    }
+
+    fn parse_step(&mut self, axis: Axis) -> anyhow::Result<PathStep> {
+        let (axis, test) = match self.peek().cloned() {
+            Some(Token::Dot) => {
+                self.pos += 1;
+                (Axis::SelfNode, NameTest::Any)
+            }
+            Some(Token::DoubleDot) => {
+                self.pos += 1;
+                (Axis::Parent, NameTest::Any)
+            }
+            Some(Token::Star) => {
+                self.pos += 1;
+                (axis, NameTest::Any)
+            }
+            Some(Token::Name(name)) => {
+                self.pos += 1;
+                (axis, NameTest::Name(name))
+            }
+            _ => return Err(self.error("expected a step")),
+        };
+        let mut predicates = Vec::new();
+        while self.eat(&Token::LBracket) {
+            predicates.push(self.parse_or()?);
+            self.expect(Token::RBracket)?;
+        }
+        Ok(PathStep {
+            axis,
+            test,
+            predicates,
+        })
+    }
+
+    fn parse_or(&mut self) -> anyhow::Result<Expr> {
+        let mut left = self.parse_and()?;
+        while self.eat(&Token::Name("or".into())) {
+            left = Expr::Or(Box::new(left), Box::new(self.parse_and()?));
+        }
+        Ok(left)
+    }
+
+    fn parse_and(&mut self) -> anyhow::Result<Expr> {
+        let mut left = self.parse_comparison()?;
+        while self.eat(&Token::Name("and".into())) {
+            left = Expr::And(Box::new(left), Box::new(self.parse_comparison()?));
+        }
+        Ok(left)
+    }
+
+    fn parse_comparison(&mut self) -> anyhow::Result<Expr> {
+        let left = self.parse_primary()?;
+        if self.eat(&Token::Eq) {
+            return Ok(Expr::Eq(Box::new(left), Box::new(self.parse_primary()?)));
+        }
+        if self.eat(&Token::Ne) {
+            return Ok(Expr::Ne(Box::new(left), Box::new(self.parse_primary()?)));
+        }
+        Ok(left)
+    }
+
+    fn parse_primary(&mut self) -> anyhow::Result<Expr> {
+        match self.peek().cloned() {
+            Some(Token::At) => {
+                self.pos += 1;
+                match self.peek().cloned() {
+                    Some(Token::Name(name)) => {
+                        self.pos += 1;
+                        Ok(Expr::Attr(name))
+                    }
+                    _ => Err(self.error("expected attribute name after @")),
+                }
+            }
+            Some(Token::Literal(text)) => {
+                self.pos += 1;
+                Ok(Expr::Literal(text))
+            }
+            Some(Token::Number(n)) => {
+                self.pos += 1;
+                Ok(Expr::Number(n))
+            }
+            Some(Token::LParen) => {
+                self.pos += 1;
+                let inner = self.parse_or()?;
+                self.expect(Token::RParen)?;
+                Ok(inner)
+            }
+            Some(Token::Dot)
+                if !matches!(
+                    self.tokens.get(self.pos + 1),
+                    Some(Token::Slash | Token::DoubleSlash)
+                ) =>
+            {
+                self.pos += 1;
+                Ok(Expr::Dot)
+            }
+            Some(Token::Name(name)) if self.tokens.get(self.pos + 1) == Some(&Token::LParen) => {
+                self.pos += 2;
+                let expr = match name.as_str() {
+                    "text" => Expr::Text,
+                    "last" => Expr::Last,
+                    "not" => Expr::Not(Box::new(self.parse_or()?)),
+                    "contains" | "starts-with" => {
+                        let left = self.parse_primary()?;
+                        self.expect(Token::Comma)?;
+                        let right = self.parse_primary()?;
+                        if name == "contains" {
+                            Expr::Contains(Box::new(left), Box::new(right))
+                        } else {
+                            Expr::StartsWith(Box::new(left), Box::new(right))
+                        }
+                    }
+                    other => return Err(self.error(&format!("unsupported function {other}()"))),
+                };
+                self.expect(Token::RParen)?;
+                Ok(expr)
+            }
+            Some(_) => Ok(Expr::Path(self.parse_path()?)),
+            None => Err(self.error("unexpected end of expression")),
+        }
+    }
+}
+
+fn tokenize(source: &str) -> anyhow::Result<Vec<Token>> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+        match c {
+            ' ' | '\t' | '\n' | '\r' => {}
+            '/' if next == Some('/') => {
+                tokens.push(Token::DoubleSlash);
+                i += 1;
+            }
+            '/' => tokens.push(Token::Slash),
+            '.' if next == Some('.') => {
+                tokens.push(Token::DoubleDot);
+                i += 1;
+            }
+            '.' if !next.is_some_and(|n| n.is_ascii_digit()) => tokens.push(Token::Dot),
+            '@' => tokens.push(Token::At),
+            '*' => tokens.push(Token::Star),
+            '|' => tokens.push(Token::Pipe),
+            ',' => tokens.push(Token::Comma),
+            '[' => tokens.push(Token::LBracket),
+            ']' => tokens.push(Token::RBracket),
+            '(' => tokens.push(Token::LParen),
+            ')' => tokens.push(Token::RParen),
+            '=' => tokens.push(Token::Eq),
+            '!' if next == Some('=') => {
+                tokens.push(Token::Ne);
+                i += 1;
+            }
+            '\'' | '"' => {
+                let end = chars[i + 1..]
+                    .iter()
+                    .position(|ch| *ch == c)
+                    .ok_or_else(|| {
+                        anyhow::anyhow!("invalid xpath `{source}`: unterminated string")
+                    })?;
+                tokens.push(Token::Literal(chars[i + 1..i + 1 + end].iter().collect()));
+                i += end + 1;
+            }
+            c if c.is_ascii_digit() || c == '.' => {
+                let start = i;
+                while i + 1 < chars.len() && (chars[i + 1].is_ascii_digit() || chars[i + 1] == '.')
+                {
+                    i += 1;
+                }
+                let text: String = chars[start..=i].iter().collect();
+                tokens.push(Token::Number(text.parse().map_err(|_| {
+                    anyhow::anyhow!("invalid xpath `{source}`: bad number {text}")
+                })?));
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                let start = i;
+                while i + 1 < chars.len()
+                    && (chars[i + 1].is_alphanumeric()
+                        || matches!(chars[i + 1], '_' | '-' | '.' | ':'))
+                {
+                    i += 1;
+                }
+                tokens.push(Token::Name(chars[start..=i].iter().collect()));
+            }
+            other => {
+                return Err(anyhow::anyhow!(
+                    "invalid xpath `{source}`: unexpected character {other:?}"
+                ))
+            }
+        }
+        i += 1;
+    }
+    Ok(tokens)
+}
@@ -0,0 +1,140 @@
+//! Progress events emitted while a script runs.
+//!
+//! The engine owns one broadcast channel; every run tags its events with a
+//! `run_id` so subscribers (UI, logs, trace writers) can follow several runs
+//! at once. Emitting never blocks and is a no-op when nobody listens.
+
+use crate::{CaptchaKind, CaptchaStrategy, Selector, StepPath};
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+
+/// Number of events buffered per subscriber before slow receivers lag.
+pub const EVENT_CHANNEL_CAPACITY: usize = 1024;
+
+/// Identifier assigned by the engine to each `run` call.
+pub type RunId = u64;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExecutionEvent {
+    pub run_id: RunId,
+    /// Milliseconds since the Unix epoch.
+    pub timestamp_ms: u64,
+    pub kind: ExecutionEventKind,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ExecutionEventKind {
+    RunStarted {
+        script_id: String,
+        version: String,
+        driver: String,
+    },
+    StepStarted {
+        path: StepPath,
+        /// Short label of the step kind, e.g. `click`.
+        step: String,
+    },
+    StepFinished {
+        path: StepPath,
+        duration_ms: u64,
+        error: Option<String>,
+    },
+    SelectorResolved {
+        path: StepPath,
+        selector: Selector,
+        duration_ms: u64,
+    },
+    BranchTaken {
+        path: StepPath,
+        condition: bool,
+    },
+    CaptchaRequested {
+        path: StepPath,
+        kind: CaptchaKind,
+    },
+    CaptchaSolved {
+        path: StepPath,
+        strategy: CaptchaStrategy,
+        duration_ms: u64,
+    },
+    HandlerInvoked {
+        handler: String,
+        error: Option<String>,
+    },
+    Retry {
+        path: StepPath,
+        attempt: u32,
+        reason: String,
+    },
+    /// Too many consecutive failures on `target`; retries are suspended
+    /// until the breaker's cooldown passes.
+    CircuitOpened {
+        target: String,
+    },
+    /// The run is held before the step at `path`.
+    Paused {
+        path: StepPath,
+    },
+    Resumed {
+        path: StepPath,
+    },
+    ValidationResult {
+        description: String,
+        passed: bool,
+    },
+    /// A trace bundle was written for this run.
+    TraceSaved {
+        directory: String,
+    },
+    RunFinished {
+        success: bool,
+        cancelled: bool,
+        duration_ms: u64,
+        error: Option<String>,
+    },
+}
+
+/// Sending half handed to the runner through `ExecutionContext`.
+#[derive(Clone, Default)]
+pub struct EventSink {
+    run_id: RunId,
+    sender: Option<broadcast::Sender<ExecutionEvent>>,
+}
+
+impl EventSink {
+    pub fn new(run_id: RunId, sender: broadcast::Sender<ExecutionEvent>) -> Self {
+        Self {
+            run_id,
+            sender: Some(sender),
+        }
+    }
+
+    /// Sink that drops every event.
+    pub fn disabled() -> Self {
+        Self::default()
+    }
+
+    pub fn run_id(&self) -> RunId {
+        self.run_id
+    }
+
+    pub fn emit(&self, kind: ExecutionEventKind) {
+        let Some(sender) = &self.sender else {
+            return;
+        };
+        // `send` only fails when there are no subscribers, which is fine.
+        let _ = sender.send(ExecutionEvent {
+            run_id: self.run_id,
+            timestamp_ms: now_ms(),
+            kind,
+        });
+    }
+}
+
+pub(crate) fn now_ms() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis() as u64)
+        .unwrap_or_default()
+}
@@ -0,0 +1,535 @@
+//! Headless driver for plain server-rendered login forms.
+//!
+//! No browser is involved: the page at `TargetApp.endpoint` is fetched over
+//! HTTP and parsed, `Input` steps fill form fields in memory, and clicking a
+//! submit button posts the enclosing form (hidden fields such as CSRF tokens
+//! included). Redirects are followed with a cookie jar, and the session
+//! cookie found in the jar afterwards becomes the login's session token.
+
+use crate::dom::{NodeId, NodeTree};
+use crate::retry::{ErrorKind, StepError};
+use crate::runner::DriverSession;
+use crate::trace::{SnapshotFormat, UiSnapshot};
+use crate::{AutomationDriver, Selector, SessionToken, TargetApp, TargetAppKind, TokenSource};
+use async_trait::async_trait;
+use reqwest::cookie::{CookieStore, Jar};
+use reqwest::Url;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// HTTP-only web driver for simple form logins.
+#[derive(Debug, Default)]
+pub struct HtmlFormDriver {
+    session_cookie: Option<String>,
+}
+
+impl HtmlFormDriver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Use the value of this cookie as the session token instead of the
+    /// whole `Cookie` header.
+    pub fn with_session_cookie(mut self, name: impl Into<String>) -> Self {
+        self.session_cookie = Some(name.into());
+        self
+    }
+}
+
+#[async_trait]
+impl AutomationDriver for HtmlFormDriver {
+    fn name(&self) -> &'static str {
+        "html-form"
+    }
+
+    fn supports(&self, target: &TargetApp) -> bool {
+        matches!(target.kind, TargetAppKind::Web)
+    }
+
+    fn poolable(&self) -> bool {
+        true
+    }
+
+    async fn open_session(&self, target: &TargetApp) -> anyhow::Result<Box<dyn DriverSession>> {
+        let endpoint = target
+            .endpoint
+            .as_deref()
+            .ok_or_else(|| anyhow::anyhow!("web target {} has no url", target.name))?;
+        let url = Url::parse(endpoint)
+            .map_err(|err| anyhow::anyhow!("invalid endpoint {endpoint}: {err}"))?;
+        let mut session = HtmlFormSession::new(self.session_cookie.clone())?;
+        session.get(url).await?;
+        Ok(Box::new(session))
+    }
+}
+
+/// Parsed view of the current page. `scraper::Html` is not `Send`, so the
+/// document is re-parsed into this owned tree whenever it is needed.
+struct ParsedPage {
+    tree: NodeTree,
+    /// Element matched by the selector, if any.
+    target: Option<NodeId>,
+}
+
+/// One cookie-jar backed browsing session.
+pub struct HtmlFormSession {
+    client: reqwest::Client,
+    jar: Arc<Jar>,
+    session_cookie: Option<String>,
+    url: Url,
+    html: String,
+    /// Field values typed by the script, keyed by element.
+    values: HashMap<NodeId, String>,
+    /// Checkbox/radio state toggled by the script, keyed by element.
+    checked: HashMap<NodeId, bool>,
+    /// Latest value of every response header seen, lowercase names.
+    headers: HashMap<String, String>,
+}
+
+impl HtmlFormSession {
+    pub fn new(session_cookie: Option<String>) -> anyhow::Result<Self> {
+        let jar = Arc::new(Jar::default());
+        let client = reqwest::Client::builder()
+            .cookie_provider(jar.clone())
+            .redirect(reqwest::redirect::Policy::limited(10))
+            .build()
+            .map_err(|err| anyhow::anyhow!("failed to build http client: {err}"))?;
+        Ok(Self {
+            client,
+            jar,
+            session_cookie,
+            url: Url::parse("about:blank").expect("static url"),
+            html: String::new(),
+            values: HashMap::new(),
+            checked: HashMap::new(),
+            headers: HashMap::new(),
+        })
+    }
+
+    /// URL of the current page, after redirects.
+    pub fn current_url(&self) -> &Url {
+        &self.url
+    }
+
+    /// Raw HTML of the current page.
+    pub fn html(&self) -> &str {
+        &self.html
+    }
+
+    /// Cookies the jar would send to the current page, as `name -> value`.
+    pub fn cookies(&self) -> HashMap<String, String> {
+        let Some(header) = self.jar.cookies(&self.url) else {
+            return HashMap::new();
+        };
+        header
+            .to_str()
+            .unwrap_or_default()
+            .split(';')
+            .filter_map(|pair| pair.trim().split_once('='))
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    pub async fn get(&mut self, url: Url) -> anyhow::Result<()> {
+        let response = self.client.get(url.clone()).send().await;
+        self.load(url, response).await
+    }
+
+    async fn load(
+        &mut self,
+        requested: Url,
+        response: reqwest::Result<reqwest::Response>,
+    ) -> anyhow::Result<()> {
+        let response = response.map_err(|err| {
+            StepError::new(
+                ErrorKind::Network,
+                format!("request to {requested} failed: {err}"),
+            )
+        })?;
+        let status = response.status();
+        let url = response.url().clone();
+        for (name, value) in response.headers() {
+            if let Ok(value) = value.to_str() {
+                self.headers.insert(name.as_str().to_string(), value.to_string());
+            }
+        }
+        let body = response.text().await.map_err(|err| {
+            StepError::new(ErrorKind::Network, format!("failed to read {url}: {err}"))
+        })?;
+        if status.is_server_error() {
+            return Err(
+                StepError::new(ErrorKind::Network, format!("{url} answered {status}")).into(),
+            );
+        }
+        tracing::debug!(%url, %status, "html-form page loaded");
+        self.url = url;
+        self.html = body;
+        self.values.clear();
+        self.checked.clear();
+        Ok(())
+    }
+
+    fn parse_page(&self, selector: Option<&Selector>) -> anyhow::Result<ParsedPage> {
+        let document = scraper::Html::parse_document(&self.html);
+        let mut tree = NodeTree::new();
+        let mut ids = HashMap::new();
+
+        fn walk(
+            tree: &mut NodeTree,
+            ids: &mut HashMap<ego_tree::NodeId, NodeId>,
+            parent: NodeId,
+            node: ego_tree::NodeRef<'_, scraper::Node>,
+        ) {
+            for child in node.children() {
+                match child.value() {
+                    scraper::Node::Element(element) => {
+                        let attributes = element
+                            .attrs()
+                            .map(|(name, value)| (name.to_string(), value.to_string()))
+                            .collect();
+                        let id = tree.push(parent, element.name(), attributes);
+                        ids.insert(child.id(), id);
+                        walk(tree, ids, id, child);
+                    }
+                    scraper::Node::Text(text) if parent != NodeTree::ROOT => {
+                        tree.append_text(parent, &text[..]);
+                    }
+                    _ => {}
+                }
+            }
+        }
+        walk(&mut tree, &mut ids, NodeTree::ROOT, document.tree.root());
+
+        let mut target = None;
+        for candidate in selector.map(Selector::candidates).unwrap_or_default() {
+            target = match candidate {
+                Selector::Css(css) => {
+                    let parsed = scraper::Selector::parse(css)
+                        .map_err(|err| anyhow::anyhow!("invalid css selector {css}: {err}"))?;
+                    document
+                        .select(&parsed)
+                        .next()
+                        .and_then(|element| ids.get(&element.id()).copied())
+                }
+                Selector::XPath(xpath) => tree.xpath(xpath)?.first().copied(),
+                Selector::AccessibilityId(label) => tree
+                    .elements()
+                    .find(|id| tree.node(*id).attr("aria-label") == Some(label.as_str())),
+                other => {
+                    return Err(anyhow::anyhow!(
+                        "selector {other:?} is not supported by the html-form driver"
+                    ))
+                }
+            };
+            if target.is_some() {
+                break;
+            }
+        }
+        Ok(ParsedPage { tree, target })
+    }
+
+    fn require(&self, selector: &Selector) -> anyhow::Result<(NodeTree, NodeId)> {
+        let page = self.parse_page(Some(selector))?;
+        let target = page.target.ok_or_else(|| StepError::not_found(selector))?;
+        Ok((page.tree, target))
+    }
+
+    fn field_value(&self, tree: &NodeTree, id: NodeId) -> String {
+        if let Some(value) = self.values.get(&id) {
+            return value.clone();
+        }
+        let node = tree.node(id);
+        match node.tag.as_str() {
+            "textarea" => tree.text_content(id),
+            "select" => {
+                let options: Vec<NodeId> = tree
+                    .elements()
+                    .filter(|option| {
+                        tree.node(*option).tag == "option"
+                            && tree.ancestors(*option).any(|a| a == id)
+                    })
+                    .collect();
+                options
+                    .iter()
+                    .find(|option| tree.node(**option).attr("selected").is_some())
+                    .or(options.first())
+                    .map(|option| {
+                        let option_node = tree.node(*option);
+                        option_node
+                            .attr("value")
+                            .map(str::to_string)
+                            .unwrap_or_else(|| tree.text_content(*option).trim().to_string())
+                    })
+                    .unwrap_or_default()
+            }
+            _ => node.attr("value").unwrap_or_default().to_string(),
+        }
+    }
+
+    fn is_checked(&self, tree: &NodeTree, id: NodeId) -> bool {
+        self.checked
+            .get(&id)
+            .copied()
+            .unwrap_or_else(|| tree.node(id).attr("checked").is_some())
+    }
+
+    /// Successful controls of `form`, plus the clicked submitter.
+    fn form_data(
+        &self,
+        tree: &NodeTree,
+        form: NodeId,
+        submitter: Option<NodeId>,
+    ) -> Vec<(String, String)> {
+        let mut data = Vec::new();
+        for id in tree.elements() {
+            if !tree.ancestors(id).any(|ancestor| ancestor == form) {
+                continue;
+            }
+            let node = tree.node(id);
+            let Some(name) = node.attr("name").filter(|name| !name.is_empty()) else {
+                continue;
+            };
+            if node.attr("disabled").is_some() {
+                continue;
+            }
+            let kind = node.attr("type").unwrap_or("text").to_ascii_lowercase();
+            let submitted = Some(id) == submitter;
+            let value = match (node.tag.as_str(), kind.as_str()) {
+                ("input", "submit" | "image") | ("button", _) if submitted => {
+                    Some(node.attr("value").unwrap_or_default().to_string())
+                }
+                ("input", "submit" | "image" | "button" | "reset" | "file") | ("button", _) => None,
+                ("input", "checkbox" | "radio") => self
+                    .is_checked(tree, id)
+                    .then(|| node.attr("value").unwrap_or("on").to_string()),
+                ("input" | "textarea" | "select", _) => Some(self.field_value(tree, id)),
+                _ => None,
+            };
+            if let Some(value) = value {
+                data.push((name.to_string(), value));
+            }
+        }
+        data
+    }
+
+    async fn submit(
+        &mut self,
+        tree: &NodeTree,
+        form: NodeId,
+        submitter: Option<NodeId>,
+    ) -> anyhow::Result<()> {
+        let node = tree.node(form);
+        let action = submitter
+            .and_then(|id| tree.node(id).attr("formaction"))
+            .or(node.attr("action"))
+            .filter(|action| !action.is_empty());
+        let url = match action {
+            Some(action) => self
+                .url
+                .join(action)
+                .map_err(|err| anyhow::anyhow!("invalid form action {action}: {err}"))?,
+            None => self.url.clone(),
+        };
+        let method = submitter
+            .and_then(|id| tree.node(id).attr("formmethod"))
+            .or(node.attr("method"))
+            .unwrap_or("get")
+            .to_ascii_lowercase();
+        let data = self.form_data(tree, form, submitter);
+        tracing::debug!(%url, %method, fields = data.len(), "submitting form");
+        let response = if method == "post" {
+            self.client.post(url.clone()).form(&data).send().await
+        } else {
+            let mut url = url.clone();
+            url.query_pairs_mut().clear().extend_pairs(&data);
+            self.client.get(url).send().await
+        };
+        self.load(url, response).await
+    }
+}
+
+fn is_submitter(tree: &NodeTree, id: NodeId) -> bool {
+    let node = tree.node(id);
+    let kind = node.attr("type").map(str::to_ascii_lowercase);
+    match node.tag.as_str() {
+        "button" => matches!(kind.as_deref(), None | Some("submit")),
+        "input" => matches!(kind.as_deref(), Some("submit" | "image")),
+        _ => false,
+    }
+}
+
+#[async_trait]
+impl DriverSession for HtmlFormSession {
+    async fn exists(&mut self, selector: &Selector) -> anyhow::Result<bool> {
+        Ok(self.parse_page(Some(selector))?.target.is_some())
+    }
+
+    async fn click(&mut self, selector: &Selector) -> anyhow::Result<()> {
+        let (tree, id) = self.require(selector)?;
+        let node = tree.node(id);
+        let form = tree
+            .ancestors(id)
+            .find(|ancestor| tree.node(*ancestor).tag == "form");
+
+        if is_submitter(&tree, id) {
+            let form = form.ok_or_else(|| {
+                anyhow::anyhow!("submit button {selector:?} is not inside a form")
+            })?;
+            return self.submit(&tree, form, Some(id)).await;
+        }
+        if node.tag == "a" {
+            let href = node
+                .attr("href")
+                .ok_or_else(|| anyhow::anyhow!("link {selector:?} has no href"))?;
+            let url = self
+                .url
+                .join(href)
+                .map_err(|err| anyhow::anyhow!("invalid link {href}: {err}"))?;
+            return self.get(url).await;
+        }
+        if node.tag == "input" {
+            match node.attr("type").map(str::to_ascii_lowercase).as_deref() {
+                Some("checkbox") => {
+                    let checked = !self.is_checked(&tree, id);
+                    self.checked.insert(id, checked);
+                    return Ok(());
+                }
+                Some("radio") => {
+                    let name = node.attr("name");
+                    for other in tree.elements() {
+                        let other_node = tree.node(other);
+                        if other_node.tag == "input"
+                            && other_node.attr("type") == Some("radio")
+                            && other_node.attr("name") == name
+                        {
+                            self.checked.insert(other, false);
+                        }
+                    }
+                    self.checked.insert(id, true);
+                    return Ok(());
+                }
+                _ => {}
+            }
+        }
+        Err(anyhow::anyhow!(
+            "html-form driver cannot click <{}> without a browser",
+            node.tag
+        ))
+    }
+
+    async fn input(&mut self, selector: &Selector, text: &str) -> anyhow::Result<()> {
+        let (tree, id) = self.require(selector)?;
+        let tag = tree.node(id).tag.as_str();
+        if !matches!(tag, "input" | "textarea" | "select") {
+            return Err(anyhow::anyhow!(
+                "element <{tag}> matched by {selector:?} is not a form field"
+            ));
+        }
+        self.values.insert(id, text.to_string());
+        Ok(())
+    }
+
+    async fn swipe(
+        &mut self,
+        _from: &Selector,
+        _to: &Selector,
+        _duration_ms: u64,
+    ) -> anyhow::Result<()> {
+        Err(anyhow::anyhow!("html-form driver does not support swipes"))
+    }
+
+    async fn text(&mut self, selector: &Selector) -> anyhow::Result<Option<String>> {
+        let page = self.parse_page(Some(selector))?;
+        Ok(page.target.map(|id| {
+            let tag = page.tree.node(id).tag.as_str();
+            if matches!(tag, "input" | "textarea" | "select") {
+                self.field_value(&page.tree, id)
+            } else {
+                page.tree.text_content(id).trim().to_string()
+            }
+        }))
+    }
+
+    /// Captcha images are fetched through the session so the challenge is
+    /// bound to the same cookies as the form.
+    async fn capture(&mut self, selector: &Selector) -> anyhow::Result<Vec<u8>> {
+        let (tree, id) = self.require(selector)?;
+        let src = tree
+            .node(id)
+            .attr("src")
+            .ok_or_else(|| anyhow::anyhow!("element {selector:?} has no src to capture"))?;
+        let url = self
+            .url
+            .join(src)
+            .map_err(|err| anyhow::anyhow!("invalid image src {src}: {err}"))?;
+        let bytes = self
+            .client
+            .get(url.clone())
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|err| anyhow::anyhow!("failed to fetch {url}: {err}"))?
+            .bytes()
+            .await
+            .map_err(|err| StepError::new(ErrorKind::Network, format!("failed to read {url}: {err}")))?;
+        Ok(bytes.to_vec())
+    }
+
+    async fn screenshot(&mut self) -> anyhow::Result<Vec<u8>> {
+        Err(anyhow::anyhow!(
+            "html-form driver cannot render screenshots"
+        ))
+    }
+
+    /// A fresh cookie jar and client; nothing survives from the last account.
+    async fn reset(&mut self, target: &TargetApp) -> anyhow::Result<()> {
+        let endpoint = target
+            .endpoint
+            .as_deref()
+            .ok_or_else(|| anyhow::anyhow!("web target {} has no url", target.name))?;
+        let url = Url::parse(endpoint)
+            .map_err(|err| anyhow::anyhow!("invalid endpoint {endpoint}: {err}"))?;
+        *self = HtmlFormSession::new(self.session_cookie.take())?;
+        self.get(url).await
+    }
+
+    /// The cookie jar does not expose expiry, so cookie tokens rely on the
+    /// rule's `ttl_secs`.
+    async fn extract_token(&mut self, source: &TokenSource) -> anyhow::Result<Option<SessionToken>> {
+        let value = match source {
+            TokenSource::Cookie(name) => self.cookies().remove(name),
+            TokenSource::ResponseHeader(name) => {
+                self.headers.get(&name.to_ascii_lowercase()).cloned()
+            }
+            TokenSource::ElementText(selector) => self.text(selector).await?,
+            TokenSource::LocalStorage(_) | TokenSource::SharedPreference { .. } => {
+                return Err(anyhow::anyhow!(
+                    "html-form driver cannot read tokens from {source:?}"
+                ))
+            }
+        };
+        Ok(value.map(|value| SessionToken::from_source(value, source, None)))
+    }
+
+    async fn snapshot(&mut self) -> anyhow::Result<Option<UiSnapshot>> {
+        Ok(Some(UiSnapshot {
+            format: SnapshotFormat::Html,
+            content: self.html.clone(),
+        }))
+    }
+
+    async fn current_url(&mut self) -> anyhow::Result<Option<String>> {
+        Ok(Some(self.url.to_string()))
+    }
+
+    async fn session_token(&mut self) -> anyhow::Result<Option<String>> {
+        let cookies = self.cookies();
+        if let Some(name) = &self.session_cookie {
+            return Ok(cookies.get(name).cloned());
+        }
+        Ok(self
+            .jar
+            .cookies(&self.url)
+            .and_then(|header| header.to_str().ok().map(str::to_string)))
+    }
+}
@@ -0,0 +1,52 @@
+//! Routing for `TargetAppKind::Hybrid` apps.
+//!
+//! A hybrid run is an Android run with context switching: the session is the
+//! native driver's own, which lists the app's WebViews through `contexts()`
+//! and attaches to one on `Step::SwitchContext` (see [`crate::android`]), so
+//! selectors resolve in whichever context is active. No web driver is
+//! involved; this driver only presents the target to the native driver as
+//! an Android app.
+
+use crate::runner::DriverSession;
+use crate::{AutomationDriver, TargetApp, TargetAppKind};
+use async_trait::async_trait;
+use std::sync::Arc;
+
+/// Hybrid driver delegating to a native (Android) driver.
+pub struct HybridDriver {
+    native: Arc<dyn AutomationDriver>,
+}
+
+impl HybridDriver {
+    pub fn new(native: Arc<dyn AutomationDriver>) -> Self {
+        Self { native }
+    }
+}
+
+#[async_trait]
+impl AutomationDriver for HybridDriver {
+    fn name(&self) -> &'static str {
+        "hybrid"
+    }
+
+    fn supports(&self, target: &TargetApp) -> bool {
+        matches!(target.kind, TargetAppKind::Hybrid)
+    }
+
+    fn poolable(&self) -> bool {
+        self.native.poolable()
+    }
+
+    /// The native session itself. Resetting it for the pool only needs the
+    /// target's package, so the hybrid target can be passed as-is.
+    async fn open_session(&self, target: &TargetApp) -> anyhow::Result<Box<dyn DriverSession>> {
+        self.native.open_session(&native_target(target)).await
+    }
+}
+
+fn native_target(target: &TargetApp) -> TargetApp {
+    TargetApp {
+        kind: TargetAppKind::Android,
+        ..target.clone()
+    }
+}
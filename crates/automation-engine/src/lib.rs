@@ -1,17 +1,61 @@
-//! Cross-platform (web + Android) UI automation engine skeleton.
+//! Cross-platform (web + Android + iOS slot) UI automation engine skeleton.
 //! Provides abstractions for drivers, captcha handling, and login script model.
 
+pub mod analysis;
+pub mod android;
+pub mod batch;
+pub mod cdp;
+pub mod control;
+pub mod dom;
+pub mod events;
+pub mod html_form;
+pub mod hybrid;
+pub mod pool;
+pub mod retry;
+mod runner;
+pub mod trace;
 mod types;
+pub use analysis::{AnalysisIssue, AnalysisReport, IssueCode, Severity};
+pub use android::AndroidDriver;
+pub use batch::{BatchJob, BatchLimits, BatchReport, BatchResult};
+pub use cdp::CdpDriver;
+pub use control::{Cancelled, RunControl, RunState};
+pub use events::{EventSink, ExecutionEvent, ExecutionEventKind, RunId};
+pub use html_form::HtmlFormDriver;
+pub use hybrid::HybridDriver;
+pub use pool::{PoolConfig, PoolMetrics, SessionPool};
+pub use retry::{
+    Attempt, AttemptScope, Backoff, CircuitBreaker, CircuitBreakerConfig, ErrorKind, RetryPolicy,
+    StepError,
+};
+pub use runner::*;
+pub use trace::{
+    ArtifactCollector, TraceConfig, TraceManifest, TraceMode, TraceOutcome, UiSnapshot,
+};
 pub use types::*;
 
 use async_trait::async_trait;
+use std::collections::HashMap;
 use std::fmt;
-use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tokio::sync::broadcast;
 
 /// High-level automation engine orchestrating platform-specific drivers.
 pub struct AutomationEngine {
     drivers: Vec<Arc<dyn AutomationDriver + Send + Sync>>,
     captcha: Arc<dyn CaptchaHandler + Send + Sync>,
+    secrets: Arc<dyn SecretResolver>,
+    /// iOS has no built-in driver yet; an XCUITest bridge plugs in here.
+    ios: Option<Arc<dyn AutomationDriver + Send + Sync>>,
+    events: broadcast::Sender<ExecutionEvent>,
+    next_run_id: AtomicU64,
+    /// Controls of runs in flight, so IPC callers can cancel by run id.
+    active: Mutex<HashMap<RunId, RunControl>>,
+    trace: TraceConfig,
+    pool: Option<Arc<SessionPool>>,
+    breaker: Arc<CircuitBreaker>,
 }
 
 impl fmt::Debug for AutomationEngine {
@@ -19,6 +63,7 @@ impl fmt::Debug for AutomationEngine {
         let driver_names: Vec<&'static str> = self.drivers.iter().map(|d| d.name()).collect();
         f.debug_struct("AutomationEngine")
             .field("drivers", &driver_names)
+            .field("ios", &self.ios.as_ref().map(|d| d.name()))
             .field("captcha", &self.captcha.label())
             .finish()
     }
@@ -35,15 +80,39 @@ impl AutomationEngine {
         drivers: Vec<Arc<dyn AutomationDriver + Send + Sync>>,
         captcha: Arc<dyn CaptchaHandler + Send + Sync>,
     ) -> Self {
-        Self { drivers, captcha }
+        Self {
+            drivers,
+            captcha,
+            secrets: Arc::new(NoSecrets),
+            ios: None,
+            events: broadcast::channel(events::EVENT_CHANNEL_CAPACITY).0,
+            next_run_id: AtomicU64::new(1),
+            active: Mutex::new(HashMap::new()),
+            trace: TraceConfig::default(),
+            pool: None,
+            breaker: Arc::new(CircuitBreaker::default()),
+        }
     }
 
-    /// Convenience initializer with built-in stubs for Web/Android and a
-    /// no-op captcha handler. This keeps the engine usable out-of-the-box.
+    /// Convenience initializer with built-in stubs for Web/Android (and a
+    /// hybrid router over the Android one) and a no-op captcha handler. This keeps the
+    /// engine usable out-of-the-box.
     pub fn with_defaults() -> Self {
         Self {
-            drivers: vec![Arc::new(WebDriverStub), Arc::new(AndroidDriverStub)],
+            drivers: vec![
+                Arc::new(WebDriverStub),
+                Arc::new(AndroidDriverStub),
+                Arc::new(HybridDriver::new(Arc::new(AndroidDriverStub))),
+            ],
             captcha: Arc::new(NoopCaptcha),
+            secrets: Arc::new(NoSecrets),
+            ios: None,
+            events: broadcast::channel(events::EVENT_CHANNEL_CAPACITY).0,
+            next_run_id: AtomicU64::new(1),
+            active: Mutex::new(HashMap::new()),
+            trace: TraceConfig::default(),
+            pool: None,
+            breaker: Arc::new(CircuitBreaker::default()),
         }
     }
 
@@ -52,33 +121,272 @@ impl AutomationEngine {
         self.drivers.push(driver);
     }
 
+    /// Install the driver handling `TargetAppKind::Ios` targets.
+    pub fn set_ios_driver(&mut self, driver: Arc<dyn AutomationDriver + Send + Sync>) {
+        self.ios = Some(driver);
+    }
+
+    /// Wire the source used to resolve `ValueRef::FromVault` inputs.
+    pub fn set_secret_resolver(&mut self, secrets: Arc<dyn SecretResolver>) {
+        self.secrets = secrets;
+    }
+
+    /// Decide whether runs leave a trace bundle behind.
+    pub fn set_trace_config(&mut self, trace: TraceConfig) {
+        self.trace = trace;
+    }
+
+    /// Reuse warm sessions of poolable drivers across runs.
+    pub fn set_session_pool(&mut self, pool: Arc<SessionPool>) {
+        self.pool = Some(pool);
+    }
+
+    /// Replace the circuit breaker that suspends retries against failing
+    /// targets.
+    pub fn set_circuit_breaker(&mut self, config: CircuitBreakerConfig) {
+        self.breaker = Arc::new(CircuitBreaker::new(config));
+    }
+
+    pub fn circuit_breaker(&self) -> &CircuitBreaker {
+        &self.breaker
+    }
+
+    pub fn session_pool(&self) -> Option<&Arc<SessionPool>> {
+        self.pool.as_ref()
+    }
+
+    /// Subscribe to progress events of every run started after this call.
+    pub fn subscribe(&self) -> broadcast::Receiver<ExecutionEvent> {
+        self.events.subscribe()
+    }
+
+    /// Control handle of a run that is still in flight.
+    pub fn active_run(&self, run_id: RunId) -> Option<RunControl> {
+        self.active.lock().unwrap().get(&run_id).cloned()
+    }
+
+    /// Check a script without executing it: driver availability, reachability,
+    /// loop bounds, selectors, vault keys and captcha support.
+    pub fn analyze(&self, script: &LoginScript) -> AnalysisReport {
+        let mut report =
+            analysis::analyze_script(script, self.secrets.as_ref(), self.captcha.as_ref());
+        if let Err(err) = self.select_driver(&script.target) {
+            report.push(
+                Severity::Error,
+                IssueCode::NoDriver,
+                StepPath::root(),
+                err.to_string(),
+            );
+        }
+        report
+    }
+
     /// Run a login script on the best-suited driver.
-    pub async fn run(&self, script: LoginScript) -> anyhow::Result<LoginOutcome> {
-        for driver in &self.drivers {
-            if driver.supports(&script.target) {
-                return driver
-                    .execute(script.clone(), self.captcha.as_ref())
-                    .await
-                    .map_err(|err| anyhow::anyhow!("driver {} failed: {err}", driver.name()));
+    pub async fn run(&self, script: &LoginScript) -> anyhow::Result<LoginOutcome> {
+        self.run_with(script, RunControl::new()).await
+    }
+
+    /// Like `run`, but the caller keeps `control` to cancel, pause or resume
+    /// the run. A cancelled run still closes its session and reports
+    /// `LoginOutcome::cancelled`.
+    pub async fn run_with(
+        &self,
+        script: &LoginScript,
+        control: RunControl,
+    ) -> anyhow::Result<LoginOutcome> {
+        self.run_inner(script, control, self.secrets.clone()).await
+    }
+
+    /// Shared body of `run_with` and batch jobs, which bring their own secrets.
+    async fn run_inner(
+        &self,
+        script: &LoginScript,
+        control: RunControl,
+        secrets: Arc<dyn SecretResolver>,
+    ) -> anyhow::Result<LoginOutcome> {
+        let run_id = self.next_run_id.fetch_add(1, Ordering::Relaxed);
+        let mut ctx = ExecutionContext::new(self.captcha.clone(), secrets);
+        ctx.events = EventSink::new(run_id, self.events.clone());
+        ctx.control = control.clone();
+        if self.trace.mode != TraceMode::Off {
+            ctx.trace = Some(Arc::new(ArtifactCollector::new(self.trace.clone())));
+        }
+        ctx.retry = Arc::new(retry::RetryLedger::new(
+            self.breaker.clone(),
+            retry::target_key(&script.target),
+        ));
+        let driver = self.select_driver(&script.target)?;
+        self.active.lock().unwrap().insert(run_id, control);
+        ctx.events.emit(ExecutionEventKind::RunStarted {
+            script_id: script.meta.id.clone(),
+            version: script.meta.version.clone(),
+            driver: driver.name().into(),
+        });
+        let started_at_ms = events::now_ms();
+        let started = Instant::now();
+        let mut attempts = Vec::new();
+        let mut attempt = 1;
+        let result = loop {
+            let attempt_started = Instant::now();
+            let result = match &self.pool {
+                Some(pool) if driver.poolable() => {
+                    run_pooled(pool, driver.as_ref(), script, &ctx).await
+                }
+                _ => driver.execute(script, &ctx).await,
+            };
+            let kind = match &result {
+                Ok(outcome) if outcome.success => None,
+                Ok(outcome) => Some(outcome.error_kind.unwrap_or(ErrorKind::Other)),
+                Err(err) => Some(ErrorKind::classify(err)),
+            };
+            let result =
+                result.map_err(|err| anyhow::anyhow!("driver {} failed: {err}", driver.name()));
+            attempts.extend(ctx.retry.take());
+            if kind != Some(ErrorKind::Cancelled) && ctx.retry.observe(kind.is_some()) {
+                ctx.events.emit(ExecutionEventKind::CircuitOpened {
+                    target: ctx.retry.target.clone(),
+                });
+            }
+            let Some(policy) = &script.retry else {
+                break result;
+            };
+            let retry_after = kind
+                .filter(|kind| policy.should_retry(*kind, attempt) && ctx.retry.allows_retry())
+                .map(|_| policy.backoff.delay(attempt));
+            let error = match &result {
+                Ok(outcome) => outcome.error.clone(),
+                Err(err) => Some(err.to_string()),
+            };
+            attempts.push(Attempt {
+                scope: AttemptScope::Run,
+                attempt,
+                error: error.clone(),
+                kind,
+                duration_ms: attempt_started.elapsed().as_millis() as u64,
+                retry_after_ms: retry_after.map(|delay| delay.as_millis() as u64),
+            });
+            let Some(delay) = retry_after else {
+                break result;
+            };
+            attempt += 1;
+            ctx.events.emit(ExecutionEventKind::Retry {
+                path: StepPath::root(),
+                attempt,
+                reason: error.unwrap_or_default(),
+            });
+            if ctx.control.sleep(delay).await.is_err() {
+                break Ok(runner::cancelled());
+            }
+        };
+        let result = result.map(|outcome| LoginOutcome {
+            attempts,
+            ..outcome
+        });
+        self.active.lock().unwrap().remove(&run_id);
+        if let Some(trace) = &ctx.trace {
+            let outcome = match &result {
+                Ok(outcome) => TraceOutcome::from(outcome),
+                Err(err) => TraceOutcome {
+                    error: Some(err.to_string()),
+                    ..TraceOutcome::default()
+                },
+            };
+            let manifest = TraceManifest {
+                format_version: trace::TRACE_FORMAT_VERSION,
+                run_id,
+                script_id: script.meta.id.clone(),
+                script_version: script.meta.version.clone(),
+                driver: driver.name().into(),
+                started_at_ms,
+                duration_ms: started.elapsed().as_millis() as u64,
+                outcome,
+                steps: Vec::new(),
+            };
+            match trace.finish(manifest) {
+                Ok(Some(directory)) => ctx.events.emit(ExecutionEventKind::TraceSaved {
+                    directory: directory.display().to_string(),
+                }),
+                Ok(None) => {}
+                Err(err) => tracing::warn!(error = %err, "failed to write trace bundle"),
             }
         }
-        Err(anyhow::anyhow!(
-            "no suitable driver found for {:?}",
-            script.target.kind
-        ))
+        let (success, cancelled, error) = match &result {
+            Ok(outcome) => (outcome.success, outcome.cancelled, outcome.error.clone()),
+            Err(err) => (false, false, Some(err.to_string())),
+        };
+        ctx.events.emit(ExecutionEventKind::RunFinished {
+            success,
+            cancelled,
+            duration_ms: started.elapsed().as_millis() as u64,
+            error,
+        });
+        result
+    }
+
+    fn select_driver(&self, target: &TargetApp) -> anyhow::Result<&Arc<dyn AutomationDriver + Send + Sync>> {
+        if target.kind == TargetAppKind::Ios {
+            return self
+                .ios
+                .as_ref()
+                .filter(|driver| driver.supports(target))
+                .ok_or_else(|| anyhow::anyhow!("no iOS driver installed"));
+        }
+        self.drivers
+            .iter()
+            .find(|driver| driver.supports(target))
+            .ok_or_else(|| anyhow::anyhow!("no suitable driver found for {:?}", target.kind))
     }
 }
 
-/// Driver interface for a platform (web / android).
+/// Run a script on a pooled session; only clean successes return it to the
+/// pool.
+async fn run_pooled(
+    pool: &SessionPool,
+    driver: &dyn AutomationDriver,
+    script: &LoginScript,
+    ctx: &ExecutionContext,
+) -> anyhow::Result<LoginOutcome> {
+    let mut pooled = pool.acquire(driver, &script.target).await?;
+    let outcome = run_script(pooled.session.as_mut(), script, ctx).await;
+    let healthy = matches!(&outcome, Ok(outcome) if outcome.success);
+    pool.release(pooled, &script.target, healthy).await;
+    outcome
+}
+
+/// Driver interface for a platform (web / android / ios).
+///
+/// Real drivers only need to implement `open_session`; the default `execute`
+/// runs the script through the shared step runner and closes the session.
 #[async_trait]
-pub trait AutomationDriver {
+pub trait AutomationDriver: Send + Sync {
     fn name(&self) -> &'static str;
     fn supports(&self, target: &TargetApp) -> bool;
+
+    /// Whether sessions of this driver can be reset and reused through a
+    /// `SessionPool`.
+    fn poolable(&self) -> bool {
+        false
+    }
+
+    async fn open_session(&self, _target: &TargetApp) -> anyhow::Result<Box<dyn DriverSession>> {
+        Err(anyhow::anyhow!(
+            "driver {} does not expose sessions",
+            self.name()
+        ))
+    }
+
     async fn execute(
         &self,
-        script: LoginScript,
-        captcha: &dyn CaptchaHandler,
-    ) -> anyhow::Result<LoginOutcome>;
+        script: &LoginScript,
+        ctx: &ExecutionContext,
+    ) -> anyhow::Result<LoginOutcome> {
+        let mut session = self.open_session(&script.target).await?;
+        let outcome = run_script(session.as_mut(), script, ctx).await;
+        if let Err(err) = session.close().await {
+            tracing::warn!(driver = self.name(), error = %err, "failed to close session");
+        }
+        outcome
+    }
 }
 
 /// Captcha handler abstraction so drivers can delegate OCR/manual/third-party flows.
@@ -87,6 +395,12 @@ pub trait CaptchaHandler: Send + Sync {
     fn label(&self) -> &'static str {
         "captcha-handler"
     }
+
+    /// Whether this handler can solve challenges of `kind`.
+    fn supports(&self, _kind: &CaptchaKind) -> bool {
+        true
+    }
+
     async fn solve(&self, challenge: CaptchaChallenge) -> anyhow::Result<CaptchaSolution>;
 }
 
@@ -122,12 +436,16 @@ impl AutomationDriver for WebDriverStub {
         matches!(target.kind, TargetAppKind::Web)
     }
 
+    async fn open_session(&self, _target: &TargetApp) -> anyhow::Result<Box<dyn DriverSession>> {
+        Ok(Box::new(StubSession { platform: "web" }))
+    }
+
     async fn execute(
         &self,
-        script: LoginScript,
-        captcha: &dyn CaptchaHandler,
+        script: &LoginScript,
+        ctx: &ExecutionContext,
     ) -> anyhow::Result<LoginOutcome> {
-        simulate_steps("web", &script, captcha).await
+        simulate_steps("web", script, ctx).await
     }
 }
 
@@ -144,12 +462,16 @@ impl AutomationDriver for AndroidDriverStub {
         matches!(target.kind, TargetAppKind::Android)
     }
 
+    async fn open_session(&self, _target: &TargetApp) -> anyhow::Result<Box<dyn DriverSession>> {
+        Ok(Box::new(StubSession { platform: "android" }))
+    }
+
     async fn execute(
         &self,
-        script: LoginScript,
-        captcha: &dyn CaptchaHandler,
+        script: &LoginScript,
+        ctx: &ExecutionContext,
     ) -> anyhow::Result<LoginOutcome> {
-        simulate_steps("android", &script, captcha).await
+        simulate_steps("android", script, ctx).await
     }
 }
 
@@ -158,10 +480,20 @@ impl AutomationDriver for AndroidDriverStub {
 async fn simulate_steps(
     platform: &str,
     script: &LoginScript,
-    captcha: &dyn CaptchaHandler,
+    ctx: &ExecutionContext,
 ) -> anyhow::Result<LoginOutcome> {
     // Capture that we would iterate over steps and handle captcha challenges.
     for step in &script.steps {
+        if ctx.control.checkpoint().await.is_err() {
+            return Ok(LoginOutcome {
+                success: false,
+                session_token: None,
+                error: Some(Cancelled.to_string()),
+                cancelled: true,
+                error_kind: Some(ErrorKind::Cancelled),
+                attempts: Vec::new(),
+            });
+        }
         match step {
             Step::WaitFor(selector) => {
                 tracing::info!(platform, ?selector, "wait for selector");
@@ -172,6 +504,9 @@ async fn simulate_steps(
             Step::Input { selector, value } => {
                 tracing::info!(platform, ?selector, ?value, "input");
             }
+            Step::Swipe { from, to, .. } => {
+                tracing::info!(platform, ?from, ?to, "swipe");
+            }
             Step::SleepMs(ms) => {
                 tracing::info!(platform, duration_ms = *ms, "sleep");
             }
@@ -190,11 +525,28 @@ async fn simulate_steps(
                     tracing::debug!(platform, ?loop_step, "loop step");
                 }
             }
+            Step::Retry { policy, body } => {
+                tracing::info!(
+                    platform,
+                    max_attempts = policy.max_attempts,
+                    "retry block (not executed)"
+                );
+                for retry_step in body {
+                    tracing::debug!(platform, ?retry_step, "retry step");
+                }
+            }
+            Step::SwitchContext(context) => {
+                tracing::info!(platform, ?context, "switch context");
+            }
+            Step::Captcha { kind, .. } => {
+                tracing::info!(platform, ?kind, "captcha step (solved below)");
+            }
         }
     }
 
     // Demonstrate captcha solving once to exercise the interface.
-    let _ = captcha
+    let _ = ctx
+        .captcha
         .solve(CaptchaChallenge {
             kind: CaptchaKind::Image,
             payload: vec![],
@@ -204,8 +556,74 @@ async fn simulate_steps(
 
     Ok(LoginOutcome {
         success: true,
-        session_token: Some(format!("{}-session-token", platform)),
+        session_token: Some(SessionToken::opaque(format!("{platform}-session-token"))),
         error: None,
+        cancelled: false,
+        error_kind: None,
+        attempts: Vec::new(),
     })
 }
 
+
+/// Session handed out by the stubs so composite drivers (e.g. hybrid) can
+/// open sessions through them. Every element "exists" and actions are only logged.
+struct StubSession {
+    platform: &'static str,
+}
+
+#[async_trait]
+impl DriverSession for StubSession {
+    async fn exists(&mut self, selector: &Selector) -> anyhow::Result<bool> {
+        tracing::info!(platform = self.platform, ?selector, "exists (simulated=true)");
+        Ok(true)
+    }
+
+    async fn click(&mut self, selector: &Selector) -> anyhow::Result<()> {
+        tracing::info!(platform = self.platform, ?selector, "click");
+        Ok(())
+    }
+
+    async fn input(&mut self, selector: &Selector, _text: &str) -> anyhow::Result<()> {
+        tracing::info!(platform = self.platform, ?selector, "input");
+        Ok(())
+    }
+
+    async fn swipe(
+        &mut self,
+        from: &Selector,
+        to: &Selector,
+        _duration_ms: u64,
+    ) -> anyhow::Result<()> {
+        tracing::info!(platform = self.platform, ?from, ?to, "swipe");
+        Ok(())
+    }
+
+    async fn text(&mut self, _selector: &Selector) -> anyhow::Result<Option<String>> {
+        Ok(None)
+    }
+
+    async fn capture(&mut self, _selector: &Selector) -> anyhow::Result<Vec<u8>> {
+        Ok(vec![])
+    }
+
+    async fn screenshot(&mut self) -> anyhow::Result<Vec<u8>> {
+        Ok(vec![])
+    }
+
+    async fn session_token(&mut self) -> anyhow::Result<Option<String>> {
+        Ok(Some(format!("{}-session-token", self.platform)))
+    }
+
+    async fn contexts(&mut self) -> anyhow::Result<Vec<AutomationContext>> {
+        Ok(vec![AutomationContext::Native, AutomationContext::WebView(None)])
+    }
+
+    async fn switch_context(&mut self, context: &AutomationContext) -> anyhow::Result<()> {
+        tracing::info!(platform = self.platform, ?context, "switch context");
+        Ok(())
+    }
+
+    async fn reset(&mut self, _target: &TargetApp) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,191 @@
+//! Warm driver sessions reused across runs.
+//!
+//! Sessions are keyed by driver and target. A session goes back into the
+//! pool only after a successful run and a successful `DriverSession::reset`,
+//! which clears cookies, storage and app data, so accounts never share
+//! state. Failed runs, failed resets and sessions past `max_uses` are closed.
+
+use crate::runner::DriverSession;
+use crate::{AutomationDriver, TargetApp};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PoolConfig {
+    /// Idle sessions kept per driver/target pair.
+    pub max_idle_per_target: usize,
+    /// Runs served by one session before it is recycled.
+    pub max_uses: u32,
+    /// Idle sessions older than this are closed instead of reused.
+    pub idle_timeout: Duration,
+}
+
+impl Default for PoolConfig {
+    fn default() -> Self {
+        Self {
+            max_idle_per_target: 2,
+            max_uses: 20,
+            idle_timeout: Duration::from_secs(300),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PoolMetrics {
+    /// Acquisitions served by a warm session.
+    pub hits: u64,
+    /// Acquisitions that had to open a new session.
+    pub misses: u64,
+    /// Sessions closed by the pool (unhealthy, expired, worn out, failed).
+    pub evictions: u64,
+}
+
+/// A session checked out of the pool; hand it back with `SessionPool::release`.
+pub struct PooledSession {
+    key: String,
+    uses: u32,
+    pub session: Box<dyn DriverSession>,
+}
+
+struct IdleSession {
+    session: Box<dyn DriverSession>,
+    uses: u32,
+    since: Instant,
+}
+
+#[derive(Default)]
+pub struct SessionPool {
+    config: PoolConfig,
+    idle: Mutex<HashMap<String, Vec<IdleSession>>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    evictions: AtomicU64,
+}
+
+impl SessionPool {
+    pub fn new(config: PoolConfig) -> Self {
+        Self {
+            config,
+            ..Self::default()
+        }
+    }
+
+    pub fn metrics(&self) -> PoolMetrics {
+        PoolMetrics {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            evictions: self.evictions.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Number of warm sessions currently parked in the pool.
+    pub fn idle_count(&self) -> usize {
+        self.idle
+            .lock()
+            .map(|idle| idle.values().map(Vec::len).sum())
+            .unwrap_or(0)
+    }
+
+    /// Reuse a healthy warm session for `target` or open a new one.
+    pub async fn acquire(
+        &self,
+        driver: &dyn AutomationDriver,
+        target: &TargetApp,
+    ) -> anyhow::Result<PooledSession> {
+        let key = pool_key(driver, target);
+        while let Some(mut idle) = self.take_idle(&key) {
+            if idle.since.elapsed() > self.config.idle_timeout {
+                self.evict(idle.session, "idle timeout").await;
+                continue;
+            }
+            if let Err(err) = idle.session.health_check().await {
+                tracing::debug!(key = %key, error = %err, "pooled session failed health check");
+                self.evict(idle.session, "unhealthy").await;
+                continue;
+            }
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            return Ok(PooledSession {
+                key,
+                uses: idle.uses,
+                session: idle.session,
+            });
+        }
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        let session = driver.open_session(target).await?;
+        Ok(PooledSession {
+            key,
+            uses: 0,
+            session,
+        })
+    }
+
+    /// Return a session after a run. `healthy` is false when the run failed
+    /// or errored, in which case the session is closed.
+    pub async fn release(&self, mut pooled: PooledSession, target: &TargetApp, healthy: bool) {
+        pooled.uses += 1;
+        if !healthy {
+            return self.evict(pooled.session, "run failed").await;
+        }
+        if pooled.uses >= self.config.max_uses {
+            return self.evict(pooled.session, "max uses reached").await;
+        }
+        if let Err(err) = pooled.session.reset(target).await {
+            tracing::debug!(key = %pooled.key, error = %err, "session reset failed");
+            return self.evict(pooled.session, "reset failed").await;
+        }
+        let overflow = {
+            let mut idle = self.idle.lock().unwrap();
+            let slot = idle.entry(pooled.key).or_default();
+            if slot.len() >= self.config.max_idle_per_target {
+                Some(pooled.session)
+            } else {
+                slot.push(IdleSession {
+                    session: pooled.session,
+                    uses: pooled.uses,
+                    since: Instant::now(),
+                });
+                None
+            }
+        };
+        if let Some(session) = overflow {
+            self.evict(session, "pool full").await;
+        }
+    }
+
+    /// Close every idle session, e.g. on shutdown.
+    pub async fn drain(&self) {
+        let idle: Vec<IdleSession> = self
+            .idle
+            .lock()
+            .map(|mut idle| idle.drain().flat_map(|(_, sessions)| sessions).collect())
+            .unwrap_or_default();
+        for entry in idle {
+            self.evict(entry.session, "drained").await;
+        }
+    }
+
+    fn take_idle(&self, key: &str) -> Option<IdleSession> {
+        self.idle.lock().ok()?.get_mut(key)?.pop()
+    }
+
+    async fn evict(&self, mut session: Box<dyn DriverSession>, reason: &str) {
+        self.evictions.fetch_add(1, Ordering::Relaxed);
+        tracing::debug!(reason, "evicting pooled session");
+        if let Err(err) = session.close().await {
+            tracing::debug!(error = %err, "failed to close evicted session");
+        }
+    }
+}
+
+fn pool_key(driver: &dyn AutomationDriver, target: &TargetApp) -> String {
+    format!(
+        "{}|{:?}|{}|{}",
+        driver.name(),
+        target.kind,
+        target.name,
+        target.endpoint.as_deref().unwrap_or_default()
+    )
+}
@@ -0,0 +1,308 @@
+//! Declarative retry policies and the per-target circuit breaker.
+//!
+//! Policies live in the script model: `Step::Retry` wraps a block of steps
+//! and `LoginScript::retry` covers the whole run. Which failures are retried
+//! depends on their `ErrorKind`, derived from the error types drivers and
+//! the runner return. Every attempt ends up in `LoginOutcome::attempts`.
+
+use crate::control::Cancelled;
+use crate::StepPath;
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Coarse classification of a failure, used to decide whether to retry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorKind {
+    Timeout,
+    ElementNotFound,
+    /// Connection, HTTP or device transport failure.
+    Network,
+    Captcha,
+    /// A validation did not hold or the session token was missing.
+    Validation,
+    Cancelled,
+    Other,
+}
+
+impl ErrorKind {
+    pub fn classify(err: &anyhow::Error) -> Self {
+        for cause in err.chain() {
+            if cause.is::<Cancelled>() {
+                return Self::Cancelled;
+            }
+            if let Some(step) = cause.downcast_ref::<StepError>() {
+                return step.kind;
+            }
+            if cause.is::<std::io::Error>()
+                || cause.is::<reqwest::Error>()
+                || cause.is::<tokio_tungstenite::tungstenite::Error>()
+            {
+                return Self::Network;
+            }
+        }
+        Self::Other
+    }
+}
+
+/// Error with a known kind, returned by the runner and drivers for the
+/// failures policies usually care about.
+#[derive(Debug)]
+pub struct StepError {
+    pub kind: ErrorKind,
+    pub message: String,
+}
+
+impl StepError {
+    pub fn new(kind: ErrorKind, message: impl Into<String>) -> Self {
+        Self {
+            kind,
+            message: message.into(),
+        }
+    }
+
+    pub fn not_found(selector: &crate::Selector) -> Self {
+        Self::new(
+            ErrorKind::ElementNotFound,
+            format!("element not found: {selector:?}"),
+        )
+    }
+}
+
+impl std::fmt::Display for StepError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for StepError {}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RetryPolicy {
+    /// Total attempts including the first one.
+    pub max_attempts: u32,
+    #[serde(default = "default_retry_on")]
+    pub retry_on: Vec<ErrorKind>,
+    #[serde(default)]
+    pub backoff: Backoff,
+}
+
+fn default_retry_on() -> Vec<ErrorKind> {
+    vec![
+        ErrorKind::Timeout,
+        ErrorKind::ElementNotFound,
+        ErrorKind::Network,
+    ]
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            retry_on: default_retry_on(),
+            backoff: Backoff::default(),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Whether a failure of `kind` on attempt `attempt` (1-based) gets
+    /// another try. Cancellation never does.
+    pub fn should_retry(&self, kind: ErrorKind, attempt: u32) -> bool {
+        kind != ErrorKind::Cancelled && attempt < self.max_attempts && self.retry_on.contains(&kind)
+    }
+}
+
+/// Exponential backoff: `initial_ms * multiplier^(attempt - 1)`, spread by
+/// up to `jitter` (a fraction) in either direction, then capped at `max_ms`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Backoff {
+    pub initial_ms: u64,
+    pub max_ms: u64,
+    pub multiplier: f64,
+    pub jitter: f64,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self {
+            initial_ms: 500,
+            max_ms: 30_000,
+            multiplier: 2.0,
+            jitter: 0.2,
+        }
+    }
+}
+
+impl Backoff {
+    /// Delay before the attempt following `attempt` (1-based).
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let exponent = attempt.saturating_sub(1).min(63) as i32;
+        let base = self.initial_ms as f64 * self.multiplier.max(1.0).powi(exponent);
+        let jitter = self.jitter.clamp(0.0, 1.0);
+        let factor = 1.0 + jitter * (2.0 * unit_random() - 1.0);
+        Duration::from_millis((base * factor).clamp(0.0, self.max_ms as f64) as u64)
+    }
+}
+
+/// Uniform value in `[0, 1)`; good enough to spread retries apart.
+fn unit_random() -> f64 {
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_nanos());
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u128(nanos);
+    (hasher.finish() >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// What a recorded attempt covered.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "scope", content = "path", rename_all = "snake_case")]
+pub enum AttemptScope {
+    Run,
+    Step(StepPath),
+}
+
+/// One try of a retried step block or run.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Attempt {
+    pub scope: AttemptScope,
+    /// 1-based.
+    pub attempt: u32,
+    /// `None` when the attempt succeeded.
+    pub error: Option<String>,
+    pub kind: Option<ErrorKind>,
+    pub duration_ms: u64,
+    /// Backoff waited before the next attempt; `None` when there was none.
+    pub retry_after_ms: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CircuitBreakerConfig {
+    /// Consecutive failures on one target before retries stop.
+    pub failure_threshold: u32,
+    /// How long retries stay suspended once the breaker opens.
+    pub cooldown: Duration,
+}
+
+impl Default for CircuitBreakerConfig {
+    fn default() -> Self {
+        Self {
+            failure_threshold: 5,
+            cooldown: Duration::from_secs(60),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct TargetHealth {
+    failures: u32,
+    open_until: Option<Instant>,
+}
+
+/// Stops retrying a target after repeated failures. First attempts still
+/// run while the breaker is open; only their retries are skipped. After the
+/// cooldown one more failure reopens it, one success closes it.
+#[derive(Debug, Default)]
+pub struct CircuitBreaker {
+    config: CircuitBreakerConfig,
+    targets: Mutex<HashMap<String, TargetHealth>>,
+}
+
+impl CircuitBreaker {
+    pub fn new(config: CircuitBreakerConfig) -> Self {
+        Self {
+            config,
+            targets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn allows_retry(&self, target: &str) -> bool {
+        self.targets
+            .lock()
+            .unwrap()
+            .get(target)
+            .and_then(|health| health.open_until)
+            .is_none_or(|until| Instant::now() >= until)
+    }
+
+    pub fn is_open(&self, target: &str) -> bool {
+        !self.allows_retry(target)
+    }
+
+    /// Count a failure; returns true when this one opened the breaker.
+    pub fn record_failure(&self, target: &str) -> bool {
+        let mut targets = self.targets.lock().unwrap();
+        let health = targets.entry(target.to_string()).or_default();
+        health.failures += 1;
+        let was_open = health
+            .open_until
+            .is_some_and(|until| Instant::now() < until);
+        if health.failures >= self.config.failure_threshold && !was_open {
+            health.open_until = Some(Instant::now() + self.config.cooldown);
+            return true;
+        }
+        false
+    }
+
+    pub fn record_success(&self, target: &str) {
+        self.targets.lock().unwrap().remove(target);
+    }
+}
+
+/// Per-run bookkeeping shared between the engine and the runner.
+#[derive(Debug, Default)]
+pub(crate) struct RetryLedger {
+    pub(crate) breaker: Option<std::sync::Arc<CircuitBreaker>>,
+    pub(crate) target: String,
+    attempts: Mutex<Vec<Attempt>>,
+}
+
+impl RetryLedger {
+    pub(crate) fn new(breaker: std::sync::Arc<CircuitBreaker>, target: String) -> Self {
+        Self {
+            breaker: Some(breaker),
+            target,
+            attempts: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub(crate) fn allows_retry(&self) -> bool {
+        self.breaker
+            .as_ref()
+            .is_none_or(|breaker| breaker.allows_retry(&self.target))
+    }
+
+    /// Record the result of an attempt with the breaker; returns true when
+    /// the breaker just opened.
+    pub(crate) fn observe(&self, failed: bool) -> bool {
+        match &self.breaker {
+            Some(breaker) if failed => breaker.record_failure(&self.target),
+            Some(breaker) => {
+                breaker.record_success(&self.target);
+                false
+            }
+            None => false,
+        }
+    }
+
+    pub(crate) fn push(&self, attempt: Attempt) {
+        self.attempts.lock().unwrap().push(attempt);
+    }
+
+    pub(crate) fn take(&self) -> Vec<Attempt> {
+        std::mem::take(&mut *self.attempts.lock().unwrap())
+    }
+}
+
+/// Key the breaker tracks a target under.
+pub(crate) fn target_key(target: &crate::TargetApp) -> String {
+    target
+        .endpoint
+        .clone()
+        .unwrap_or_else(|| format!("{:?}:{}", target.kind, target.name))
+}
@@ -0,0 +1,667 @@
+//! Shared step interpreter used by every driver that exposes a live
+//! [`DriverSession`]. Drivers only implement the UI primitives; waiting,
+//! branching, loops, captcha delegation, error handlers and validations are
+//! handled here so all platforms behave the same way.
+
+use crate::control::{Cancelled, RunControl, RunState};
+use crate::events::{now_ms, EventSink, ExecutionEventKind};
+use crate::retry::{Attempt, AttemptScope, ErrorKind, RetryLedger, RetryPolicy, StepError};
+use crate::trace::{ArtifactCollector, StepArtifacts, UiSnapshot};
+use crate::{
+    AutomationContext, CaptchaChallenge, CaptchaHandler, Condition, LoginOutcome, LoginScript,
+    PathSegment, Selector, SessionToken, Step, StepPath, TargetApp, TokenSource, ValueRef,
+};
+use async_trait::async_trait;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Default upper bound for `Step::WaitFor`.
+pub const DEFAULT_WAIT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Interval between two `exists` probes while waiting for a selector.
+const WAIT_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// A live connection to one target (browser page, device, ...). Sessions
+/// expose the UI primitives the runner needs and nothing else.
+#[async_trait]
+pub trait DriverSession: Send {
+    /// Whether the selector currently resolves to at least one element.
+    async fn exists(&mut self, selector: &Selector) -> anyhow::Result<bool>;
+
+    async fn click(&mut self, selector: &Selector) -> anyhow::Result<()>;
+
+    /// Type `text` into the element matched by `selector`.
+    async fn input(&mut self, selector: &Selector, text: &str) -> anyhow::Result<()>;
+
+    /// Drag from one element/point to another over `duration_ms`.
+    async fn swipe(
+        &mut self,
+        from: &Selector,
+        to: &Selector,
+        duration_ms: u64,
+    ) -> anyhow::Result<()>;
+
+    /// Visible text (or value) of the element, `None` when it does not exist.
+    async fn text(&mut self, selector: &Selector) -> anyhow::Result<Option<String>>;
+
+    /// Image bytes of a single element, used as captcha payload.
+    async fn capture(&mut self, selector: &Selector) -> anyhow::Result<Vec<u8>>;
+
+    /// Full-screen screenshot (PNG).
+    async fn screenshot(&mut self) -> anyhow::Result<Vec<u8>>;
+
+    /// Serialized UI tree (DOM or accessibility dump) for trace bundles.
+    async fn snapshot(&mut self) -> anyhow::Result<Option<UiSnapshot>> {
+        Ok(None)
+    }
+
+    /// Session token produced by a successful login, if the driver can see one.
+    async fn session_token(&mut self) -> anyhow::Result<Option<String>>;
+
+    /// URL of the page the session shows, `None` for native screens.
+    async fn current_url(&mut self) -> anyhow::Result<Option<String>> {
+        Ok(None)
+    }
+
+    /// Read the token from the place a script's `TokenRule` names. Sessions
+    /// only understand element text unless they override this.
+    async fn extract_token(&mut self, source: &TokenSource) -> anyhow::Result<Option<SessionToken>> {
+        match source {
+            TokenSource::ElementText(selector) => Ok(self
+                .text(selector)
+                .await?
+                .map(|text| SessionToken::from_source(text, source, None))),
+            other => Err(anyhow::anyhow!("this session cannot read tokens from {other:?}")),
+        }
+    }
+
+    /// Contexts this session can switch to. Drivers with a single UI tree
+    /// report none.
+    async fn contexts(&mut self) -> anyhow::Result<Vec<AutomationContext>> {
+        Ok(Vec::new())
+    }
+
+    /// Switch between the native tree and a WebView. Drivers with a single
+    /// UI tree reject every switch.
+    async fn switch_context(&mut self, context: &AutomationContext) -> anyhow::Result<()> {
+        Err(anyhow::anyhow!("this session cannot switch to {context:?}"))
+    }
+
+    /// Cheap liveness probe run before a pooled session is handed out again.
+    async fn health_check(&mut self) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    /// Drop cookies, storage and app data and return to `target`'s start
+    /// screen so the session can serve another account. Sessions that cannot
+    /// guarantee this keep the default and are never reused.
+    async fn reset(&mut self, _target: &TargetApp) -> anyhow::Result<()> {
+        Err(anyhow::anyhow!("this session cannot be reset"))
+    }
+
+    /// Release the underlying connection. Called once after the run.
+    async fn close(&mut self) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+/// Resolves `ValueRef::FromVault` keys into plaintext right before input.
+pub trait SecretResolver: Send + Sync {
+    fn resolve(&self, key: &str) -> anyhow::Result<String>;
+
+    /// Whether `key` would resolve, without exposing the value. `None` when
+    /// the resolver cannot tell up front.
+    fn contains(&self, _key: &str) -> Option<bool> {
+        None
+    }
+}
+
+/// Resolver used when no vault is wired in; every lookup fails.
+pub struct NoSecrets;
+
+impl SecretResolver for NoSecrets {
+    fn resolve(&self, key: &str) -> anyhow::Result<String> {
+        Err(anyhow::anyhow!(
+            "no secret resolver configured for key {key}"
+        ))
+    }
+
+    fn contains(&self, _key: &str) -> Option<bool> {
+        Some(false)
+    }
+}
+
+/// Per-run services handed to drivers.
+#[derive(Clone)]
+pub struct ExecutionContext {
+    pub captcha: Arc<dyn CaptchaHandler>,
+    pub secrets: Arc<dyn SecretResolver>,
+    pub wait_timeout: Duration,
+    /// Progress events for this run; disabled unless the engine wires one.
+    pub events: EventSink,
+    /// Cancellation / pause handle checked between steps and during waits.
+    pub control: RunControl,
+    /// Set when the run writes a trace bundle.
+    pub trace: Option<Arc<ArtifactCollector>>,
+    pub(crate) retry: Arc<RetryLedger>,
+}
+
+impl ExecutionContext {
+    pub fn new(captcha: Arc<dyn CaptchaHandler>, secrets: Arc<dyn SecretResolver>) -> Self {
+        Self {
+            captcha,
+            secrets,
+            wait_timeout: DEFAULT_WAIT_TIMEOUT,
+            events: EventSink::disabled(),
+            control: RunControl::new(),
+            trace: None,
+            retry: Arc::new(RetryLedger::default()),
+        }
+    }
+
+    fn resolve_value(&self, value: &ValueRef) -> anyhow::Result<String> {
+        match value {
+            ValueRef::Literal(text) => Ok(text.clone()),
+            ValueRef::FromVault(key) => self.secrets.resolve(key),
+        }
+    }
+}
+
+/// Execute a whole script against an open session.
+///
+/// A step failure triggers the script's error handlers and ends the run
+/// with `success = false`; only `Step::Retry` blocks and the script's own
+/// `retry` policy run steps again.
+/// Cancellation skips handlers and yields `cancelled = true`.
+/// Infrastructure errors (e.g. lost connection while reading the token) are
+/// returned as `Err`.
+pub async fn run_script(
+    session: &mut dyn DriverSession,
+    script: &LoginScript,
+    ctx: &ExecutionContext,
+) -> anyhow::Result<LoginOutcome> {
+    let root = StepPath::root();
+    for (index, step) in script.steps.iter().enumerate() {
+        let path = root.child(PathSegment::Steps(index));
+        let Err(err) = run_step(session, step, &path, ctx).await else {
+            continue;
+        };
+        if err.is::<Cancelled>() {
+            return Ok(cancelled());
+        }
+        let kind = ErrorKind::classify(&err);
+        tracing::warn!(error = %err, "step failed, invoking error handlers");
+        for (handler_index, handler) in script.error_handlers.iter().enumerate() {
+            let result = run_handler(session, &handler.on_error, handler_index, ctx).await;
+            if let Err(handler_err) = &result {
+                tracing::debug!(handler = %handler.name, error = %handler_err, "error handler failed");
+            }
+            ctx.events.emit(ExecutionEventKind::HandlerInvoked {
+                handler: handler.name.clone(),
+                error: result.err().map(|err| err.to_string()),
+            });
+        }
+        return Ok(failed(err.to_string(), kind));
+    }
+
+    if ctx.control.checkpoint().await.is_err() {
+        return Ok(cancelled());
+    }
+    for validation in &script.validations {
+        let passed = evaluate(session, &validation.condition).await?;
+        ctx.events.emit(ExecutionEventKind::ValidationResult {
+            description: validation.description.clone(),
+            passed,
+        });
+        if !passed {
+            return Ok(failed(
+                format!("validation failed: {}", validation.description),
+                ErrorKind::Validation,
+            ));
+        }
+    }
+
+    let session_token = match &script.token {
+        Some(rule) => match extract_token(session, &rule.source).await? {
+            Some(mut token) => {
+                if token.expires_at.is_none() {
+                    token.expires_at = rule.ttl_secs.map(|ttl| now_ms() / 1000 + ttl);
+                }
+                Some(token)
+            }
+            None => {
+                return Ok(failed(
+                    format!("session token not found in {:?}", rule.source),
+                    ErrorKind::Validation,
+                ))
+            }
+        },
+        None => session.session_token().await?.map(SessionToken::opaque),
+    };
+
+    Ok(LoginOutcome {
+        success: true,
+        session_token,
+        error: None,
+        cancelled: false,
+        error_kind: None,
+        attempts: Vec::new(),
+    })
+}
+
+fn failed(error: String, kind: ErrorKind) -> LoginOutcome {
+    LoginOutcome {
+        success: false,
+        session_token: None,
+        error: Some(error),
+        cancelled: false,
+        error_kind: Some(kind),
+        attempts: Vec::new(),
+    }
+}
+
+pub(crate) fn cancelled() -> LoginOutcome {
+    LoginOutcome {
+        cancelled: true,
+        ..failed(Cancelled.to_string(), ErrorKind::Cancelled)
+    }
+}
+
+async fn run_handler(
+    session: &mut dyn DriverSession,
+    steps: &[Step],
+    handler: usize,
+    ctx: &ExecutionContext,
+) -> anyhow::Result<()> {
+    let root = StepPath::root();
+    for (index, step) in steps.iter().enumerate() {
+        let path = root.child(PathSegment::OnError { handler, index });
+        run_step(session, step, &path, ctx).await?;
+    }
+    Ok(())
+}
+
+fn run_steps<'a>(
+    session: &'a mut dyn DriverSession,
+    steps: &'a [Step],
+    parent: &'a StepPath,
+    segment: fn(usize) -> PathSegment,
+    ctx: &'a ExecutionContext,
+) -> BoxFuture<'a, anyhow::Result<()>> {
+    Box::pin(async move {
+        for (index, step) in steps.iter().enumerate() {
+            run_step(session, step, &parent.child(segment(index)), ctx).await?;
+        }
+        Ok(())
+    })
+}
+
+/// Run one step, reporting its start and finish on the event stream.
+fn run_step<'a>(
+    session: &'a mut dyn DriverSession,
+    step: &'a Step,
+    path: &'a StepPath,
+    ctx: &'a ExecutionContext,
+) -> BoxFuture<'a, anyhow::Result<()>> {
+    Box::pin(async move {
+        pause_point(path, ctx).await?;
+        ctx.events.emit(ExecutionEventKind::StepStarted {
+            path: path.clone(),
+            step: step_label(step).into(),
+        });
+        let started_at_ms = now_ms();
+        let started = Instant::now();
+        let result = execute_step(session, step, path, ctx).await;
+        let duration_ms = elapsed_ms(started);
+        let error = result.as_ref().err().map(|err| err.to_string());
+        ctx.events.emit(ExecutionEventKind::StepFinished {
+            path: path.clone(),
+            duration_ms,
+            error: error.clone(),
+        });
+        // Containers are traced through their children.
+        if let Some(trace) = &ctx.trace {
+            if !matches!(
+                step,
+                Step::Conditional { .. } | Step::Loop { .. } | Step::Retry { .. }
+            ) {
+                let (screenshot, snapshot) = capture_artifacts(session, trace).await;
+                trace.record(StepArtifacts {
+                    path: path.clone(),
+                    step: step_label(step),
+                    started_at_ms,
+                    duration_ms,
+                    error,
+                    screenshot,
+                    snapshot,
+                });
+            }
+        }
+        result
+    })
+}
+
+async fn execute_step(
+    session: &mut dyn DriverSession,
+    step: &Step,
+    path: &StepPath,
+    ctx: &ExecutionContext,
+) -> anyhow::Result<()> {
+    let started = Instant::now();
+    match step {
+        Step::WaitFor(selector) => {
+            let found = wait_for(session, selector, ctx).await?;
+            resolved(ctx, path, found, started);
+            Ok(())
+        }
+        Step::Click(selector) => {
+            let selector = pick(session, selector).await?;
+            session.click(selector).await?;
+            resolved(ctx, path, selector, started);
+            Ok(())
+        }
+        Step::Input { selector, value } => {
+            let text = ctx.resolve_value(value)?;
+            let selector = pick(session, selector).await?;
+            session.input(selector, &text).await?;
+            resolved(ctx, path, selector, started);
+            Ok(())
+        }
+        Step::Swipe {
+            from,
+            to,
+            duration_ms,
+        } => {
+            let from = pick(session, from).await?;
+            let to = pick(session, to).await?;
+            session.swipe(from, to, *duration_ms).await
+        }
+        Step::SleepMs(ms) => Ok(ctx.control.sleep(Duration::from_millis(*ms)).await?),
+        Step::Conditional {
+            condition,
+            on_true,
+            on_false,
+        } => {
+            let taken = evaluate(session, condition).await?;
+            ctx.events.emit(ExecutionEventKind::BranchTaken {
+                path: path.clone(),
+                condition: taken,
+            });
+            if taken {
+                run_steps(session, on_true, path, PathSegment::OnTrue, ctx).await
+            } else {
+                run_steps(session, on_false, path, PathSegment::OnFalse, ctx).await
+            }
+        }
+        Step::Loop { times, body } => {
+            for _ in 0..*times {
+                ctx.control.checkpoint().await?;
+                run_steps(session, body, path, PathSegment::Body, ctx).await?;
+            }
+            Ok(())
+        }
+        Step::Retry { policy, body } => run_with_retry(session, policy, body, path, ctx).await,
+        Step::SwitchContext(context) => session.switch_context(context).await,
+        Step::Captcha {
+            kind,
+            challenge,
+            response,
+        } => {
+            let challenge = pick(session, challenge).await?;
+            let payload = session.capture(challenge).await?;
+            ctx.events.emit(ExecutionEventKind::CaptchaRequested {
+                path: path.clone(),
+                kind: kind.clone(),
+            });
+            let solving = Instant::now();
+            let solution = ctx
+                .captcha
+                .solve(CaptchaChallenge {
+                    kind: kind.clone(),
+                    payload,
+                    metadata: None,
+                })
+                .await
+                .map_err(|err| {
+                    StepError::new(ErrorKind::Captcha, format!("captcha not solved: {err}"))
+                })?;
+            ctx.events.emit(ExecutionEventKind::CaptchaSolved {
+                path: path.clone(),
+                strategy: solution.strategy.clone(),
+                duration_ms: elapsed_ms(solving),
+            });
+            let response = pick(session, response).await?;
+            session.input(response, &solution.response).await
+        }
+    }
+}
+
+/// Run `body` until it succeeds, the policy gives up, or the target's
+/// circuit breaker suspends retries. Each try is recorded on the run.
+async fn run_with_retry(
+    session: &mut dyn DriverSession,
+    policy: &RetryPolicy,
+    body: &[Step],
+    path: &StepPath,
+    ctx: &ExecutionContext,
+) -> anyhow::Result<()> {
+    let mut attempt = 1;
+    loop {
+        let started = Instant::now();
+        let result = run_steps(session, body, path, PathSegment::Body, ctx).await;
+        let kind = result.as_ref().err().map(ErrorKind::classify);
+        let mut retry_after = match kind {
+            Some(kind) if policy.should_retry(kind, attempt) && ctx.retry.allows_retry() => {
+                Some(policy.backoff.delay(attempt))
+            }
+            _ => None,
+        };
+        // The breaker sees a failure here only if it is retried; the last one
+        // propagates and is observed once for the whole run.
+        if retry_after.is_some() && ctx.retry.observe(true) {
+            ctx.events.emit(ExecutionEventKind::CircuitOpened {
+                target: ctx.retry.target.clone(),
+            });
+            retry_after = None;
+        }
+        ctx.retry.push(Attempt {
+            scope: AttemptScope::Step(path.clone()),
+            attempt,
+            error: result.as_ref().err().map(|err| err.to_string()),
+            kind,
+            duration_ms: elapsed_ms(started),
+            retry_after_ms: retry_after.map(|delay| delay.as_millis() as u64),
+        });
+        let (err, delay) = match (result, retry_after) {
+            (Err(err), Some(delay)) => (err, delay),
+            (result, _) => return result,
+        };
+        attempt += 1;
+        ctx.events.emit(ExecutionEventKind::Retry {
+            path: path.clone(),
+            attempt,
+            reason: err.to_string(),
+        });
+        ctx.control.sleep(delay).await?;
+    }
+}
+
+/// Best-effort capture; drivers without screenshots or snapshots just
+/// leave gaps in the trace.
+async fn capture_artifacts(
+    session: &mut dyn DriverSession,
+    trace: &ArtifactCollector,
+) -> (Option<Vec<u8>>, Option<UiSnapshot>) {
+    let screenshot = if trace.captures_screenshots() {
+        session
+            .screenshot()
+            .await
+            .map_err(|err| tracing::debug!(error = %err, "trace screenshot skipped"))
+            .ok()
+    } else {
+        None
+    };
+    let snapshot = session
+        .snapshot()
+        .await
+        .map_err(|err| tracing::debug!(error = %err, "trace snapshot skipped"))
+        .ok()
+        .flatten();
+    (screenshot, snapshot)
+}
+
+fn resolved(ctx: &ExecutionContext, path: &StepPath, selector: &Selector, started: Instant) {
+    ctx.events.emit(ExecutionEventKind::SelectorResolved {
+        path: path.clone(),
+        selector: selector.clone(),
+        duration_ms: elapsed_ms(started),
+    });
+}
+
+fn elapsed_ms(started: Instant) -> u64 {
+    started.elapsed().as_millis() as u64
+}
+
+/// Short, stable name of a step kind for events and logs.
+pub fn step_label(step: &Step) -> &'static str {
+    match step {
+        Step::Click(_) => "click",
+        Step::Input { .. } => "input",
+        Step::WaitFor(_) => "wait_for",
+        Step::Swipe { .. } => "swipe",
+        Step::SleepMs(_) => "sleep",
+        Step::Conditional { .. } => "conditional",
+        Step::Loop { .. } => "loop",
+        Step::Retry { .. } => "retry",
+        Step::SwitchContext(_) => "switch_context",
+        Step::Captcha { .. } => "captcha",
+    }
+}
+
+/// Block while the run is paused, reporting the pause on the event stream.
+async fn pause_point(path: &StepPath, ctx: &ExecutionContext) -> anyhow::Result<()> {
+    if ctx.control.state() == RunState::Paused {
+        ctx.events.emit(ExecutionEventKind::Paused { path: path.clone() });
+        ctx.control.checkpoint().await?;
+        ctx.events.emit(ExecutionEventKind::Resumed { path: path.clone() });
+    }
+    Ok(ctx.control.checkpoint().await?)
+}
+
+/// Poll until the selector exists and return the candidate that matched.
+/// Time spent paused does not count against the timeout.
+async fn wait_for<'a>(
+    session: &mut dyn DriverSession,
+    selector: &'a Selector,
+    ctx: &ExecutionContext,
+) -> anyhow::Result<&'a Selector> {
+    let timeout = ctx.wait_timeout;
+    let mut deadline = tokio::time::Instant::now() + timeout;
+    loop {
+        let paused_at = tokio::time::Instant::now();
+        ctx.control.checkpoint().await?;
+        deadline += paused_at.elapsed();
+        if let Some(found) = first_existing(session, selector).await? {
+            return Ok(found);
+        }
+        if tokio::time::Instant::now() >= deadline {
+            return Err(StepError::new(
+                ErrorKind::Timeout,
+                format!("timed out after {timeout:?} waiting for {selector:?}"),
+            )
+            .into());
+        }
+        ctx.control.sleep(WAIT_POLL_INTERVAL).await?;
+    }
+}
+
+/// `DriverSession::extract_token` with an `AnyOf` element resolved first,
+/// since sessions only understand concrete selectors.
+async fn extract_token(
+    session: &mut dyn DriverSession,
+    source: &TokenSource,
+) -> anyhow::Result<Option<SessionToken>> {
+    match source {
+        TokenSource::ElementText(selector @ Selector::AnyOf(_)) => {
+            let selector = pick(session, selector).await?.clone();
+            session
+                .extract_token(&TokenSource::ElementText(selector))
+                .await
+        }
+        other => session.extract_token(other).await,
+    }
+}
+
+/// First candidate of `selector` that exists on the current screen.
+async fn first_existing<'a>(
+    session: &mut dyn DriverSession,
+    selector: &'a Selector,
+) -> anyhow::Result<Option<&'a Selector>> {
+    for candidate in selector.candidates() {
+        if session.exists(candidate).await? {
+            return Ok(Some(candidate));
+        }
+    }
+    Ok(None)
+}
+
+/// Concrete selector to act on. `AnyOf` resolves to its first matching
+/// candidate, or to its first candidate when none matches so the driver
+/// reports a missing element; other selectors are used as they are.
+async fn pick<'a>(
+    session: &mut dyn DriverSession,
+    selector: &'a Selector,
+) -> anyhow::Result<&'a Selector> {
+    if !matches!(selector, Selector::AnyOf(_)) {
+        return Ok(selector);
+    }
+    if let Some(found) = first_existing(session, selector).await? {
+        return Ok(found);
+    }
+    selector
+        .candidates()
+        .first()
+        .copied()
+        .ok_or_else(|| StepError::not_found(selector).into())
+}
+
+/// Evaluate a condition against the current UI state.
+pub fn evaluate<'a>(
+    session: &'a mut dyn DriverSession,
+    condition: &'a Condition,
+) -> BoxFuture<'a, anyhow::Result<bool>> {
+    Box::pin(async move {
+        match condition {
+            Condition::Exists(selector) => Ok(first_existing(session, selector).await?.is_some()),
+            Condition::TextEquals { selector, expected } => {
+                let selector = pick(session, selector).await?;
+                Ok(session.text(selector).await?.as_deref() == Some(expected.as_str()))
+            }
+            Condition::UrlContains(fragment) => match session.current_url().await? {
+                Some(url) => Ok(url.contains(fragment.as_str())),
+                None => Err(anyhow::anyhow!("this session cannot report its URL")),
+            },
+            Condition::And(conditions) => {
+                for condition in conditions {
+                    if !evaluate(session, condition).await? {
+                        return Ok(false);
+                    }
+                }
+                Ok(true)
+            }
+            Condition::Or(conditions) => {
+                for condition in conditions {
+                    if evaluate(session, condition).await? {
+                        return Ok(true);
+                    }
+                }
+                Ok(false)
+            }
+            Condition::Not(inner) => Ok(!evaluate(session, inner).await?),
+        }
+    })
+}
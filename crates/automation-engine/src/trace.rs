@@ -0,0 +1,238 @@
+//! Trace bundles: per-step screenshots, UI snapshots and timing written to a
+//! directory with a `manifest.json`, for debugging and replaying failed runs.
+//!
+//! Artifacts are buffered in memory during the run and only written once the
+//! outcome is known, so `TraceMode::OnFailure` costs no disk space on success.
+
+use crate::{Attempt, ErrorKind, LoginOutcome, RunId, StepPath};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Name of the manifest file at the root of a bundle.
+pub const TRACE_MANIFEST: &str = "manifest.json";
+
+/// Bumped whenever the manifest layout changes incompatibly.
+pub const TRACE_FORMAT_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TraceMode {
+    #[default]
+    Off,
+    Always,
+    OnFailure,
+}
+
+/// Where and when the engine writes trace bundles.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TraceConfig {
+    pub mode: TraceMode,
+    /// Parent directory; each run gets its own sub-directory.
+    pub directory: PathBuf,
+    /// Skip screenshots (snapshots and timing are still recorded).
+    pub skip_screenshots: bool,
+}
+
+/// Serialized UI tree of the current screen.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UiSnapshot {
+    pub format: SnapshotFormat,
+    pub content: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SnapshotFormat {
+    /// Page DOM serialized as HTML.
+    Html,
+    /// Native accessibility tree (uiautomator dump).
+    AccessibilityXml,
+}
+
+impl SnapshotFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            SnapshotFormat::Html => "html",
+            SnapshotFormat::AccessibilityXml => "xml",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TraceManifest {
+    pub format_version: u32,
+    pub run_id: RunId,
+    pub script_id: String,
+    pub script_version: String,
+    pub driver: String,
+    /// Milliseconds since the Unix epoch.
+    pub started_at_ms: u64,
+    pub duration_ms: u64,
+    pub outcome: TraceOutcome,
+    /// Steps in execution order; nested and retried steps appear each time
+    /// they ran.
+    pub steps: Vec<TraceStep>,
+}
+
+/// The run's `LoginOutcome` without the session token: bundles are shared
+/// for debugging and must not carry credentials.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TraceOutcome {
+    pub success: bool,
+    pub error: Option<String>,
+    #[serde(default)]
+    pub cancelled: bool,
+    #[serde(default)]
+    pub error_kind: Option<ErrorKind>,
+    #[serde(default)]
+    pub attempts: Vec<Attempt>,
+    /// Whether the run produced a session token.
+    #[serde(default)]
+    pub has_token: bool,
+}
+
+impl From<&LoginOutcome> for TraceOutcome {
+    fn from(outcome: &LoginOutcome) -> Self {
+        Self {
+            success: outcome.success,
+            error: outcome.error.clone(),
+            cancelled: outcome.cancelled,
+            error_kind: outcome.error_kind,
+            attempts: outcome.attempts.clone(),
+            has_token: outcome.session_token.is_some(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TraceStep {
+    /// Position in execution order, starting at 0.
+    pub sequence: usize,
+    pub path: StepPath,
+    pub step: String,
+    pub started_at_ms: u64,
+    pub duration_ms: u64,
+    pub error: Option<String>,
+    /// Bundle-relative paths of the artifacts captured after the step.
+    pub screenshot: Option<String>,
+    pub snapshot: Option<String>,
+    pub snapshot_format: Option<SnapshotFormat>,
+}
+
+/// Captured artifacts of one step, still in memory.
+pub(crate) struct StepArtifacts {
+    pub path: StepPath,
+    pub step: &'static str,
+    pub started_at_ms: u64,
+    pub duration_ms: u64,
+    pub error: Option<String>,
+    pub screenshot: Option<Vec<u8>>,
+    pub snapshot: Option<UiSnapshot>,
+}
+
+/// Collects step artifacts for one run and writes the bundle at the end.
+pub struct ArtifactCollector {
+    config: TraceConfig,
+    steps: Mutex<Vec<StepArtifacts>>,
+}
+
+impl ArtifactCollector {
+    pub fn new(config: TraceConfig) -> Self {
+        Self {
+            config,
+            steps: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn captures_screenshots(&self) -> bool {
+        !self.config.skip_screenshots
+    }
+
+    pub(crate) fn record(&self, artifacts: StepArtifacts) {
+        if let Ok(mut steps) = self.steps.lock() {
+            steps.push(artifacts);
+        }
+    }
+
+    /// Write the bundle if the mode asks for it; returns its directory.
+    pub fn finish(&self, mut manifest: TraceManifest) -> anyhow::Result<Option<PathBuf>> {
+        let keep = match self.config.mode {
+            TraceMode::Off => false,
+            TraceMode::Always => true,
+            TraceMode::OnFailure => !manifest.outcome.success,
+        };
+        if !keep {
+            return Ok(None);
+        }
+        let bundle = self.config.directory.join(format!(
+            "{}-{}-{}",
+            sanitize(&manifest.script_id),
+            manifest.started_at_ms,
+            manifest.run_id
+        ));
+        std::fs::create_dir_all(bundle.join("steps"))?;
+
+        let steps = std::mem::take(
+            &mut *self
+                .steps
+                .lock()
+                .map_err(|_| anyhow::anyhow!("trace collector poisoned"))?,
+        );
+        for (sequence, artifacts) in steps.into_iter().enumerate() {
+            let screenshot = match &artifacts.screenshot {
+                Some(png) => Some(write_artifact(&bundle, sequence, "png", png)?),
+                None => None,
+            };
+            let snapshot = match &artifacts.snapshot {
+                Some(snapshot) => Some(write_artifact(
+                    &bundle,
+                    sequence,
+                    snapshot.format.extension(),
+                    snapshot.content.as_bytes(),
+                )?),
+                None => None,
+            };
+            manifest.steps.push(TraceStep {
+                sequence,
+                path: artifacts.path,
+                step: artifacts.step.into(),
+                started_at_ms: artifacts.started_at_ms,
+                duration_ms: artifacts.duration_ms,
+                error: artifacts.error,
+                screenshot,
+                snapshot,
+                snapshot_format: artifacts.snapshot.map(|snapshot| snapshot.format),
+            });
+        }
+
+        std::fs::write(
+            bundle.join(TRACE_MANIFEST),
+            serde_json::to_vec_pretty(&manifest)?,
+        )?;
+        Ok(Some(bundle))
+    }
+}
+
+fn write_artifact(
+    bundle: &Path,
+    sequence: usize,
+    extension: &str,
+    bytes: &[u8],
+) -> anyhow::Result<String> {
+    let relative = format!("steps/{sequence:04}.{extension}");
+    std::fs::write(bundle.join(&relative), bytes)?;
+    Ok(relative)
+}
+
+fn sanitize(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
@@ -1,31 +1,96 @@
+use crate::retry::{Attempt, ErrorKind, RetryPolicy};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
 
 /// Metadata about a target app (web/native).
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct TargetApp {
     pub kind: TargetAppKind,
     pub name: String,
     pub version: Option<String>,
-    pub endpoint: Option<String>, // URL or package id
+    pub endpoint: Option<String>, // URL or package/bundle id
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub enum TargetAppKind {
     Web,
     Android,
+    Ios,
+    /// Native shell with WebView screens; see `Step::SwitchContext`.
+    Hybrid,
 }
 
 /// Core login script model.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct LoginScript {
     pub meta: ScriptMeta,
     pub target: TargetApp,
     pub steps: Vec<Step>,
     pub validations: Vec<Validation>,
     pub error_handlers: Vec<ErrorHandler>,
+    /// How to read the session token after a successful login; without a
+    /// rule the driver reports whatever it considers the session.
+    #[serde(default)]
+    pub token: Option<TokenRule>,
+    /// Re-run the whole script on failures the policy covers.
+    #[serde(default)]
+    pub retry: Option<RetryPolicy>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+impl LoginScript {
+    /// Every `ValueRef::FromVault` key the script may read, including keys
+    /// in branches, loops and error handlers.
+    pub fn vault_keys(&self) -> BTreeSet<String> {
+        fn collect(steps: &[Step], keys: &mut BTreeSet<String>) {
+            for step in steps {
+                match step {
+                    Step::Input {
+                        value: ValueRef::FromVault(key),
+                        ..
+                    } => {
+                        keys.insert(key.clone());
+                    }
+                    Step::Conditional {
+                        on_true, on_false, ..
+                    } => {
+                        collect(on_true, keys);
+                        collect(on_false, keys);
+                    }
+                    Step::Loop { body, .. } | Step::Retry { body, .. } => collect(body, keys),
+                    _ => {}
+                }
+            }
+        }
+
+        let mut keys = BTreeSet::new();
+        collect(&self.steps, &mut keys);
+        for handler in &self.error_handlers {
+            collect(&handler.on_error, &mut keys);
+        }
+        keys
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TokenRule {
+    pub source: TokenSource,
+    /// Lifetime assumed when the source carries no expiry of its own.
+    #[serde(default)]
+    pub ttl_secs: Option<u64>,
+}
+
+/// Where a session token lives once the login succeeded.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TokenSource {
+    Cookie(String),
+    LocalStorage(String),
+    ResponseHeader(String),
+    ElementText(Selector),
+    /// Android `shared_prefs/<file>.xml` entry of the target package.
+    SharedPreference { file: String, key: String },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ScriptMeta {
     pub id: String,
     pub version: String,
@@ -34,11 +99,16 @@ pub struct ScriptMeta {
     pub updated_at: Option<String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Step {
     Click(Selector),
     Input { selector: Selector, value: ValueRef },
     WaitFor(Selector),
+    Swipe {
+        from: Selector,
+        to: Selector,
+        duration_ms: u64,
+    },
     SleepMs(u64),
     Conditional {
         condition: Condition,
@@ -46,39 +116,76 @@ pub enum Step {
         on_false: Vec<Step>,
     },
     Loop { times: u32, body: Vec<Step> },
+    /// Run `body` again from its first step when it fails with an error the
+    /// policy covers.
+    Retry { policy: RetryPolicy, body: Vec<Step> },
+    /// Move the session between the native UI tree and a WebView's DOM.
+    SwitchContext(AutomationContext),
+    /// Hand the challenge element to the captcha pipeline and type the
+    /// solution into `response`.
+    Captcha {
+        kind: CaptchaKind,
+        challenge: Selector,
+        response: Selector,
+    },
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// UI tree a session is currently addressing.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub enum AutomationContext {
+    Native,
+    /// A WebView, optionally picked by name when several are present.
+    WebView(Option<String>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Selector {
     Css(String),
     XPath(String),
     AccessibilityId(String),
     Image(String),
     Coordinates { x: i32, y: i32 },
+    /// Candidates in order of preference; the runner uses the first one that
+    /// matches. Recorded steps carry several so a script survives markup
+    /// changes that break one of them.
+    AnyOf(Vec<Selector>),
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+impl Selector {
+    /// Concrete selectors this one stands for, nested `AnyOf`s flattened.
+    pub fn candidates(&self) -> Vec<&Selector> {
+        match self {
+            Selector::AnyOf(candidates) => candidates.iter().flat_map(Self::candidates).collect(),
+            other => vec![other],
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Condition {
     Exists(Selector),
     TextEquals { selector: Selector, expected: String },
+    /// The current page URL contains the text. Only sessions that show web
+    /// pages know their URL; native screens fail the check with an error.
+    UrlContains(String),
     And(Vec<Condition>),
     Or(Vec<Condition>),
     Not(Box<Condition>),
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Validation {
     pub description: String,
     pub condition: Condition,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ErrorHandler {
     pub name: String,
     pub on_error: Vec<Step>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum ValueRef {
     Literal(String),
     FromVault(String), // key in secure storage
@@ -87,8 +194,56 @@ pub enum ValueRef {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LoginOutcome {
     pub success: bool,
-    pub session_token: Option<String>,
+    pub session_token: Option<SessionToken>,
     pub error: Option<String>,
+    /// Set when the run was stopped through `RunControl::cancel`.
+    #[serde(default)]
+    pub cancelled: bool,
+    /// Kind of the failure in `error`, when known.
+    #[serde(default)]
+    pub error_kind: Option<ErrorKind>,
+    /// Tries of retried step blocks and runs, in order.
+    #[serde(default)]
+    pub attempts: Vec<Attempt>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SessionToken {
+    pub value: String,
+    /// `None` when the driver picked the token without a script rule.
+    pub source: Option<TokenSource>,
+    /// Seconds since the Unix epoch; `None` when unknown.
+    pub expires_at: Option<u64>,
+}
+
+impl SessionToken {
+    /// Token found by a driver's own heuristics, without expiry.
+    pub fn opaque(value: impl Into<String>) -> Self {
+        Self {
+            value: value.into(),
+            source: None,
+            expires_at: None,
+        }
+    }
+
+    pub fn from_source(
+        value: impl Into<String>,
+        source: &TokenSource,
+        expires_at: Option<u64>,
+    ) -> Self {
+        Self {
+            value: value.into(),
+            source: Some(source.clone()),
+            expires_at,
+        }
+    }
+
+    /// Whether the token is known to stay valid for at least `margin_secs`
+    /// after `now` (epoch seconds). Tokens without expiry never qualify.
+    pub fn valid_for(&self, now: u64, margin_secs: u64) -> bool {
+        self.expires_at
+            .is_some_and(|expires_at| expires_at > now.saturating_add(margin_secs))
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -98,7 +253,7 @@ pub struct CaptchaChallenge {
     pub metadata: Option<String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum CaptchaKind {
     Image,
     Slider,
@@ -119,3 +274,130 @@ pub struct CaptchaSolution {
     pub strategy: CaptchaStrategy,
 }
 
+
+/// Location of a step inside a script, e.g. `steps[2].on_true[0]`.
+/// `validations[n]` addresses a validation rather than a step.
+/// Serialized in its display form so editors and logs can show it as-is.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(into = "String", try_from = "String")]
+pub struct StepPath(pub Vec<PathSegment>);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PathSegment {
+    Steps(usize),
+    OnTrue(usize),
+    OnFalse(usize),
+    Body(usize),
+    Validation(usize),
+    OnError { handler: usize, index: usize },
+}
+
+impl StepPath {
+    pub fn root() -> Self {
+        Self::default()
+    }
+
+    pub fn child(&self, segment: PathSegment) -> Self {
+        let mut segments = self.0.clone();
+        segments.push(segment);
+        Self(segments)
+    }
+
+    /// Look up the step this path points at.
+    pub fn resolve<'a>(&self, script: &'a LoginScript) -> Option<&'a Step> {
+        let mut current: Option<&Step> = None;
+        for segment in &self.0 {
+            current = match (current, segment) {
+                (None, PathSegment::Steps(i)) => script.steps.get(*i),
+                (None, PathSegment::OnError { handler, index }) => script
+                    .error_handlers
+                    .get(*handler)
+                    .and_then(|handler| handler.on_error.get(*index)),
+                (Some(Step::Conditional { on_true, .. }), PathSegment::OnTrue(i)) => on_true.get(*i),
+                (Some(Step::Conditional { on_false, .. }), PathSegment::OnFalse(i)) => {
+                    on_false.get(*i)
+                }
+                (Some(Step::Loop { body, .. } | Step::Retry { body, .. }), PathSegment::Body(i)) => {
+                    body.get(*i)
+                }
+                _ => None,
+            };
+            current?;
+        }
+        current
+    }
+}
+
+impl std::fmt::Display for StepPath {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, segment) in self.0.iter().enumerate() {
+            if i > 0 {
+                f.write_str(".")?;
+            }
+            match segment {
+                PathSegment::Steps(n) => write!(f, "steps[{n}]")?,
+                PathSegment::OnTrue(n) => write!(f, "on_true[{n}]")?,
+                PathSegment::OnFalse(n) => write!(f, "on_false[{n}]")?,
+                PathSegment::Body(n) => write!(f, "body[{n}]")?,
+                PathSegment::Validation(n) => write!(f, "validations[{n}]")?,
+                PathSegment::OnError { handler, index } => {
+                    write!(f, "error_handlers[{handler}].on_error[{index}]")?
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl std::str::FromStr for StepPath {
+    type Err = String;
+
+    fn from_str(raw: &str) -> Result<Self, Self::Err> {
+        let mut segments = Vec::new();
+        let mut parts = raw.split('.').filter(|part| !part.is_empty());
+        while let Some(part) = parts.next() {
+            let (name, index) = part
+                .strip_suffix(']')
+                .and_then(|part| part.split_once('['))
+                .ok_or_else(|| format!("invalid step path segment `{part}`"))?;
+            let index: usize = index
+                .parse()
+                .map_err(|_| format!("invalid index in `{part}`"))?;
+            segments.push(match name {
+                "steps" => PathSegment::Steps(index),
+                "on_true" => PathSegment::OnTrue(index),
+                "on_false" => PathSegment::OnFalse(index),
+                "body" => PathSegment::Body(index),
+                "validations" => PathSegment::Validation(index),
+                "error_handlers" => {
+                    let next = parts
+                        .next()
+                        .and_then(|part| part.strip_prefix("on_error["))
+                        .and_then(|part| part.strip_suffix(']'))
+                        .and_then(|part| part.parse().ok())
+                        .ok_or_else(|| format!("expected on_error[n] after `{part}`"))?;
+                    PathSegment::OnError {
+                        handler: index,
+                        index: next,
+                    }
+                }
+                other => return Err(format!("unknown step path segment `{other}`")),
+            });
+        }
+        Ok(Self(segments))
+    }
+}
+
+impl From<StepPath> for String {
+    fn from(path: StepPath) -> Self {
+        path.to_string()
+    }
+}
+
+impl TryFrom<String> for StepPath {
+    type Error = String;
+
+    fn try_from(raw: String) -> Result<Self, Self::Error> {
+        raw.parse()
+    }
+}
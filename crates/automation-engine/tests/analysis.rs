@@ -0,0 +1,384 @@
+//! Static analysis: selectors per target kind, reachability, loop and retry
+//! bounds, vault keys and captcha support.
+
+use async_trait::async_trait;
+use automation_engine::{
+    AnalysisReport, AutomationEngine, Backoff, CaptchaChallenge, CaptchaHandler, CaptchaKind,
+    CaptchaSolution, Condition, ErrorKind, IssueCode, LoginScript, PathSegment, RetryPolicy,
+    ScriptMeta, SecretResolver, Selector, Severity, Step, StepPath, TargetApp, TargetAppKind,
+    Validation, ValueRef, WebDriverStub,
+};
+use std::sync::Arc;
+
+fn script(kind: TargetAppKind, selector: Selector) -> LoginScript {
+    steps(kind, vec![Step::Click(selector)])
+}
+
+fn steps(kind: TargetAppKind, steps: Vec<Step>) -> LoginScript {
+    LoginScript {
+        meta: ScriptMeta {
+            id: "analysis".into(),
+            version: "1.0.0".into(),
+            author: None,
+            created_at: None,
+            updated_at: None,
+        },
+        target: TargetApp {
+            kind,
+            name: "app".into(),
+            version: None,
+            endpoint: None,
+        },
+        steps,
+        validations: Vec::new(),
+        error_handlers: Vec::new(),
+        token: None,
+        retry: None,
+    }
+}
+
+fn selector_issues(kind: TargetAppKind, selector: Selector) -> Vec<Severity> {
+    AutomationEngine::with_defaults()
+        .analyze(&script(kind, selector))
+        .issues
+        .into_iter()
+        .filter(|issue| issue.code == IssueCode::InvalidSelector)
+        .map(|issue| issue.severity)
+        .collect()
+}
+
+#[test]
+fn xpath_outside_the_subset_is_an_error_only_on_android() {
+    // `normalize-space()` is valid XPath but not part of the subset.
+    let xpath = Selector::XPath("//button[normalize-space()='Log in']".into());
+    assert_eq!(
+        selector_issues(TargetAppKind::Android, xpath.clone()),
+        [Severity::Error]
+    );
+    assert_eq!(
+        selector_issues(TargetAppKind::Web, xpath.clone()),
+        [Severity::Warning]
+    );
+    assert_eq!(
+        selector_issues(TargetAppKind::Hybrid, xpath),
+        [Severity::Warning]
+    );
+}
+
+#[test]
+fn invalid_css_is_an_error_everywhere() {
+    let css = Selector::Css("input[".into());
+    assert_eq!(selector_issues(TargetAppKind::Web, css), [Severity::Error]);
+}
+
+fn css(selector: &str) -> Selector {
+    Selector::Css(selector.into())
+}
+
+fn path(segments: &[PathSegment]) -> StepPath {
+    segments
+        .iter()
+        .fold(StepPath::root(), |path, segment| path.child(*segment))
+}
+
+/// `(severity, code, path)` of every issue, in report order.
+fn issues(report: AnalysisReport) -> Vec<(Severity, IssueCode, String)> {
+    report
+        .issues
+        .into_iter()
+        .map(|issue| (issue.severity, issue.code, issue.path.to_string()))
+        .collect()
+}
+
+fn analyze(script: &LoginScript) -> Vec<(Severity, IssueCode, String)> {
+    issues(AutomationEngine::with_defaults().analyze(script))
+}
+
+fn retry(max_attempts: u32) -> RetryPolicy {
+    RetryPolicy {
+        max_attempts,
+        retry_on: vec![ErrorKind::Timeout],
+        backoff: Backoff::default(),
+    }
+}
+
+#[test]
+fn branches_behind_constant_conditions_are_unreachable() {
+    let remember = Condition::Exists(css("#remember"));
+    let script = steps(
+        TargetAppKind::Web,
+        vec![
+            // A condition and its negation can never hold together.
+            Step::Conditional {
+                condition: Condition::And(vec![
+                    remember.clone(),
+                    Condition::Not(Box::new(remember.clone())),
+                ]),
+                on_true: vec![Step::Click(css("#remember"))],
+                on_false: vec![Step::Click(css("#login"))],
+            },
+            Step::Conditional {
+                condition: Condition::Or(vec![
+                    Condition::Not(Box::new(remember.clone())),
+                    remember,
+                ]),
+                on_true: vec![Step::Click(css("#login"))],
+                on_false: vec![Step::Click(css("#never"))],
+            },
+            Step::Loop {
+                times: 0,
+                body: vec![Step::Click(css("#next"))],
+            },
+            // Conditions that depend on the UI leave both branches alone.
+            Step::Conditional {
+                condition: Condition::Exists(css("#banner")),
+                on_true: vec![Step::Click(css("#dismiss"))],
+                on_false: Vec::new(),
+            },
+        ],
+    );
+
+    let unreachable = [
+        path(&[PathSegment::Steps(0), PathSegment::OnTrue(0)]),
+        path(&[PathSegment::Steps(1), PathSegment::OnFalse(0)]),
+        path(&[PathSegment::Steps(2), PathSegment::Body(0)]),
+    ];
+    assert_eq!(
+        analyze(&script),
+        [
+            (
+                Severity::Warning,
+                IssueCode::ConstantCondition,
+                path(&[PathSegment::Steps(0)]).to_string()
+            ),
+            (
+                Severity::Warning,
+                IssueCode::UnreachableStep,
+                unreachable[0].to_string()
+            ),
+            (
+                Severity::Warning,
+                IssueCode::ConstantCondition,
+                path(&[PathSegment::Steps(1)]).to_string()
+            ),
+            (
+                Severity::Warning,
+                IssueCode::UnreachableStep,
+                unreachable[1].to_string()
+            ),
+            (
+                Severity::Warning,
+                IssueCode::UnreachableStep,
+                unreachable[2].to_string()
+            ),
+        ]
+    );
+}
+
+#[test]
+fn validations_that_cannot_depend_on_the_ui_are_flagged() {
+    let mut script = steps(TargetAppKind::Web, Vec::new());
+    script.validations = vec![
+        Validation {
+            description: "no candidates".into(),
+            condition: Condition::Exists(Selector::AnyOf(Vec::new())),
+        },
+        Validation {
+            description: "any url".into(),
+            condition: Condition::UrlContains(String::new()),
+        },
+        Validation {
+            description: "inbox".into(),
+            condition: Condition::UrlContains("/inbox".into()),
+        },
+    ];
+
+    let report = analyze(&script);
+    let validation = |index| path(&[PathSegment::Validation(index)]).to_string();
+    assert_eq!(
+        report,
+        [
+            (Severity::Error, IssueCode::EmptySelector, validation(0)),
+            (Severity::Error, IssueCode::ConstantCondition, validation(0)),
+            (
+                Severity::Warning,
+                IssueCode::ConstantCondition,
+                validation(1)
+            ),
+        ]
+    );
+}
+
+#[test]
+fn loops_and_retries_multiply_towards_the_limit() {
+    let nested = steps(
+        TargetAppKind::Web,
+        vec![Step::Loop {
+            times: 50,
+            body: vec![Step::Retry {
+                policy: retry(30),
+                body: vec![Step::Click(css("#next"))],
+            }],
+        }],
+    );
+    assert_eq!(
+        analyze(&nested),
+        [(
+            Severity::Error,
+            IssueCode::UnboundedLoop,
+            path(&[PathSegment::Steps(0), PathSegment::Body(0)]).to_string()
+        )]
+    );
+
+    let huge = steps(
+        TargetAppKind::Web,
+        vec![Step::Retry {
+            policy: retry(u32::MAX),
+            body: vec![Step::Click(css("#login"))],
+        }],
+    );
+    assert_eq!(
+        analyze(&huge),
+        [(
+            Severity::Error,
+            IssueCode::UnboundedLoop,
+            path(&[PathSegment::Steps(0)]).to_string()
+        )]
+    );
+
+    // The script-level policy reruns every step, loops included.
+    let mut rerun = steps(
+        TargetAppKind::Web,
+        vec![Step::Loop {
+            times: 400,
+            body: vec![Step::Click(css("#next"))],
+        }],
+    );
+    rerun.retry = Some(retry(2));
+    assert!(analyze(&rerun).is_empty());
+    rerun.retry = Some(retry(3));
+    assert_eq!(
+        analyze(&rerun),
+        [(
+            Severity::Error,
+            IssueCode::UnboundedLoop,
+            path(&[PathSegment::Steps(0)]).to_string()
+        )]
+    );
+    rerun.retry = Some(retry(u32::MAX));
+    assert_eq!(
+        analyze(&rerun)
+            .into_iter()
+            .map(|(_, code, path)| (code, path))
+            .collect::<Vec<_>>(),
+        [
+            (IssueCode::UnboundedLoop, String::new()),
+            (
+                IssueCode::UnboundedLoop,
+                path(&[PathSegment::Steps(0)]).to_string()
+            ),
+        ]
+    );
+}
+
+/// Resolver that knows a fixed set of keys.
+struct Keys(&'static [&'static str]);
+
+impl SecretResolver for Keys {
+    fn resolve(&self, key: &str) -> anyhow::Result<String> {
+        Ok(key.into())
+    }
+
+    fn contains(&self, key: &str) -> Option<bool> {
+        Some(self.0.contains(&key))
+    }
+}
+
+/// Resolver that cannot tell which keys exist before a run.
+struct Opaque;
+
+impl SecretResolver for Opaque {
+    fn resolve(&self, key: &str) -> anyhow::Result<String> {
+        Ok(key.into())
+    }
+}
+
+#[test]
+fn vault_keys_are_checked_when_the_resolver_can_tell() {
+    let input = |key: &str| Step::Input {
+        selector: css("#field"),
+        value: ValueRef::FromVault(key.into()),
+    };
+    let script = steps(
+        TargetAppKind::Web,
+        vec![
+            input("alice.username"),
+            input("alice.secret"),
+            Step::Input {
+                selector: css("#remember"),
+                value: ValueRef::Literal("yes".into()),
+            },
+        ],
+    );
+
+    let mut engine = AutomationEngine::with_defaults();
+    engine.set_secret_resolver(Arc::new(Keys(&["alice.username"])));
+    assert_eq!(
+        issues(engine.analyze(&script)),
+        [(
+            Severity::Error,
+            IssueCode::MissingVaultKey,
+            path(&[PathSegment::Steps(1)]).to_string()
+        )]
+    );
+
+    engine.set_secret_resolver(Arc::new(Opaque));
+    assert!(issues(engine.analyze(&script)).is_empty());
+}
+
+/// Handler that only solves image challenges.
+struct ImageOnly;
+
+#[async_trait]
+impl CaptchaHandler for ImageOnly {
+    fn label(&self) -> &'static str {
+        "image-only"
+    }
+
+    fn supports(&self, kind: &CaptchaKind) -> bool {
+        *kind == CaptchaKind::Image
+    }
+
+    async fn solve(&self, _challenge: CaptchaChallenge) -> anyhow::Result<CaptchaSolution> {
+        anyhow::bail!("not used")
+    }
+}
+
+#[test]
+fn captcha_kinds_need_a_handler_that_supports_them() {
+    let captcha = |kind| Step::Captcha {
+        kind,
+        challenge: css("#challenge"),
+        response: css("#response"),
+    };
+    let script = steps(
+        TargetAppKind::Web,
+        vec![captcha(CaptchaKind::Image), captcha(CaptchaKind::Slider)],
+    );
+
+    let engine = AutomationEngine::new(vec![Arc::new(WebDriverStub)], Arc::new(ImageOnly));
+    let report = engine.analyze(&script);
+    assert_eq!(
+        issues(report.clone()),
+        [(
+            Severity::Error,
+            IssueCode::UnsupportedCaptcha,
+            path(&[PathSegment::Steps(1)]).to_string()
+        )]
+    );
+    assert_eq!(
+        report.issues[0].message,
+        "captcha handler image-only cannot solve Slider challenges"
+    );
+    assert!(report.has_errors());
+}
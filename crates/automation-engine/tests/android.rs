@@ -0,0 +1,570 @@
+//! Android driver against canned `uiautomator` dumps from `fixtures/`, with
+//! WebView contexts served by the mock DevTools page in `common::devtools`.
+
+mod common;
+
+use async_trait::async_trait;
+use automation_engine::android::{parse_hierarchy, AdbTransport, AndroidSession};
+use automation_engine::{
+    AndroidDriver, AutomationContext, AutomationEngine, Condition, DriverSession, HybridDriver,
+    LoginScript, NoopCaptcha, PoolConfig, ScriptMeta, Selector, SessionPool, Step, TargetApp,
+    TargetAppKind, TokenRule, TokenSource, Validation, ValueRef,
+};
+use common::devtools::{self, Page, LOGIN_BUTTON_XPATH, PASS, USER};
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+fn fixture(name: &str) -> String {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("fixtures/uiautomator")
+        .join(name);
+    std::fs::read_to_string(&path).unwrap_or_else(|err| panic!("{}: {err}", path.display()))
+}
+
+/// Centre of the "Log in" button in `login.xml`.
+const LOGIN_TAP: &str = "input tap 806 1060";
+
+/// Device that serves `login.xml` until the login button is tapped and
+/// `inbox.xml` afterwards, and records every other shell command and port
+/// forward. Its abstract sockets all belong to `com.example.mail`; with a
+/// DevTools address, forwarding any of them reaches that server.
+struct CannedDevice {
+    screens: [String; 2],
+    sockets: Vec<String>,
+    devtools: Option<SocketAddr>,
+    logged_in: Mutex<bool>,
+    commands: Mutex<Vec<String>>,
+}
+
+impl CannedDevice {
+    fn new() -> Arc<Self> {
+        Self::with_sockets(&[])
+    }
+
+    fn with_sockets(sockets: &[&str]) -> Arc<Self> {
+        Arc::new(Self {
+            screens: [fixture("login.xml"), fixture("inbox.xml")],
+            sockets: sockets.iter().map(|name| name.to_string()).collect(),
+            devtools: None,
+            logged_in: Mutex::new(false),
+            commands: Mutex::new(Vec::new()),
+        })
+    }
+
+    /// Device whose app has one debuggable WebView, `WEBVIEW`, showing the
+    /// page served on `devtools`.
+    fn with_webview(devtools: SocketAddr) -> Arc<Self> {
+        let mut device = Arc::into_inner(Self::with_sockets(&[WEBVIEW])).unwrap();
+        device.devtools = Some(devtools);
+        Arc::new(device)
+    }
+
+    /// `/proc/net/unix` listing the device's abstract sockets.
+    fn unix_table(&self) -> String {
+        let mut table = String::from("Num       RefCount Protocol Flags    Type St Inode Path\n");
+        for (inode, name) in self.sockets.iter().enumerate() {
+            table.push_str(&format!(
+                "0000000000000000: 00000002 00000000 00010000 0001 01 {inode} @{name}\n"
+            ));
+        }
+        table
+    }
+
+    fn commands(&self) -> Vec<String> {
+        self.commands.lock().unwrap().clone()
+    }
+}
+
+#[async_trait]
+impl AdbTransport for CannedDevice {
+    async fn shell(&self, command: &str) -> anyhow::Result<String> {
+        if command.starts_with("uiautomator dump ") {
+            let logged_in = *self.logged_in.lock().unwrap();
+            return Ok(self.screens[usize::from(logged_in)].clone());
+        }
+        self.commands.lock().unwrap().push(command.to_string());
+        if command == LOGIN_TAP {
+            *self.logged_in.lock().unwrap() = true;
+        }
+        if command.starts_with("run-as ") {
+            return Ok(fixture("shared_prefs_auth.xml"));
+        }
+        if command == "cat /proc/net/unix" {
+            return Ok(self.unix_table());
+        }
+        if command == "echo ok" {
+            return Ok("ok\n".into());
+        }
+        if command.starts_with("cat /proc/") && command.ends_with("/cmdline") {
+            return Ok("com.example.mail\0".into());
+        }
+        Ok(String::new())
+    }
+
+    async fn exec_out(&self, _command: &str) -> anyhow::Result<Vec<u8>> {
+        Ok(Vec::new())
+    }
+
+    async fn forward(&self, abstract_socket: &str) -> anyhow::Result<u16> {
+        self.commands
+            .lock()
+            .unwrap()
+            .push(format!("forward {abstract_socket}"));
+        match self.devtools {
+            Some(addr) if self.sockets.iter().any(|name| name == abstract_socket) => {
+                Ok(addr.port())
+            }
+            _ => anyhow::bail!("no such socket {abstract_socket}"),
+        }
+    }
+
+    async fn remove_forward(&self, port: u16) -> anyhow::Result<()> {
+        self.commands
+            .lock()
+            .unwrap()
+            .push(format!("forward --remove {port}"));
+        Ok(())
+    }
+}
+
+const WEBVIEW: &str = "webview_devtools_remote_4242";
+
+/// Serve a fresh login page; returns it and its DevTools address.
+async fn webview_page() -> (Arc<Mutex<Page>>, SocketAddr) {
+    let page = Arc::new(Mutex::new(Page::default()));
+    let addr = devtools::serve(page.clone()).await;
+    (page, addr)
+}
+
+fn css(selector: &str) -> Selector {
+    Selector::Css(selector.into())
+}
+
+fn xpath(expression: &str) -> Selector {
+    Selector::XPath(expression.into())
+}
+
+#[test]
+fn descendant_positions_count_per_parent() {
+    let tree = parse_hierarchy(&fixture("login.xml")).unwrap();
+    let texts = |expression: &str| -> Vec<String> {
+        tree.xpath(expression)
+            .unwrap()
+            .into_iter()
+            .map(|node| {
+                let node = tree.node(node);
+                let id = node.attr("resource-id").unwrap_or_default();
+                id.rsplit('/').next().unwrap().to_string()
+            })
+            .collect()
+    };
+
+    // `//x[n]` picks the n-th `x` under each parent, not the n-th overall.
+    assert_eq!(texts("//*[@clickable='true'][1]"), ["username", "forgot"]);
+    assert_eq!(texts("//*[2]"), ["form", "password", "login"]);
+    assert_eq!(texts("//android.widget.Button[last()]"), ["login"]);
+    assert_eq!(
+        texts("//*[@password='true']/../android.widget.EditText[1]"),
+        ["username"]
+    );
+    assert_eq!(texts("//*[contains(@text, 'Log')]"), ["login"]);
+}
+
+#[tokio::test]
+async fn taps_and_types_at_matched_nodes() {
+    let device = CannedDevice::new();
+    let mut session = AndroidSession::new(device.clone());
+
+    assert!(session
+        .exists(&Selector::AccessibilityId("Email".into()))
+        .await
+        .unwrap());
+    assert!(!session
+        .exists(&xpath("//android.widget.CheckBox"))
+        .await
+        .unwrap());
+    session
+        .input(&Selector::AccessibilityId("password".into()), "p4ss word$")
+        .await
+        .unwrap();
+    assert_eq!(
+        session
+            .text(&xpath("//android.widget.Button[2]"))
+            .await
+            .unwrap(),
+        Some("Log in".into())
+    );
+    let missing = session.click(&xpath("//android.widget.Switch")).await;
+    assert!(missing.is_err());
+    // Newlines and tabs become key events; other control characters are
+    // refused before anything is tapped.
+    let username = Selector::AccessibilityId("username".into());
+    session.input(&username, "al ice\tx\n").await.unwrap();
+    assert!(session.input(&username, "a\u{1b}b").await.is_err());
+    assert!(session.input(&username, "a\rreboot").await.is_err());
+
+    assert_eq!(
+        device.commands(),
+        [
+            "input tap 540 620",
+            r"input text p4ss%sword\$",
+            "input tap 540 460",
+            "input text al%sice",
+            "input keyevent 61",
+            "input text x",
+            "input keyevent 66",
+        ]
+    );
+}
+
+#[tokio::test]
+async fn webview_sockets_need_a_numeric_pid() {
+    let device = CannedDevice::with_sockets(&[
+        "webview_devtools_remote_4242",
+        "webview_devtools_remote_1;reboot",
+        "webview_devtools_remote_$(reboot)",
+        "webview_devtools_remote_4242`reboot`",
+    ]);
+    let mut session = AndroidSession::new(device.clone());
+    session.launch("com.example.mail").await.unwrap();
+
+    assert_eq!(
+        session.contexts().await.unwrap(),
+        [
+            AutomationContext::Native,
+            AutomationContext::WebView(Some("webview_devtools_remote_4242".into())),
+        ]
+    );
+    assert_eq!(
+        device.commands()[1..],
+        ["cat /proc/net/unix", "cat /proc/4242/cmdline"]
+    );
+}
+
+#[tokio::test]
+async fn webview_context_resolves_selectors_over_devtools() {
+    let (page, addr) = webview_page().await;
+    let device = CannedDevice::with_webview(addr);
+    let mut session = AndroidSession::new(device.clone());
+    session.launch("com.example.mail").await.unwrap();
+    let webview = AutomationContext::WebView(Some(WEBVIEW.into()));
+
+    assert_eq!(
+        session.contexts().await.unwrap(),
+        [AutomationContext::Native, webview.clone()]
+    );
+    session
+        .switch_context(&AutomationContext::WebView(None))
+        .await
+        .unwrap();
+
+    // Selectors now go to the page, not the native hierarchy.
+    assert!(session.exists(&css("#user")).await.unwrap());
+    assert!(!session
+        .exists(&Selector::AccessibilityId("username".into()))
+        .await
+        .unwrap());
+    session.input(&css("#user"), "alice").await.unwrap();
+    session
+        .click(&Selector::XPath(LOGIN_BUTTON_XPATH.into()))
+        .await
+        .unwrap();
+    assert_eq!(
+        session.text(&css("#logout")).await.unwrap().as_deref(),
+        Some("Log out")
+    );
+    assert_eq!(
+        session.current_url().await.unwrap().as_deref(),
+        Some("https://mail.example/inbox")
+    );
+
+    // Back in the native context the dump answers again, while the
+    // WebView stays attached for its cookies.
+    session
+        .switch_context(&AutomationContext::Native)
+        .await
+        .unwrap();
+    assert!(session
+        .exists(&Selector::AccessibilityId("username".into()))
+        .await
+        .unwrap());
+    assert!(session.exists(&css("#user")).await.is_err());
+    assert_eq!(session.current_url().await.unwrap(), None);
+    assert_eq!(
+        session.session_token().await.unwrap().as_deref(),
+        Some("SID=sid-1; lang=en")
+    );
+
+    // Switching back to the attached WebView does not forward again.
+    session.switch_context(&webview).await.unwrap();
+    assert!(session.exists(&css("#logout")).await.unwrap());
+    session.close().await.unwrap();
+
+    assert_eq!(page.lock().unwrap().typed[&USER], "alice");
+    let port = addr.port();
+    assert_eq!(
+        device.commands(),
+        [
+            "monkey -p 'com.example.mail' -c android.intent.category.LAUNCHER 1",
+            "cat /proc/net/unix",
+            "cat /proc/4242/cmdline",
+            "cat /proc/net/unix",
+            "cat /proc/4242/cmdline",
+            &format!("forward {WEBVIEW}"),
+            &format!("forward --remove {port}"),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn switching_to_a_missing_webview_stays_native() {
+    let mut session = AndroidSession::new(CannedDevice::new());
+    session.launch("com.example.mail").await.unwrap();
+    let err = session
+        .switch_context(&AutomationContext::WebView(None))
+        .await
+        .unwrap_err();
+    assert_eq!(err.to_string(), "no debuggable webview found");
+
+    let (_page, addr) = webview_page().await;
+    let mut session = AndroidSession::new(CannedDevice::with_webview(addr));
+    session.launch("com.example.mail").await.unwrap();
+    let other = AutomationContext::WebView(Some("webview_devtools_remote_7".into()));
+    assert!(session.switch_context(&other).await.is_err());
+    assert!(session
+        .exists(&Selector::AccessibilityId("username".into()))
+        .await
+        .unwrap());
+}
+
+#[tokio::test]
+async fn shell_arguments_are_validated_and_quoted() {
+    let device = CannedDevice::new();
+    let mut session = AndroidSession::new(device.clone());
+
+    session
+        .launch("com.example.mail/.Login$Activity")
+        .await
+        .unwrap();
+    assert!(session.launch("com.example.mail; reboot").await.is_err());
+    assert!(session.launch("$(reboot)").await.is_err());
+    let escape = TokenSource::SharedPreference {
+        file: "../../databases/mail".into(),
+        key: "session_token".into(),
+    };
+    assert!(session.extract_token(&escape).await.is_err());
+    let quoted = TokenSource::SharedPreference {
+        file: "auth'; reboot; '".into(),
+        key: "session_token".into(),
+    };
+    assert!(session.extract_token(&quoted).await.is_err());
+
+    assert_eq!(
+        device.commands(),
+        ["am start -W -n 'com.example.mail/.Login$Activity'"]
+    );
+}
+
+#[tokio::test]
+async fn runs_a_login_script_end_to_end() {
+    let device = CannedDevice::new();
+    let engine = AutomationEngine::new(
+        vec![Arc::new(AndroidDriver::new(device.clone()))],
+        Arc::new(NoopCaptcha),
+    );
+    let script = LoginScript {
+        meta: ScriptMeta {
+            id: "mail-android".into(),
+            version: "1.0.0".into(),
+            author: None,
+            created_at: None,
+            updated_at: None,
+        },
+        target: TargetApp {
+            kind: TargetAppKind::Android,
+            name: "mail".into(),
+            version: None,
+            endpoint: Some("com.example.mail".into()),
+        },
+        steps: vec![
+            Step::WaitFor(Selector::AccessibilityId("username".into())),
+            Step::Input {
+                selector: Selector::AccessibilityId("username".into()),
+                value: ValueRef::Literal("alice".into()),
+            },
+            Step::Input {
+                selector: xpath("//android.widget.EditText[@password='true']"),
+                value: ValueRef::Literal("hunter2".into()),
+            },
+            Step::Click(xpath("//android.widget.Button[@text='Log in']")),
+        ],
+        validations: vec![Validation {
+            description: "inbox visible".into(),
+            condition: Condition::TextEquals {
+                selector: Selector::AccessibilityId("title".into()),
+                expected: "Inbox".into(),
+            },
+        }],
+        error_handlers: Vec::new(),
+        token: Some(TokenRule {
+            source: TokenSource::SharedPreference {
+                file: "auth".into(),
+                key: "session_token".into(),
+            },
+            ttl_secs: None,
+        }),
+        retry: None,
+    };
+
+    let outcome = engine.run(&script).await.unwrap();
+    assert!(outcome.success, "{outcome:?}");
+    assert_eq!(outcome.session_token.unwrap().value, "tok-123");
+    assert_eq!(
+        device.commands(),
+        [
+            "monkey -p 'com.example.mail' -c android.intent.category.LAUNCHER 1",
+            "input tap 540 460",
+            "input text alice",
+            "input tap 540 620",
+            "input text hunter2",
+            LOGIN_TAP,
+            "run-as 'com.example.mail' cat 'shared_prefs/auth.xml'",
+        ]
+    );
+}
+
+/// Log in on the WebView page, switch back and validate on the native
+/// screen.
+fn webview_login(kind: TargetAppKind) -> LoginScript {
+    let logged_in = Condition::And(vec![
+        Condition::Exists(css("#logout")),
+        Condition::TextEquals {
+            selector: css("#logout"),
+            expected: "Log out".into(),
+        },
+        Condition::UrlContains("/inbox".into()),
+    ]);
+    LoginScript {
+        meta: ScriptMeta {
+            id: "mail-webview".into(),
+            version: "1.0.0".into(),
+            author: None,
+            created_at: None,
+            updated_at: None,
+        },
+        target: TargetApp {
+            kind,
+            name: "mail".into(),
+            version: None,
+            endpoint: Some("com.example.mail".into()),
+        },
+        steps: vec![
+            Step::SwitchContext(AutomationContext::WebView(None)),
+            Step::WaitFor(css("#user")),
+            Step::Input {
+                selector: css("#user"),
+                value: ValueRef::Literal("alice".into()),
+            },
+            Step::Input {
+                selector: Selector::AnyOf(vec![css("#password"), css("#pass")]),
+                value: ValueRef::Literal("hunter2".into()),
+            },
+            Step::Click(Selector::XPath(LOGIN_BUTTON_XPATH.into())),
+            Step::Conditional {
+                condition: logged_in,
+                on_true: vec![Step::SwitchContext(AutomationContext::Native)],
+                on_false: Vec::new(),
+            },
+        ],
+        // Only the native hierarchy has this node, so it passes only once
+        // the run is back in the native context.
+        validations: vec![Validation {
+            description: "native screen".into(),
+            condition: Condition::Exists(Selector::AccessibilityId("username".into())),
+        }],
+        error_handlers: Vec::new(),
+        token: Some(TokenRule {
+            source: TokenSource::Cookie("SID".into()),
+            ttl_secs: None,
+        }),
+        retry: None,
+    }
+}
+
+#[tokio::test]
+async fn switches_into_the_webview_and_back_during_a_run() {
+    let (page, addr) = webview_page().await;
+    let device = CannedDevice::with_webview(addr);
+    let engine = AutomationEngine::new(
+        vec![Arc::new(AndroidDriver::new(device.clone()))],
+        Arc::new(NoopCaptcha),
+    );
+
+    let outcome = engine
+        .run(&webview_login(TargetAppKind::Android))
+        .await
+        .unwrap();
+    assert!(outcome.success, "{outcome:?}");
+    assert_eq!(outcome.session_token.unwrap().value, "sid-1");
+    let page = page.lock().unwrap();
+    assert!(page.logged_in);
+    assert_eq!(page.typed[&USER], "alice");
+    assert_eq!(page.typed[&PASS], "hunter2");
+    assert_eq!(
+        device.commands(),
+        [
+            "monkey -p 'com.example.mail' -c android.intent.category.LAUNCHER 1",
+            "cat /proc/net/unix",
+            "cat /proc/4242/cmdline",
+            &format!("forward {WEBVIEW}"),
+            &format!("forward --remove {}", addr.port()),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn hybrid_targets_run_as_pooled_android_sessions() {
+    let (page, addr) = webview_page().await;
+    let device = CannedDevice::with_webview(addr);
+    let android = Arc::new(AndroidDriver::new(device.clone()));
+    // Only the hybrid driver accepts the target; it hands out the Android
+    // driver's own sessions.
+    let mut engine = AutomationEngine::new(
+        vec![Arc::new(HybridDriver::new(android))],
+        Arc::new(NoopCaptcha),
+    );
+    let pool = Arc::new(SessionPool::new(PoolConfig::default()));
+    engine.set_session_pool(pool.clone());
+    let script = webview_login(TargetAppKind::Hybrid);
+
+    for _ in 0..2 {
+        let outcome = engine.run(&script).await.unwrap();
+        assert!(outcome.success, "{outcome:?}");
+        assert_eq!(outcome.session_token.unwrap().value, "sid-1");
+    }
+    assert_eq!(pool.metrics().hits, 1);
+    pool.drain().await;
+    assert_eq!(page.lock().unwrap().typed[&USER], "alicealice");
+
+    // Each reset detaches the WebView, clears the app by its package and
+    // launches it again.
+    let launch = "monkey -p 'com.example.mail' -c android.intent.category.LAUNCHER 1".to_string();
+    let login = [
+        "cat /proc/net/unix".to_string(),
+        "cat /proc/4242/cmdline".to_string(),
+        format!("forward {WEBVIEW}"),
+    ];
+    let reset = [
+        format!("forward --remove {}", addr.port()),
+        "am force-stop 'com.example.mail' && pm clear 'com.example.mail'".to_string(),
+        launch.clone(),
+    ];
+    let mut expected = vec![launch];
+    expected.extend(login.clone());
+    expected.extend(reset.clone());
+    expected.push("echo ok".to_string());
+    expected.extend(login);
+    expected.extend(reset);
+    assert_eq!(device.commands(), expected);
+}
@@ -0,0 +1,216 @@
+//! Batch scheduling against fake drivers: fairness between users, the
+//! concurrency caps, per-target spacing and cancellation.
+
+mod common;
+
+use automation_engine::{
+    AutomationEngine, BatchJob, BatchLimits, NoopCaptcha, RunControl, Step, TargetAppKind,
+};
+use common::{css, script, FakeDriver, Shared};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+fn engine(drivers: Vec<FakeDriver>) -> AutomationEngine {
+    AutomationEngine::new(
+        drivers
+            .into_iter()
+            .map(|driver| Arc::new(driver) as _)
+            .collect(),
+        Arc::new(NoopCaptcha),
+    )
+}
+
+/// A job for `user` against the target `target`, holding its session for
+/// `hold_ms`.
+fn job(user: &str, account: &str, kind: TargetAppKind, target: &str, hold_ms: u64) -> BatchJob {
+    BatchJob {
+        user: user.into(),
+        account: account.into(),
+        script: Arc::new(script(
+            account,
+            kind,
+            target,
+            vec![Step::Click(css("#login")), Step::SleepMs(hold_ms)],
+        )),
+        secrets: None,
+    }
+}
+
+fn opened(state: &Shared) -> Vec<String> {
+    let state = state.lock().unwrap();
+    state.opened.iter().map(|(name, _)| name.clone()).collect()
+}
+
+#[tokio::test]
+async fn rotates_between_users() {
+    let driver = FakeDriver::new("web-fake", TargetAppKind::Web);
+    let state = driver.state();
+    let engine = engine(vec![driver]);
+    let jobs = [
+        ("ann", "a1"),
+        ("ann", "a2"),
+        ("ann", "a3"),
+        ("bob", "b1"),
+        ("cid", "c1"),
+        ("cid", "c2"),
+    ]
+    .map(|(user, account)| job(user, account, TargetAppKind::Web, account, 0));
+    let limits = BatchLimits {
+        max_concurrency: 1,
+        ..BatchLimits::default()
+    };
+
+    let report = engine
+        .run_batch(jobs.to_vec(), &limits, RunControl::new())
+        .await;
+    // One job per user in turn; a user's own jobs keep their order.
+    assert_eq!(opened(&state), ["a1", "b1", "c1", "a2", "c2", "a3"]);
+    assert_eq!(
+        (report.succeeded, report.failed, report.cancelled),
+        (6, 0, 0)
+    );
+    let accounts: Vec<_> = report
+        .results
+        .iter()
+        .map(|result| result.account.as_str())
+        .collect();
+    assert_eq!(accounts, ["a1", "a2", "a3", "b1", "c1", "c2"]);
+    assert!(report
+        .results
+        .iter()
+        .all(|result| result.driver.as_deref() == Some("web-fake")));
+}
+
+#[tokio::test]
+async fn keeps_to_the_global_and_per_driver_limits() {
+    let state = Shared::default();
+    let engine = engine(vec![
+        FakeDriver::with_state("web-fake", TargetAppKind::Web, state.clone()),
+        FakeDriver::with_state("android-fake", TargetAppKind::Android, state.clone()),
+    ]);
+    let mut jobs = Vec::new();
+    for n in 0..4 {
+        let (web, app) = (format!("web{n}"), format!("app{n}"));
+        jobs.push(job(&web, &web, TargetAppKind::Web, &web, 40));
+        jobs.push(job(&app, &app, TargetAppKind::Android, &app, 40));
+    }
+    let limits = BatchLimits {
+        max_concurrency: 3,
+        per_driver: HashMap::from([("web-fake".to_string(), 1)]),
+        target_interval: Duration::ZERO,
+    };
+
+    let report = engine.run_batch(jobs, &limits, RunControl::new()).await;
+    assert_eq!(report.succeeded, 8);
+    let state = state.lock().unwrap();
+    assert_eq!(state.max_open, 3);
+    assert_eq!(state.max_by_driver["web-fake"], 1);
+    assert_eq!(state.open, 0);
+}
+
+#[tokio::test]
+async fn spaces_out_starts_against_one_target() {
+    let driver = FakeDriver::new("web-fake", TargetAppKind::Web);
+    let state = driver.state();
+    let engine = engine(vec![driver]);
+    let jobs = vec![
+        job("ann", "a1", TargetAppKind::Web, "mail", 0),
+        job("bob", "b1", TargetAppKind::Web, "mail", 0),
+        job("cid", "c1", TargetAppKind::Web, "mail", 0),
+        job("dan", "d1", TargetAppKind::Web, "shop", 0),
+    ];
+    let limits = BatchLimits {
+        max_concurrency: 4,
+        target_interval: Duration::from_millis(60),
+        ..BatchLimits::default()
+    };
+
+    let report = engine.run_batch(jobs, &limits, RunControl::new()).await;
+    assert_eq!(report.succeeded, 4);
+    let state = state.lock().unwrap();
+    let started = |name: &str| -> Vec<Instant> {
+        state
+            .opened
+            .iter()
+            .filter(|(target, _)| target == name)
+            .map(|(_, at)| *at)
+            .collect()
+    };
+    let mail = started("mail");
+    assert_eq!(mail.len(), 3);
+    for pair in mail.windows(2) {
+        assert!(pair[1] - pair[0] >= Duration::from_millis(50), "{mail:?}");
+    }
+    // Other targets do not wait for the mail server's interval.
+    assert!(started("shop")[0] - mail[0] < Duration::from_millis(50));
+}
+
+#[tokio::test]
+async fn cancelling_stops_running_and_queued_jobs() {
+    let driver = FakeDriver::new("web-fake", TargetAppKind::Web);
+    let state = driver.state();
+    let engine = engine(vec![driver]);
+    let jobs = (0..5)
+        .map(|n| {
+            let account = format!("acct{n}");
+            job("ann", &account, TargetAppKind::Web, &account, 10_000)
+        })
+        .collect();
+    let limits = BatchLimits {
+        max_concurrency: 2,
+        ..BatchLimits::default()
+    };
+    let control = RunControl::new();
+    let cancel = control.clone();
+    tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        cancel.cancel();
+    });
+
+    let started = Instant::now();
+    let report = engine.run_batch(jobs, &limits, control).await;
+    assert!(started.elapsed() < Duration::from_secs(5));
+    assert_eq!((report.succeeded, report.cancelled), (0, 5));
+    assert!(report.results.iter().all(|result| result
+        .outcome
+        .as_ref()
+        .is_some_and(|outcome| outcome.cancelled)));
+    // Only the two running jobs opened sessions, and both were closed.
+    let state = state.lock().unwrap();
+    assert_eq!((state.opened.len(), state.closed), (2, 2));
+}
+
+#[tokio::test]
+async fn unrunnable_jobs_fail_without_holding_up_the_rest() {
+    let engine = engine(vec![
+        FakeDriver::new("web-fake", TargetAppKind::Web),
+        FakeDriver::new("android-fake", TargetAppKind::Android),
+    ]);
+    let jobs = vec![
+        job("ann", "web", TargetAppKind::Web, "mail", 0),
+        job("ann", "app", TargetAppKind::Android, "mail-app", 0),
+        job("ann", "ios", TargetAppKind::Ios, "mail-ios", 0),
+    ];
+    let limits = BatchLimits {
+        per_driver: HashMap::from([("web-fake".to_string(), 0)]),
+        ..BatchLimits::default()
+    };
+
+    let report = engine.run_batch(jobs, &limits, RunControl::new()).await;
+    assert_eq!((report.succeeded, report.failed), (1, 2));
+    let errors: Vec<_> = report
+        .results
+        .iter()
+        .map(|result| result.error.as_deref())
+        .collect();
+    assert_eq!(
+        errors,
+        [
+            Some("driver web-fake has a limit of zero"),
+            None,
+            Some("no iOS driver installed"),
+        ]
+    );
+    assert!(report.results[1].succeeded());
+}
@@ -0,0 +1,153 @@
+//! CDP driver against the mock DevTools endpoint in `common::devtools`.
+
+mod common;
+
+use automation_engine::cdp::CdpSession;
+use automation_engine::{
+    AutomationEngine, CdpDriver, Condition, DriverSession, ErrorKind, LoginScript, NoopCaptcha,
+    ScriptMeta, Selector, Step, StepError, TargetApp, TargetAppKind, TokenRule, TokenSource,
+    Validation, ValueRef,
+};
+use common::devtools::{debugger_url, serve, Page, COOKIE_EXPIRES, LOGIN_BUTTON_XPATH, PASS, USER};
+use serde_json::json;
+use std::sync::{Arc, Mutex};
+
+fn login_script() -> LoginScript {
+    LoginScript {
+        meta: ScriptMeta {
+            id: "mail-web".into(),
+            version: "1.0.0".into(),
+            author: None,
+            created_at: None,
+            updated_at: None,
+        },
+        target: TargetApp {
+            kind: TargetAppKind::Web,
+            name: "mail".into(),
+            version: None,
+            endpoint: Some("https://mail.example/login".into()),
+        },
+        steps: vec![
+            Step::WaitFor(Selector::Css("#user".into())),
+            Step::Input {
+                selector: Selector::Css("#user".into()),
+                value: ValueRef::Literal("alice".into()),
+            },
+            Step::Input {
+                selector: Selector::AnyOf(vec![
+                    Selector::Css("#password".into()),
+                    Selector::Css("#pass".into()),
+                ]),
+                value: ValueRef::Literal("hunter2".into()),
+            },
+            Step::Click(Selector::XPath(LOGIN_BUTTON_XPATH.into())),
+        ],
+        validations: vec![
+            Validation {
+                description: "logout link".into(),
+                condition: Condition::Exists(Selector::Css("#logout".into())),
+            },
+            Validation {
+                description: "inbox".into(),
+                condition: Condition::UrlContains("/inbox".into()),
+            },
+        ],
+        error_handlers: Vec::new(),
+        token: Some(TokenRule {
+            source: TokenSource::Cookie("SID".into()),
+            ttl_secs: None,
+        }),
+        retry: None,
+    }
+}
+
+#[tokio::test]
+async fn runs_a_login_script_over_the_devtools_protocol() {
+    let page = Arc::new(Mutex::new(Page::default()));
+    let url = debugger_url(serve(page.clone()).await);
+    let engine = AutomationEngine::new(vec![Arc::new(CdpDriver::new(url))], Arc::new(NoopCaptcha));
+
+    let outcome = engine.run(&login_script()).await.unwrap();
+    assert!(outcome.success, "{outcome:?}");
+    let token = outcome.session_token.unwrap();
+    assert_eq!(token.value, "sid-1");
+    assert_eq!(token.expires_at, Some(COOKIE_EXPIRES));
+
+    let page = page.lock().unwrap();
+    assert_eq!(page.typed[&USER], "alice");
+    assert_eq!(page.typed[&PASS], "hunter2");
+    assert_eq!(page.clicks, [(140.0, 420.0)]);
+    assert_eq!(page.url, "https://mail.example/inbox");
+    assert_eq!(
+        page.methods[..5],
+        [
+            "Page.enable",
+            "Network.enable",
+            "DOM.enable",
+            "Runtime.enable",
+            "Page.navigate"
+        ]
+    );
+    assert_eq!(
+        page.methods
+            .iter()
+            .filter(|method| *method == "DOM.discardSearchResults")
+            .count(),
+        1
+    );
+}
+
+#[tokio::test]
+async fn session_reports_headers_cookies_and_errors() {
+    let page = Arc::new(Mutex::new(Page::default()));
+    let url = debugger_url(serve(page.clone()).await);
+    let mut session = CdpSession::connect(&url).await.unwrap();
+    session
+        .navigate("https://mail.example/login")
+        .await
+        .unwrap();
+
+    assert_eq!(
+        session
+            .captured_headers()
+            .get("x-session")
+            .map(String::as_str),
+        Some("hdr-1")
+    );
+    let header = session
+        .extract_token(&TokenSource::ResponseHeader("X-Session".into()))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(header.value, "hdr-1");
+    assert_eq!(session.session_token().await.unwrap(), None);
+    assert!(session
+        .exists(&Selector::AccessibilityId("Log in".into()))
+        .await
+        .unwrap());
+
+    let missing = session
+        .click(&Selector::Css("#remember-me".into()))
+        .await
+        .unwrap_err();
+    assert_eq!(
+        missing.downcast_ref::<StepError>().map(|err| err.kind),
+        Some(ErrorKind::ElementNotFound)
+    );
+    let unknown = session.call("Bogus.method", json!({})).await.unwrap_err();
+    assert_eq!(
+        unknown.to_string(),
+        "cdp Bogus.method failed: 'Bogus.method' wasn't found"
+    );
+
+    session
+        .click(&Selector::XPath(LOGIN_BUTTON_XPATH.into()))
+        .await
+        .unwrap();
+    assert_eq!(
+        session.session_token().await.unwrap().as_deref(),
+        Some("SID=sid-1; lang=en")
+    );
+    session.health_check().await.unwrap();
+    session.close().await.unwrap();
+}
@@ -0,0 +1,199 @@
+//! Mock DevTools endpoint for the CDP and Android WebView tests: a local
+//! server that lists one page on `/json` and answers DOM/Input/Network/Page
+//! commands for its one-form login page over WebSocket.
+
+use futures::{SinkExt, StreamExt};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio_tungstenite::tungstenite::Message;
+
+pub const LOGIN_BUTTON_XPATH: &str = "//button[text()='Log in']";
+pub const COOKIE_EXPIRES: u64 = 2_000_000_000;
+
+/// Node ids of the login page: user field, password field, login button,
+/// and the logout link shown once logged in.
+pub const USER: i64 = 2;
+pub const PASS: i64 = 3;
+pub const LOGIN: i64 = 4;
+pub const LOGOUT: i64 = 5;
+
+/// What the mock page saw and shows.
+#[derive(Debug, Default)]
+pub struct Page {
+    pub url: String,
+    pub focused: Option<i64>,
+    pub typed: HashMap<i64, String>,
+    pub clicks: Vec<(f64, f64)>,
+    pub logged_in: bool,
+    pub methods: Vec<String>,
+}
+
+impl Page {
+    /// `(x, y, width, height)` of each node.
+    fn rect(node: i64) -> (f64, f64, f64, f64) {
+        (100.0, 100.0 * node as f64, 80.0, 40.0)
+    }
+
+    fn query(&self, css: &str) -> i64 {
+        match css {
+            "#user" => USER,
+            "#pass" => PASS,
+            "[aria-label=\"Log in\"]" => LOGIN,
+            "#logout" if self.logged_in => LOGOUT,
+            _ => 0,
+        }
+    }
+
+    /// Result of `method`, or the CDP error message, and the events it
+    /// triggers.
+    fn handle(&mut self, method: &str, params: &Value) -> (Result<Value, String>, Vec<Value>) {
+        self.methods.push(method.to_string());
+        let mut events = Vec::new();
+        let result = match method {
+            "Page.enable" | "Network.enable" | "DOM.enable" | "Runtime.enable" => Ok(json!({})),
+            "Page.navigate" => {
+                self.url = params["url"].as_str().unwrap_or_default().to_string();
+                events.push(json!({
+                    "method": "Network.responseReceived",
+                    "params": { "response": { "headers": { "X-Session": "hdr-1" } } },
+                }));
+                events.push(json!({ "method": "Page.loadEventFired", "params": {} }));
+                Ok(json!({ "frameId": "main" }))
+            }
+            "DOM.getDocument" => Ok(json!({ "root": { "nodeId": 1 } })),
+            "DOM.querySelector" => {
+                Ok(json!({ "nodeId": self.query(params["selector"].as_str().unwrap_or("")) }))
+            }
+            "DOM.performSearch" => {
+                let count = u32::from(params["query"] == LOGIN_BUTTON_XPATH);
+                Ok(json!({ "searchId": "s1", "resultCount": count }))
+            }
+            "DOM.getSearchResults" => Ok(json!({ "nodeIds": [LOGIN] })),
+            "DOM.discardSearchResults" | "DOM.scrollIntoViewIfNeeded" => Ok(json!({})),
+            "DOM.resolveNode" => Ok(json!({ "object": { "objectId": params["nodeId"] } })),
+            "Runtime.callFunctionOn" => {
+                let node = params["objectId"].as_i64().unwrap_or_default();
+                let text = match node {
+                    LOGIN => "Log in".to_string(),
+                    LOGOUT => "Log out".to_string(),
+                    _ => self.typed.get(&node).cloned().unwrap_or_default(),
+                };
+                Ok(json!({ "result": { "value": text } }))
+            }
+            "DOM.getBoxModel" => {
+                let (x, y, w, h) = Self::rect(params["nodeId"].as_i64().unwrap_or_default());
+                Ok(json!({ "model": { "border": [x, y, x + w, y, x + w, y + h, x, y + h] } }))
+            }
+            "DOM.focus" => {
+                self.focused = params["nodeId"].as_i64();
+                Ok(json!({}))
+            }
+            "Input.dispatchKeyEvent" => {
+                if let (Some(node), Some(text)) = (self.focused, params["text"].as_str()) {
+                    self.typed.entry(node).or_default().push_str(text);
+                }
+                Ok(json!({}))
+            }
+            "Input.dispatchMouseEvent" => {
+                if params["type"] == "mouseReleased" {
+                    let point = (params["x"].as_f64().unwrap(), params["y"].as_f64().unwrap());
+                    self.clicks.push(point);
+                    let (x, y, w, h) = Self::rect(LOGIN);
+                    if point == (x + w / 2.0, y + h / 2.0) {
+                        self.logged_in = true;
+                        self.url = "https://mail.example/inbox".into();
+                    }
+                }
+                Ok(json!({}))
+            }
+            "Network.getCookies" => Ok(if self.logged_in {
+                json!({ "cookies": [
+                    { "name": "SID", "value": "sid-1", "expires": COOKIE_EXPIRES as f64 },
+                    { "name": "lang", "value": "en", "expires": -1 },
+                ] })
+            } else {
+                json!({ "cookies": [] })
+            }),
+            "Runtime.evaluate" => match params["expression"].as_str() {
+                Some("location.href") => Ok(json!({ "result": { "value": self.url } })),
+                Some("1") => Ok(json!({ "result": { "value": 1 } })),
+                _ => Err("unsupported expression".to_string()),
+            },
+            _ => Err(format!("'{method}' wasn't found")),
+        };
+        (result, events)
+    }
+}
+
+/// WebSocket endpoint of the page served on `addr`.
+pub fn debugger_url(addr: SocketAddr) -> String {
+    format!("ws://{addr}/devtools/page/main")
+}
+
+/// Serve `page` to every WebSocket client, and its target list on
+/// `GET /json` as a DevTools port does; returns the listening address.
+pub async fn serve(page: Arc<Mutex<Page>>) -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            let page = page.clone();
+            tokio::spawn(async move {
+                let mut start = [0; 9];
+                let read = stream.peek(&mut start).await.unwrap();
+                if &start[..read] == b"GET /json" {
+                    return list_targets(stream, addr).await;
+                }
+                let mut socket = tokio_tungstenite::accept_async(stream).await.unwrap();
+                while let Some(Ok(Message::Text(text))) = socket.next().await {
+                    let command: Value = serde_json::from_str(&text).unwrap();
+                    let method = command["method"].as_str().unwrap_or_default();
+                    let (result, events) = page.lock().unwrap().handle(method, &command["params"]);
+                    let reply = match result {
+                        Ok(result) => json!({ "id": command["id"], "result": result }),
+                        Err(message) => json!({
+                            "id": command["id"],
+                            "error": { "code": -32601, "message": message },
+                        }),
+                    };
+                    // Events come before the reply, as Chromium often sends them.
+                    for frame in events.into_iter().chain([reply]) {
+                        socket
+                            .send(Message::Text(frame.to_string().into()))
+                            .await
+                            .unwrap();
+                    }
+                }
+            });
+        }
+    });
+    addr
+}
+
+/// Answer a `/json` request with the page and a worker that cannot be
+/// debugged as a page.
+async fn list_targets(mut stream: TcpStream, addr: SocketAddr) {
+    let mut request = Vec::new();
+    let mut buf = [0; 1024];
+    while !request.ends_with(b"\r\n\r\n") {
+        match stream.read(&mut buf).await {
+            Ok(0) | Err(_) => return,
+            Ok(read) => request.extend_from_slice(&buf[..read]),
+        }
+    }
+    let body = json!([
+        { "type": "service_worker", "webSocketDebuggerUrl": format!("ws://{addr}/devtools/sw") },
+        { "type": "page", "url": "https://mail.example/login", "webSocketDebuggerUrl": debugger_url(addr) },
+    ])
+    .to_string();
+    let response = format!(
+        "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    );
+    let _ = stream.write_all(response.as_bytes()).await;
+    let _ = stream.shutdown().await;
+}
@@ -0,0 +1,239 @@
+//! Fake driver shared by the engine tests. Its sessions accept every
+//! selector, record what they were asked to do in a [`FakeState`] the test
+//! keeps, and fail on demand.
+
+#![allow(dead_code)]
+
+pub mod devtools;
+
+use async_trait::async_trait;
+use automation_engine::trace::{SnapshotFormat, UiSnapshot};
+use automation_engine::{
+    AutomationDriver, DriverSession, ErrorKind, LoginScript, ScriptMeta, Selector, Step, StepError,
+    TargetApp, TargetAppKind,
+};
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+/// What the sessions of one or more fake drivers saw, and the failures they
+/// still have to produce.
+#[derive(Debug, Default)]
+pub struct FakeState {
+    /// Target name and time of every opened session, in order.
+    pub opened: Vec<(String, Instant)>,
+    pub open: usize,
+    pub max_open: usize,
+    /// Sessions open per driver, and the most seen at once.
+    pub open_by_driver: HashMap<&'static str, usize>,
+    pub max_by_driver: HashMap<&'static str, usize>,
+    pub closed: usize,
+    pub resets: usize,
+    pub health_checks: usize,
+    /// `click`/`input` calls, as `"click <selector>"` and `"input <text>"`.
+    pub actions: Vec<String>,
+    /// Kinds the next clicks fail with, one per click.
+    pub failing_clicks: VecDeque<ErrorKind>,
+    /// Selectors that do not exist.
+    pub absent: Vec<Selector>,
+    pub fail_reset: bool,
+    pub fail_health_check: bool,
+}
+
+pub type Shared = Arc<Mutex<FakeState>>;
+
+pub struct FakeDriver {
+    name: &'static str,
+    kind: TargetAppKind,
+    poolable: bool,
+    state: Shared,
+}
+
+impl FakeDriver {
+    pub fn new(name: &'static str, kind: TargetAppKind) -> Self {
+        Self::with_state(name, kind, Shared::default())
+    }
+
+    /// Driver recording into `state`, e.g. one shared with another driver.
+    pub fn with_state(name: &'static str, kind: TargetAppKind, state: Shared) -> Self {
+        Self {
+            name,
+            kind,
+            poolable: false,
+            state,
+        }
+    }
+
+    /// Let a `SessionPool` reset and reuse the sessions.
+    pub fn poolable(mut self) -> Self {
+        self.poolable = true;
+        self
+    }
+
+    pub fn state(&self) -> Shared {
+        self.state.clone()
+    }
+}
+
+#[async_trait]
+impl AutomationDriver for FakeDriver {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn supports(&self, target: &TargetApp) -> bool {
+        target.kind == self.kind
+    }
+
+    fn poolable(&self) -> bool {
+        self.poolable
+    }
+
+    async fn open_session(&self, target: &TargetApp) -> anyhow::Result<Box<dyn DriverSession>> {
+        let mut state = self.state.lock().unwrap();
+        state.opened.push((target.name.clone(), Instant::now()));
+        state.open += 1;
+        state.max_open = state.max_open.max(state.open);
+        let open = state.open_by_driver.entry(self.name).or_default();
+        *open += 1;
+        let open = *open;
+        let max = state.max_by_driver.entry(self.name).or_default();
+        *max = (*max).max(open);
+        Ok(Box::new(FakeSession {
+            driver: self.name,
+            state: self.state.clone(),
+        }))
+    }
+}
+
+pub struct FakeSession {
+    driver: &'static str,
+    state: Shared,
+}
+
+impl FakeSession {
+    fn require(&self, selector: &Selector) -> anyhow::Result<()> {
+        if self.state.lock().unwrap().absent.contains(selector) {
+            return Err(StepError::not_found(selector).into());
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl DriverSession for FakeSession {
+    async fn exists(&mut self, selector: &Selector) -> anyhow::Result<bool> {
+        Ok(!self.state.lock().unwrap().absent.contains(selector))
+    }
+
+    async fn click(&mut self, selector: &Selector) -> anyhow::Result<()> {
+        self.require(selector)?;
+        let mut state = self.state.lock().unwrap();
+        state.actions.push(format!("click {selector:?}"));
+        match state.failing_clicks.pop_front() {
+            Some(kind) => Err(StepError::new(kind, format!("click on {selector:?} failed")).into()),
+            None => Ok(()),
+        }
+    }
+
+    async fn input(&mut self, selector: &Selector, text: &str) -> anyhow::Result<()> {
+        self.require(selector)?;
+        self.state
+            .lock()
+            .unwrap()
+            .actions
+            .push(format!("input {text}"));
+        Ok(())
+    }
+
+    async fn swipe(
+        &mut self,
+        from: &Selector,
+        to: &Selector,
+        _duration_ms: u64,
+    ) -> anyhow::Result<()> {
+        self.require(from)?;
+        self.require(to)
+    }
+
+    async fn text(&mut self, selector: &Selector) -> anyhow::Result<Option<String>> {
+        let exists = self.exists(selector).await?;
+        Ok(exists.then(|| format!("text of {selector:?}")))
+    }
+
+    async fn capture(&mut self, selector: &Selector) -> anyhow::Result<Vec<u8>> {
+        self.require(selector)?;
+        Ok(b"captcha".to_vec())
+    }
+
+    async fn screenshot(&mut self) -> anyhow::Result<Vec<u8>> {
+        Ok(b"\x89PNG".to_vec())
+    }
+
+    async fn snapshot(&mut self) -> anyhow::Result<Option<UiSnapshot>> {
+        Ok(Some(UiSnapshot {
+            format: SnapshotFormat::Html,
+            content: "<html></html>".into(),
+        }))
+    }
+
+    async fn session_token(&mut self) -> anyhow::Result<Option<String>> {
+        Ok(Some(format!("{}-token", self.driver)))
+    }
+
+    async fn health_check(&mut self) -> anyhow::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        state.health_checks += 1;
+        if state.fail_health_check {
+            anyhow::bail!("session of {} is gone", self.driver);
+        }
+        Ok(())
+    }
+
+    async fn reset(&mut self, _target: &TargetApp) -> anyhow::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        state.resets += 1;
+        if state.fail_reset {
+            anyhow::bail!("reset of {} failed", self.driver);
+        }
+        Ok(())
+    }
+
+    async fn close(&mut self) -> anyhow::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        state.open -= 1;
+        state.closed += 1;
+        if let Some(open) = state.open_by_driver.get_mut(self.driver) {
+            *open -= 1;
+        }
+        Ok(())
+    }
+}
+
+/// Script `id` against the target `name` of `kind`.
+pub fn script(id: &str, kind: TargetAppKind, name: &str, steps: Vec<Step>) -> LoginScript {
+    LoginScript {
+        meta: ScriptMeta {
+            id: id.into(),
+            version: "1.0.0".into(),
+            author: None,
+            created_at: None,
+            updated_at: None,
+        },
+        target: TargetApp {
+            kind,
+            name: name.into(),
+            version: None,
+            endpoint: None,
+        },
+        steps,
+        validations: Vec::new(),
+        error_handlers: Vec::new(),
+        token: None,
+        retry: None,
+    }
+}
+
+pub fn css(selector: &str) -> Selector {
+    Selector::Css(selector.into())
+}
@@ -0,0 +1,300 @@
+//! Order of the `ExecutionEvent`s a run emits, as subscribers (UI, logs,
+//! trace writers) see them.
+
+mod common;
+
+use automation_engine::{
+    AutomationEngine, Backoff, Condition, ErrorHandler, ErrorKind, ExecutionEvent,
+    ExecutionEventKind, NoopCaptcha, RetryPolicy, RunControl, RunId, Step, StepPath, TargetAppKind,
+    TraceConfig, TraceMode, Validation,
+};
+use common::{css, script, FakeDriver, Shared};
+use std::sync::Arc;
+use tokio::sync::broadcast;
+
+fn engine() -> (AutomationEngine, Shared) {
+    let driver = FakeDriver::new("web-fake", TargetAppKind::Web);
+    let state = driver.state();
+    let engine = AutomationEngine::new(vec![Arc::new(driver)], Arc::new(NoopCaptcha));
+    (engine, state)
+}
+
+/// Everything queued on `events`, without waiting for more.
+fn drain(events: &mut broadcast::Receiver<ExecutionEvent>) -> Vec<ExecutionEvent> {
+    std::iter::from_fn(|| events.try_recv().ok()).collect()
+}
+
+/// One line per event, with what the order depends on.
+fn summary(events: &[ExecutionEvent]) -> Vec<String> {
+    events
+        .iter()
+        .map(|event| match &event.kind {
+            ExecutionEventKind::RunStarted { script_id, .. } => format!("run_started {script_id}"),
+            ExecutionEventKind::StepStarted { path, step } => format!("started {path} {step}"),
+            ExecutionEventKind::StepFinished { path, error, .. } => match error {
+                Some(_) => format!("failed {path}"),
+                None => format!("finished {path}"),
+            },
+            ExecutionEventKind::SelectorResolved { path, .. } => format!("resolved {path}"),
+            ExecutionEventKind::BranchTaken { path, condition } => {
+                format!("branch {path} {condition}")
+            }
+            ExecutionEventKind::HandlerInvoked { handler, .. } => format!("handler {handler}"),
+            // The root path, i.e. the whole run, prints as nothing.
+            ExecutionEventKind::Retry { path, attempt, .. } if *path == StepPath::root() => {
+                format!("retry run {attempt}")
+            }
+            ExecutionEventKind::Retry { path, attempt, .. } => format!("retry {path} {attempt}"),
+            ExecutionEventKind::Paused { path } => format!("paused {path}"),
+            ExecutionEventKind::Resumed { path } => format!("resumed {path}"),
+            ExecutionEventKind::ValidationResult {
+                description,
+                passed,
+            } => format!("validation {description} {passed}"),
+            ExecutionEventKind::TraceSaved { .. } => "trace_saved".into(),
+            ExecutionEventKind::RunFinished { success, .. } => format!("run_finished {success}"),
+            other => format!("{other:?}"),
+        })
+        .collect()
+}
+
+fn of_run(events: &[ExecutionEvent], run_id: RunId) -> Vec<ExecutionEvent> {
+    events
+        .iter()
+        .filter(|event| event.run_id == run_id)
+        .cloned()
+        .collect()
+}
+
+#[tokio::test]
+async fn steps_nest_inside_their_parents_and_the_run() {
+    let (mut engine, _) = engine();
+    let directory = std::env::temp_dir().join(format!("engine-events-{}", std::process::id()));
+    engine.set_trace_config(TraceConfig {
+        mode: TraceMode::Always,
+        directory: directory.clone(),
+        skip_screenshots: true,
+    });
+    let mut events = engine.subscribe();
+    let mut login = script(
+        "mail",
+        TargetAppKind::Web,
+        "mail",
+        vec![
+            Step::WaitFor(css("#user")),
+            Step::Conditional {
+                condition: Condition::Exists(css("#remember")),
+                on_true: vec![Step::Click(css("#remember"))],
+                on_false: vec![],
+            },
+            Step::Click(css("#login")),
+        ],
+    );
+    login.validations.push(Validation {
+        description: "inbox".into(),
+        condition: Condition::Exists(css("#inbox")),
+    });
+
+    assert!(engine.run(&login).await.unwrap().success);
+    let events = drain(&mut events);
+    std::fs::remove_dir_all(&directory).unwrap();
+    assert_eq!(
+        summary(&events),
+        [
+            "run_started mail",
+            "started steps[0] wait_for",
+            "resolved steps[0]",
+            "finished steps[0]",
+            "started steps[1] conditional",
+            "branch steps[1] true",
+            "started steps[1].on_true[0] click",
+            "resolved steps[1].on_true[0]",
+            "finished steps[1].on_true[0]",
+            "finished steps[1]",
+            "started steps[2] click",
+            "resolved steps[2]",
+            "finished steps[2]",
+            "validation inbox true",
+            "trace_saved",
+            "run_finished true",
+        ]
+    );
+    assert!(events
+        .windows(2)
+        .all(|pair| pair[0].timestamp_ms <= pair[1].timestamp_ms));
+}
+
+#[tokio::test]
+async fn handlers_run_after_the_failed_step_and_before_the_run_ends() {
+    let (engine, state) = engine();
+    state.lock().unwrap().absent.push(css("#banner"));
+    state
+        .lock()
+        .unwrap()
+        .failing_clicks
+        .push_back(ErrorKind::Other);
+    let mut events = engine.subscribe();
+    let mut login = script(
+        "mail",
+        TargetAppKind::Web,
+        "mail",
+        vec![Step::Click(css("#login")), Step::Click(css("#never"))],
+    );
+    login.error_handlers = vec![
+        ErrorHandler {
+            name: "dismiss".into(),
+            on_error: vec![Step::Conditional {
+                condition: Condition::Exists(css("#banner")),
+                on_true: vec![Step::Click(css("#banner"))],
+                on_false: vec![],
+            }],
+        },
+        ErrorHandler {
+            name: "broken".into(),
+            on_error: vec![Step::Click(css("#banner"))],
+        },
+    ];
+
+    let outcome = engine.run(&login).await.unwrap();
+    assert!(!outcome.success);
+    assert_eq!(
+        summary(&drain(&mut events)),
+        [
+            "run_started mail",
+            "started steps[0] click",
+            "failed steps[0]",
+            "started error_handlers[0].on_error[0] conditional",
+            "branch error_handlers[0].on_error[0] false",
+            "finished error_handlers[0].on_error[0]",
+            "handler dismiss",
+            "started error_handlers[1].on_error[0] click",
+            "failed error_handlers[1].on_error[0]",
+            "handler broken",
+            "run_finished false",
+        ]
+    );
+}
+
+#[tokio::test]
+async fn retried_runs_report_each_attempt_between_one_start_and_finish() {
+    let (engine, state) = engine();
+    state
+        .lock()
+        .unwrap()
+        .failing_clicks
+        .push_back(ErrorKind::Network);
+    let mut events = engine.subscribe();
+    let mut login = script(
+        "mail",
+        TargetAppKind::Web,
+        "mail",
+        vec![Step::Click(css("#login"))],
+    );
+    login.retry = Some(RetryPolicy {
+        max_attempts: 2,
+        retry_on: vec![ErrorKind::Network],
+        backoff: Backoff {
+            initial_ms: 1,
+            max_ms: 1,
+            multiplier: 1.0,
+            jitter: 0.0,
+        },
+    });
+
+    assert!(engine.run(&login).await.unwrap().success);
+    assert_eq!(
+        summary(&drain(&mut events)),
+        [
+            "run_started mail",
+            "started steps[0] click",
+            "failed steps[0]",
+            "retry run 2",
+            "started steps[0] click",
+            "resolved steps[0]",
+            "finished steps[0]",
+            "run_finished true",
+        ]
+    );
+}
+
+#[tokio::test]
+async fn a_paused_run_reports_where_it_waits() {
+    let (engine, _) = engine();
+    let engine = Arc::new(engine);
+    let mut events = engine.subscribe();
+    let login = script(
+        "mail",
+        TargetAppKind::Web,
+        "mail",
+        vec![Step::Click(css("#login"))],
+    );
+    let control = RunControl::new();
+    control.pause();
+    let run = tokio::spawn({
+        let (engine, control) = (engine.clone(), control.clone());
+        async move { engine.run_with(&login, control).await }
+    });
+
+    let mut seen = Vec::new();
+    while !matches!(
+        seen.last().map(|event: &ExecutionEvent| &event.kind),
+        Some(ExecutionEventKind::Paused { .. })
+    ) {
+        seen.push(events.recv().await.unwrap());
+    }
+    control.resume();
+    assert!(run.await.unwrap().unwrap().success);
+    seen.extend(drain(&mut events));
+    assert_eq!(
+        summary(&seen),
+        [
+            "run_started mail",
+            "paused steps[0]",
+            "resumed steps[0]",
+            "started steps[0] click",
+            "resolved steps[0]",
+            "finished steps[0]",
+            "run_finished true",
+        ]
+    );
+}
+
+#[tokio::test]
+async fn concurrent_runs_keep_their_own_order() {
+    let (engine, _) = engine();
+    let mut events = engine.subscribe();
+    let steps = || {
+        vec![
+            Step::WaitFor(css("#user")),
+            Step::Click(css("#next")),
+            Step::Click(css("#login")),
+        ]
+    };
+    let mail = script("mail", TargetAppKind::Web, "mail", steps());
+    let shop = script("shop", TargetAppKind::Web, "shop", steps());
+
+    let (a, b) = tokio::join!(engine.run(&mail), engine.run(&shop));
+    assert!(a.unwrap().success && b.unwrap().success);
+    let events = drain(&mut events);
+    let mut run_ids: Vec<RunId> = events.iter().map(|event| event.run_id).collect();
+    run_ids.sort();
+    run_ids.dedup();
+    assert_eq!(run_ids.len(), 2);
+    let runs: Vec<Vec<String>> = run_ids
+        .iter()
+        .map(|run_id| summary(&of_run(&events, *run_id)))
+        .collect();
+    for run in &runs {
+        assert_eq!(run.len(), 11, "{run:?}");
+        assert!(run[0].starts_with("run_started "));
+        assert_eq!(
+            run[1..10]
+                .iter()
+                .filter(|line| line.starts_with("started"))
+                .count(),
+            3
+        );
+        assert_eq!(run[10], "run_finished true");
+    }
+    assert_ne!(runs[0][0], runs[1][0]);
+}
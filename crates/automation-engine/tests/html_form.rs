@@ -0,0 +1,318 @@
+//! HTML form driver against a local HTTP server that serves the pages in
+//! `fixtures/html_form/`.
+
+use automation_engine::html_form::HtmlFormSession;
+use automation_engine::{
+    AutomationEngine, Condition, DriverSession, ErrorKind, HtmlFormDriver, LoginScript,
+    NoopCaptcha, ScriptMeta, Selector, Step, StepError, TargetApp, TargetAppKind, TokenRule,
+    TokenSource, Validation, ValueRef,
+};
+use reqwest::Url;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+
+const SESSION: &str = "s-42";
+
+fn fixture(name: &str) -> String {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("fixtures/html_form")
+        .join(name);
+    std::fs::read_to_string(&path).unwrap_or_else(|err| panic!("{}: {err}", path.display()))
+}
+
+/// A request as the server saw it.
+#[derive(Debug, Clone)]
+struct Request {
+    method: String,
+    path: String,
+    cookie: String,
+    body: String,
+}
+
+impl Request {
+    fn form(&self) -> Vec<(String, String)> {
+        url_decode(&self.body)
+    }
+}
+
+fn url_decode(body: &str) -> Vec<(String, String)> {
+    Url::parse(&format!("http://form/?{body}"))
+        .unwrap()
+        .query_pairs()
+        .map(|(name, value)| (name.into_owned(), value.into_owned()))
+        .collect()
+}
+
+/// Mail app: `/login` shows the form, a correct `POST /session` sets the
+/// session cookie and redirects to `/inbox`, which needs that cookie.
+/// `/broken` fails with a 500. Every request is recorded.
+async fn serve(requests: Arc<Mutex<Vec<Request>>>) -> Url {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let base = Url::parse(&format!("http://{}/", listener.local_addr().unwrap())).unwrap();
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            let requests = requests.clone();
+            tokio::spawn(async move {
+                let mut stream = BufReader::new(stream);
+                let Some(request) = read_request(&mut stream).await else {
+                    return;
+                };
+                requests.lock().unwrap().push(request.clone());
+                respond(stream.get_mut(), &request).await;
+            });
+        }
+    });
+    base
+}
+
+async fn read_request(stream: &mut BufReader<TcpStream>) -> Option<Request> {
+    let mut line = String::new();
+    stream.read_line(&mut line).await.ok()?;
+    let mut parts = line.split_whitespace();
+    let (method, path) = (parts.next()?.to_string(), parts.next()?.to_string());
+    let (mut cookie, mut length) = (String::new(), 0);
+    loop {
+        let mut header = String::new();
+        stream.read_line(&mut header).await.ok()?;
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        let (name, value) = header.split_once(':')?;
+        match name.to_ascii_lowercase().as_str() {
+            "cookie" => cookie = value.trim().to_string(),
+            "content-length" => length = value.trim().parse().ok()?,
+            _ => {}
+        }
+    }
+    let mut body = vec![0; length];
+    stream.read_exact(&mut body).await.ok()?;
+    Some(Request {
+        method,
+        path,
+        cookie,
+        body: String::from_utf8(body).ok()?,
+    })
+}
+
+async fn respond(stream: &mut TcpStream, request: &Request) {
+    let logged_in = request.cookie.contains(&format!("SID={SESSION}"));
+    let login_page = |error: &str| fixture("login.html").replace("{error}", error);
+    let (status, headers, body) = match (request.method.as_str(), request.path.as_str()) {
+        ("GET", "/login") => ("200 OK", Vec::new(), login_page("")),
+        ("POST", "/session") => {
+            let form = request.form();
+            let field = |name: &str| {
+                form.iter()
+                    .find(|(field, _)| field == name)
+                    .map(|(_, value)| value.as_str())
+            };
+            if field("csrf") == Some("tok-csrf")
+                && field("user") == Some("alice")
+                && field("password") == Some("hunter2")
+            {
+                (
+                    "303 See Other",
+                    vec![
+                        format!("Set-Cookie: SID={SESSION}; Path=/; HttpOnly"),
+                        "Location: /inbox".to_string(),
+                    ],
+                    String::new(),
+                )
+            } else {
+                ("200 OK", Vec::new(), login_page("Wrong email or password"))
+            }
+        }
+        ("GET", "/inbox") if logged_in => (
+            "200 OK",
+            vec!["X-Session: hdr-7".into()],
+            fixture("inbox.html"),
+        ),
+        ("GET", "/inbox") => ("302 Found", vec!["Location: /login".into()], String::new()),
+        ("GET", "/broken") => ("500 Internal Server Error", Vec::new(), "oops".into()),
+        _ => ("404 Not Found", Vec::new(), String::new()),
+    };
+    let mut response = format!(
+        "HTTP/1.1 {status}\r\nContent-Type: text/html\r\nContent-Length: {}\r\nConnection: close\r\n",
+        body.len()
+    );
+    for header in headers {
+        response.push_str(&header);
+        response.push_str("\r\n");
+    }
+    response.push_str("\r\n");
+    response.push_str(&body);
+    let _ = stream.write_all(response.as_bytes()).await;
+    let _ = stream.shutdown().await;
+}
+
+fn login_script(base: &Url, password: &str) -> LoginScript {
+    LoginScript {
+        meta: ScriptMeta {
+            id: "mail-form".into(),
+            version: "1.0.0".into(),
+            author: None,
+            created_at: None,
+            updated_at: None,
+        },
+        target: TargetApp {
+            kind: TargetAppKind::Web,
+            name: "mail".into(),
+            version: None,
+            endpoint: Some(base.join("/login").unwrap().to_string()),
+        },
+        steps: vec![
+            Step::WaitFor(Selector::Css("#login".into())),
+            Step::Input {
+                selector: Selector::Css("#user".into()),
+                value: ValueRef::Literal("alice".into()),
+            },
+            Step::Input {
+                selector: Selector::XPath("//input[@type='password']".into()),
+                value: ValueRef::Literal(password.into()),
+            },
+            Step::Click(Selector::Css("#remember".into())),
+            Step::Click(Selector::XPath("//button[text()='Log in']".into())),
+        ],
+        validations: vec![
+            Validation {
+                description: "inbox".into(),
+                condition: Condition::UrlContains("/inbox".into()),
+            },
+            Validation {
+                description: "inbox heading".into(),
+                condition: Condition::TextEquals {
+                    selector: Selector::Css("h1".into()),
+                    expected: "Inbox".into(),
+                },
+            },
+        ],
+        error_handlers: Vec::new(),
+        token: Some(TokenRule {
+            source: TokenSource::Cookie("SID".into()),
+            ttl_secs: None,
+        }),
+        retry: None,
+    }
+}
+
+fn engine() -> AutomationEngine {
+    AutomationEngine::new(
+        vec![Arc::new(HtmlFormDriver::new().with_session_cookie("SID"))],
+        Arc::new(NoopCaptcha),
+    )
+}
+
+#[tokio::test]
+async fn posts_the_login_form_and_follows_the_redirect() {
+    let requests = Arc::new(Mutex::new(Vec::new()));
+    let base = serve(requests.clone()).await;
+
+    let outcome = engine().run(&login_script(&base, "hunter2")).await.unwrap();
+    assert!(outcome.success, "{outcome:?}");
+    assert_eq!(outcome.session_token.unwrap().value, SESSION);
+
+    let requests = requests.lock().unwrap();
+    let paths: Vec<_> = requests
+        .iter()
+        .map(|request| format!("{} {}", request.method, request.path))
+        .collect();
+    assert_eq!(paths, ["GET /login", "POST /session", "GET /inbox"]);
+    // Only the login form, without disabled fields or unclicked buttons.
+    assert_eq!(
+        requests[1].form(),
+        [
+            ("csrf", "tok-csrf"),
+            ("user", "alice"),
+            ("password", "hunter2"),
+            ("remember", "on"),
+            ("action", "login"),
+        ]
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+    );
+    assert_eq!(requests[2].cookie, format!("SID={SESSION}"));
+}
+
+#[tokio::test]
+async fn wrong_password_stays_on_the_form() {
+    let requests = Arc::new(Mutex::new(Vec::new()));
+    let base = serve(requests.clone()).await;
+
+    let outcome = engine().run(&login_script(&base, "nope")).await.unwrap();
+    assert!(!outcome.success);
+    assert_eq!(
+        outcome.error_kind,
+        Some(ErrorKind::Validation),
+        "{outcome:?}"
+    );
+    assert!(outcome.session_token.is_none());
+    assert_eq!(requests.lock().unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn session_reads_pages_headers_and_errors() {
+    let requests = Arc::new(Mutex::new(Vec::new()));
+    let base = serve(requests.clone()).await;
+    let mut session = HtmlFormSession::new(None).unwrap();
+    session.get(base.join("/login").unwrap()).await.unwrap();
+
+    assert_eq!(
+        session
+            .text(&Selector::Css("#search input".into()))
+            .await
+            .unwrap(),
+        Some("inbox".into())
+    );
+    let help = session
+        .click(&Selector::Css("button[name=help]".into()))
+        .await
+        .unwrap_err();
+    assert!(help.to_string().contains("cannot click <button>"), "{help}");
+    let missing = session
+        .input(&Selector::Css("#otp".into()), "123456")
+        .await
+        .unwrap_err();
+    assert_eq!(
+        missing.downcast_ref::<StepError>().map(|err| err.kind),
+        Some(ErrorKind::ElementNotFound)
+    );
+
+    session
+        .input(&Selector::Css("#user".into()), "alice")
+        .await
+        .unwrap();
+    session
+        .input(&Selector::Css("[name=password]".into()), "hunter2")
+        .await
+        .unwrap();
+    session
+        .click(&Selector::Css("button[name=action]".into()))
+        .await
+        .unwrap();
+    assert_eq!(session.current_url().path(), "/inbox");
+    assert_eq!(
+        session.session_token().await.unwrap(),
+        Some(format!("SID={SESSION}"))
+    );
+    let header = session
+        .extract_token(&TokenSource::ResponseHeader("X-Session".into()))
+        .await
+        .unwrap();
+    assert_eq!(header.map(|token| token.value).as_deref(), Some("hdr-7"));
+    session
+        .click(&Selector::Css("#logout".into()))
+        .await
+        .unwrap();
+    assert_eq!(session.current_url().path(), "/logout");
+
+    let broken = session
+        .get(base.join("/broken").unwrap())
+        .await
+        .unwrap_err();
+    assert_eq!(
+        broken.downcast_ref::<StepError>().map(|err| err.kind),
+        Some(ErrorKind::Network)
+    );
+}
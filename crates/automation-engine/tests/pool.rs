@@ -0,0 +1,183 @@
+//! `SessionPool` reuse, recycling and metrics, through the engine and on its
+//! own, with the fake driver's sessions.
+
+mod common;
+
+use automation_engine::{
+    AutomationEngine, NoopCaptcha, PoolConfig, PoolMetrics, SessionPool, Step, TargetAppKind,
+};
+use common::{css, script, FakeDriver, Shared};
+use std::sync::Arc;
+use std::time::Duration;
+
+fn engine(driver: FakeDriver, config: PoolConfig) -> (AutomationEngine, Arc<SessionPool>, Shared) {
+    let state = driver.state();
+    let pool = Arc::new(SessionPool::new(config));
+    let mut engine = AutomationEngine::new(vec![Arc::new(driver)], Arc::new(NoopCaptcha));
+    engine.set_session_pool(pool.clone());
+    (engine, pool, state)
+}
+
+fn poolable() -> FakeDriver {
+    FakeDriver::new("web-fake", TargetAppKind::Web).poolable()
+}
+
+fn metrics(hits: u64, misses: u64, evictions: u64) -> PoolMetrics {
+    PoolMetrics {
+        hits,
+        misses,
+        evictions,
+    }
+}
+
+async fn login(engine: &AutomationEngine, target: &str) -> bool {
+    let login = script(
+        "login",
+        TargetAppKind::Web,
+        target,
+        vec![Step::Click(css("#login"))],
+    );
+    engine.run(&login).await.unwrap().success
+}
+
+#[tokio::test]
+async fn warm_sessions_are_reset_and_reused_per_target() {
+    let (engine, pool, state) = engine(poolable(), PoolConfig::default());
+
+    assert!(login(&engine, "mail").await);
+    assert!(login(&engine, "mail").await);
+    assert_eq!(pool.metrics(), metrics(1, 1, 0));
+    {
+        let state = state.lock().unwrap();
+        assert_eq!(state.opened.len(), 1);
+        // Reset after every run, health-checked before reuse.
+        assert_eq!(state.resets, 2);
+        assert_eq!(state.health_checks, 1);
+        assert_eq!(state.closed, 0);
+    }
+
+    // Another target never gets the mail session.
+    assert!(login(&engine, "shop").await);
+    assert_eq!(pool.metrics(), metrics(1, 2, 0));
+    assert_eq!(pool.idle_count(), 2);
+
+    pool.drain().await;
+    assert_eq!(pool.idle_count(), 0);
+    assert_eq!(pool.metrics(), metrics(1, 2, 2));
+    assert_eq!(state.lock().unwrap().open, 0);
+}
+
+#[tokio::test]
+async fn sessions_are_recycled_after_max_uses() {
+    let config = PoolConfig {
+        max_uses: 2,
+        ..PoolConfig::default()
+    };
+    let (engine, pool, state) = engine(poolable(), config);
+
+    for _ in 0..3 {
+        assert!(login(&engine, "mail").await);
+    }
+    // Runs 1 and 2 share a session, which is then closed unreset; run 3
+    // opens a fresh one.
+    assert_eq!(pool.metrics(), metrics(1, 2, 1));
+    let state = state.lock().unwrap();
+    assert_eq!(state.opened.len(), 2);
+    assert_eq!(state.closed, 1);
+    assert_eq!(state.resets, 2);
+}
+
+#[tokio::test]
+async fn idle_sessions_expire() {
+    let config = PoolConfig {
+        idle_timeout: Duration::from_millis(20),
+        ..PoolConfig::default()
+    };
+    let (engine, pool, state) = engine(poolable(), config);
+
+    assert!(login(&engine, "mail").await);
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert!(login(&engine, "mail").await);
+
+    assert_eq!(pool.metrics(), metrics(0, 2, 1));
+    let state = state.lock().unwrap();
+    assert_eq!(state.opened.len(), 2);
+    // Expired sessions are closed without a health check.
+    assert_eq!(state.health_checks, 0);
+    assert_eq!(state.closed, 1);
+}
+
+#[tokio::test]
+async fn failed_runs_and_resets_close_the_session() {
+    let (engine, pool, state) = engine(poolable(), PoolConfig::default());
+
+    state.lock().unwrap().absent.push(css("#login"));
+    assert!(!login(&engine, "mail").await);
+    assert_eq!(pool.metrics(), metrics(0, 1, 1));
+    assert_eq!(pool.idle_count(), 0);
+    // A failed run is not worth resetting.
+    assert_eq!(state.lock().unwrap().resets, 0);
+
+    {
+        let mut state = state.lock().unwrap();
+        state.absent.clear();
+        state.fail_reset = true;
+    }
+    assert!(login(&engine, "mail").await);
+    assert_eq!(pool.metrics(), metrics(0, 2, 2));
+    assert_eq!(pool.idle_count(), 0);
+    let state = state.lock().unwrap();
+    assert_eq!(state.resets, 1);
+    assert_eq!(state.open, 0);
+}
+
+#[tokio::test]
+async fn unhealthy_sessions_are_replaced() {
+    let (engine, pool, state) = engine(poolable(), PoolConfig::default());
+
+    assert!(login(&engine, "mail").await);
+    state.lock().unwrap().fail_health_check = true;
+    assert!(login(&engine, "mail").await);
+
+    assert_eq!(pool.metrics(), metrics(0, 2, 1));
+    let state = state.lock().unwrap();
+    assert_eq!(state.health_checks, 1);
+    assert_eq!(state.opened.len(), 2);
+    assert_eq!(state.closed, 1);
+}
+
+#[tokio::test]
+async fn extra_sessions_beyond_the_idle_limit_are_closed() {
+    let config = PoolConfig {
+        max_idle_per_target: 1,
+        ..PoolConfig::default()
+    };
+    let pool = SessionPool::new(config);
+    let driver = poolable();
+    let state = driver.state();
+    let target = script("login", TargetAppKind::Web, "mail", Vec::new()).target;
+
+    let first = pool.acquire(&driver, &target).await.unwrap();
+    let second = pool.acquire(&driver, &target).await.unwrap();
+    pool.release(first, &target, true).await;
+    pool.release(second, &target, true).await;
+
+    assert_eq!(pool.idle_count(), 1);
+    assert_eq!(pool.metrics(), metrics(0, 2, 1));
+    assert_eq!(state.lock().unwrap().open, 1);
+}
+
+#[tokio::test]
+async fn drivers_that_are_not_poolable_bypass_the_pool() {
+    let driver = FakeDriver::new("web-fake", TargetAppKind::Web);
+    let (engine, pool, state) = engine(driver, PoolConfig::default());
+
+    assert!(login(&engine, "mail").await);
+    assert!(login(&engine, "mail").await);
+
+    assert_eq!(pool.metrics(), PoolMetrics::default());
+    let state = state.lock().unwrap();
+    assert_eq!(state.opened.len(), 2);
+    assert_eq!(state.closed, 2);
+    assert_eq!(state.resets, 0);
+}
@@ -0,0 +1,222 @@
+//! Retry policies, backoff and the circuit breaker, on their own and as the
+//! engine records them in `LoginOutcome::attempts`.
+
+mod common;
+
+use automation_engine::{
+    AttemptScope, AutomationEngine, Backoff, CircuitBreaker, CircuitBreakerConfig, ErrorKind,
+    NoopCaptcha, PathSegment, RetryPolicy, Step, StepPath, TargetAppKind,
+};
+use common::{css, script, FakeDriver, Shared};
+use std::sync::Arc;
+use std::time::Duration;
+
+fn backoff(jitter: f64) -> Backoff {
+    Backoff {
+        initial_ms: 100,
+        max_ms: 1_000,
+        multiplier: 2.0,
+        jitter,
+    }
+}
+
+/// Policy retrying `retry_on` failures with 1ms, 2ms, ... delays.
+fn policy(max_attempts: u32, retry_on: Vec<ErrorKind>) -> RetryPolicy {
+    RetryPolicy {
+        max_attempts,
+        retry_on,
+        backoff: Backoff {
+            initial_ms: 1,
+            max_ms: 10,
+            multiplier: 2.0,
+            jitter: 0.0,
+        },
+    }
+}
+
+fn engine() -> (AutomationEngine, Shared) {
+    let driver = FakeDriver::new("web-fake", TargetAppKind::Web);
+    let state = driver.state();
+    let engine = AutomationEngine::new(vec![Arc::new(driver)], Arc::new(NoopCaptcha));
+    (engine, state)
+}
+
+#[test]
+fn backoff_doubles_up_to_the_cap() {
+    let delays: Vec<u128> = (1..=6)
+        .map(|attempt| backoff(0.0).delay(attempt).as_millis())
+        .collect();
+    assert_eq!(delays, [100, 200, 400, 800, 1_000, 1_000]);
+    assert_eq!(backoff(0.0).delay(u32::MAX), Duration::from_millis(1_000));
+}
+
+#[test]
+fn jitter_spreads_delays_without_passing_the_cap() {
+    let backoff = backoff(0.5);
+    for attempt in 1..=6 {
+        let base = (100 << (attempt - 1)) as f64;
+        let samples: Vec<u64> = (0..200)
+            .map(|_| backoff.delay(attempt).as_millis() as u64)
+            .collect();
+        for delay in &samples {
+            assert!(*delay as f64 >= (base * 0.5).min(1_000.0) - 1.0, "{delay}");
+            assert!(*delay as f64 <= base * 1.5, "{delay}");
+            assert!(*delay <= 1_000, "attempt {attempt} waited {delay}ms");
+        }
+        if attempt == 1 {
+            assert!(samples.iter().any(|delay| *delay != samples[0]));
+        }
+    }
+}
+
+#[test]
+fn policies_retry_listed_kinds_up_to_max_attempts() {
+    let policy = RetryPolicy::default();
+    assert!(policy.should_retry(ErrorKind::Timeout, 1));
+    assert!(policy.should_retry(ErrorKind::Network, 2));
+    assert!(!policy.should_retry(ErrorKind::Network, 3));
+    assert!(!policy.should_retry(ErrorKind::Validation, 1));
+
+    let everything = RetryPolicy {
+        retry_on: vec![ErrorKind::Cancelled, ErrorKind::Other],
+        ..RetryPolicy::default()
+    };
+    assert!(everything.should_retry(ErrorKind::Other, 1));
+    assert!(!everything.should_retry(ErrorKind::Cancelled, 1));
+}
+
+#[test]
+fn breaker_opens_then_half_opens_after_the_cooldown() {
+    let breaker = CircuitBreaker::new(CircuitBreakerConfig {
+        failure_threshold: 2,
+        cooldown: Duration::from_millis(80),
+    });
+    assert!(!breaker.record_failure("mail"));
+    assert!(breaker.allows_retry("mail"));
+    assert!(breaker.record_failure("mail"));
+    assert!(breaker.is_open("mail"));
+    // Failures while open do not restart the cooldown; other targets are
+    // unaffected.
+    assert!(!breaker.record_failure("mail"));
+    assert!(breaker.allows_retry("shop"));
+
+    std::thread::sleep(Duration::from_millis(100));
+    // Half-open: retries are allowed again, and a single failure reopens.
+    assert!(breaker.allows_retry("mail"));
+    assert!(breaker.record_failure("mail"));
+    assert!(breaker.is_open("mail"));
+
+    std::thread::sleep(Duration::from_millis(100));
+    breaker.record_success("mail");
+    assert!(!breaker.record_failure("mail"));
+    assert!(breaker.allows_retry("mail"));
+}
+
+#[tokio::test]
+async fn retried_blocks_record_every_attempt() {
+    let (engine, state) = engine();
+    state
+        .lock()
+        .unwrap()
+        .failing_clicks
+        .extend([ErrorKind::Timeout, ErrorKind::Network]);
+    let login = script(
+        "mail",
+        TargetAppKind::Web,
+        "mail",
+        vec![Step::Retry {
+            policy: policy(3, vec![ErrorKind::Timeout, ErrorKind::Network]),
+            body: vec![Step::Click(css("#login"))],
+        }],
+    );
+
+    let outcome = engine.run(&login).await.unwrap();
+    assert!(outcome.success, "{outcome:?}");
+    let path = StepPath::root().child(PathSegment::Steps(0));
+    assert!(outcome
+        .attempts
+        .iter()
+        .all(|attempt| attempt.scope == AttemptScope::Step(path.clone())));
+    let summary: Vec<_> = outcome
+        .attempts
+        .iter()
+        .map(|attempt| (attempt.attempt, attempt.kind, attempt.retry_after_ms))
+        .collect();
+    assert_eq!(
+        summary,
+        [
+            (1, Some(ErrorKind::Timeout), Some(1)),
+            (2, Some(ErrorKind::Network), Some(2)),
+            (3, None, None),
+        ]
+    );
+    assert_eq!(
+        outcome.attempts[0].error.as_deref(),
+        Some("click on Css(\"#login\") failed")
+    );
+}
+
+#[tokio::test]
+async fn run_policy_reopens_the_session_for_each_attempt() {
+    let (engine, state) = engine();
+    state.lock().unwrap().absent.push(css("#gone"));
+    let mut login = script(
+        "mail",
+        TargetAppKind::Web,
+        "mail",
+        vec![Step::Click(css("#gone"))],
+    );
+    login.retry = Some(policy(2, vec![ErrorKind::ElementNotFound]));
+
+    let outcome = engine.run(&login).await.unwrap();
+    assert!(!outcome.success);
+    assert_eq!(outcome.error_kind, Some(ErrorKind::ElementNotFound));
+    let summary: Vec<_> = outcome
+        .attempts
+        .iter()
+        .map(|attempt| (&attempt.scope, attempt.attempt, attempt.retry_after_ms))
+        .collect();
+    assert_eq!(
+        summary,
+        [
+            (&AttemptScope::Run, 1, Some(1)),
+            (&AttemptScope::Run, 2, None)
+        ]
+    );
+    assert_eq!(state.lock().unwrap().opened.len(), 2);
+}
+
+#[tokio::test]
+async fn open_breaker_stops_retries() {
+    let (mut engine, state) = engine();
+    engine.set_circuit_breaker(CircuitBreakerConfig {
+        failure_threshold: 1,
+        cooldown: Duration::from_secs(60),
+    });
+    state
+        .lock()
+        .unwrap()
+        .failing_clicks
+        .extend([ErrorKind::Network; 4]);
+    let login = script(
+        "mail",
+        TargetAppKind::Web,
+        "mail",
+        vec![Step::Retry {
+            policy: policy(5, vec![ErrorKind::Network]),
+            body: vec![Step::Click(css("#login"))],
+        }],
+    );
+
+    // The first failure opens the breaker, so it is not retried.
+    let outcome = engine.run(&login).await.unwrap();
+    assert!(!outcome.success);
+    assert_eq!(outcome.attempts.len(), 1);
+    assert_eq!(outcome.attempts[0].retry_after_ms, None);
+    assert!(engine.circuit_breaker().is_open("Web:mail"));
+
+    // While open, later runs still make their first attempt.
+    let outcome = engine.run(&login).await.unwrap();
+    assert_eq!(outcome.attempts.len(), 1);
+    assert_eq!(state.lock().unwrap().actions.len(), 2);
+}
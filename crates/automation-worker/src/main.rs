@@ -0,0 +1,157 @@
+//! Sandboxed automation worker. Hosts an `AutomationEngine` for the
+//! supervisor in `integration-ipc` and speaks its line-delimited JSON
+//! protocol on stdin/stdout; logs go to stderr.
+
+use automation_engine::{
+    AutomationEngine, ExecutionEvent, LoginScript, RunControl, SecretResolver,
+};
+use integration_ipc::worker::{WorkerMessage, WorkerRequest};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::sync::{broadcast, mpsc};
+
+/// Secrets the supervisor resolved for the current run, and nothing else.
+#[derive(Default)]
+struct RunSecrets(Mutex<HashMap<String, String>>);
+
+impl SecretResolver for RunSecrets {
+    fn resolve(&self, key: &str) -> anyhow::Result<String> {
+        self.0
+            .lock()
+            .unwrap()
+            .get(key)
+            .cloned()
+            .ok_or_else(|| anyhow::anyhow!("secret {key} was not provided to the worker"))
+    }
+
+    fn contains(&self, key: &str) -> Option<bool> {
+        Some(self.0.lock().unwrap().contains_key(key))
+    }
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    tracing_subscriber::fmt()
+        .with_writer(std::io::stderr)
+        .init();
+
+    // A single writer keeps events and results from interleaving mid-line.
+    let (tx, mut rx) = mpsc::unbounded_channel::<WorkerMessage>();
+    let writer = tokio::spawn(async move {
+        let mut stdout = tokio::io::stdout();
+        while let Some(message) = rx.recv().await {
+            let mut line = serde_json::to_vec(&message)?;
+            line.push(b'\n');
+            stdout.write_all(&line).await?;
+            stdout.flush().await?;
+        }
+        anyhow::Ok(())
+    });
+
+    // One engine serves every run, so pooled sessions and the circuit
+    // breaker carry over; the supervisor runs one script at a time.
+    let secrets = Arc::new(RunSecrets::default());
+    let mut engine: Option<Arc<AutomationEngine>> = None;
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+    let mut current: Option<RunControl> = None;
+    while let Some(line) = lines.next_line().await? {
+        let request = match serde_json::from_str::<WorkerRequest>(&line) {
+            Ok(request) => request,
+            Err(err) => {
+                tx.send(WorkerMessage::Failed(format!("invalid request: {err}")))?;
+                continue;
+            }
+        };
+        match (request, &engine) {
+            (WorkerRequest::Init(settings), None) => {
+                let mut built = settings.engine();
+                built.set_secret_resolver(secrets.clone());
+                engine = Some(Arc::new(built));
+                tx.send(WorkerMessage::Ready)?;
+            }
+            (WorkerRequest::Init(_), Some(_)) => {
+                tx.send(WorkerMessage::Failed(
+                    "worker is already initialized".into(),
+                ))?;
+            }
+            (WorkerRequest::Run { .. }, None) => {
+                tx.send(WorkerMessage::Failed("worker is not initialized".into()))?;
+            }
+            (
+                WorkerRequest::Run {
+                    script,
+                    secrets: provided,
+                },
+                Some(engine),
+            ) => {
+                *secrets.0.lock().unwrap() = provided;
+                let control = RunControl::new();
+                current = Some(control.clone());
+                tokio::spawn(execute(engine.clone(), *script, control, tx.clone()));
+            }
+            (WorkerRequest::Pause, _) => current.iter().for_each(RunControl::pause),
+            (WorkerRequest::Resume, _) => current.iter().for_each(RunControl::resume),
+            (WorkerRequest::Cancel, _) => current.iter().for_each(RunControl::cancel),
+        }
+    }
+
+    // The supervisor closed stdin: stop any run, close warm sessions and
+    // exit.
+    if let Some(control) = current {
+        control.cancel();
+    }
+    if let Some(pool) = engine.as_ref().and_then(|engine| engine.session_pool()) {
+        pool.drain().await;
+    }
+    drop(tx);
+    writer.await?
+}
+
+async fn execute(
+    engine: Arc<AutomationEngine>,
+    script: LoginScript,
+    control: RunControl,
+    tx: mpsc::UnboundedSender<WorkerMessage>,
+) {
+    let mut events = engine.subscribe();
+    let run = engine.run_with(&script, control);
+    tokio::pin!(run);
+    let result = loop {
+        tokio::select! {
+            result = &mut run => break result,
+            event = events.recv() => forward(event, &tx),
+        }
+    };
+    // The run emitted its last event before returning; send what is still
+    // queued ahead of the result.
+    loop {
+        match events.try_recv() {
+            Ok(event) => forward(Ok(event), &tx),
+            Err(broadcast::error::TryRecvError::Lagged(missed)) => {
+                forward(Err(broadcast::error::RecvError::Lagged(missed)), &tx)
+            }
+            Err(_) => break,
+        }
+    }
+    let message = match result {
+        Ok(outcome) => WorkerMessage::Finished(outcome),
+        Err(err) => WorkerMessage::Failed(err.to_string()),
+    };
+    let _ = tx.send(message);
+}
+
+fn forward(
+    event: Result<ExecutionEvent, broadcast::error::RecvError>,
+    tx: &mpsc::UnboundedSender<WorkerMessage>,
+) {
+    match event {
+        Ok(event) => {
+            let _ = tx.send(WorkerMessage::Event(event));
+        }
+        Err(broadcast::error::RecvError::Lagged(missed)) => {
+            tracing::warn!(missed, "dropped execution events");
+        }
+        Err(broadcast::error::RecvError::Closed) => {}
+    }
+}
@@ -0,0 +1,374 @@
+//! `WorkerSupervisor` driving the real worker binary, with the simulated
+//! drivers and with the HTML form driver against a local server that serves
+//! the engine's `fixtures/html_form/` pages.
+
+use automation_engine::{
+    CircuitBreakerConfig, Condition, ErrorKind, ExecutionEventKind, LoginScript, NoSecrets,
+    RetryPolicy, RunControl, ScriptMeta, SecretResolver, Selector, Step, TargetApp, TargetAppKind,
+    Validation, ValueRef,
+};
+use integration_ipc::keepalive::ManualClock;
+use integration_ipc::{
+    DriverSpec, EngineSettings, KeepAliveConfig, KeepAliveEvent, KeepAliveScheduler,
+    TracingEmitter, VaultSecrets, WorkerConfig, WorkerSupervisor,
+};
+use secure_vault::{CredentialEntry, CredentialVault};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpListener;
+use tokio::sync::broadcast;
+
+const SESSION: &str = "s-42";
+
+fn script() -> LoginScript {
+    LoginScript {
+        meta: ScriptMeta {
+            id: "sandboxed".into(),
+            version: "1.0.0".into(),
+            author: None,
+            created_at: None,
+            updated_at: None,
+        },
+        target: TargetApp {
+            kind: TargetAppKind::Web,
+            name: "mail".into(),
+            version: None,
+            endpoint: None,
+        },
+        steps: vec![Step::Click(Selector::Css("#login".into()))],
+        validations: Vec::new(),
+        error_handlers: Vec::new(),
+        token: None,
+        retry: None,
+    }
+}
+
+fn worker(engine: EngineSettings) -> WorkerConfig {
+    WorkerConfig {
+        engine,
+        ..WorkerConfig::new(env!("CARGO_BIN_EXE_automation-worker"))
+    }
+}
+
+fn html_form() -> EngineSettings {
+    EngineSettings {
+        drivers: vec![DriverSpec::HtmlForm {
+            session_cookie: Some("SID".into()),
+        }],
+        ..EngineSettings::default()
+    }
+}
+
+/// The vault as far as the supervisor is concerned.
+struct Secrets(&'static str);
+
+impl SecretResolver for Secrets {
+    fn resolve(&self, key: &str) -> anyhow::Result<String> {
+        match key {
+            "alice.username" => Ok("alice".into()),
+            "alice.secret" => Ok(self.0.into()),
+            _ => Err(anyhow::anyhow!("no secret {key}")),
+        }
+    }
+}
+
+fn fixture(name: &str) -> String {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("../automation-engine/fixtures/html_form")
+        .join(name);
+    std::fs::read_to_string(&path).unwrap_or_else(|err| panic!("{}: {err}", path.display()))
+}
+
+/// Mail app: `/login` shows the form, posting alice/hunter2 to `/session`
+/// sets the session cookie and redirects to `/inbox`.
+async fn serve() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let base = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            tokio::spawn(async move {
+                let mut stream = BufReader::new(stream);
+                let mut request = String::new();
+                let mut length = 0;
+                loop {
+                    let mut line = String::new();
+                    if stream.read_line(&mut line).await.unwrap_or(0) == 0 {
+                        return;
+                    }
+                    if let Some(value) = line.to_ascii_lowercase().strip_prefix("content-length:") {
+                        length = value.trim().parse().unwrap_or(0);
+                    }
+                    if line == "\r\n" {
+                        break;
+                    }
+                    request.push_str(&line);
+                }
+                let mut body = vec![0; length];
+                if stream.read_exact(&mut body).await.is_err() {
+                    return;
+                }
+                let body = String::from_utf8_lossy(&body);
+                let login_page = |error: &str| fixture("login.html").replace("{error}", error);
+                let (status, headers, page) = match request.split(' ').take(2).collect::<Vec<_>>()[..]
+                {
+                    ["GET", "/login"] => ("200 OK", String::new(), login_page("")),
+                    ["POST", "/session"]
+                        if body.contains("user=alice") && body.contains("password=hunter2") =>
+                    {
+                        (
+                            "303 See Other",
+                            format!("Set-Cookie: SID={SESSION}; Path=/\r\nLocation: /inbox\r\n"),
+                            String::new(),
+                        )
+                    }
+                    ["POST", "/session"] => ("200 OK", String::new(), login_page("Wrong password")),
+                    ["GET", "/inbox"] if request.contains(&format!("SID={SESSION}")) => {
+                        ("200 OK", String::new(), fixture("inbox.html"))
+                    }
+                    _ => (
+                        "303 See Other",
+                        "Location: /login\r\n".into(),
+                        String::new(),
+                    ),
+                };
+                let response = format!(
+                    "HTTP/1.1 {status}\r\nContent-Type: text/html\r\nContent-Length: {}\r\nConnection: close\r\n{headers}\r\n{page}",
+                    page.len()
+                );
+                let _ = stream.get_mut().write_all(response.as_bytes()).await;
+                let _ = stream.get_mut().shutdown().await;
+            });
+        }
+    });
+    base
+}
+
+fn login_script(base: &str, extra: Vec<Step>) -> LoginScript {
+    let css = |selector: &str| Selector::Css(selector.into());
+    let mut steps = vec![
+        Step::WaitFor(css("#login")),
+        Step::Input {
+            selector: css("#user"),
+            value: ValueRef::FromVault("alice.username".into()),
+        },
+        Step::Input {
+            selector: css("input[name=password]"),
+            value: ValueRef::FromVault("alice.secret".into()),
+        },
+    ];
+    steps.extend(extra);
+    steps.push(Step::Click(css("button[value=login]")));
+    LoginScript {
+        meta: ScriptMeta {
+            id: "mail-form".into(),
+            ..script().meta
+        },
+        target: TargetApp {
+            endpoint: Some(format!("{base}/login")),
+            ..script().target
+        },
+        steps,
+        validations: vec![Validation {
+            description: "inbox".into(),
+            condition: Condition::Exists(css("#logout")),
+        }],
+        ..script()
+    }
+}
+
+/// Wait for the first event `matches` accepts.
+async fn wait_for(
+    events: &mut broadcast::Receiver<automation_engine::ExecutionEvent>,
+    matches: impl Fn(&ExecutionEventKind) -> bool,
+) {
+    tokio::time::timeout(Duration::from_secs(10), async {
+        while !matches(&events.recv().await.unwrap().kind) {}
+    })
+    .await
+    .expect("event not seen");
+}
+
+#[tokio::test]
+async fn runs_scripts_and_relays_events_under_its_own_run_ids() {
+    let supervisor = WorkerSupervisor::new(
+        WorkerConfig::new(env!("CARGO_BIN_EXE_automation-worker")),
+        Arc::new(NoSecrets),
+    );
+    let mut events = supervisor.subscribe();
+
+    for _ in 0..2 {
+        let outcome = supervisor.run(&script()).await.unwrap();
+        assert!(outcome.success, "{outcome:?}");
+    }
+    assert_eq!(supervisor.restarts(), 0);
+
+    let mut started = Vec::new();
+    while let Ok(event) = events.try_recv() {
+        if let ExecutionEventKind::RunStarted { script_id, .. } = event.kind {
+            assert_eq!(script_id, "sandboxed");
+            started.push(event.run_id);
+        }
+    }
+    assert_eq!(started, [1, 2]);
+    assert!(supervisor.active_run(2).is_none());
+    supervisor.shutdown().await;
+}
+
+#[tokio::test]
+async fn logs_in_with_the_drivers_it_was_configured_with() {
+    let base = serve().await;
+    let supervisor = WorkerSupervisor::new(worker(html_form()), Arc::new(Secrets("hunter2")));
+    let mut events = supervisor.subscribe();
+
+    let outcome = supervisor
+        .run(&login_script(&base, Vec::new()))
+        .await
+        .unwrap();
+    assert!(outcome.success, "{outcome:?}");
+    assert_eq!(outcome.session_token.unwrap().value, SESSION);
+    let mut driver = None;
+    while let Ok(event) = events.try_recv() {
+        if let ExecutionEventKind::RunStarted { driver: name, .. } = event.kind {
+            driver = Some(name);
+        }
+    }
+    assert_eq!(driver.as_deref(), Some("html-form"));
+
+    // The simulated drivers are only there when the settings ask for them.
+    let err = supervisor.run(&script()).await.unwrap_err();
+    assert!(err.to_string().contains("driver"), "{err}");
+    supervisor.shutdown().await;
+}
+
+#[tokio::test]
+async fn breaker_state_carries_over_between_runs_of_one_worker() {
+    let base = serve().await;
+    let mut wrong_password = login_script(&base, Vec::new());
+    wrong_password.retry = Some(RetryPolicy {
+        max_attempts: 3,
+        retry_on: vec![ErrorKind::Validation],
+        ..RetryPolicy::default()
+    });
+    let settings = EngineSettings {
+        breaker: CircuitBreakerConfig {
+            failure_threshold: 2,
+            cooldown: Duration::from_secs(60),
+        },
+        ..html_form()
+    };
+
+    // Two failures open the breaker; while it is open the next run does not
+    // retry. A fresh worker per run would retry again.
+    for (max_runs, expected) in [(20, [2, 1]), (1, [2, 2])] {
+        let supervisor = WorkerSupervisor::new(
+            WorkerConfig {
+                max_runs,
+                ..worker(settings.clone())
+            },
+            Arc::new(Secrets("wrong")),
+        );
+        let mut attempts = Vec::new();
+        for _ in 0..2 {
+            let outcome = supervisor.run(&wrong_password).await.unwrap();
+            assert_eq!(outcome.error_kind, Some(ErrorKind::Validation));
+            attempts.push(outcome.attempts.len());
+        }
+        assert_eq!(attempts, expected, "max_runs {max_runs}");
+        supervisor.shutdown().await;
+    }
+}
+
+#[tokio::test]
+async fn pause_resume_and_cancel_reach_the_worker() {
+    let base = serve().await;
+    let supervisor = Arc::new(WorkerSupervisor::new(
+        worker(html_form()),
+        Arc::new(Secrets("hunter2")),
+    ));
+    let mut events = supervisor.subscribe();
+
+    // Paused while the sleep step runs, the run holds before the click.
+    let control = RunControl::new();
+    let run = tokio::spawn({
+        let (supervisor, control) = (supervisor.clone(), control.clone());
+        let login = login_script(&base, vec![Step::SleepMs(300)]);
+        async move { supervisor.run_with(&login, control).await }
+    });
+    wait_for(
+        &mut events,
+        |kind| matches!(kind, ExecutionEventKind::StepStarted { step, .. } if step == "sleep"),
+    )
+    .await;
+    control.pause();
+    wait_for(&mut events, |kind| {
+        matches!(kind, ExecutionEventKind::Paused { path } if path.to_string() == "steps[4]")
+    })
+    .await;
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert!(!run.is_finished());
+    control.resume();
+    let outcome = run.await.unwrap().unwrap();
+    assert!(outcome.success, "{outcome:?}");
+
+    let control = RunControl::new();
+    let run = tokio::spawn({
+        let (supervisor, control) = (supervisor.clone(), control.clone());
+        let login = login_script(&base, vec![Step::SleepMs(60_000)]);
+        async move { supervisor.run_with(&login, control).await }
+    });
+    wait_for(
+        &mut events,
+        |kind| matches!(kind, ExecutionEventKind::StepStarted { step, .. } if step == "sleep"),
+    )
+    .await;
+    control.cancel();
+    let outcome = tokio::time::timeout(Duration::from_secs(10), run)
+        .await
+        .expect("cancel was not relayed")
+        .unwrap()
+        .unwrap();
+    assert!(outcome.cancelled, "{outcome:?}");
+    supervisor.shutdown().await;
+}
+
+#[tokio::test]
+async fn keep_alive_logs_in_again_through_the_worker() {
+    let base = serve().await;
+    let vault = CredentialVault::default();
+    vault
+        .store(CredentialEntry {
+            id: "alice".into(),
+            username: "alice".into(),
+            secret: "hunter2".into(),
+            token: None,
+            metadata: None,
+            token_expires_at: None,
+        })
+        .unwrap();
+    let supervisor = WorkerSupervisor::new(
+        worker(html_form()),
+        Arc::new(VaultSecrets::new(vault.clone())),
+    );
+    let scheduler = KeepAliveScheduler::new(
+        vault.clone(),
+        Arc::new(ManualClock::new(1_000_000)),
+        Arc::new(TracingEmitter),
+        KeepAliveConfig::default(),
+    );
+    scheduler.track("alice", login_script(&base, Vec::new()), None);
+
+    assert_eq!(
+        scheduler.tick(&supervisor).await,
+        [KeepAliveEvent::Refreshed {
+            credential_id: "alice".into(),
+            expires_at: None,
+        }]
+    );
+    assert_eq!(
+        vault.fetch("alice").unwrap().token.as_deref(),
+        Some(SESSION)
+    );
+    supervisor.shutdown().await;
+}
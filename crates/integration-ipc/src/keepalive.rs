@@ -0,0 +1,365 @@
+//! Keeps stored session tokens fresh by re-running login scripts before
+//! they expire.
+//!
+//! The scheduler is driven by `tick`, which handles every credential that is
+//! due and returns what happened; `run` is the long-lived loop around it,
+//! woken early whenever a credential starts being tracked. Logins go through
+//! a `LoginRunner`: the engine itself, or the `WorkerSupervisor` when runs
+//! are sandboxed. Time comes from a `Clock`, so `ManualClock` can step
+//! through expiries and backoff without waiting.
+
+use crate::{FrontendEmitter, IpcEvent};
+use async_trait::async_trait;
+use automation_engine::{AutomationEngine, LoginOutcome, LoginScript};
+use secure_vault::CredentialVault;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::Notify;
+
+/// Event name the frontend listens on for keep-alive notifications.
+pub const KEEPALIVE_EVENT_CHANNEL: &str = "automation://keepalive";
+
+/// Executes the login and probe scripts.
+#[async_trait]
+pub trait LoginRunner: Send + Sync {
+    async fn run(&self, script: &LoginScript) -> anyhow::Result<LoginOutcome>;
+}
+
+#[async_trait]
+impl LoginRunner for AutomationEngine {
+    async fn run(&self, script: &LoginScript) -> anyhow::Result<LoginOutcome> {
+        AutomationEngine::run(self, script).await
+    }
+}
+
+/// Source of the current time, in seconds since the Unix epoch.
+#[async_trait]
+pub trait Clock: Send + Sync {
+    fn now(&self) -> u64;
+    async fn sleep(&self, duration: Duration);
+}
+
+pub struct SystemClock;
+
+#[async_trait]
+impl Clock for SystemClock {
+    fn now(&self) -> u64 {
+        crate::epoch_secs()
+    }
+
+    async fn sleep(&self, duration: Duration) {
+        tokio::time::sleep(duration).await;
+    }
+}
+
+/// Clock that only moves when told to; `sleep` returns once `advance` or
+/// `set` has moved it past the deadline.
+#[derive(Debug, Default)]
+pub struct ManualClock {
+    now: AtomicU64,
+    moved: Notify,
+}
+
+impl ManualClock {
+    pub fn new(now: u64) -> Self {
+        Self {
+            now: AtomicU64::new(now),
+            moved: Notify::new(),
+        }
+    }
+
+    pub fn advance(&self, secs: u64) {
+        self.now.fetch_add(secs, Ordering::SeqCst);
+        self.moved.notify_waiters();
+    }
+
+    pub fn set(&self, now: u64) {
+        self.now.store(now, Ordering::SeqCst);
+        self.moved.notify_waiters();
+    }
+}
+
+#[async_trait]
+impl Clock for ManualClock {
+    fn now(&self) -> u64 {
+        self.now.load(Ordering::SeqCst)
+    }
+
+    async fn sleep(&self, duration: Duration) {
+        let deadline = self.now().saturating_add(duration.as_secs().max(1));
+        loop {
+            let moved = self.moved.notified();
+            tokio::pin!(moved);
+            moved.as_mut().enable();
+            if self.now() >= deadline {
+                return;
+            }
+            moved.await;
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct KeepAliveConfig {
+    /// Re-login this long before the token expires.
+    pub refresh_before_secs: u64,
+    /// How often the probe script runs for tokens far from expiry.
+    pub probe_interval_secs: u64,
+    /// First retry delay after a failed login; doubles per failure.
+    pub backoff_base_secs: u64,
+    pub backoff_max_secs: u64,
+    /// Stop tracking a credential after this many failures in a row.
+    pub max_failures: u32,
+}
+
+impl Default for KeepAliveConfig {
+    fn default() -> Self {
+        Self {
+            refresh_before_secs: 300,
+            probe_interval_secs: 900,
+            backoff_base_secs: 30,
+            backoff_max_secs: 3600,
+            max_failures: 8,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum KeepAliveEvent {
+    /// The probe script confirmed the session is still valid.
+    StillValid { credential_id: String },
+    Refreshed {
+        credential_id: String,
+        expires_at: Option<u64>,
+    },
+    ReloginFailed {
+        credential_id: String,
+        attempt: u32,
+        retry_at: u64,
+        error: String,
+    },
+    /// `max_failures` reached; the credential is no longer tracked.
+    GaveUp { credential_id: String },
+}
+
+struct Tracked {
+    login: Arc<LoginScript>,
+    /// Cheap "still logged in" check; success keeps the current token.
+    probe: Option<Arc<LoginScript>>,
+    next_check: u64,
+    failures: u32,
+}
+
+pub struct KeepAliveScheduler {
+    vault: CredentialVault,
+    clock: Arc<dyn Clock>,
+    emitter: Arc<dyn FrontendEmitter>,
+    config: KeepAliveConfig,
+    tracked: Mutex<HashMap<String, Tracked>>,
+    /// Wakes `run` when a credential starts being tracked.
+    added: Notify,
+}
+
+impl KeepAliveScheduler {
+    pub fn new(
+        vault: CredentialVault,
+        clock: Arc<dyn Clock>,
+        emitter: Arc<dyn FrontendEmitter>,
+        config: KeepAliveConfig,
+    ) -> Self {
+        Self {
+            vault,
+            clock,
+            emitter,
+            config,
+            tracked: Mutex::new(HashMap::new()),
+            added: Notify::new(),
+        }
+    }
+
+    /// Start tracking a credential; `run` checks it right away.
+    pub fn track(&self, credential_id: &str, login: LoginScript, probe: Option<LoginScript>) {
+        self.tracked.lock().unwrap().insert(
+            credential_id.to_string(),
+            Tracked {
+                login: Arc::new(login),
+                probe: probe.map(Arc::new),
+                next_check: self.clock.now(),
+                failures: 0,
+            },
+        );
+        // `notify_one` keeps the permit if `run` is busy ticking, so the
+        // next wait ends immediately.
+        self.added.notify_one();
+    }
+
+    pub fn untrack(&self, credential_id: &str) -> bool {
+        self.tracked.lock().unwrap().remove(credential_id).is_some()
+    }
+
+    /// Earliest time any tracked credential needs attention.
+    pub fn next_due(&self) -> Option<u64> {
+        self.tracked
+            .lock()
+            .unwrap()
+            .values()
+            .map(|tracked| tracked.next_check)
+            .min()
+    }
+
+    /// Handle every credential that is due now.
+    pub async fn tick(&self, runner: &dyn LoginRunner) -> Vec<KeepAliveEvent> {
+        let now = self.clock.now();
+        let due: Vec<(String, Arc<LoginScript>, Option<Arc<LoginScript>>)> = self
+            .tracked
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, tracked)| tracked.next_check <= now)
+            .map(|(id, tracked)| (id.clone(), tracked.login.clone(), tracked.probe.clone()))
+            .collect();
+
+        let mut events = Vec::new();
+        for (credential_id, login, probe) in due {
+            let event = self
+                .check(runner, &credential_id, &login, probe.as_deref())
+                .await;
+            if let Some(event) = event {
+                if let Err(err) = self
+                    .emitter
+                    .emit(KEEPALIVE_EVENT_CHANNEL, &IpcEvent::KeepAlive(event.clone()))
+                {
+                    tracing::warn!(error = %err, "failed to forward keep-alive event");
+                }
+                events.push(event);
+            }
+        }
+        events
+    }
+
+    /// Tick forever, sleeping until the next credential is due or a new
+    /// one is tracked.
+    pub async fn run(&self, runner: &dyn LoginRunner) {
+        loop {
+            self.tick(runner).await;
+            let now = self.clock.now();
+            let wait = self
+                .next_due()
+                .map_or(self.config.probe_interval_secs, |due| {
+                    due.saturating_sub(now)
+                })
+                .max(1);
+            tokio::select! {
+                _ = self.clock.sleep(Duration::from_secs(wait)) => {}
+                _ = self.added.notified() => {}
+            }
+        }
+    }
+
+    async fn check(
+        &self,
+        runner: &dyn LoginRunner,
+        credential_id: &str,
+        login: &LoginScript,
+        probe: Option<&LoginScript>,
+    ) -> Option<KeepAliveEvent> {
+        let now = self.clock.now();
+        // A credential removed from the vault fails like a login would, so it
+        // backs off and is eventually dropped.
+        let stored = match self.vault.fetch(credential_id) {
+            Ok(stored) => stored,
+            Err(err) => return self.record_failure(credential_id, now, err.to_string()),
+        };
+        let fresh = match (stored.token.as_ref(), stored.token_expires_at) {
+            (Some(_), Some(expires_at)) => {
+                expires_at > now.saturating_add(self.config.refresh_before_secs)
+            }
+            // Without a known expiry only the probe can vouch for the token.
+            (Some(_), None) => probe.is_some(),
+            (None, _) => false,
+        };
+
+        if fresh {
+            let still_valid = match probe {
+                Some(probe) => runner.run(probe).await.is_ok_and(|outcome| outcome.success),
+                None => true,
+            };
+            if still_valid {
+                self.reschedule(credential_id, now, stored.token_expires_at, 0);
+                return probe.map(|_| KeepAliveEvent::StillValid {
+                    credential_id: credential_id.to_string(),
+                });
+            }
+        }
+
+        let error = match runner.run(login).await {
+            Ok(outcome) if outcome.success => {
+                let expires_at = outcome
+                    .session_token
+                    .as_ref()
+                    .and_then(|token| token.expires_at);
+                if let Some(token) = outcome.session_token {
+                    if let Err(err) =
+                        self.vault
+                            .store_token(credential_id, token.value, token.expires_at)
+                    {
+                        tracing::warn!(credential_id, error = %err, "failed to store refreshed token");
+                    }
+                }
+                self.reschedule(credential_id, now, expires_at, 0);
+                return Some(KeepAliveEvent::Refreshed {
+                    credential_id: credential_id.to_string(),
+                    expires_at,
+                });
+            }
+            Ok(outcome) => outcome.error.unwrap_or_else(|| "login failed".into()),
+            Err(err) => err.to_string(),
+        };
+        self.record_failure(credential_id, now, error)
+    }
+
+    /// Next check: before expiry, but at least every probe interval.
+    fn reschedule(&self, credential_id: &str, now: u64, expires_at: Option<u64>, failures: u32) {
+        let probe_at = now + self.config.probe_interval_secs;
+        let refresh_at = expires_at
+            .map(|expires_at| expires_at.saturating_sub(self.config.refresh_before_secs))
+            .unwrap_or(probe_at);
+        if let Some(tracked) = self.tracked.lock().unwrap().get_mut(credential_id) {
+            tracked.next_check = refresh_at.min(probe_at).max(now + 1);
+            tracked.failures = failures;
+        }
+    }
+
+    fn record_failure(
+        &self,
+        credential_id: &str,
+        now: u64,
+        error: String,
+    ) -> Option<KeepAliveEvent> {
+        let mut tracked = self.tracked.lock().unwrap();
+        let entry = tracked.get_mut(credential_id)?;
+        entry.failures += 1;
+        if entry.failures >= self.config.max_failures {
+            tracked.remove(credential_id);
+            return Some(KeepAliveEvent::GaveUp {
+                credential_id: credential_id.to_string(),
+            });
+        }
+        let exponent = (entry.failures - 1).min(31);
+        let delay = self
+            .config
+            .backoff_base_secs
+            .saturating_mul(1 << exponent)
+            .min(self.config.backoff_max_secs);
+        entry.next_check = now + delay.max(1);
+        Some(KeepAliveEvent::ReloginFailed {
+            credential_id: credential_id.to_string(),
+            attempt: entry.failures,
+            retry_at: entry.next_check,
+            error,
+        })
+    }
+}
@@ -1,19 +1,73 @@
 //! Tauri + IPC integration skeleton.
 
-use automation_engine::{LoginOutcome, LoginScript};
+pub mod keepalive;
+pub mod worker;
+pub use keepalive::{KeepAliveConfig, KeepAliveEvent, KeepAliveScheduler, LoginRunner};
+pub use worker::{DriverSpec, EngineSettings, ResourceLimits, WorkerConfig, WorkerSupervisor};
+
+use automation_engine::{
+    AnalysisReport, ExecutionEvent, LoginOutcome, LoginScript, RunControl, RunId, SecretResolver,
+    SessionToken,
+};
+use script_manager::{MergeResult, ScriptDiff, SuiteReport, TestSuite};
 use secure_vault::CredentialVault;
+use tokio::sync::broadcast;
+
+/// Stored tokens closer than this to their expiry trigger a fresh login.
+pub const TOKEN_REUSE_MARGIN_SECS: u64 = 60;
+
+/// Event name the frontend listens on for run progress.
+pub const EXECUTION_EVENT_CHANNEL: &str = "automation://execution";
 
 /// IPC message formats (simplified).
 #[derive(Debug, serde::Serialize, serde::Deserialize)]
 pub enum IpcRequest {
     RunScript(LoginScript),
+    /// Log in as `credential_id`, reusing its stored token while valid and
+    /// storing the new token otherwise.
+    Login { script: LoginScript, credential_id: String },
+    /// Static checks only; nothing is executed.
+    AnalyzeScript(LoginScript),
+    /// Structural diff from `base` to `changed`.
+    DiffScripts {
+        base: Box<LoginScript>,
+        changed: Box<LoginScript>,
+    },
+    /// Three-way merge of two edits of `base`; nothing is saved.
+    MergeScripts {
+        base: Box<LoginScript>,
+        ours: Box<LoginScript>,
+        theirs: Box<LoginScript>,
+    },
+    /// Run `suite` against `script` on fake sessions; no real app is
+    /// touched.
+    TestScript {
+        script: Box<LoginScript>,
+        suite: TestSuite,
+    },
     StoreCredential { id: String, username: String, secret: String },
+    /// Keep the credential's token fresh; `probe` is an optional cheap
+    /// "still logged in" script.
+    TrackSession {
+        credential_id: String,
+        login: LoginScript,
+        probe: Option<Box<LoginScript>>,
+    },
+    UntrackSession(String),
+    /// Run ids come from the `RunStarted` execution event.
+    CancelRun(RunId),
+    PauseRun(RunId),
+    ResumeRun(RunId),
 }
 
 #[derive(Debug, serde::Serialize, serde::Deserialize)]
 pub enum IpcResponse {
     Ack,
     ScriptResult(LoginOutcome),
+    Analysis(AnalysisReport),
+    Diff(ScriptDiff),
+    Merge(Box<MergeResult>),
+    TestReport(SuiteReport),
     Error(String),
 }
 
@@ -21,16 +75,40 @@ pub enum IpcResponse {
 pub struct IpcHandler<'a> {
     pub vault: &'a CredentialVault,
     pub automation: &'a automation_engine::AutomationEngine,
+    pub keepalive: Option<&'a KeepAliveScheduler>,
+    /// When set, `RunScript` and `Login` execute in the sandboxed worker
+    /// instead of `automation`.
+    pub worker: Option<&'a WorkerSupervisor>,
 }
 
 impl<'a> IpcHandler<'a> {
-    pub fn handle(&self, req: IpcRequest) -> anyhow::Result<IpcResponse> {
+    /// Requests are independent: while one `handle` call awaits a run,
+    /// others (e.g. `CancelRun` with the id from `RunStarted`) proceed.
+    pub async fn handle(&self, req: IpcRequest) -> anyhow::Result<IpcResponse> {
         match req {
             IpcRequest::RunScript(script) => {
-                // In real code, spawn async task; simplified here.
-                let outcome = futures::executor::block_on(self.automation.run(script))?;
+                let outcome = self.execute(&script).await?;
+                Ok(IpcResponse::ScriptResult(outcome))
+            }
+            IpcRequest::Login {
+                script,
+                credential_id,
+            } => {
+                let outcome = self.login(&script, &credential_id).await?;
                 Ok(IpcResponse::ScriptResult(outcome))
             }
+            IpcRequest::AnalyzeScript(script) => {
+                Ok(IpcResponse::Analysis(self.automation.analyze(&script)))
+            }
+            IpcRequest::DiffScripts { base, changed } => Ok(IpcResponse::Diff(
+                script_manager::diff_scripts(&base, &changed),
+            )),
+            IpcRequest::MergeScripts { base, ours, theirs } => Ok(IpcResponse::Merge(Box::new(
+                script_manager::merge_scripts(&base, &ours, &theirs),
+            ))),
+            IpcRequest::TestScript { script, suite } => Ok(IpcResponse::TestReport(
+                script_manager::run_suite(&script, &suite).await,
+            )),
             IpcRequest::StoreCredential {
                 id,
                 username,
@@ -42,10 +120,169 @@ impl<'a> IpcHandler<'a> {
                     secret,
                     token: None,
                     metadata: None,
+                    token_expires_at: None,
                 })?;
                 Ok(IpcResponse::Ack)
             }
+            IpcRequest::TrackSession {
+                credential_id,
+                login,
+                probe,
+            } => match self.keepalive {
+                Some(keepalive) => {
+                    keepalive.track(&credential_id, login, probe.map(|probe| *probe));
+                    Ok(IpcResponse::Ack)
+                }
+                None => Ok(IpcResponse::Error("keep-alive scheduler is not running".into())),
+            },
+            IpcRequest::UntrackSession(credential_id) => {
+                match self.keepalive.map(|keepalive| keepalive.untrack(&credential_id)) {
+                    Some(true) => Ok(IpcResponse::Ack),
+                    _ => Ok(IpcResponse::Error(format!("{credential_id} is not tracked"))),
+                }
+            }
+            IpcRequest::CancelRun(run_id) => Ok(self.control_run(run_id, RunControl::cancel)),
+            IpcRequest::PauseRun(run_id) => Ok(self.control_run(run_id, RunControl::pause)),
+            IpcRequest::ResumeRun(run_id) => Ok(self.control_run(run_id, RunControl::resume)),
+        }
+    }
+
+    async fn login(&self, script: &LoginScript, credential_id: &str) -> anyhow::Result<LoginOutcome> {
+        let now = epoch_secs();
+        if let Some((value, expires_at)) =
+            self.vault
+                .valid_token(credential_id, now, TOKEN_REUSE_MARGIN_SECS)?
+        {
+            tracing::debug!(credential_id, "reusing stored session token");
+            return Ok(LoginOutcome {
+                success: true,
+                session_token: Some(SessionToken {
+                    value,
+                    source: script.token.as_ref().map(|rule| rule.source.clone()),
+                    expires_at: Some(expires_at),
+                }),
+                error: None,
+                cancelled: false,
+                error_kind: None,
+                attempts: Vec::new(),
+            });
+        }
+        let outcome = self.execute(script).await?;
+        if let Some(token) = outcome.session_token.as_ref().filter(|_| outcome.success) {
+            self.vault
+                .store_token(credential_id, token.value.clone(), token.expires_at)?;
+        }
+        Ok(outcome)
+    }
+
+    async fn execute(&self, script: &LoginScript) -> anyhow::Result<LoginOutcome> {
+        match self.worker {
+            Some(worker) => worker.run(script).await,
+            None => self.automation.run(script).await,
+        }
+    }
+
+    fn control_run(&self, run_id: RunId, action: fn(&RunControl)) -> IpcResponse {
+        let worker_run = self.worker.and_then(|worker| worker.active_run(run_id));
+        match worker_run.or_else(|| self.automation.active_run(run_id)) {
+            Some(control) => {
+                action(&control);
+                IpcResponse::Ack
+            }
+            None => IpcResponse::Error(format!("run {run_id} is not active")),
         }
     }
 }
 
+
+/// Resolves `ValueRef::FromVault` keys against the credential vault.
+///
+/// Keys have the form `<credential id>.<field>` with field `username`,
+/// `secret` or `token`; a key without a known field suffix names the
+/// credential's secret.
+pub struct VaultSecrets {
+    vault: CredentialVault,
+}
+
+impl VaultSecrets {
+    pub fn new(vault: CredentialVault) -> Self {
+        Self { vault }
+    }
+
+    fn split(key: &str) -> (&str, &str) {
+        match key.rsplit_once('.') {
+            Some((id, field @ ("username" | "secret" | "token"))) => (id, field),
+            _ => (key, "secret"),
+        }
+    }
+}
+
+impl SecretResolver for VaultSecrets {
+    fn resolve(&self, key: &str) -> anyhow::Result<String> {
+        let (id, field) = Self::split(key);
+        let entry = self.vault.fetch(id)?;
+        let value = match field {
+            "username" => Some(entry.username),
+            "token" => entry.token,
+            _ => Some(entry.secret),
+        };
+        value.ok_or_else(|| anyhow::anyhow!("credential {id} has no {field}"))
+    }
+
+    fn contains(&self, key: &str) -> Option<bool> {
+        let (id, field) = Self::split(key);
+        if field == "token" {
+            return Some(self.vault.fetch(id).is_ok_and(|entry| entry.token.is_some()));
+        }
+        Some(self.vault.contains(id))
+    }
+}
+
+/// Push-style messages sent to the frontend outside of request/response.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub enum IpcEvent {
+    Execution(ExecutionEvent),
+    /// The forwarder fell behind and dropped this many events.
+    Lagged(u64),
+    KeepAlive(KeepAliveEvent),
+}
+
+/// Sink for frontend notifications; in Tauri this wraps `AppHandle::emit`.
+pub trait FrontendEmitter: Send + Sync {
+    fn emit(&self, channel: &str, event: &IpcEvent) -> anyhow::Result<()>;
+}
+
+/// Emitter used until a window is attached; logs events instead.
+pub struct TracingEmitter;
+
+impl FrontendEmitter for TracingEmitter {
+    fn emit(&self, channel: &str, event: &IpcEvent) -> anyhow::Result<()> {
+        tracing::debug!(channel, ?event, "frontend event");
+        Ok(())
+    }
+}
+
+/// Forward engine events to the frontend until the engine is dropped.
+/// Spawn it once per window with `AutomationEngine::subscribe()`.
+pub async fn forward_events(
+    mut events: broadcast::Receiver<ExecutionEvent>,
+    emitter: &dyn FrontendEmitter,
+) {
+    loop {
+        let event = match events.recv().await {
+            Ok(event) => IpcEvent::Execution(event),
+            Err(broadcast::error::RecvError::Lagged(skipped)) => IpcEvent::Lagged(skipped),
+            Err(broadcast::error::RecvError::Closed) => break,
+        };
+        if let Err(err) = emitter.emit(EXECUTION_EVENT_CHANNEL, &event) {
+            tracing::warn!(error = %err, "failed to forward execution event");
+        }
+    }
+}
+
+fn epoch_secs() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default()
+}
@@ -0,0 +1,569 @@
+//! Out-of-process execution in the sandboxed `automation-worker` binary.
+//!
+//! The supervisor talks to the worker over its stdin/stdout, one JSON message
+//! per line. A new worker first receives the `EngineSettings` to build its
+//! engine from, then serves runs one after the other with that engine, so
+//! warm sessions and circuit breaker state carry over between runs. Each run
+//! ships the script together with the plaintext of only the vault keys that
+//! script references; the worker never sees the vault. Pausing, resuming
+//! and cancelling the supervisor's `RunControl` is relayed to the worker.
+//!
+//! Workers start under the configured memory and CPU limits and are
+//! replaced after `max_runs` runs, so the CPU limit bounds that many runs.
+//! Crashes are counted towards `max_restarts` until `restart_cooldown` has
+//! passed.
+
+use crate::keepalive::LoginRunner;
+use async_trait::async_trait;
+use automation_engine::android::AdbCommand;
+use automation_engine::{
+    AndroidDriver, AndroidDriverStub, AutomationDriver, AutomationEngine, CdpDriver,
+    CircuitBreakerConfig, ExecutionEvent, HtmlFormDriver, HybridDriver, LoginOutcome,
+    LoginScript, NoopCaptcha, PoolConfig, RunControl, RunId, RunState, SecretResolver,
+    SessionPool, TraceConfig, WebDriverStub,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::process::{ExitStatus, Stdio};
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines};
+use tokio::process::{Child, ChildStdin, ChildStdout, Command};
+use tokio::sync::{broadcast, Mutex};
+
+/// File name of the worker binary, installed next to the app executable.
+pub const WORKER_BINARY: &str = "automation-worker";
+
+/// Supervisor → worker.
+#[derive(Debug, Serialize, Deserialize)]
+pub enum WorkerRequest {
+    /// First request to a new worker: the engine every run uses.
+    Init(Box<EngineSettings>),
+    /// Execute one script; `secrets` maps vault keys to their plaintext.
+    Run {
+        script: Box<LoginScript>,
+        secrets: HashMap<String, String>,
+    },
+    /// Control the current run, like the `RunControl` methods.
+    Pause,
+    Resume,
+    Cancel,
+}
+
+/// Worker → supervisor.
+#[derive(Debug, Serialize, Deserialize)]
+pub enum WorkerMessage {
+    /// Sent once the worker has built its engine from `Init`.
+    Ready,
+    Event(ExecutionEvent),
+    Finished(LoginOutcome),
+    /// The run could not execute at all (no driver, bad request, ...).
+    Failed(String),
+}
+
+/// A driver of the worker's engine.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum DriverSpec {
+    /// The simulated web, Android and hybrid drivers of
+    /// `AutomationEngine::with_defaults`.
+    Stubs,
+    HtmlForm {
+        #[serde(default)]
+        session_cookie: Option<String>,
+    },
+    Cdp {
+        debugger_url: String,
+        #[serde(default)]
+        session_cookie: Option<String>,
+        #[serde(default)]
+        session_header: Option<String>,
+    },
+    /// Devices reached through the `adb` binary; also runs hybrid targets.
+    Android {
+        adb: PathBuf,
+        #[serde(default)]
+        serial: Option<String>,
+    },
+}
+
+/// What the worker builds its `AutomationEngine` from.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EngineSettings {
+    pub drivers: Vec<DriverSpec>,
+    pub trace: TraceConfig,
+    /// Reuse warm sessions across the worker's runs; `None` opens a new
+    /// session for every run.
+    pub pool: Option<PoolConfig>,
+    pub breaker: CircuitBreakerConfig,
+}
+
+impl Default for EngineSettings {
+    fn default() -> Self {
+        Self {
+            drivers: vec![DriverSpec::Stubs],
+            trace: TraceConfig::default(),
+            pool: Some(PoolConfig::default()),
+            breaker: CircuitBreakerConfig::default(),
+        }
+    }
+}
+
+impl EngineSettings {
+    pub fn engine(&self) -> AutomationEngine {
+        let mut drivers: Vec<Arc<dyn AutomationDriver + Send + Sync>> = Vec::new();
+        for spec in &self.drivers {
+            match spec {
+                DriverSpec::Stubs => drivers.extend([
+                    Arc::new(WebDriverStub) as Arc<dyn AutomationDriver + Send + Sync>,
+                    Arc::new(AndroidDriverStub),
+                    Arc::new(HybridDriver::new(Arc::new(AndroidDriverStub))),
+                ]),
+                DriverSpec::HtmlForm { session_cookie } => {
+                    let mut driver = HtmlFormDriver::new();
+                    if let Some(name) = session_cookie {
+                        driver = driver.with_session_cookie(name);
+                    }
+                    drivers.push(Arc::new(driver));
+                }
+                DriverSpec::Cdp {
+                    debugger_url,
+                    session_cookie,
+                    session_header,
+                } => {
+                    let mut driver = CdpDriver::new(debugger_url);
+                    if let Some(name) = session_cookie {
+                        driver = driver.with_session_cookie(name);
+                    }
+                    if let Some(name) = session_header {
+                        driver = driver.with_session_header(name);
+                    }
+                    drivers.push(Arc::new(driver));
+                }
+                DriverSpec::Android { adb, serial } => {
+                    let mut transport = AdbCommand::new(adb);
+                    if let Some(serial) = serial {
+                        transport = transport.with_serial(serial);
+                    }
+                    let android = Arc::new(AndroidDriver::new(Arc::new(transport)));
+                    drivers.push(android.clone());
+                    drivers.push(Arc::new(HybridDriver::new(android)));
+                }
+            }
+        }
+        let mut engine = AutomationEngine::new(drivers, Arc::new(NoopCaptcha));
+        engine.set_trace_config(self.trace.clone());
+        if let Some(pool) = &self.pool {
+            engine.set_session_pool(Arc::new(SessionPool::new(pool.clone())));
+        }
+        engine.set_circuit_breaker(self.breaker.clone());
+        engine
+    }
+}
+
+/// Limits applied to the worker process. `None` keeps the inherited limit.
+#[derive(Debug, Clone)]
+pub struct ResourceLimits {
+    /// Address-space limit (`RLIMIT_AS`).
+    pub memory_bytes: Option<u64>,
+    /// CPU time limit (`RLIMIT_CPU`) for one worker, i.e. for up to
+    /// `WorkerConfig::max_runs` runs; the worker is killed when it is used
+    /// up.
+    pub cpu_secs: Option<u64>,
+}
+
+impl Default for ResourceLimits {
+    fn default() -> Self {
+        Self {
+            memory_bytes: Some(2 << 30),
+            cpu_secs: Some(600),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct WorkerConfig {
+    pub program: PathBuf,
+    pub args: Vec<String>,
+    pub engine: EngineSettings,
+    pub limits: ResourceLimits,
+    /// Runs one worker serves before it is replaced by a fresh one.
+    pub max_runs: u32,
+    /// Consecutive crashes after which the supervisor stops restarting.
+    pub max_restarts: u32,
+    /// Delay before a restart; multiplied by the number of crashes in a row.
+    pub restart_delay: Duration,
+    /// Quiet time after the last crash that clears the crash count, so a
+    /// supervisor that gave up tries again later.
+    pub restart_cooldown: Duration,
+}
+
+impl WorkerConfig {
+    pub fn new(program: impl Into<PathBuf>) -> Self {
+        Self {
+            program: program.into(),
+            args: Vec::new(),
+            engine: EngineSettings::default(),
+            limits: ResourceLimits::default(),
+            max_runs: 20,
+            max_restarts: 5,
+            restart_delay: Duration::from_millis(500),
+            restart_cooldown: Duration::from_secs(300),
+        }
+    }
+
+    /// Worker binary in the same directory as the running executable.
+    pub fn beside_current_exe() -> anyhow::Result<Self> {
+        let exe = std::env::current_exe()?;
+        let dir = exe
+            .parent()
+            .ok_or_else(|| anyhow::anyhow!("executable {} has no parent", exe.display()))?;
+        Ok(Self::new(dir.join(format!(
+            "{WORKER_BINARY}{}",
+            std::env::consts::EXE_SUFFIX
+        ))))
+    }
+}
+
+struct WorkerProcess {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: Lines<BufReader<ChildStdout>>,
+    runs: u32,
+}
+
+impl WorkerProcess {
+    async fn send(&mut self, request: &WorkerRequest) -> anyhow::Result<()> {
+        let mut line = serde_json::to_vec(request)?;
+        line.push(b'\n');
+        self.stdin.write_all(&line).await?;
+        Ok(self.stdin.flush().await?)
+    }
+
+    async fn receive(&mut self) -> anyhow::Result<Option<WorkerMessage>> {
+        match self.stdout.next_line().await? {
+            Some(line) => Ok(Some(serde_json::from_str(&line).map_err(|err| {
+                anyhow::anyhow!("invalid worker message {line:?}: {err}")
+            })?)),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Runs scripts in worker processes, one at a time.
+pub struct WorkerSupervisor {
+    config: WorkerConfig,
+    secrets: Arc<dyn SecretResolver>,
+    process: Mutex<Option<WorkerProcess>>,
+    crashes: AtomicU32,
+    last_crash: std::sync::Mutex<Option<Instant>>,
+    restarts: AtomicU64,
+    events: broadcast::Sender<ExecutionEvent>,
+    next_run_id: AtomicU64,
+    active: std::sync::Mutex<Option<(RunId, RunControl)>>,
+}
+
+impl WorkerSupervisor {
+    pub fn new(config: WorkerConfig, secrets: Arc<dyn SecretResolver>) -> Self {
+        Self {
+            config,
+            secrets,
+            process: Mutex::new(None),
+            crashes: AtomicU32::new(0),
+            last_crash: std::sync::Mutex::new(None),
+            restarts: AtomicU64::new(0),
+            events: broadcast::channel(1024).0,
+            next_run_id: AtomicU64::new(1),
+            active: std::sync::Mutex::new(None),
+        }
+    }
+
+    /// Execution events relayed from the worker, like
+    /// `AutomationEngine::subscribe`. Run ids are the supervisor's own.
+    pub fn subscribe(&self) -> broadcast::Receiver<ExecutionEvent> {
+        self.events.subscribe()
+    }
+
+    /// How often a crashed worker has been replaced.
+    pub fn restarts(&self) -> u64 {
+        self.restarts.load(Ordering::Relaxed)
+    }
+
+    /// Control handle of the run in flight, like
+    /// `AutomationEngine::active_run`.
+    pub fn active_run(&self, run_id: RunId) -> Option<RunControl> {
+        self.active
+            .lock()
+            .unwrap()
+            .as_ref()
+            .filter(|(active, _)| *active == run_id)
+            .map(|(_, control)| control.clone())
+    }
+
+    pub async fn run(&self, script: &LoginScript) -> anyhow::Result<LoginOutcome> {
+        self.run_with(script, RunControl::new()).await
+    }
+
+    /// Run `script` in the worker; pausing, resuming and cancelling
+    /// `control` is forwarded to it.
+    pub async fn run_with(
+        &self,
+        script: &LoginScript,
+        control: RunControl,
+    ) -> anyhow::Result<LoginOutcome> {
+        let secrets = self.secrets_for(script);
+        let mut guard = self.process.lock().await;
+        let run_id = self.next_run_id.fetch_add(1, Ordering::Relaxed);
+        *self.active.lock().unwrap() = Some((run_id, control.clone()));
+        let result = self.run_in_worker(&mut guard, script, secrets, run_id, control).await;
+        *self.active.lock().unwrap() = None;
+        // Past `max_runs` the next run starts a fresh worker with its full
+        // CPU allowance.
+        if guard
+            .as_ref()
+            .is_some_and(|process| process.runs >= self.config.max_runs)
+        {
+            retire(guard.take().expect("worker is running")).await;
+        }
+        result
+    }
+
+    async fn run_in_worker(
+        &self,
+        guard: &mut Option<WorkerProcess>,
+        script: &LoginScript,
+        secrets: HashMap<String, String>,
+        run_id: RunId,
+        control: RunControl,
+    ) -> anyhow::Result<LoginOutcome> {
+        let process = self.ensure_running(guard).await?;
+        process.runs += 1;
+        let request = WorkerRequest::Run {
+            script: Box::new(script.clone()),
+            secrets,
+        };
+        if let Err(err) = process.send(&request).await {
+            return Err(self.crashed(guard, err).await);
+        }
+        drop(request);
+
+        // State of the worker's own control, which starts out running.
+        let mut relayed = RunState::Running;
+        loop {
+            let message = tokio::select! {
+                message = process.receive() => message,
+                state = control.changed_from(relayed), if relayed != RunState::Cancelled => {
+                    relayed = state;
+                    let request = match state {
+                        RunState::Running => WorkerRequest::Resume,
+                        RunState::Paused => WorkerRequest::Pause,
+                        RunState::Cancelled => WorkerRequest::Cancel,
+                    };
+                    if let Err(err) = process.send(&request).await {
+                        return Err(self.crashed(guard, err).await);
+                    }
+                    continue;
+                }
+            };
+            match message {
+                Ok(Some(WorkerMessage::Event(event))) => {
+                    let _ = self.events.send(ExecutionEvent { run_id, ..event });
+                }
+                Ok(Some(WorkerMessage::Finished(outcome))) => {
+                    self.crashes.store(0, Ordering::Relaxed);
+                    return Ok(outcome);
+                }
+                Ok(Some(WorkerMessage::Failed(error))) => {
+                    self.crashes.store(0, Ordering::Relaxed);
+                    return Err(anyhow::anyhow!(error));
+                }
+                Ok(Some(WorkerMessage::Ready)) => {}
+                Ok(None) => {
+                    let err = anyhow::anyhow!("worker closed its output");
+                    return Err(self.crashed(guard, err).await);
+                }
+                Err(err) => return Err(self.crashed(guard, err).await),
+            }
+        }
+    }
+
+    /// Stop the worker, letting it close its sessions; the next run starts
+    /// a fresh one.
+    pub async fn shutdown(&self) {
+        if let Some(process) = self.process.lock().await.take() {
+            retire(process).await;
+        }
+    }
+
+    /// Plaintext of the keys `script` references. Keys the resolver cannot
+    /// provide are left out; the run fails at the step that needs them.
+    fn secrets_for(&self, script: &LoginScript) -> HashMap<String, String> {
+        script
+            .vault_keys()
+            .into_iter()
+            .filter_map(|key| match self.secrets.resolve(&key) {
+                Ok(value) => Some((key, value)),
+                Err(err) => {
+                    tracing::debug!(key, error = %err, "secret not passed to worker");
+                    None
+                }
+            })
+            .collect()
+    }
+
+    async fn ensure_running<'a>(
+        &self,
+        slot: &'a mut Option<WorkerProcess>,
+    ) -> anyhow::Result<&'a mut WorkerProcess> {
+        if slot.is_none() {
+            self.forget_old_crashes();
+            let crashes = self.crashes.load(Ordering::Relaxed);
+            if crashes > self.config.max_restarts {
+                return Err(anyhow::anyhow!(
+                    "automation worker crashed {crashes} times in a row; not restarting"
+                ));
+            }
+            if crashes > 0 {
+                tokio::time::sleep(self.config.restart_delay * crashes).await;
+                self.restarts.fetch_add(1, Ordering::Relaxed);
+            }
+            *slot = Some(self.spawn().await?);
+        }
+        Ok(slot.as_mut().expect("worker was just started"))
+    }
+
+    async fn spawn(&self) -> anyhow::Result<WorkerProcess> {
+        let mut command = Command::new(&self.config.program);
+        command
+            .args(&self.config.args)
+            .env_clear()
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .kill_on_drop(true);
+        // Drivers still need to find adb and friends, and adb its keys
+        // under HOME.
+        for name in ["PATH", "HOME", "RUST_LOG"] {
+            if let Some(value) = std::env::var_os(name) {
+                command.env(name, value);
+            }
+        }
+        apply_limits(&mut command, &self.config.limits);
+
+        let mut child = command.spawn().map_err(|err| {
+            anyhow::anyhow!("failed to start {}: {err}", self.config.program.display())
+        })?;
+        let stdin = child.stdin.take().expect("stdin is piped");
+        let stdout = child.stdout.take().expect("stdout is piped");
+        let mut process = WorkerProcess {
+            child,
+            stdin,
+            stdout: BufReader::new(stdout).lines(),
+            runs: 0,
+        };
+        process
+            .send(&WorkerRequest::Init(Box::new(self.config.engine.clone())))
+            .await?;
+        match process.receive().await? {
+            Some(WorkerMessage::Ready) => {
+                tracing::info!(pid = process.child.id(), "automation worker started");
+                Ok(process)
+            }
+            other => Err(anyhow::anyhow!(
+                "automation worker did not report ready: {other:?}"
+            )),
+        }
+    }
+
+    /// Clear the crash count once the last crash is `restart_cooldown` old.
+    fn forget_old_crashes(&self) {
+        let mut last_crash = self.last_crash.lock().unwrap();
+        if last_crash.is_some_and(|at| at.elapsed() >= self.config.restart_cooldown) {
+            tracing::info!("automation worker crash count reset after cool-down");
+            self.crashes.store(0, Ordering::Relaxed);
+            *last_crash = None;
+        }
+    }
+
+    /// Reap a dead worker; returns the error for the run that was in flight.
+    /// The replacement starts with the next run.
+    async fn crashed(&self, slot: &mut Option<WorkerProcess>, err: anyhow::Error) -> anyhow::Error {
+        let status = match slot.take() {
+            Some(mut process) => exit_status(&mut process.child).await,
+            None => None,
+        };
+        let crashes = self.crashes.fetch_add(1, Ordering::Relaxed) + 1;
+        *self.last_crash.lock().unwrap() = Some(Instant::now());
+        tracing::warn!(?status, crashes, error = %err, "automation worker crashed");
+        match status {
+            Some(status) => anyhow::anyhow!("automation worker died during the run ({status})"),
+            None => anyhow::anyhow!("automation worker failed during the run: {err}"),
+        }
+    }
+}
+
+#[async_trait]
+impl LoginRunner for WorkerSupervisor {
+    async fn run(&self, script: &LoginScript) -> anyhow::Result<LoginOutcome> {
+        WorkerSupervisor::run(self, script).await
+    }
+}
+
+/// Close a finished worker's input so it exits, killing it if it lingers.
+async fn retire(mut process: WorkerProcess) {
+    drop(process.stdin);
+    if let Some(status) = exit_status(&mut process.child).await {
+        tracing::debug!(%status, "automation worker retired");
+    }
+}
+
+/// Exit status of a worker that stopped talking, killing it if it still
+/// runs.
+async fn exit_status(child: &mut Child) -> Option<ExitStatus> {
+    match tokio::time::timeout(Duration::from_secs(1), child.wait()).await {
+        Ok(status) => status.ok(),
+        Err(_) => {
+            let _ = child.kill().await;
+            child.wait().await.ok()
+        }
+    }
+}
+
+#[cfg(unix)]
+fn apply_limits(command: &mut Command, limits: &ResourceLimits) {
+    let limits = limits.clone();
+    // SAFETY: the closure runs between fork and exec and only calls
+    // `setrlimit`, which is async-signal-safe.
+    unsafe {
+        command.pre_exec(move || {
+            let set = |resource, value: u64| {
+                let limit = libc::rlimit {
+                    rlim_cur: value as libc::rlim_t,
+                    rlim_max: value as libc::rlim_t,
+                };
+                if libc::setrlimit(resource, &limit) == 0 {
+                    Ok(())
+                } else {
+                    Err(std::io::Error::last_os_error())
+                }
+            };
+            if let Some(bytes) = limits.memory_bytes {
+                set(libc::RLIMIT_AS, bytes)?;
+            }
+            if let Some(secs) = limits.cpu_secs {
+                set(libc::RLIMIT_CPU, secs)?;
+            }
+            Ok(())
+        });
+    }
+}
+
+#[cfg(not(unix))]
+fn apply_limits(_command: &mut Command, limits: &ResourceLimits) {
+    if limits.memory_bytes.is_some() || limits.cpu_secs.is_some() {
+        tracing::warn!("resource limits for the automation worker are only enforced on unix");
+    }
+}
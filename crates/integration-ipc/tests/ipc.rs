@@ -0,0 +1,128 @@
+//! Run control over IPC while a run request is still being handled.
+
+use async_trait::async_trait;
+use automation_engine::{
+    AutomationDriver, AutomationEngine, DriverSession, ExecutionEventKind, LoginScript,
+    NoopCaptcha, ScriptMeta, Selector, Step, TargetApp, TargetAppKind,
+};
+use integration_ipc::{IpcHandler, IpcRequest, IpcResponse};
+use secure_vault::CredentialVault;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Runs scripts through the shared step runner on a page with nothing on it.
+struct BlankDriver;
+
+#[async_trait]
+impl AutomationDriver for BlankDriver {
+    fn name(&self) -> &'static str {
+        "blank"
+    }
+
+    fn supports(&self, target: &TargetApp) -> bool {
+        target.kind == TargetAppKind::Web
+    }
+
+    async fn open_session(&self, _target: &TargetApp) -> anyhow::Result<Box<dyn DriverSession>> {
+        Ok(Box::new(BlankSession))
+    }
+}
+
+struct BlankSession;
+
+#[async_trait]
+impl DriverSession for BlankSession {
+    async fn exists(&mut self, _selector: &Selector) -> anyhow::Result<bool> {
+        Ok(false)
+    }
+
+    async fn click(&mut self, selector: &Selector) -> anyhow::Result<()> {
+        anyhow::bail!("{selector:?} not found")
+    }
+
+    async fn input(&mut self, selector: &Selector, _text: &str) -> anyhow::Result<()> {
+        anyhow::bail!("{selector:?} not found")
+    }
+
+    async fn swipe(
+        &mut self,
+        from: &Selector,
+        _to: &Selector,
+        _duration_ms: u64,
+    ) -> anyhow::Result<()> {
+        anyhow::bail!("{from:?} not found")
+    }
+
+    async fn text(&mut self, _selector: &Selector) -> anyhow::Result<Option<String>> {
+        Ok(None)
+    }
+
+    async fn capture(&mut self, selector: &Selector) -> anyhow::Result<Vec<u8>> {
+        anyhow::bail!("{selector:?} not found")
+    }
+
+    async fn screenshot(&mut self) -> anyhow::Result<Vec<u8>> {
+        Ok(Vec::new())
+    }
+
+    async fn session_token(&mut self) -> anyhow::Result<Option<String>> {
+        Ok(None)
+    }
+}
+
+fn sleeping_script() -> LoginScript {
+    LoginScript {
+        meta: ScriptMeta {
+            id: "slow".into(),
+            version: "1.0.0".into(),
+            author: None,
+            created_at: None,
+            updated_at: None,
+        },
+        target: TargetApp {
+            kind: TargetAppKind::Web,
+            name: "mail".into(),
+            version: None,
+            endpoint: None,
+        },
+        steps: vec![Step::SleepMs(60_000)],
+        validations: Vec::new(),
+        error_handlers: Vec::new(),
+        token: None,
+        retry: None,
+    }
+}
+
+#[tokio::test]
+async fn cancel_run_while_run_script_is_pending() {
+    let engine = AutomationEngine::new(vec![Arc::new(BlankDriver)], Arc::new(NoopCaptcha));
+    let vault = CredentialVault::default();
+    let ipc = IpcHandler {
+        vault: &vault,
+        automation: &engine,
+        keepalive: None,
+        worker: None,
+    };
+    let mut events = engine.subscribe();
+
+    let run = ipc.handle(IpcRequest::RunScript(sleeping_script()));
+    let cancel = async {
+        let run_id = loop {
+            let event = events.recv().await.unwrap();
+            if let ExecutionEventKind::RunStarted { .. } = event.kind {
+                break event.run_id;
+            }
+        };
+        ipc.handle(IpcRequest::CancelRun(run_id)).await.unwrap()
+    };
+    let (run, cancel) =
+        tokio::time::timeout(Duration::from_secs(10), async { tokio::join!(run, cancel) })
+            .await
+            .expect("run was not cancelled");
+
+    assert!(matches!(cancel, IpcResponse::Ack), "{cancel:?}");
+    match run.unwrap() {
+        IpcResponse::ScriptResult(outcome) => assert!(outcome.cancelled, "{outcome:?}"),
+        other => panic!("unexpected response {other:?}"),
+    }
+}
@@ -0,0 +1,320 @@
+//! `KeepAliveScheduler::tick` and `run` driven by a `ManualClock` and a driver
+//! that replays canned outcomes.
+
+use async_trait::async_trait;
+use automation_engine::{
+    AutomationDriver, AutomationEngine, ExecutionContext, LoginOutcome, LoginScript, NoopCaptcha,
+    ScriptMeta, SessionToken, TargetApp, TargetAppKind,
+};
+use integration_ipc::keepalive::{Clock, ManualClock};
+use integration_ipc::{KeepAliveConfig, KeepAliveEvent, KeepAliveScheduler, TracingEmitter};
+use secure_vault::{CredentialEntry, CredentialVault};
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+const START: u64 = 1_000_000;
+
+/// Answers each run of a script (by id) with the next queued outcome and
+/// counts the runs.
+#[derive(Default)]
+struct ScriptedDriver {
+    outcomes: Mutex<HashMap<String, VecDeque<LoginOutcome>>>,
+    runs: Mutex<HashMap<String, u32>>,
+}
+
+impl ScriptedDriver {
+    fn push(&self, script_id: &str, outcome: LoginOutcome) {
+        self.outcomes
+            .lock()
+            .unwrap()
+            .entry(script_id.into())
+            .or_default()
+            .push_back(outcome);
+    }
+
+    fn runs(&self, script_id: &str) -> u32 {
+        self.runs
+            .lock()
+            .unwrap()
+            .get(script_id)
+            .copied()
+            .unwrap_or(0)
+    }
+}
+
+#[async_trait]
+impl AutomationDriver for ScriptedDriver {
+    fn name(&self) -> &'static str {
+        "scripted"
+    }
+
+    fn supports(&self, target: &TargetApp) -> bool {
+        target.kind == TargetAppKind::Web
+    }
+
+    async fn execute(
+        &self,
+        script: &LoginScript,
+        _ctx: &ExecutionContext,
+    ) -> anyhow::Result<LoginOutcome> {
+        *self
+            .runs
+            .lock()
+            .unwrap()
+            .entry(script.meta.id.clone())
+            .or_default() += 1;
+        self.outcomes
+            .lock()
+            .unwrap()
+            .get_mut(&script.meta.id)
+            .and_then(VecDeque::pop_front)
+            .ok_or_else(|| anyhow::anyhow!("no outcome queued for {}", script.meta.id))
+    }
+}
+
+fn script(id: &str) -> LoginScript {
+    LoginScript {
+        meta: ScriptMeta {
+            id: id.into(),
+            version: "1.0.0".into(),
+            author: None,
+            created_at: None,
+            updated_at: None,
+        },
+        target: TargetApp {
+            kind: TargetAppKind::Web,
+            name: "mail".into(),
+            version: None,
+            endpoint: None,
+        },
+        steps: Vec::new(),
+        validations: Vec::new(),
+        error_handlers: Vec::new(),
+        token: None,
+        retry: None,
+    }
+}
+
+fn success(token: &str, expires_at: Option<u64>) -> LoginOutcome {
+    LoginOutcome {
+        success: true,
+        session_token: Some(SessionToken {
+            value: token.into(),
+            source: None,
+            expires_at,
+        }),
+        error: None,
+        cancelled: false,
+        error_kind: None,
+        attempts: Vec::new(),
+    }
+}
+
+fn failure(error: &str) -> LoginOutcome {
+    LoginOutcome {
+        success: false,
+        session_token: None,
+        error: Some(error.into()),
+        cancelled: false,
+        error_kind: None,
+        attempts: Vec::new(),
+    }
+}
+
+struct Fixture {
+    clock: Arc<ManualClock>,
+    driver: Arc<ScriptedDriver>,
+    engine: AutomationEngine,
+    vault: CredentialVault,
+    scheduler: KeepAliveScheduler,
+}
+
+fn fixture(token_expires_at: Option<u64>) -> Fixture {
+    let clock = Arc::new(ManualClock::new(START));
+    let driver = Arc::new(ScriptedDriver::default());
+    let engine = AutomationEngine::new(vec![driver.clone()], Arc::new(NoopCaptcha));
+    let vault = CredentialVault::default();
+    vault
+        .store(CredentialEntry {
+            id: "alice".into(),
+            username: "alice".into(),
+            secret: "hunter2".into(),
+            token: Some("old-token".into()),
+            metadata: None,
+            token_expires_at,
+        })
+        .unwrap();
+    let scheduler = KeepAliveScheduler::new(
+        vault.clone(),
+        clock.clone(),
+        Arc::new(TracingEmitter),
+        KeepAliveConfig {
+            refresh_before_secs: 300,
+            probe_interval_secs: 900,
+            backoff_base_secs: 30,
+            backoff_max_secs: 100,
+            max_failures: 4,
+        },
+    );
+    Fixture {
+        clock,
+        driver,
+        engine,
+        vault,
+        scheduler,
+    }
+}
+
+#[tokio::test]
+async fn refreshes_shortly_before_expiry() {
+    let expires_at = START + 3_600;
+    let f = fixture(Some(expires_at));
+    f.scheduler.track("alice", script("login"), None);
+
+    // Far from expiry: nothing to do, next look after one probe interval.
+    assert!(f.scheduler.tick(&f.engine).await.is_empty());
+    assert_eq!(f.driver.runs("login"), 0);
+    assert_eq!(f.scheduler.next_due(), Some(START + 900));
+
+    f.clock.set(expires_at - 300);
+    f.driver
+        .push("login", success("new-token", Some(expires_at + 3_600)));
+    assert_eq!(
+        f.scheduler.tick(&f.engine).await,
+        vec![KeepAliveEvent::Refreshed {
+            credential_id: "alice".into(),
+            expires_at: Some(expires_at + 3_600),
+        }]
+    );
+    let stored = f.vault.fetch("alice").unwrap();
+    assert_eq!(stored.token.as_deref(), Some("new-token"));
+    assert_eq!(stored.token_expires_at, Some(expires_at + 3_600));
+}
+
+#[tokio::test]
+async fn valid_probe_keeps_the_token() {
+    let f = fixture(None);
+    f.scheduler
+        .track("alice", script("login"), Some(script("probe")));
+    f.driver.push("probe", success("ignored", None));
+
+    assert_eq!(
+        f.scheduler.tick(&f.engine).await,
+        vec![KeepAliveEvent::StillValid {
+            credential_id: "alice".into(),
+        }]
+    );
+    assert_eq!(f.driver.runs("probe"), 1);
+    assert_eq!(f.driver.runs("login"), 0);
+    assert_eq!(
+        f.vault.fetch("alice").unwrap().token.as_deref(),
+        Some("old-token")
+    );
+    assert_eq!(f.scheduler.next_due(), Some(START + 900));
+}
+
+#[tokio::test]
+async fn failed_probe_falls_back_to_login() {
+    let f = fixture(None);
+    f.scheduler
+        .track("alice", script("login"), Some(script("probe")));
+    f.driver.push("probe", failure("logged out"));
+    f.driver.push("login", success("new-token", None));
+
+    assert!(matches!(
+        f.scheduler.tick(&f.engine).await.as_slice(),
+        [KeepAliveEvent::Refreshed { .. }]
+    ));
+    assert_eq!(f.driver.runs("login"), 1);
+}
+
+#[tokio::test]
+async fn failures_back_off_and_give_up() {
+    let f = fixture(Some(START));
+    f.scheduler.track("alice", script("login"), None);
+
+    let mut retry_times = Vec::new();
+    for attempt in 1..=3 {
+        f.driver.push("login", failure("wrong password"));
+        let events = f.scheduler.tick(&f.engine).await;
+        let [KeepAliveEvent::ReloginFailed {
+            attempt: reported,
+            retry_at,
+            error,
+            ..
+        }] = events.as_slice()
+        else {
+            panic!("unexpected events {events:?}");
+        };
+        assert_eq!(*reported, attempt);
+        assert_eq!(error, "wrong password");
+        retry_times.push(retry_at - f.clock.now());
+
+        // Not due again before the backoff has passed.
+        f.clock.set(retry_at - 1);
+        assert!(f.scheduler.tick(&f.engine).await.is_empty());
+        f.clock.set(*retry_at);
+    }
+    // Doubling from 30s, capped at 100s.
+    assert_eq!(retry_times, vec![30, 60, 100]);
+
+    f.driver.push("login", failure("wrong password"));
+    assert_eq!(
+        f.scheduler.tick(&f.engine).await,
+        vec![KeepAliveEvent::GaveUp {
+            credential_id: "alice".into(),
+        }]
+    );
+    assert_eq!(f.scheduler.next_due(), None);
+    assert_eq!(f.driver.runs("login"), 4);
+}
+
+#[tokio::test]
+async fn missing_credential_backs_off() {
+    let f = fixture(None);
+    f.scheduler.track("bob", script("login"), None);
+
+    let events = f.scheduler.tick(&f.engine).await;
+    assert!(
+        matches!(
+            events.as_slice(),
+            [KeepAliveEvent::ReloginFailed { credential_id, attempt: 1, retry_at, .. }]
+                if credential_id == "bob" && *retry_at == START + 30
+        ),
+        "{events:?}"
+    );
+    assert_eq!(f.driver.runs("login"), 0);
+}
+
+#[tokio::test]
+async fn run_wakes_up_for_newly_tracked_credentials() {
+    let Fixture {
+        clock,
+        driver,
+        engine,
+        scheduler,
+        ..
+    } = fixture(Some(START));
+    let (engine, scheduler) = (Arc::new(engine), Arc::new(scheduler));
+    let runner = tokio::spawn({
+        let (engine, scheduler) = (engine.clone(), scheduler.clone());
+        async move { scheduler.run(engine.as_ref()).await }
+    });
+    // Let the loop find nothing to do and go to sleep for a probe interval.
+    tokio::task::yield_now().await;
+
+    driver.push("login", success("new-token", Some(START + 3_600)));
+    scheduler.track("alice", script("login"), None);
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while driver.runs("login") == 0 {
+            tokio::task::yield_now().await;
+        }
+    })
+    .await
+    .expect("tracked credential was not checked");
+    // The manual clock never moved: the loop woke up without sleeping out
+    // its 900s wait.
+    assert_eq!(clock.now(), START);
+    runner.abort();
+}
@@ -0,0 +1,61 @@
+//! Crash handling of `WorkerSupervisor`, with `sh` standing in for a worker
+//! that dies as soon as it receives a run.
+#![cfg(unix)]
+
+use automation_engine::{LoginScript, NoSecrets, ScriptMeta, TargetApp, TargetAppKind};
+use integration_ipc::{WorkerConfig, WorkerSupervisor};
+use std::sync::Arc;
+use std::time::Duration;
+
+fn crashing_worker(cooldown: Duration) -> WorkerSupervisor {
+    let mut config = WorkerConfig::new("sh");
+    config.args = vec![
+        "-c".into(),
+        r#"echo '"Ready"'; read request; exit 3"#.into(),
+    ];
+    config.max_restarts = 1;
+    config.restart_delay = Duration::ZERO;
+    config.restart_cooldown = cooldown;
+    WorkerSupervisor::new(config, Arc::new(NoSecrets))
+}
+
+fn script() -> LoginScript {
+    LoginScript {
+        meta: ScriptMeta {
+            id: "crash".into(),
+            version: "1.0.0".into(),
+            author: None,
+            created_at: None,
+            updated_at: None,
+        },
+        target: TargetApp {
+            kind: TargetAppKind::Web,
+            name: "mail".into(),
+            version: None,
+            endpoint: None,
+        },
+        steps: Vec::new(),
+        validations: Vec::new(),
+        error_handlers: Vec::new(),
+        token: None,
+        retry: None,
+    }
+}
+
+async fn run_error(supervisor: &WorkerSupervisor) -> String {
+    supervisor.run(&script()).await.unwrap_err().to_string()
+}
+
+#[tokio::test]
+async fn gives_up_after_max_restarts_until_the_cooldown_passed() {
+    let supervisor = crashing_worker(Duration::from_millis(300));
+
+    assert!(run_error(&supervisor).await.contains("died during the run"));
+    assert!(run_error(&supervisor).await.contains("died during the run"));
+    assert_eq!(supervisor.restarts(), 1);
+    assert!(run_error(&supervisor).await.contains("not restarting"));
+
+    tokio::time::sleep(Duration::from_millis(350)).await;
+    assert!(run_error(&supervisor).await.contains("died during the run"));
+    assert_eq!(supervisor.restarts(), 1, "first start after the reset");
+}
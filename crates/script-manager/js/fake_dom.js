@@ -0,0 +1,160 @@
+// Just enough of a DOM to run recorder.js under node: elements with
+// attributes, text and labels, selector matching for the selectors the
+// recorder uses, event listeners, history and timers run by hand.
+"use strict";
+
+const FORM_FIELDS = new Set(["input", "select", "textarea"]);
+
+class FakeElement {
+  constructor(tag, attributes, children) {
+    this.nodeType = 1;
+    this.localName = tag;
+    this.attributes = attributes;
+    this.parentNode = null;
+    this.children = [];
+    this.ownText = "";
+    for (const child of children) {
+      if (typeof child === "string") {
+        this.ownText += child;
+      } else {
+        child.parentNode = this;
+        this.children.push(child);
+      }
+    }
+    this.id = attributes.id || "";
+    this.classList = (attributes.class || "").split(/\s+/).filter(Boolean);
+    if (FORM_FIELDS.has(tag)) {
+      this.type = attributes.type || "text";
+      this.value = attributes.value || "";
+    }
+  }
+
+  get textContent() {
+    return this.ownText + this.children.map((child) => child.textContent).join("");
+  }
+
+  getAttribute(name) {
+    return Object.prototype.hasOwnProperty.call(this.attributes, name)
+      ? this.attributes[name]
+      : null;
+  }
+
+  getClientRects() {
+    return this.attributes.hidden === undefined ? [{}] : [];
+  }
+
+  get labels() {
+    if (!FORM_FIELDS.has(this.localName)) {
+      return undefined;
+    }
+    const root = this.root();
+    return root
+      .descendants()
+      .filter(
+        (label) =>
+          label.localName === "label" &&
+          ((this.id && label.getAttribute("for") === this.id) || label.contains(this))
+      );
+  }
+
+  root() {
+    let node = this;
+    while (node.parentNode) {
+      node = node.parentNode;
+    }
+    return node;
+  }
+
+  contains(other) {
+    for (let node = other; node; node = node.parentNode) {
+      if (node === this) {
+        return true;
+      }
+    }
+    return false;
+  }
+
+  descendants() {
+    return this.children.flatMap((child) => [child, ...child.descendants()]);
+  }
+
+  // Comma-separated lists of `tag`, `[attr=value]` and `tag[attr=value]`.
+  matches(selectors) {
+    return selectors.split(",").some((selector) => {
+      const [, tag, attribute, value] = selector.trim().match(/^([a-z]*)(?:\[([a-z-]+)=([^\]]+)\])?$/);
+      return (
+        (!tag || tag === this.localName) &&
+        (!attribute || this.getAttribute(attribute) === value.replace(/^"|"$/g, ""))
+      );
+    });
+  }
+
+  closest(selectors) {
+    for (let node = this; node; node = node.parentNode) {
+      if (node.matches(selectors)) {
+        return node;
+      }
+    }
+    return null;
+  }
+}
+
+function el(tag, attributes = {}, ...children) {
+  return new FakeElement(tag, attributes, children);
+}
+
+function listeners() {
+  const registered = {};
+  return {
+    addEventListener(type, listener) {
+      (registered[type] = registered[type] || []).push(listener);
+    },
+    dispatch(type, event = {}) {
+      for (const listener of registered[type] || []) {
+        listener(event);
+      }
+    },
+  };
+}
+
+// A loaded page showing `body` at `url`.
+function page(url, body) {
+  const html = el("html", {}, el("head"), body);
+  const document = {
+    ...listeners(),
+    readyState: "complete",
+    documentElement: html,
+    body,
+    querySelectorAll: (selectors) => html.descendants().filter((node) => node.matches(selectors)),
+  };
+  const timers = [];
+  const window = {
+    ...listeners(),
+    document,
+    location: { href: url },
+    history: {
+      pushState(state, title, to) {
+        window.location.href = new URL(to, window.location.href).href;
+      },
+      replaceState(state, title, to) {
+        window.location.href = new URL(to, window.location.href).href;
+      },
+    },
+    setTimeout(callback, delay) {
+      timers.push({ callback, delay });
+    },
+    // Run the timers set so far; returns their delays.
+    runTimers() {
+      return timers.splice(0).map(({ callback, delay }) => {
+        callback();
+        return delay;
+      });
+    },
+  };
+  // Events dispatched on an element reach the document's capturing
+  // listeners, which is where the recorder listens.
+  window.fire = (type, target) => document.dispatch(type, { target });
+  return window;
+}
+
+module.exports = { el, page };
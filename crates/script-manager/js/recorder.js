@@ -0,0 +1,154 @@
+// Injected into pages under recording (Page.addScriptToEvaluateOnNewDocument
+// plus a Runtime.addBinding named __aiHelperRecord). Reports clicks,
+// keystrokes, changes and navigations as JSON events whose shape matches
+// `RecordedEvent` in recorder.rs.
+//
+// Loaded with `require` (node, jsdom) nothing is installed; the helpers are
+// exported so the event shape can be tested without a browser.
+(function (root, factory) {
+  const api = factory();
+  if (typeof module === "object" && module.exports) {
+    module.exports = api;
+  } else if (typeof root[api.BINDING] === "function" && !root.__aiHelperRecorder) {
+    root.__aiHelperRecorder = true;
+    api.install(root, (event) => root[api.BINDING](JSON.stringify(event)));
+  }
+})(typeof window !== "undefined" ? window : globalThis, function () {
+  const BINDING = "__aiHelperRecord";
+  const TEXT_LIMIT = 80;
+  const CONTROL_LIMIT = 100;
+  // How long a single-page app gets to render a new route before its
+  // controls are reported again.
+  const RENDER_DELAY_MS = 500;
+
+  // Absolute path with positions only where siblings share a tag, e.g.
+  // /html/body/form/div[2]/input.
+  function xpath(element) {
+    const parts = [];
+    for (let node = element; node && node.nodeType === 1; node = node.parentNode) {
+      const tag = node.localName;
+      const siblings = node.parentNode ? Array.from(node.parentNode.children) : [node];
+      const same = siblings.filter((sibling) => sibling.localName === tag);
+      parts.unshift(same.length > 1 ? `${tag}[${same.indexOf(node) + 1}]` : tag);
+    }
+    return "/" + parts.join("/");
+  }
+
+  function text(element) {
+    const value = (element.innerText || element.textContent || "").trim().replace(/\s+/g, " ");
+    return value && value.length <= TEXT_LIMIT ? value : null;
+  }
+
+  // Text of the <label> attached to a form field, if any.
+  function label(element) {
+    const labels = element.labels ? Array.from(element.labels) : [];
+    const value = labels.map(text).filter(Boolean).join(" ");
+    return value || null;
+  }
+
+  function describe(element) {
+    return {
+      tag: element.localName,
+      id: element.id || null,
+      name: element.getAttribute("name"),
+      classes: Array.from(element.classList || []),
+      aria_label: element.getAttribute("aria-label"),
+      text: text(element),
+      xpath: xpath(element),
+      input_type: element.getAttribute("type"),
+      autocomplete: element.getAttribute("autocomplete"),
+      placeholder: element.getAttribute("placeholder"),
+      label: label(element),
+      role: element.getAttribute("role"),
+    };
+  }
+
+  // Clicks land on the innermost node; record the control the user meant.
+  function clickable(target) {
+    const control =
+      target.closest && target.closest("button, a, input, select, textarea, label, [role=button]");
+    return control || target;
+  }
+
+  // Checkboxes and radios are recorded as the click that toggled them.
+  function togglable(element) {
+    return element.localName === "input" && /^(checkbox|radio)$/i.test(element.type || "");
+  }
+
+  // Visible buttons and links, so the recorder can see what appeared after
+  // logging in (a logout button, an account menu).
+  function controls(document) {
+    const found = document.querySelectorAll(
+      "button, a, [role=button], input[type=submit], input[type=button]"
+    );
+    return Array.from(found)
+      .filter((element) => !element.getClientRects || element.getClientRects().length > 0)
+      .slice(0, CONTROL_LIMIT)
+      .map(describe);
+  }
+
+  // Password values never leave the page; the recorder stores a vault
+  // reference for them anyway.
+  function value(element) {
+    return /^password$/i.test(element.type || "") ? "" : element.value;
+  }
+
+  function install(window, emit) {
+    const document = window.document;
+    const send = (event) => {
+      try {
+        emit(event);
+      } catch (err) {
+        // A failed report must never break the page being recorded.
+      }
+    };
+    document.addEventListener(
+      "click",
+      (event) => send({ type: "click", element: describe(clickable(event.target)) }),
+      true
+    );
+    document.addEventListener(
+      "input",
+      (event) => {
+        if (!togglable(event.target) && "value" in event.target) {
+          send({ type: "input", element: describe(event.target), value: value(event.target) });
+        }
+      },
+      true
+    );
+    document.addEventListener(
+      "change",
+      (event) => {
+        if (!togglable(event.target) && "value" in event.target) {
+          send({ type: "change", element: describe(event.target), value: value(event.target) });
+        }
+      },
+      true
+    );
+    // Reported again once the page has rendered; the recorder merges
+    // reports of one URL that no action separates.
+    const report = () =>
+      send({ type: "navigation", url: window.location.href, controls: controls(document) });
+    const routed = () => {
+      report();
+      window.setTimeout(report, RENDER_DELAY_MS);
+    };
+    // Single-page apps route with pushState; replaceState only tweaks the
+    // current entry and is not a navigation the script has to wait for.
+    const pushState = window.history.pushState;
+    window.history.pushState = function () {
+      const result = pushState.apply(this, arguments);
+      routed();
+      return result;
+    };
+    window.addEventListener("popstate", routed);
+    if (document.readyState === "loading") {
+      document.addEventListener("DOMContentLoaded", report);
+    } else {
+      report();
+    }
+    window.addEventListener("load", report);
+  }
+
+  return { BINDING, describe, xpath, clickable, controls, value, install };
+});
@@ -0,0 +1,135 @@
+// Run with `node --test js/recorder.test.js`; `cargo test` runs it too when
+// node is on PATH (tests/recorder.rs).
+"use strict";
+
+const test = require("node:test");
+const assert = require("node:assert/strict");
+const recorder = require("./recorder.js");
+const { el, page } = require("./fake_dom.js");
+
+function loginPage() {
+  const user = el("input", { id: "user", name: "email", autocomplete: "username" });
+  const pass = el("input", { id: "pass", type: "password" });
+  const remember = el("input", { type: "checkbox", name: "remember" });
+  const submit = el("button", { type: "submit", class: "btn primary" }, el("span", {}, "Log in"));
+  const form = el(
+    "form",
+    {},
+    el("div", {}, el("label", { for: "user" }, "Email"), user),
+    el("div", {}, el("label", {}, "Password ", pass)),
+    el("div", {}, remember),
+    submit
+  );
+  const window = page("https://mail.example/login", el("body", {}, form, el("a", { hidden: "" }, "Help")));
+  return { window, user, pass, remember, submit };
+}
+
+function record(window) {
+  const events = [];
+  recorder.install(window, (event) => events.push(event));
+  return events;
+}
+
+test("xpaths number only siblings that share a tag", () => {
+  const { user, pass, submit } = loginPage();
+  assert.equal(recorder.xpath(user), "/html/body/form/div[1]/input");
+  assert.equal(recorder.xpath(pass), "/html/body/form/div[2]/label/input");
+  assert.equal(recorder.xpath(submit), "/html/body/form/button");
+});
+
+test("descriptors carry what the selectors are built from", () => {
+  const { user, pass, submit } = loginPage();
+  assert.deepEqual(recorder.describe(user), {
+    tag: "input",
+    id: "user",
+    name: "email",
+    classes: [],
+    aria_label: null,
+    text: null,
+    xpath: "/html/body/form/div[1]/input",
+    input_type: null,
+    autocomplete: "username",
+    placeholder: null,
+    label: "Email",
+    role: null,
+  });
+  assert.equal(recorder.describe(pass).label, "Password");
+  assert.equal(recorder.describe(pass).input_type, "password");
+  assert.deepEqual(recorder.describe(submit).classes, ["btn", "primary"]);
+  assert.equal(recorder.describe(submit).text, "Log in");
+  assert.equal(recorder.describe(el("p", {}, "x".repeat(81))).text, null);
+});
+
+test("clicks are recorded on the control, not the node under the pointer", () => {
+  const { window, submit } = loginPage();
+  const events = record(window);
+  window.fire("click", submit.children[0]);
+  assert.equal(events.at(-1).type, "click");
+  assert.equal(events.at(-1).element.tag, "button");
+  assert.equal(events.at(-1).element.xpath, "/html/body/form/button");
+});
+
+test("typed values are reported except for passwords and toggles", () => {
+  const { window, user, pass, remember } = loginPage();
+  const events = record(window);
+  user.value = "al";
+  window.fire("input", user);
+  pass.value = "hunter2";
+  window.fire("input", pass);
+  window.fire("change", pass);
+  window.fire("input", remember);
+  window.fire("change", remember);
+  const typed = events
+    .filter((event) => event.type !== "navigation")
+    .map((event) => [event.type, event.element.id, event.value]);
+  assert.deepEqual(typed, [
+    ["input", "user", "al"],
+    ["input", "pass", ""],
+    ["change", "pass", ""],
+  ]);
+});
+
+test("navigations report visible controls, again once pushState routes have rendered", () => {
+  const { window } = loginPage();
+  const events = record(window);
+  assert.deepEqual(
+    events.map((event) => [event.type, event.url, event.controls.map((control) => control.tag)]),
+    [["navigation", "https://mail.example/login", ["button"]]]
+  );
+
+  window.history.replaceState(null, "", "/login?step=2");
+  assert.equal(events.length, 1);
+  window.history.pushState(null, "", "/inbox");
+  assert.equal(events.at(-1).url, "https://mail.example/inbox");
+  assert.deepEqual(window.runTimers(), [500]);
+  assert.equal(events.length, 3);
+  window.dispatch("popstate");
+  window.dispatch("load");
+  assert.equal(events.length, 5);
+});
+
+test("a failing binding does not break the page", () => {
+  const { window, submit } = loginPage();
+  recorder.install(window, () => {
+    throw new Error("binding gone");
+  });
+  assert.doesNotThrow(() => window.fire("click", submit));
+});
+
+test("the injected copy installs once, through the binding", () => {
+  const fs = require("node:fs");
+  const vm = require("node:vm");
+  const { window, submit } = loginPage();
+  const reported = [];
+  window[recorder.BINDING] = (json) => reported.push(JSON.parse(json));
+  window.window = window;
+  const source = fs.readFileSync(require.resolve("./recorder.js"), "utf8");
+  const context = vm.createContext(window);
+  vm.runInContext(source, context);
+  vm.runInContext(source, context);
+  window.fire("click", submit);
+  assert.deepEqual(
+    reported.map((event) => event.type),
+    ["navigation", "click"]
+  );
+});
@@ -0,0 +1,258 @@
+//! Recording native Android apps from accessibility events.
+//!
+//! An on-device accessibility service reports clicks, text changes and
+//! window changes together with the node they concern. Each node becomes
+//! a `Selector::AnyOf` of the candidates the Android driver understands:
+//! `AccessibilityId` by content description and resource id, XPath over the
+//! `uiautomator` hierarchy (widget class names as tags), and the centre of
+//! the node's bounds as a last resort. Events then go through the same
+//! [`Recorder`] as web recordings, so keystroke merging, waits and
+//! credential fields work alike.
+//!
+//! Events arrive through an [`AccessibilityEventSource`]; [`JsonEventLog`]
+//! replays a log of them.
+
+use crate::recorder::{present, xpath_literal};
+use crate::{ElementDescriptor, RecordedSession, Recorder};
+use automation_engine::android::Bounds;
+use automation_engine::Selector;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::path::Path;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AccessibilityEvent {
+    /// `TYPE_VIEW_CLICKED`.
+    ViewClicked { node: AccessibilityNode },
+    /// `TYPE_VIEW_TEXT_CHANGED`; `text` is the field's whole text afterwards.
+    ViewTextChanged {
+        node: AccessibilityNode,
+        text: String,
+    },
+    /// `TYPE_WINDOW_STATE_CHANGED`: a new activity, dialog or popup.
+    WindowStateChanged {
+        package: String,
+        class_name: Option<String>,
+        /// Clickable nodes of the new window.
+        #[serde(default)]
+        nodes: Vec<AccessibilityNode>,
+    },
+}
+
+/// The parts of an `AccessibilityNodeInfo` the recorder uses.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AccessibilityNode {
+    /// Widget class, e.g. `android.widget.EditText`.
+    pub class_name: String,
+    /// Full resource id, e.g. `com.example:id/username`.
+    pub resource_id: Option<String>,
+    pub content_desc: Option<String>,
+    pub text: Option<String>,
+    pub hint: Option<String>,
+    pub password: bool,
+    pub editable: bool,
+    pub clickable: bool,
+    /// Screen bounds in `uiautomator` notation, `[l,t][r,b]`.
+    pub bounds: Option<String>,
+    /// Absolute path in the `uiautomator` hierarchy, e.g.
+    /// `/hierarchy/android.widget.FrameLayout/android.widget.EditText[2]`.
+    pub xpath: Option<String>,
+}
+
+impl AccessibilityNode {
+    /// Resource id without the `<package>:id/` prefix.
+    pub fn short_id(&self) -> Option<&str> {
+        let id = self.resource_id.as_deref()?;
+        let short = id.rsplit_once(":id/").map_or(id, |(_, short)| short);
+        (!short.is_empty()).then_some(short)
+    }
+
+    fn is_field(&self) -> bool {
+        self.editable || self.password || self.class_name.ends_with("EditText")
+    }
+
+    /// Candidate selectors, most stable first: content description,
+    /// resource id, visible text, hierarchy path (or the bounds as an XPath
+    /// attribute when the service sent no path), then the centre of the
+    /// bounds.
+    pub fn selectors(&self) -> Vec<Selector> {
+        let mut candidates = Vec::new();
+        if let Some(desc) = present(&self.content_desc) {
+            candidates.push(Selector::AccessibilityId(desc.to_string()));
+        }
+        if let Some(id) = self.short_id() {
+            candidates.push(Selector::AccessibilityId(id.to_string()));
+        }
+        // A field's text is what was typed into it, not a label.
+        if let (Some(text), false) = (present(&self.text), self.is_field()) {
+            if let Some(literal) = xpath_literal(text) {
+                candidates.push(Selector::XPath(format!(
+                    "//{}[@text={literal}]",
+                    self.class_tag()
+                )));
+            }
+        }
+        match (present(&self.xpath), present(&self.bounds)) {
+            (Some(xpath), _) => candidates.push(Selector::XPath(xpath.to_string())),
+            (None, Some(bounds)) => candidates.push(Selector::XPath(format!(
+                "//{}[@bounds='{bounds}']",
+                self.class_tag()
+            ))),
+            (None, None) => {}
+        }
+        if let Some(bounds) = self.bounds.as_deref().and_then(Bounds::parse) {
+            let (x, y) = bounds.center();
+            candidates.push(Selector::Coordinates { x, y });
+        }
+        candidates.dedup();
+        candidates
+    }
+
+    /// The node in web terms, for field classification and for telling
+    /// nodes apart while typing. Its selectors are the node's own.
+    pub fn descriptor(&self) -> ElementDescriptor {
+        let tag = if self.is_field() {
+            "input"
+        } else if self.class_name.ends_with("Button") {
+            "button"
+        } else {
+            self.class_tag()
+        };
+        ElementDescriptor {
+            tag: tag.to_string(),
+            id: self.short_id().map(str::to_string),
+            aria_label: self.content_desc.clone(),
+            text: (!self.is_field()).then(|| self.text.clone()).flatten(),
+            // Without a hierarchy path the bounds still identify the node.
+            xpath: self.xpath.clone().or_else(|| self.bounds.clone()),
+            input_type: self.password.then(|| "password".to_string()),
+            placeholder: self.hint.clone(),
+            role: self.clickable.then(|| "button".to_string()),
+            candidates: self.selectors(),
+            ..ElementDescriptor::default()
+        }
+    }
+
+    fn class_tag(&self) -> &str {
+        if self.class_name.is_empty() {
+            "*"
+        } else {
+            &self.class_name
+        }
+    }
+}
+
+/// Where accessibility events come from: the on-device service during a
+/// live recording, a [`JsonEventLog`] in tests.
+pub trait AccessibilityEventSource {
+    /// Next event, `None` once the recording has ended.
+    fn next_event(&mut self) -> anyhow::Result<Option<AccessibilityEvent>>;
+}
+
+/// Events replayed from JSON: either one array of events or one event per
+/// line (blank lines are skipped).
+#[derive(Debug, Clone, Default)]
+pub struct JsonEventLog {
+    events: VecDeque<AccessibilityEvent>,
+}
+
+impl JsonEventLog {
+    pub fn parse(text: &str) -> anyhow::Result<Self> {
+        if text.trim_start().starts_with('[') {
+            let events: Vec<AccessibilityEvent> = serde_json::from_str(text)
+                .map_err(|err| anyhow::anyhow!("invalid accessibility event log: {err}"))?;
+            return Ok(Self {
+                events: events.into(),
+            });
+        }
+        let events = text
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(index, line)| {
+                serde_json::from_str(line).map_err(|err| {
+                    anyhow::anyhow!("invalid accessibility event on line {}: {err}", index + 1)
+                })
+            })
+            .collect::<anyhow::Result<_>>()?;
+        Ok(Self { events })
+    }
+
+    pub fn open(path: &Path) -> anyhow::Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|err| anyhow::anyhow!("failed to read {}: {err}", path.display()))?;
+        Self::parse(&text).map_err(|err| anyhow::anyhow!("{}: {err}", path.display()))
+    }
+}
+
+impl AccessibilityEventSource for JsonEventLog {
+    fn next_event(&mut self) -> anyhow::Result<Option<AccessibilityEvent>> {
+        Ok(self.events.pop_front())
+    }
+}
+
+/// Folds accessibility events into a [`RecordedSession`]. Each window
+/// change counts as a navigation, with `<package>/<class>` as its URL.
+#[derive(Debug, Clone)]
+pub struct AccessibilityRecorder {
+    recorder: Recorder,
+}
+
+impl AccessibilityRecorder {
+    pub fn new(recorder: Recorder) -> Self {
+        Self { recorder }
+    }
+
+    pub fn push(&mut self, event: AccessibilityEvent) {
+        match event {
+            AccessibilityEvent::ViewClicked { node } => {
+                let (element, selector) = target(&node);
+                self.recorder.click(element, selector);
+            }
+            AccessibilityEvent::ViewTextChanged { node, text } => {
+                let (element, selector) = target(&node);
+                self.recorder.input(element, selector, text);
+            }
+            AccessibilityEvent::WindowStateChanged {
+                package,
+                class_name,
+                nodes,
+            } => {
+                let screen = match class_name {
+                    Some(class_name) => format!("{package}/{class_name}"),
+                    None => package,
+                };
+                let controls = nodes.iter().map(AccessibilityNode::descriptor).collect();
+                self.recorder.navigate(screen, controls);
+            }
+        }
+    }
+
+    /// Record every event `source` yields until it ends.
+    pub fn record(&mut self, source: &mut dyn AccessibilityEventSource) -> anyhow::Result<()> {
+        while let Some(event) = source.next_event()? {
+            self.push(event);
+        }
+        Ok(())
+    }
+
+    /// Steps recorded so far.
+    pub fn session(&self) -> &RecordedSession {
+        self.recorder.session()
+    }
+
+    pub fn finish(self) -> RecordedSession {
+        self.recorder.finish()
+    }
+}
+
+fn target(node: &AccessibilityNode) -> (ElementDescriptor, Option<Selector>) {
+    let element = node.descriptor();
+    let selector = element.selector();
+    if selector.is_none() {
+        tracing::warn!(?node, "accessibility node has no usable selector, skipping");
+    }
+    (element, selector)
+}
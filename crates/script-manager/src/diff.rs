@@ -0,0 +1,490 @@
+//! Structural diff between two versions of a script.
+//!
+//! Step lists are compared level by level: the longest common run of
+//! identical steps is left alone, identical steps found at another position
+//! are reported as moved, and the remaining steps of the same kind between
+//! two unchanged steps are paired up as modified. A modified `Conditional`,
+//! `Loop` or `Retry` is diffed recursively, so a change deep inside a body
+//! shows up at its own path. Validations are matched by description and
+//! error handlers by name.
+
+use automation_engine::{
+    ErrorHandler, LoginScript, PathSegment, Selector, Step, StepPath, Validation,
+};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ScriptDiff {
+    pub from_version: String,
+    pub to_version: String,
+    pub steps: Vec<StepChange>,
+    pub validations: Vec<ValidationChange>,
+    pub handlers: Vec<HandlerChange>,
+    /// Changes to `target`, `token` and `retry`.
+    pub fields: Vec<FieldChange>,
+}
+
+impl ScriptDiff {
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+            && self.validations.is_empty()
+            && self.handlers.is_empty()
+            && self.fields.is_empty()
+    }
+}
+
+/// Paths of removed steps point into the old script, all others into the
+/// new one; `Modified` and `Moved` carry both.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "change", rename_all = "snake_case")]
+pub enum StepChange {
+    Added {
+        path: StepPath,
+        step: Step,
+    },
+    Removed {
+        path: StepPath,
+        step: Step,
+    },
+    /// Same kind of step with different arguments. For steps with a body
+    /// only the step's own arguments count; body changes are listed
+    /// separately.
+    Modified {
+        from: StepPath,
+        to: StepPath,
+        before: Step,
+        after: Step,
+        selectors: Vec<SelectorChange>,
+    },
+    /// An unchanged step at a different position.
+    Moved {
+        from: StepPath,
+        to: StepPath,
+        step: Step,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SelectorChange {
+    /// Which of the step's selectors, e.g. `selector` or `challenge`.
+    pub field: String,
+    pub before: Selector,
+    pub after: Selector,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "change", rename_all = "snake_case")]
+pub enum ValidationChange {
+    Added {
+        index: usize,
+        validation: Validation,
+    },
+    Removed {
+        index: usize,
+        validation: Validation,
+    },
+    Modified {
+        index: usize,
+        before: Validation,
+        after: Validation,
+    },
+}
+
+/// Handlers that were added or removed as a whole; edits inside a handler
+/// kept under the same name are step changes under `error_handlers[n]`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "change", rename_all = "snake_case")]
+pub enum HandlerChange {
+    Added { index: usize, handler: ErrorHandler },
+    Removed { index: usize, handler: ErrorHandler },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FieldChange {
+    pub field: String,
+    pub before: serde_json::Value,
+    pub after: serde_json::Value,
+}
+
+/// Everything that changed from `before` to `after`.
+pub fn diff_scripts(before: &LoginScript, after: &LoginScript) -> ScriptDiff {
+    let mut diff = ScriptDiff {
+        from_version: before.meta.version.clone(),
+        to_version: after.meta.version.clone(),
+        ..ScriptDiff::default()
+    };
+    diff_steps(
+        &before.steps,
+        &after.steps,
+        (&StepPath::root(), PathSegment::Steps),
+        (&StepPath::root(), PathSegment::Steps),
+        &mut diff.steps,
+    );
+    diff_validations(&before.validations, &after.validations, &mut diff);
+    diff_handlers(&before.error_handlers, &after.error_handlers, &mut diff);
+    for (field, before, after) in [
+        ("target", to_value(&before.target), to_value(&after.target)),
+        ("token", to_value(&before.token), to_value(&after.token)),
+        ("retry", to_value(&before.retry), to_value(&after.retry)),
+    ] {
+        if before != after {
+            diff.fields.push(FieldChange {
+                field: field.into(),
+                before,
+                after,
+            });
+        }
+    }
+    diff
+}
+
+/// One line per change, in the spirit of a unified diff: `+` added, `-`
+/// removed, `~` modified, `>` moved.
+impl fmt::Display for ScriptDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "--- {}", self.from_version)?;
+        writeln!(f, "+++ {}", self.to_version)?;
+        for change in &self.steps {
+            match change {
+                StepChange::Added { path, step } => writeln!(f, "+ {path}: {}", compact(step))?,
+                StepChange::Removed { path, step } => writeln!(f, "- {path}: {}", compact(step))?,
+                StepChange::Modified {
+                    from,
+                    to,
+                    before,
+                    after,
+                    selectors,
+                } => {
+                    if selectors.is_empty() {
+                        writeln!(
+                            f,
+                            "~ {from} -> {to}: {} -> {}",
+                            compact(before),
+                            compact(after)
+                        )?;
+                    }
+                    for selector in selectors {
+                        writeln!(
+                            f,
+                            "~ {from} -> {to}: {} {} -> {}",
+                            selector.field,
+                            compact(&selector.before),
+                            compact(&selector.after)
+                        )?;
+                    }
+                }
+                StepChange::Moved { from, to, step } => {
+                    writeln!(f, "> {from} -> {to}: {}", compact(step))?
+                }
+            }
+        }
+        for change in &self.validations {
+            match change {
+                ValidationChange::Added { index, validation } => {
+                    writeln!(f, "+ validations[{index}]: {}", validation.description)?
+                }
+                ValidationChange::Removed { index, validation } => {
+                    writeln!(f, "- validations[{index}]: {}", validation.description)?
+                }
+                ValidationChange::Modified { index, after, .. } => writeln!(
+                    f,
+                    "~ validations[{index}]: {} {}",
+                    after.description,
+                    compact(&after.condition)
+                )?,
+            }
+        }
+        for change in &self.handlers {
+            match change {
+                HandlerChange::Added { index, handler } => {
+                    writeln!(f, "+ error_handlers[{index}]: {}", handler.name)?
+                }
+                HandlerChange::Removed { index, handler } => {
+                    writeln!(f, "- error_handlers[{index}]: {}", handler.name)?
+                }
+            }
+        }
+        for change in &self.fields {
+            writeln!(
+                f,
+                "~ {}: {} -> {}",
+                change.field, change.before, change.after
+            )?;
+        }
+        Ok(())
+    }
+}
+
+fn compact<T: Serialize>(value: &T) -> String {
+    to_value(value).to_string()
+}
+
+fn to_value<T: Serialize>(value: &T) -> serde_json::Value {
+    serde_json::to_value(value).unwrap_or_default()
+}
+
+/// Index pairs `(i, j)` of a longest common subsequence of `a` and `b`.
+pub(crate) fn lcs<T: PartialEq>(a: &[T], b: &[T]) -> Vec<(usize, usize)> {
+    let mut lengths = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            lengths[i][j] = if a[i] == b[j] {
+                lengths[i + 1][j + 1] + 1
+            } else {
+                lengths[i + 1][j].max(lengths[i][j + 1])
+            };
+        }
+    }
+    let (mut i, mut j) = (0, 0);
+    let mut pairs = Vec::new();
+    while i < a.len() && j < b.len() {
+        if a[i] == b[j] {
+            pairs.push((i, j));
+            i += 1;
+            j += 1;
+        } else if lengths[i + 1][j] >= lengths[i][j + 1] {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+    pairs
+}
+
+/// `old_at`/`new_at` give the parent path and the segment kind of each
+/// side's list.
+fn diff_steps(
+    before: &[Step],
+    after: &[Step],
+    old_at: (&StepPath, impl Fn(usize) -> PathSegment),
+    new_at: (&StepPath, impl Fn(usize) -> PathSegment),
+    changes: &mut Vec<StepChange>,
+) {
+    let old_path = |i: usize| old_at.0.child(old_at.1(i));
+    let new_path = |j: usize| new_at.0.child(new_at.1(j));
+
+    let anchors = lcs(before, after);
+    let mut old_left: Vec<bool> = vec![true; before.len()];
+    let mut new_left: Vec<bool> = vec![true; after.len()];
+    for &(i, j) in &anchors {
+        old_left[i] = false;
+        new_left[j] = false;
+    }
+
+    // Identical steps that changed position.
+    for (i, step) in before.iter().enumerate() {
+        if !old_left[i] {
+            continue;
+        }
+        if let Some(j) = (0..after.len()).find(|&j| new_left[j] && after[j] == *step) {
+            old_left[i] = false;
+            new_left[j] = false;
+            changes.push(StepChange::Moved {
+                from: old_path(i),
+                to: new_path(j),
+                step: step.clone(),
+            });
+        }
+    }
+
+    // Pair leftovers of the same kind within each gap between anchors.
+    let mut bounds = anchors.clone();
+    bounds.push((before.len(), after.len()));
+    let (mut old_start, mut new_start) = (0, 0);
+    for (old_end, new_end) in bounds {
+        for i in old_start..old_end {
+            if !old_left[i] {
+                continue;
+            }
+            let Some(j) =
+                (new_start..new_end).find(|&j| new_left[j] && same_kind(&before[i], &after[j]))
+            else {
+                continue;
+            };
+            old_left[i] = false;
+            new_left[j] = false;
+            diff_pair(&before[i], &after[j], &old_path(i), &new_path(j), changes);
+        }
+        old_start = old_end + 1;
+        new_start = new_end + 1;
+    }
+
+    for (i, step) in before.iter().enumerate().filter(|(i, _)| old_left[*i]) {
+        changes.push(StepChange::Removed {
+            path: old_path(i),
+            step: step.clone(),
+        });
+    }
+    for (j, step) in after.iter().enumerate().filter(|(j, _)| new_left[*j]) {
+        changes.push(StepChange::Added {
+            path: new_path(j),
+            step: step.clone(),
+        });
+    }
+}
+
+/// Two steps of the same kind that are not identical.
+fn diff_pair(
+    before: &Step,
+    after: &Step,
+    from: &StepPath,
+    to: &StepPath,
+    changes: &mut Vec<StepChange>,
+) {
+    if !same_arguments(before, after) {
+        changes.push(StepChange::Modified {
+            from: from.clone(),
+            to: to.clone(),
+            before: before.clone(),
+            after: after.clone(),
+            selectors: selector_changes(before, after),
+        });
+    }
+    match (before, after) {
+        (
+            Step::Conditional {
+                on_true: old_true,
+                on_false: old_false,
+                ..
+            },
+            Step::Conditional {
+                on_true: new_true,
+                on_false: new_false,
+                ..
+            },
+        ) => {
+            diff_steps(
+                old_true,
+                new_true,
+                (from, PathSegment::OnTrue),
+                (to, PathSegment::OnTrue),
+                changes,
+            );
+            diff_steps(
+                old_false,
+                new_false,
+                (from, PathSegment::OnFalse),
+                (to, PathSegment::OnFalse),
+                changes,
+            );
+        }
+        (
+            Step::Loop { body: old, .. } | Step::Retry { body: old, .. },
+            Step::Loop { body: new, .. } | Step::Retry { body: new, .. },
+        ) => diff_steps(
+            old,
+            new,
+            (from, PathSegment::Body),
+            (to, PathSegment::Body),
+            changes,
+        ),
+        _ => {}
+    }
+}
+
+pub(crate) fn same_kind(a: &Step, b: &Step) -> bool {
+    std::mem::discriminant(a) == std::mem::discriminant(b)
+}
+
+/// Equal apart from nested step lists.
+pub(crate) fn same_arguments(a: &Step, b: &Step) -> bool {
+    match (a, b) {
+        (Step::Conditional { condition: a, .. }, Step::Conditional { condition: b, .. }) => a == b,
+        (Step::Loop { times: a, .. }, Step::Loop { times: b, .. }) => a == b,
+        (Step::Retry { policy: a, .. }, Step::Retry { policy: b, .. }) => a == b,
+        _ => a == b,
+    }
+}
+
+fn selector_changes(before: &Step, after: &Step) -> Vec<SelectorChange> {
+    let after_selectors = step_selectors(after);
+    step_selectors(before)
+        .into_iter()
+        .filter_map(|(field, old)| {
+            let (_, new) = after_selectors.iter().find(|(name, _)| *name == field)?;
+            (old != *new).then(|| SelectorChange {
+                field: field.into(),
+                before: old.clone(),
+                after: (*new).clone(),
+            })
+        })
+        .collect()
+}
+
+fn step_selectors(step: &Step) -> Vec<(&'static str, &Selector)> {
+    match step {
+        Step::Click(selector) | Step::WaitFor(selector) | Step::Input { selector, .. } => {
+            vec![("selector", selector)]
+        }
+        Step::Swipe { from, to, .. } => vec![("from", from), ("to", to)],
+        Step::Captcha {
+            challenge,
+            response,
+            ..
+        } => vec![("challenge", challenge), ("response", response)],
+        _ => Vec::new(),
+    }
+}
+
+fn diff_validations(before: &[Validation], after: &[Validation], diff: &mut ScriptDiff) {
+    for (index, old) in before.iter().enumerate() {
+        match after
+            .iter()
+            .position(|new| new.description == old.description)
+        {
+            Some(new_index) if after[new_index] != *old => {
+                diff.validations.push(ValidationChange::Modified {
+                    index: new_index,
+                    before: old.clone(),
+                    after: after[new_index].clone(),
+                })
+            }
+            Some(_) => {}
+            None => diff.validations.push(ValidationChange::Removed {
+                index,
+                validation: old.clone(),
+            }),
+        }
+    }
+    for (index, new) in after.iter().enumerate() {
+        if !before.iter().any(|old| old.description == new.description) {
+            diff.validations.push(ValidationChange::Added {
+                index,
+                validation: new.clone(),
+            });
+        }
+    }
+}
+
+fn diff_handlers(before: &[ErrorHandler], after: &[ErrorHandler], diff: &mut ScriptDiff) {
+    for (index, old) in before.iter().enumerate() {
+        match after.iter().position(|new| new.name == old.name) {
+            Some(new_index) => diff_steps(
+                &old.on_error,
+                &after[new_index].on_error,
+                (&StepPath::root(), |i| PathSegment::OnError {
+                    handler: index,
+                    index: i,
+                }),
+                (&StepPath::root(), |i| PathSegment::OnError {
+                    handler: new_index,
+                    index: i,
+                }),
+                &mut diff.steps,
+            ),
+            None => diff.handlers.push(HandlerChange::Removed {
+                index,
+                handler: old.clone(),
+            }),
+        }
+    }
+    for (index, new) in after.iter().enumerate() {
+        if !before.iter().any(|old| old.name == new.name) {
+            diff.handlers.push(HandlerChange::Added {
+                index,
+                handler: new.clone(),
+            });
+        }
+    }
+}
@@ -0,0 +1,241 @@
+//! Recognizes the parts of a login form while recording: the username
+//! field, the password field and the submit button.
+//!
+//! Fields are classified from their input type and `autocomplete` first,
+//! then from keywords (English and Chinese) in their name, id, label,
+//! placeholder and accessibility label. Plain text fields that carry no
+//! hint are classified by layout order in [`crate::Recorder`]: the text
+//! field filled in right before the password field is the username, and
+//! the first button clicked after it submits the form.
+
+use crate::ElementDescriptor;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FieldRole {
+    Username,
+    Password,
+    Submit,
+}
+
+impl FieldRole {
+    /// Vault key of the credential part typed into this field, e.g.
+    /// `mail.username` for account `mail`.
+    pub fn vault_key(self, account: &str) -> Option<String> {
+        match self {
+            FieldRole::Username => Some(format!("{account}.username")),
+            FieldRole::Password => Some(format!("{account}.secret")),
+            FieldRole::Submit => None,
+        }
+    }
+}
+
+/// A classified step of a [`crate::RecordedSession`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LoginField {
+    /// Index into `RecordedSession::steps`.
+    pub step: usize,
+    pub role: FieldRole,
+}
+
+const PASSWORD_WORDS: &[&str] = &["password", "passwd", "pwd", "密码", "密碼", "口令"];
+
+const USERNAME_WORDS: &[&str] = &[
+    "user",
+    "login",
+    "account",
+    "mail",
+    "phone",
+    "mobile",
+    "用户",
+    "用戶",
+    "账号",
+    "帳號",
+    "账户",
+    "帳戶",
+    "登录名",
+    "邮箱",
+    "郵箱",
+    "手机",
+    "手機",
+];
+
+/// One-time codes, captchas and search boxes look like text fields but
+/// never hold credentials. Matched as whole words (see [`mentions_any`]),
+/// so a `postcode` or `hotpot` field is not mistaken for one.
+const NOT_CREDENTIAL_WORDS: &[&str] = &[
+    "captcha",
+    "otp",
+    "code",
+    "verification",
+    "verify",
+    "search",
+    "验证码",
+    "驗證碼",
+    "校验码",
+    "搜索",
+];
+
+const SUBMIT_WORDS: &[&str] = &[
+    "log in",
+    "login",
+    "sign in",
+    "signin",
+    "submit",
+    "continue",
+    "next",
+    "登录",
+    "登入",
+    "登陆",
+    "提交",
+    "确定",
+    "確定",
+    "下一步",
+    "继续",
+];
+
+const LOGOUT_WORDS: &[&str] = &[
+    "logout", "log out", "log off", "signout", "sign out", "退出", "注销", "註銷", "登出",
+];
+
+/// Role of an element judging by the element alone; `None` when it gives
+/// no hint.
+pub fn classify(element: &ElementDescriptor) -> Option<FieldRole> {
+    let input_type = lowercase(&element.input_type);
+    let autocomplete = lowercase(&element.autocomplete);
+    let hints = hints(element);
+    match element.tag.as_str() {
+        "input" | "textarea" => {
+            if input_type == "password"
+                || autocomplete.contains("current-password")
+                || autocomplete.contains("new-password")
+            {
+                return Some(FieldRole::Password);
+            }
+            if matches!(input_type.as_str(), "submit" | "image") {
+                return Some(FieldRole::Submit);
+            }
+            if !is_text_field(element) || mentions_any(element, NOT_CREDENTIAL_WORDS) {
+                return None;
+            }
+            if autocomplete.contains("username") || autocomplete.contains("email") {
+                return Some(FieldRole::Username);
+            }
+            if contains_any(&hints, PASSWORD_WORDS) {
+                Some(FieldRole::Password)
+            } else if input_type == "email" || contains_any(&hints, USERNAME_WORDS) {
+                Some(FieldRole::Username)
+            } else {
+                None
+            }
+        }
+        _ if is_button(element) => {
+            let text = format!("{hints} {}", lowercase(&element.text));
+            // "退出登录" (log out) contains "登录" (log in).
+            (contains_any(&text, SUBMIT_WORDS) && !contains_any(&text, LOGOUT_WORDS))
+                .then_some(FieldRole::Submit)
+        }
+        _ => None,
+    }
+}
+
+/// Control that logs the user out; seeing one means the login worked.
+pub fn is_logout(element: &ElementDescriptor) -> bool {
+    let text = format!("{} {}", hints(element), lowercase(&element.text));
+    contains_any(&text, LOGOUT_WORDS)
+}
+
+/// Single-line field a username could be typed into.
+pub fn is_text_field(element: &ElementDescriptor) -> bool {
+    match element.tag.as_str() {
+        "input" => matches!(
+            lowercase(&element.input_type).as_str(),
+            "" | "text" | "email" | "tel" | "number"
+        ),
+        _ => false,
+    }
+}
+
+/// Element that triggers an action when clicked.
+pub fn is_button(element: &ElementDescriptor) -> bool {
+    match element.tag.as_str() {
+        "button" | "a" => true,
+        "input" => matches!(
+            lowercase(&element.input_type).as_str(),
+            "submit" | "button" | "image"
+        ),
+        _ => element.role.as_deref() == Some("button"),
+    }
+}
+
+fn hints(element: &ElementDescriptor) -> String {
+    [
+        &element.name,
+        &element.id,
+        &element.label,
+        &element.placeholder,
+        &element.aria_label,
+    ]
+    .into_iter()
+    .flatten()
+    .map(|hint| hint.to_lowercase())
+    .collect::<Vec<_>>()
+    .join(" ")
+}
+
+/// Whether a hint of `element` has one of `words` as a whole word: hints
+/// are split at non-alphanumerics and camelCase humps (`smsCode` gives
+/// `sms` and `code`). Chinese has no word breaks, so non-ASCII words match
+/// anywhere in a word.
+fn mentions_any(element: &ElementDescriptor, words: &[&str]) -> bool {
+    let hint_words = hint_words(element);
+    words.iter().any(|word| {
+        hint_words.iter().any(|hint| {
+            if word.is_ascii() {
+                hint == word
+            } else {
+                hint.contains(word)
+            }
+        })
+    })
+}
+
+fn hint_words(element: &ElementDescriptor) -> Vec<String> {
+    let mut words = Vec::new();
+    for hint in [
+        &element.name,
+        &element.id,
+        &element.label,
+        &element.placeholder,
+        &element.aria_label,
+    ]
+    .into_iter()
+    .flatten()
+    {
+        let mut word = String::new();
+        let mut after_lowercase = false;
+        for c in hint.chars() {
+            let hump = after_lowercase && c.is_uppercase();
+            if (!c.is_alphanumeric() || hump) && !word.is_empty() {
+                words.push(std::mem::take(&mut word));
+            }
+            if c.is_alphanumeric() {
+                word.extend(c.to_lowercase());
+            }
+            after_lowercase = c.is_lowercase();
+        }
+        if !word.is_empty() {
+            words.push(word);
+        }
+    }
+    words
+}
+
+fn lowercase(value: &Option<String>) -> String {
+    value.as_deref().unwrap_or_default().trim().to_lowercase()
+}
+
+fn contains_any(haystack: &str, words: &[&str]) -> bool {
+    words.iter().any(|word| haystack.contains(word))
+}
@@ -0,0 +1,379 @@
+//! YAML/JSON file format for login scripts.
+//!
+//! A script file is the serialized `LoginScript` with a top-level
+//! `format_version` next to `meta`, `target` and `steps`. Errors point at the
+//! offending field path (e.g. `steps[2].Input.value`) and, where the parser
+//! knows it, the line and column; for files in an older format the position
+//! is looked up in the original text. The JSON Schema for editors lives in
+//! `docs/schema/login-script.schema.json` and is also exposed as
+//! [`JSON_SCHEMA`]. Files in an older format are upgraded by the chain in
+//! [`crate::migrate`] on the way in.
+
+use crate::migrate::{self, MigrationRecord};
+use automation_engine::LoginScript;
+use serde::de::{self, DeserializeSeed, IgnoredAny, MapAccess, SeqAccess, Visitor};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::fmt;
+use std::path::Path;
+
+/// Version written by this build and the newest one it can read. Bump it
+/// together with a new entry in [`crate::migrate::MIGRATIONS`].
+pub const FORMAT_VERSION: u32 = 1;
+
+/// JSON Schema (draft 2020-12) describing the current file format.
+pub const JSON_SCHEMA: &str = include_str!("../../../docs/schema/login-script.schema.json");
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScriptFormat {
+    Json,
+    Yaml,
+}
+
+impl ScriptFormat {
+    /// Pick the format from a file extension (`.json`, `.yaml`, `.yml`).
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()?.to_ascii_lowercase().as_str() {
+            "json" => Some(Self::Json),
+            "yaml" | "yml" => Some(Self::Yaml),
+            _ => None,
+        }
+    }
+}
+
+/// A script file that could not be read, with the location of the problem.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScriptFormatError {
+    /// Field path such as `steps[1].Click`; empty for the document root.
+    pub path: String,
+    /// 1-based, when the parser reports positions.
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+    pub message: String,
+}
+
+impl ScriptFormatError {
+    pub(crate) fn at(path: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            path: path.into(),
+            line: None,
+            column: None,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for ScriptFormatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !self.path.is_empty() {
+            write!(f, "{}", self.path)?;
+        }
+        match (self.line, self.column) {
+            (Some(line), Some(column)) => write!(f, " (line {line}, column {column})")?,
+            (Some(line), None) => write!(f, " (line {line})")?,
+            _ => {}
+        }
+        if !self.path.is_empty() || self.line.is_some() {
+            f.write_str(": ")?;
+        }
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for ScriptFormatError {}
+
+#[derive(Deserialize)]
+struct Header {
+    format_version: Option<u32>,
+}
+
+/// A script read from a file or the store, with the migrations that
+/// upgraded it (empty when it was already in the current format).
+#[derive(Debug, Clone)]
+pub struct LoadedScript {
+    pub script: LoginScript,
+    pub migrations: Vec<MigrationRecord>,
+}
+
+/// Parse and validate a script file, upgrading older format versions.
+pub fn parse_script(text: &str, format: ScriptFormat) -> Result<LoginScript, ScriptFormatError> {
+    read_script(text, format).map(|loaded| loaded.script)
+}
+
+/// Like [`parse_script`], but also reports which migrations ran.
+pub fn read_script(text: &str, format: ScriptFormat) -> Result<LoadedScript, ScriptFormatError> {
+    let header: Header = deserialize(text, format)?;
+    if header.format_version == Some(FORMAT_VERSION) {
+        // Current files deserialize straight from the text so errors keep
+        // their line and column.
+        let script: LoginScript = deserialize(text, format)?;
+        validate(&script)?;
+        return Ok(LoadedScript {
+            script,
+            migrations: Vec::new(),
+        });
+    }
+    let document: serde_json::Value = deserialize(text, format)?;
+    let from = migrate::document_version(&document).unwrap_or(FORMAT_VERSION);
+    read_document(document).map_err(|err| locate(err, text, format, from))
+}
+
+/// Upgrade and decode a document in its JSON form.
+pub fn read_document(mut document: serde_json::Value) -> Result<LoadedScript, ScriptFormatError> {
+    let migrations = migrate::migrate(&mut document)?;
+    let script: LoginScript = serde_path_to_error::deserialize(document).map_err(|err| {
+        ScriptFormatError::at(
+            clean_path(err.path().to_string()),
+            err.into_inner().to_string(),
+        )
+    })?;
+    validate(&script)?;
+    Ok(LoadedScript { script, migrations })
+}
+
+/// A script as written to files and the store: the current
+/// `format_version` first, then the `LoginScript` fields.
+#[derive(Debug, Serialize)]
+pub struct Document<'a> {
+    pub format_version: u32,
+    #[serde(flatten)]
+    pub script: &'a LoginScript,
+}
+
+impl<'a> Document<'a> {
+    pub fn new(script: &'a LoginScript) -> Self {
+        Self {
+            format_version: FORMAT_VERSION,
+            script,
+        }
+    }
+}
+
+/// Serialize `script` with the current `format_version` first.
+pub fn write_script(script: &LoginScript, format: ScriptFormat) -> anyhow::Result<String> {
+    let document = Document::new(script);
+    Ok(match format {
+        ScriptFormat::Json => {
+            let mut text = serde_json::to_string_pretty(&document)?;
+            text.push('\n');
+            text
+        }
+        ScriptFormat::Yaml => to_yaml(&document)?,
+    })
+}
+
+/// YAML with list items indented below their key. In the compact default,
+/// an empty list inside a struct variant (`on_false: []`) lands on its own
+/// line at the key's indentation, which the parser then rejects.
+pub(crate) fn to_yaml<T: Serialize>(value: &T) -> anyhow::Result<String> {
+    let options = serde_saphyr::ser_options! { compact_list_indent: false };
+    Ok(serde_saphyr::to_string_with_options(value, options)?)
+}
+
+pub(crate) fn deserialize<T: serde::de::DeserializeOwned>(
+    text: &str,
+    format: ScriptFormat,
+) -> Result<T, ScriptFormatError> {
+    match format {
+        ScriptFormat::Json => {
+            let mut deserializer = serde_json::Deserializer::from_str(text);
+            serde_path_to_error::deserialize(&mut deserializer).map_err(|err| {
+                let path = err.path().to_string();
+                let inner = err.into_inner();
+                ScriptFormatError {
+                    path: clean_path(path),
+                    line: Some(inner.line()).filter(|line| *line > 0),
+                    column: Some(inner.column()).filter(|_| inner.line() > 0),
+                    message: strip_position(&inner.to_string()),
+                }
+            })
+        }
+        ScriptFormat::Yaml => {
+            let mut path = String::new();
+            serde_saphyr::with_deserializer_from_str(text, |deserializer| {
+                serde_path_to_error::deserialize(deserializer).map_err(|err| {
+                    path = err.path().to_string();
+                    err.into_inner()
+                })
+            })
+            .map_err(|err| {
+                let location = err.location();
+                ScriptFormatError {
+                    path: clean_path(path),
+                    line: location.map(|location| location.line() as usize),
+                    column: location.map(|location| location.column() as usize),
+                    message: strip_position(&err.without_snippet().to_string()),
+                }
+            })
+        }
+    }
+}
+
+/// Checks serde cannot express.
+fn validate(script: &LoginScript) -> Result<(), ScriptFormatError> {
+    for (path, value) in [
+        ("meta.id", &script.meta.id),
+        ("meta.version", &script.meta.version),
+    ] {
+        if value.trim().is_empty() {
+            return Err(ScriptFormatError::at(path, "must not be empty"));
+        }
+    }
+    Ok(())
+}
+
+/// `serde_path_to_error` renders the root as `.`.
+fn clean_path(path: String) -> String {
+    if path == "." {
+        String::new()
+    } else {
+        path
+    }
+}
+
+/// Parsers append "at line X column Y"; the position has its own fields.
+fn strip_position(message: &str) -> String {
+    match message.rfind(" at line ") {
+        Some(index) => message[..index].to_string(),
+        None => message.to_string(),
+    }
+}
+
+/// Fill in the line and column of `err`, reported against the migrated form
+/// of a version `from` file, from the file's original `text`. Left as is
+/// when the field cannot be found there.
+fn locate(
+    mut err: ScriptFormatError,
+    text: &str,
+    format: ScriptFormat,
+    from: u32,
+) -> ScriptFormatError {
+    if err.line.is_some() {
+        return err;
+    }
+    let path = migrate::source_path(from, &err.path);
+    let Some(segments) = PathSegment::parse(&path) else {
+        return err;
+    };
+    let probe = Probe { path: &segments };
+    let position = match format {
+        ScriptFormat::Json => {
+            match probe.deserialize(&mut serde_json::Deserializer::from_str(text)) {
+                Err(found) if found.to_string().starts_with(FOUND) => {
+                    Some((found.line(), found.column()))
+                }
+                _ => None,
+            }
+        }
+        ScriptFormat::Yaml => {
+            match serde_saphyr::with_deserializer_from_str(text, |d| probe.deserialize(d)) {
+                Err(found) if found.without_snippet().to_string().contains(FOUND) => found
+                    .location()
+                    .map(|location| (location.line() as usize, location.column() as usize)),
+                _ => None,
+            }
+        }
+    };
+    if let Some((line, column)) = position.filter(|(line, _)| *line > 0) {
+        err.line = Some(line);
+        err.column = Some(column);
+    }
+    err
+}
+
+/// Error a [`Probe`] raises once it reaches its field; the parser attaches
+/// the position it is at.
+const FOUND: &str = "probe reached the field";
+
+/// One step of a `serde_path_to_error` path: a map key (struct field or
+/// enum variant) or a sequence index.
+#[derive(Debug, PartialEq)]
+enum PathSegment<'a> {
+    Key(&'a str),
+    Index(usize),
+}
+
+impl<'a> PathSegment<'a> {
+    /// `None` for paths with unknown (`?`) segments.
+    fn parse(path: &'a str) -> Option<Vec<Self>> {
+        let mut segments = Vec::new();
+        for part in path.split('.').filter(|part| !part.is_empty()) {
+            let (key, mut indices) = part.split_at(part.find('[').unwrap_or(part.len()));
+            if key == "?" {
+                return None;
+            }
+            if !key.is_empty() {
+                segments.push(Self::Key(key));
+            }
+            while let Some(rest) = indices.strip_prefix('[') {
+                let (index, rest) = rest.split_once(']')?;
+                segments.push(Self::Index(index.parse().ok()?));
+                indices = rest;
+            }
+        }
+        Some(segments)
+    }
+}
+
+/// Walks a document along `path` without building it and fails with
+/// [`FOUND`] when it gets there.
+struct Probe<'p, 'a> {
+    path: &'p [PathSegment<'a>],
+}
+
+impl<'de> DeserializeSeed<'de> for Probe<'_, '_> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        if self.path.is_empty() {
+            return Err(de::Error::custom(FOUND));
+        }
+        deserializer.deserialize_any(self)
+    }
+}
+
+impl<'de> Visitor<'de> for Probe<'_, '_> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("any value")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<(), A::Error> {
+        let wanted = match self.path.first() {
+            Some(PathSegment::Key(key)) => Some(*key),
+            _ => None,
+        };
+        while let Some(key) = map.next_key::<String>()? {
+            if Some(key.as_str()) == wanted {
+                map.next_value_seed(Probe {
+                    path: &self.path[1..],
+                })?;
+            } else {
+                map.next_value::<IgnoredAny>()?;
+            }
+        }
+        Ok(())
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        let wanted = match self.path.first() {
+            Some(PathSegment::Index(index)) => Some(*index),
+            _ => None,
+        };
+        let mut index = 0;
+        loop {
+            let more = if Some(index) == wanted {
+                seq.next_element_seed(Probe {
+                    path: &self.path[1..],
+                })?
+                .is_some()
+            } else {
+                seq.next_element::<IgnoredAny>()?.is_some()
+            };
+            if !more {
+                return Ok(());
+            }
+            index += 1;
+        }
+    }
+}
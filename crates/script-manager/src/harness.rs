@@ -0,0 +1,574 @@
+//! Test harness for login scripts: sandboxed runs against a fake app,
+//! assertions on what the run did, and a replay log of every run.
+//!
+//! A [`TestCase`] describes the app as a set of pages, each listing the
+//! elements it shows, and moves between them when an element with a `goto`
+//! is clicked. The script runs through the engine's shared step runner on
+//! that fake session, so branching, error handlers, retries and validations
+//! behave exactly as they do on a real driver. Nothing changes by itself on
+//! a fake page, so waits fail at once instead of running into their
+//! timeout.
+//!
+//! Suites are stored next to each script version (see
+//! [`crate::ScriptManager::save_tests`]) and written as JSON or YAML files
+//! like scripts.
+
+use crate::format::{self, deserialize, ScriptFormat, ScriptFormatError};
+use async_trait::async_trait;
+use automation_engine::{
+    run_script, DriverSession, EventSink, ExecutionContext, ExecutionEventKind, LoginOutcome,
+    LoginScript, NoopCaptcha, SecretResolver, Selector, StepError, StepPath,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::broadcast;
+
+/// Events one case may emit before the oldest ones are lost.
+const EVENT_CAPACITY: usize = 4096;
+
+/// Test cases kept with one version of a script.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TestSuite {
+    pub cases: Vec<TestCase>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TestCase {
+    pub name: String,
+    /// Pages of the fake app; the run starts on the first one.
+    pub pages: Vec<FakePage>,
+    /// Values `ValueRef::FromVault` keys resolve to.
+    #[serde(default)]
+    pub secrets: BTreeMap<String, String>,
+    #[serde(default)]
+    pub assertions: Vec<Assertion>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FakePage {
+    pub name: String,
+    /// Reported by `current_url`; `None` for native screens.
+    #[serde(default)]
+    pub url: Option<String>,
+    #[serde(default)]
+    pub elements: Vec<FakeElement>,
+    /// Token the session reports while this page is shown.
+    #[serde(default)]
+    pub session_token: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FakeElement {
+    /// What finds the element; an `AnyOf` lists every selector that does.
+    pub selector: Selector,
+    #[serde(default)]
+    pub text: Option<String>,
+    /// Page shown once the element is clicked.
+    #[serde(default)]
+    pub goto: Option<String>,
+}
+
+/// A check on a finished run. Step paths are written like
+/// `steps[2].on_true[0]`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Assertion {
+    /// The step at `path` started at least once.
+    StepRan { path: StepPath },
+    /// The step at `path` never started.
+    StepSkipped { path: StepPath },
+    /// The conditional at `path` last took `on_true` (`taken: true`) or
+    /// `on_false`.
+    Branch { path: StepPath, taken: bool },
+    /// The last text typed into the element `selector` names.
+    Entered { selector: Selector, value: String },
+    /// How the run ended; `error_contains` is matched against the error.
+    Outcome {
+        success: bool,
+        #[serde(default)]
+        error_contains: Option<String>,
+    },
+    /// Page shown when the run ended.
+    FinalPage { page: String },
+    /// The error handler named `handler` ran.
+    HandlerInvoked { handler: String },
+}
+
+/// Something the script did to the fake app, in order.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum FakeAction {
+    Click {
+        page: String,
+        selector: Selector,
+    },
+    Input {
+        page: String,
+        selector: Selector,
+        value: String,
+    },
+    Swipe {
+        page: String,
+        from: Selector,
+        to: Selector,
+    },
+    /// A click moved the app to another page.
+    Navigated {
+        from: String,
+        to: String,
+    },
+}
+
+/// Result of one case, with everything needed to replay the run.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CaseReport {
+    pub name: String,
+    /// Unmet assertions, or why the case could not run; empty when it
+    /// passed.
+    pub failures: Vec<String>,
+    pub outcome: LoginOutcome,
+    pub final_page: String,
+    pub actions: Vec<FakeAction>,
+    pub events: Vec<ExecutionEventKind>,
+}
+
+impl CaseReport {
+    pub fn passed(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+/// Result of running a whole suite against one script version.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SuiteReport {
+    pub script_id: String,
+    pub version: String,
+    pub cases: Vec<CaseReport>,
+}
+
+impl SuiteReport {
+    pub fn passed(&self) -> usize {
+        self.cases.iter().filter(|case| case.passed()).count()
+    }
+
+    pub fn failed(&self) -> usize {
+        self.cases.len() - self.passed()
+    }
+
+    pub fn is_success(&self) -> bool {
+        self.failed() == 0
+    }
+}
+
+impl fmt::Display for SuiteReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} {}: {} passed, {} failed",
+            self.script_id,
+            self.version,
+            self.passed(),
+            self.failed()
+        )?;
+        for case in &self.cases {
+            let status = if case.passed() { "ok" } else { "FAIL" };
+            writeln!(f, "  {status:<4} {}", case.name)?;
+            for failure in &case.failures {
+                writeln!(f, "       {failure}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Parse a suite file, with the same error locations as script files.
+pub fn parse_suite(text: &str, format: ScriptFormat) -> Result<TestSuite, ScriptFormatError> {
+    let suite: TestSuite = deserialize(text, format)?;
+    for (index, case) in suite.cases.iter().enumerate() {
+        if let Err(message) = check_pages(&case.pages) {
+            return Err(ScriptFormatError::at(
+                format!("cases[{index}].pages"),
+                message,
+            ));
+        }
+    }
+    Ok(suite)
+}
+
+pub fn write_suite(suite: &TestSuite, format: ScriptFormat) -> anyhow::Result<String> {
+    Ok(match format {
+        ScriptFormat::Json => {
+            let mut text = serde_json::to_string_pretty(suite)?;
+            text.push('\n');
+            text
+        }
+        ScriptFormat::Yaml => format::to_yaml(suite)?,
+    })
+}
+
+/// Run every case of `suite` against `script`, one after the other.
+pub async fn run_suite(script: &LoginScript, suite: &TestSuite) -> SuiteReport {
+    let mut cases = Vec::with_capacity(suite.cases.len());
+    for case in &suite.cases {
+        cases.push(run_case(script, case).await);
+    }
+    SuiteReport {
+        script_id: script.meta.id.clone(),
+        version: script.meta.version.clone(),
+        cases,
+    }
+}
+
+/// Run `script` on the fake app `case` describes and check its assertions.
+pub async fn run_case(script: &LoginScript, case: &TestCase) -> CaseReport {
+    if let Err(message) = check_pages(&case.pages) {
+        return CaseReport {
+            name: case.name.clone(),
+            failures: vec![message.clone()],
+            outcome: failed(message),
+            final_page: String::new(),
+            actions: Vec::new(),
+            events: Vec::new(),
+        };
+    }
+    let (sender, mut receiver) = broadcast::channel(EVENT_CAPACITY);
+    let mut ctx = ExecutionContext::new(
+        Arc::new(NoopCaptcha),
+        Arc::new(CaseSecrets(case.secrets.clone())),
+    );
+    ctx.events = EventSink::new(0, sender);
+    ctx.wait_timeout = Duration::ZERO;
+    let mut app = FakeApp::new(&case.pages);
+    let outcome = run_script(&mut app, script, &ctx)
+        .await
+        .unwrap_or_else(|err| failed(err.to_string()));
+
+    let mut events = Vec::new();
+    let mut failures = Vec::new();
+    loop {
+        match receiver.try_recv() {
+            Ok(event) => events.push(event.kind),
+            Err(broadcast::error::TryRecvError::Lagged(missed)) => failures.push(format!(
+                "the run emitted more events than the harness keeps; {missed} were lost"
+            )),
+            Err(_) => break,
+        }
+    }
+    let report = CaseReport {
+        name: case.name.clone(),
+        failures,
+        outcome,
+        final_page: app.page().name.clone(),
+        actions: app.actions,
+        events,
+    };
+    let failures = case
+        .assertions
+        .iter()
+        .filter_map(|assertion| check(assertion, &report, &app.pages))
+        .collect::<Vec<_>>();
+    CaseReport {
+        failures: report.failures.into_iter().chain(failures).collect(),
+        ..report
+    }
+}
+
+/// Why `assertion` does not hold for `report`, `None` when it does.
+fn check(assertion: &Assertion, report: &CaseReport, pages: &[FakePage]) -> Option<String> {
+    match assertion {
+        Assertion::StepRan { path } => (!started(report).contains(path))
+            .then(|| format!("expected {path} to run, but it did not")),
+        Assertion::StepSkipped { path } => started(report)
+            .contains(path)
+            .then(|| format!("expected {path} to be skipped, but it ran")),
+        Assertion::Branch { path, taken } => {
+            let actual = report.events.iter().rev().find_map(|event| match event {
+                ExecutionEventKind::BranchTaken {
+                    path: branch,
+                    condition,
+                } if branch == path => Some(*condition),
+                _ => None,
+            });
+            match actual {
+                Some(actual) if actual == *taken => None,
+                Some(actual) => Some(format!(
+                    "expected {path} to take {}, but it took {}",
+                    branch_name(*taken),
+                    branch_name(actual)
+                )),
+                None => Some(format!("expected {path} to branch, but it never ran")),
+            }
+        }
+        Assertion::Entered { selector, value } => {
+            let actual = report.actions.iter().rev().find_map(|action| match action {
+                FakeAction::Input {
+                    page,
+                    selector: used,
+                    value,
+                } if names(pages, page, used, selector) => Some(value),
+                _ => None,
+            });
+            match actual {
+                Some(actual) if actual == value => None,
+                Some(actual) => Some(format!(
+                    "expected {value:?} in {selector:?}, but {actual:?} was entered"
+                )),
+                None => Some(format!(
+                    "expected {value:?} in {selector:?}, but nothing was entered"
+                )),
+            }
+        }
+        Assertion::Outcome {
+            success,
+            error_contains,
+        } => {
+            let error = report.outcome.error.as_deref().unwrap_or_default();
+            if report.outcome.success != *success {
+                Some(format!(
+                    "expected the run to {}, but it {}",
+                    if *success { "succeed" } else { "fail" },
+                    if report.outcome.success {
+                        "succeeded".to_string()
+                    } else {
+                        format!("failed: {error}")
+                    }
+                ))
+            } else {
+                error_contains
+                    .as_deref()
+                    .filter(|expected| !error.contains(expected))
+                    .map(|expected| {
+                        format!("expected the error to mention {expected:?}, got {error:?}")
+                    })
+            }
+        }
+        Assertion::FinalPage { page } => (report.final_page != *page).then(|| {
+            format!(
+                "expected to end on page {page}, but ended on {}",
+                report.final_page
+            )
+        }),
+        Assertion::HandlerInvoked { handler } => {
+            let invoked = report.events.iter().any(|event| {
+                matches!(event, ExecutionEventKind::HandlerInvoked { handler: name, .. } if name == handler)
+            });
+            (!invoked).then(|| format!("expected error handler {handler} to run, but it did not"))
+        }
+    }
+}
+
+fn started(report: &CaseReport) -> HashSet<&StepPath> {
+    report
+        .events
+        .iter()
+        .filter_map(|event| match event {
+            ExecutionEventKind::StepStarted { path, .. } => Some(path),
+            _ => None,
+        })
+        .collect()
+}
+
+fn branch_name(taken: bool) -> &'static str {
+    if taken {
+        "on_true"
+    } else {
+        "on_false"
+    }
+}
+
+/// Whether `wanted` names the element that `used` found on `page`: either
+/// selector directly, or one of the element's declared selectors.
+fn names(pages: &[FakePage], page: &str, used: &Selector, wanted: &Selector) -> bool {
+    if used == wanted {
+        return true;
+    }
+    pages
+        .iter()
+        .filter(|candidate| candidate.name == page)
+        .flat_map(|page| &page.elements)
+        .find(|element| matches(element, used))
+        .is_some_and(|element| {
+            wanted
+                .candidates()
+                .into_iter()
+                .any(|wanted| matches(element, wanted))
+        })
+}
+
+fn matches(element: &FakeElement, selector: &Selector) -> bool {
+    element.selector == *selector || element.selector.candidates().contains(&selector)
+}
+
+/// Page names must be unique and every `goto` must name one of them.
+fn check_pages(pages: &[FakePage]) -> Result<(), String> {
+    if pages.is_empty() {
+        return Err("a test case needs at least one page".into());
+    }
+    let mut names = HashSet::new();
+    for page in pages {
+        if !names.insert(page.name.as_str()) {
+            return Err(format!("page {} is defined twice", page.name));
+        }
+    }
+    for page in pages {
+        for element in &page.elements {
+            if let Some(goto) = element.goto.as_deref().filter(|goto| !names.contains(goto)) {
+                return Err(format!(
+                    "element {:?} on page {} goes to unknown page {goto}",
+                    element.selector, page.name
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn failed(error: String) -> LoginOutcome {
+    LoginOutcome {
+        success: false,
+        session_token: None,
+        error: Some(error),
+        cancelled: false,
+        error_kind: None,
+        attempts: Vec::new(),
+    }
+}
+
+struct CaseSecrets(BTreeMap<String, String>);
+
+impl SecretResolver for CaseSecrets {
+    fn resolve(&self, key: &str) -> anyhow::Result<String> {
+        self.0
+            .get(key)
+            .cloned()
+            .ok_or_else(|| anyhow::anyhow!("test case has no secret {key}"))
+    }
+
+    fn contains(&self, key: &str) -> Option<bool> {
+        Some(self.0.contains_key(key))
+    }
+}
+
+/// Session over the pages of a test case.
+struct FakeApp {
+    pages: Vec<FakePage>,
+    current: usize,
+    actions: Vec<FakeAction>,
+    /// Text typed into elements, by page and element index.
+    entered: BTreeMap<(usize, usize), String>,
+}
+
+impl FakeApp {
+    fn new(pages: &[FakePage]) -> Self {
+        Self {
+            pages: pages.to_vec(),
+            current: 0,
+            actions: Vec::new(),
+            entered: BTreeMap::new(),
+        }
+    }
+
+    fn page(&self) -> &FakePage {
+        &self.pages[self.current]
+    }
+
+    fn find(&self, selector: &Selector) -> Option<usize> {
+        self.page()
+            .elements
+            .iter()
+            .position(|element| matches(element, selector))
+    }
+
+    /// Element `selector` finds; coordinates always hit something, like
+    /// on a real screen.
+    fn target(&self, selector: &Selector) -> Result<Option<usize>, StepError> {
+        match self.find(selector) {
+            Some(index) => Ok(Some(index)),
+            None if matches!(selector, Selector::Coordinates { .. }) => Ok(None),
+            None => Err(StepError::not_found(selector)),
+        }
+    }
+}
+
+#[async_trait]
+impl DriverSession for FakeApp {
+    async fn exists(&mut self, selector: &Selector) -> anyhow::Result<bool> {
+        Ok(matches!(selector, Selector::Coordinates { .. }) || self.find(selector).is_some())
+    }
+
+    async fn click(&mut self, selector: &Selector) -> anyhow::Result<()> {
+        let element = self.target(selector)?;
+        let from = self.page().name.clone();
+        self.actions.push(FakeAction::Click {
+            page: from.clone(),
+            selector: selector.clone(),
+        });
+        let goto = element.and_then(|index| self.page().elements[index].goto.clone());
+        if let Some(to) = goto {
+            self.current = self
+                .pages
+                .iter()
+                .position(|page| page.name == to)
+                .ok_or_else(|| anyhow::anyhow!("unknown page {to}"))?;
+            self.actions.push(FakeAction::Navigated { from, to });
+        }
+        Ok(())
+    }
+
+    async fn input(&mut self, selector: &Selector, text: &str) -> anyhow::Result<()> {
+        if let Some(index) = self.target(selector)? {
+            self.entered.insert((self.current, index), text.to_string());
+        }
+        self.actions.push(FakeAction::Input {
+            page: self.page().name.clone(),
+            selector: selector.clone(),
+            value: text.to_string(),
+        });
+        Ok(())
+    }
+
+    async fn swipe(
+        &mut self,
+        from: &Selector,
+        to: &Selector,
+        _duration_ms: u64,
+    ) -> anyhow::Result<()> {
+        self.target(from)?;
+        self.target(to)?;
+        self.actions.push(FakeAction::Swipe {
+            page: self.page().name.clone(),
+            from: from.clone(),
+            to: to.clone(),
+        });
+        Ok(())
+    }
+
+    async fn text(&mut self, selector: &Selector) -> anyhow::Result<Option<String>> {
+        Ok(self.find(selector).and_then(|index| {
+            self.entered
+                .get(&(self.current, index))
+                .cloned()
+                .or_else(|| self.page().elements[index].text.clone())
+        }))
+    }
+
+    async fn capture(&mut self, selector: &Selector) -> anyhow::Result<Vec<u8>> {
+        self.target(selector)?;
+        Ok(Vec::new())
+    }
+
+    async fn screenshot(&mut self) -> anyhow::Result<Vec<u8>> {
+        Ok(Vec::new())
+    }
+
+    async fn session_token(&mut self) -> anyhow::Result<Option<String>> {
+        Ok(self.page().session_token.clone())
+    }
+
+    async fn current_url(&mut self) -> anyhow::Result<Option<String>> {
+        Ok(self.page().url.clone())
+    }
+}
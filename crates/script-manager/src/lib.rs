@@ -1,72 +1,243 @@
 //! Script recording, editing, and versioning skeleton crate.
 
+pub mod accessibility;
+pub mod diff;
+pub mod fields;
+pub mod format;
+pub mod harness;
+pub mod merge;
+pub mod migrate;
+pub mod postprocess;
+pub mod recorder;
+pub mod store;
+pub mod trace_viewer;
+pub use accessibility::{
+    AccessibilityEvent, AccessibilityEventSource, AccessibilityNode, AccessibilityRecorder,
+    JsonEventLog,
+};
+pub use diff::{
+    diff_scripts, FieldChange, HandlerChange, ScriptDiff, SelectorChange, StepChange,
+    ValidationChange,
+};
+pub use fields::{FieldRole, LoginField};
+pub use format::{
+    parse_script, read_script, write_script, LoadedScript, ScriptFormat, ScriptFormatError,
+    FORMAT_VERSION,
+};
+pub use harness::{
+    parse_suite, run_case, run_suite, write_suite, Assertion, CaseReport, FakeAction, FakeElement,
+    FakePage, SuiteReport, TestCase, TestSuite,
+};
+pub use merge::{merge_scripts, MergeConflict, MergeResult};
+pub use migrate::MigrationRecord;
+pub use postprocess::script_from_recording;
+pub use recorder::{
+    ElementDescriptor, RecordedEvent, RecordedPage, Recorder, RECORDER_BINDING, RECORDER_JS,
+};
+pub use store::{HistoryEntry, HistoryKind, InMemoryStore, ScriptStore, SqliteStore};
+pub use trace_viewer::{TraceBundle, TraceCursor, TraceFrame};
+
 use automation_engine::{LoginScript, Selector, Step, TargetApp};
+use semver::Version;
 use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
 use std::collections::HashMap;
-use std::sync::{Arc, Mutex};
+use std::path::Path;
+use std::sync::Arc;
 
-/// In-memory script manager that supports recording, saving, loading and
-/// keeping multiple versions per script id. This can later be swapped for a
-/// persistent backend (SQLite/kv).
-#[derive(Debug, Default)]
+/// Script manager that supports recording, saving, loading and keeping
+/// multiple versions per script id on top of a `ScriptStore`. The default
+/// keeps everything in memory; `open` persists to SQLite.
+#[derive(Debug, Clone)]
 pub struct ScriptManager {
-    store: Arc<Mutex<HashMap<String, Vec<LoginScript>>>>,
+    store: Arc<dyn ScriptStore>,
+}
+
+impl Default for ScriptManager {
+    fn default() -> Self {
+        Self::with_store(Arc::new(InMemoryStore::default()))
+    }
 }
 
 impl ScriptManager {
+    pub fn with_store(store: Arc<dyn ScriptStore>) -> Self {
+        Self { store }
+    }
+
+    /// Manager backed by the SQLite database at `path`.
+    pub fn open(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        Ok(Self::with_store(Arc::new(SqliteStore::open(path)?)))
+    }
+
+    pub fn store(&self) -> &dyn ScriptStore {
+        self.store.as_ref()
+    }
+
     pub fn record(&self, target: TargetApp) -> RecordedSession {
         RecordedSession {
             target,
             steps: Vec::new(),
             selectors: Vec::new(),
+            fields: Vec::new(),
+            pages: Vec::new(),
         }
     }
 
-    /// Save a script, keeping versions under the same id ordered by insertion.
+    /// Start recording against `target`; feed it the events `RECORDER_JS`
+    /// reports and call `finish` for the session.
+    pub fn recorder(&self, target: TargetApp) -> Recorder {
+        Recorder::new(self.record(target))
+    }
+
+    /// Save a script as a new version. `meta.version` must be a semantic
+    /// version not yet saved under the same id.
     pub fn save(&self, script: LoginScript) -> anyhow::Result<()> {
-        let mut guard = self
-            .store
-            .lock()
-            .map_err(|_| anyhow::anyhow!("script store poisoned"))?;
-        guard.entry(script.meta.id.clone()).or_default().push(script);
-        Ok(())
+        self.publish(&script, None)
     }
 
-    /// Load a specific version or the latest version of the script.
-    pub fn load(&self, id: &str, version: Option<&str>) -> anyhow::Result<LoginScript> {
-        let guard = self
-            .store
-            .lock()
-            .map_err(|_| anyhow::anyhow!("script store poisoned"))?;
-        let versions = guard
-            .get(id)
-            .ok_or_else(|| anyhow::anyhow!("script {id} not found"))?;
+    /// Like `save`, recording `changelog` for the new version.
+    pub fn save_with_changelog(&self, script: LoginScript, changelog: &str) -> anyhow::Result<()> {
+        self.publish(&script, Some(changelog))
+    }
 
+    fn publish(&self, script: &LoginScript, changelog: Option<&str>) -> anyhow::Result<()> {
+        parse_version(&script.meta.id, &script.meta.version)?;
+        // The store rejects duplicate versions inside its save.
+        self.store.save(script, changelog)
+    }
+
+    /// Load a specific version, or the highest semantic version when
+    /// `version` is `None`.
+    pub fn load(&self, id: &str, version: Option<&str>) -> anyhow::Result<LoginScript> {
         if let Some(ver) = version {
-            versions
-                .iter()
-                .find(|s| s.meta.version == ver)
-                .cloned()
+            self.store
+                .get(id, ver)?
                 .ok_or_else(|| anyhow::anyhow!("script {id} version {ver} not found"))
         } else {
-            versions
-                .last()
-                .cloned()
-                .ok_or_else(|| anyhow::anyhow!("script {id} has no versions"))
+            self.store
+                .versions(id)?
+                .into_iter()
+                .max_by(|a, b| version_order(&a.meta.version, &b.meta.version))
+                .ok_or_else(|| anyhow::anyhow!("script {id} not found"))
+        }
+    }
+
+    /// Publish `version` again as the next patch release above the current
+    /// latest, so it becomes the version `load` returns. Earlier versions
+    /// stay untouched.
+    pub fn rollback(&self, id: &str, version: &str) -> anyhow::Result<LoginScript> {
+        let mut script = self.load(id, Some(version))?;
+        let latest = self.load(id, None)?;
+        let latest = parse_version(id, &latest.meta.version)?;
+        let next = if latest.pre.is_empty() {
+            Version::new(latest.major, latest.minor, latest.patch + 1)
+        } else {
+            Version::new(latest.major, latest.minor, latest.patch)
+        };
+        script.meta.version = next.to_string();
+        self.publish(&script, Some(&format!("rollback to {version}")))?;
+        Ok(script)
+    }
+
+    /// Read a `.json`/`.yaml` script file and save it as a new version.
+    /// Files in an older format are upgraded first and the migrations are
+    /// recorded in the script's history.
+    pub fn import(&self, path: &Path) -> anyhow::Result<LoginScript> {
+        let format = format_of(path)?;
+        let text = std::fs::read_to_string(path)
+            .map_err(|err| anyhow::anyhow!("failed to read {}: {err}", path.display()))?;
+        let loaded = read_script(&text, format)
+            .map_err(|err| anyhow::anyhow!("{}: {err}", path.display()))?;
+        self.save(loaded.script.clone())?;
+        for migration in &loaded.migrations {
+            self.store
+                .append_history(&HistoryEntry::migrated(&loaded.script, migration))?;
         }
+        Ok(loaded.script)
     }
 
-    /// List available versions for a script id.
+    /// Write a stored script to `path`, in the format its extension names.
+    pub fn export(&self, id: &str, version: Option<&str>, path: &Path) -> anyhow::Result<()> {
+        let format = format_of(path)?;
+        let script = self.load(id, version)?;
+        std::fs::write(path, write_script(&script, format)?)
+            .map_err(|err| anyhow::anyhow!("failed to write {}: {err}", path.display()))
+    }
+
+    /// What changed between two stored versions of `id`.
+    pub fn diff(&self, id: &str, from: &str, to: &str) -> anyhow::Result<ScriptDiff> {
+        Ok(diff_scripts(
+            &self.load(id, Some(from))?,
+            &self.load(id, Some(to))?,
+        ))
+    }
+
+    /// Three-way merge of two versions edited concurrently from `base`.
+    /// Nothing is saved; publish the merged script with `save` once its
+    /// conflicts are resolved.
+    pub fn merge(
+        &self,
+        id: &str,
+        base: &str,
+        ours: &str,
+        theirs: &str,
+    ) -> anyhow::Result<MergeResult> {
+        Ok(merge_scripts(
+            &self.load(id, Some(base))?,
+            &self.load(id, Some(ours))?,
+            &self.load(id, Some(theirs))?,
+        ))
+    }
+
+    /// Keep `suite` as the tests of a stored version, replacing earlier
+    /// ones.
+    pub fn save_tests(&self, id: &str, version: &str, suite: &TestSuite) -> anyhow::Result<()> {
+        self.load(id, Some(version))?;
+        self.store.save_suite(id, version, suite)
+    }
+
+    /// Tests of a stored version; empty when none were saved.
+    pub fn tests(&self, id: &str, version: &str) -> anyhow::Result<TestSuite> {
+        Ok(self.store.suite(id, version)?.unwrap_or_default())
+    }
+
+    /// Run the tests stored with a version (the highest one when `version`
+    /// is `None`) against that version.
+    pub async fn run_tests(&self, id: &str, version: Option<&str>) -> anyhow::Result<SuiteReport> {
+        let script = self.load(id, version)?;
+        let suite = self.tests(id, &script.meta.version)?;
+        Ok(run_suite(&script, &suite).await)
+    }
+
+    /// Saves and format upgrades of `id`, oldest first.
+    pub fn history(&self, id: &str) -> anyhow::Result<Vec<HistoryEntry>> {
+        self.store.history(id)
+    }
+
+    /// List available versions for a script id, lowest semantic version
+    /// first.
     pub fn list_versions(&self, id: &str) -> Vec<ScriptVersion> {
-        let guard = self.store.lock().ok();
-        guard
-            .and_then(|map| map.get(id).cloned())
+        let changelogs: HashMap<String, String> = self
+            .store
+            .history(id)
+            .map_err(|err| tracing::warn!(id, error = %err, "failed to read script history"))
             .unwrap_or_default()
+            .into_iter()
+            .filter(|entry| entry.kind == HistoryKind::Saved)
+            .filter_map(|entry| Some((entry.version, entry.note?)))
+            .collect();
+        let mut versions = self
+            .store
+            .versions(id)
+            .map_err(|err| tracing::warn!(id, error = %err, "failed to list script versions"))
+            .unwrap_or_default();
+        versions.sort_by(|a, b| version_order(&a.meta.version, &b.meta.version));
+        versions
             .into_iter()
             .map(|s| ScriptVersion {
                 id: s.meta.id.clone(),
                 version: s.meta.version.clone(),
-                changelog: None,
+                changelog: changelogs.get(&s.meta.version).cloned(),
                 created_at: s.meta.created_at.clone().unwrap_or_default(),
                 author: s.meta.author.clone(),
             })
@@ -74,12 +245,41 @@ impl ScriptManager {
     }
 }
 
+fn parse_version(id: &str, version: &str) -> anyhow::Result<Version> {
+    Version::parse(version).map_err(|err| {
+        anyhow::anyhow!("script {id} version {version:?} is not a semantic version: {err}")
+    })
+}
+
+/// Semantic version precedence; versions that do not parse (saved before
+/// versions were enforced) sort below all others.
+fn version_order(a: &str, b: &str) -> Ordering {
+    match (Version::parse(a), Version::parse(b)) {
+        (Ok(a), Ok(b)) => a.cmp_precedence(&b),
+        (Ok(_), Err(_)) => Ordering::Greater,
+        (Err(_), Ok(_)) => Ordering::Less,
+        (Err(_), Err(_)) => Ordering::Equal,
+    }
+}
+
+fn format_of(path: &Path) -> anyhow::Result<ScriptFormat> {
+    ScriptFormat::from_path(path)
+        .ok_or_else(|| anyhow::anyhow!("{} is not a .json, .yaml or .yml file", path.display()))
+}
+
 /// Captured artifacts during recording.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RecordedSession {
     pub target: TargetApp,
     pub steps: Vec<Step>,
     pub selectors: Vec<Selector>,
+    /// Steps recognized as the username, password or submit of the login
+    /// form.
+    #[serde(default)]
+    pub fields: Vec<LoginField>,
+    /// Pages visited, in order; the last one is where the login ended.
+    #[serde(default)]
+    pub pages: Vec<RecordedPage>,
 }
 
 /// Version control metadata for scripts.
@@ -106,6 +306,8 @@ pub fn build_login_script(meta: (&str, &str), target: TargetApp, steps: Vec<Step
         steps,
         validations: vec![],
         error_handlers: vec![],
+        token: None,
+        retry: None,
     }
 }
 
@@ -0,0 +1,326 @@
+//! Three-way merge of two concurrent edits of one script.
+//!
+//! Step lists, validations and error handlers are merged like lines in
+//! `diff3`: regions only one side touched take that side's edit, regions
+//! both sides changed the same way are taken once, anything else is a
+//! conflict. Such a region is narrowed down where possible: edits to
+//! different steps at the same positions merge step by step, and when both
+//! sides edited the same `Conditional`, `Loop` or `Retry` (or the same
+//! error handler) its body is merged recursively instead of conflicting as
+//! a whole. Conflicting regions keep "our" side
+//! in the merged script and are listed in [`MergeResult::conflicts`].
+
+use crate::diff::{lcs, same_arguments, same_kind};
+use automation_engine::{LoginScript, PathSegment, Step, StepPath};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MergeResult {
+    /// `meta` is taken from our side; conflicting regions hold our version.
+    pub script: LoginScript,
+    pub conflicts: Vec<MergeConflict>,
+}
+
+impl MergeResult {
+    pub fn is_clean(&self) -> bool {
+        self.conflicts.is_empty()
+    }
+}
+
+/// A region both sides changed differently. The values are the region's
+/// content on each side: a list of steps, validations or handlers, a
+/// single step, or a field.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MergeConflict {
+    /// Where the region starts in the merged script, e.g. `steps[3]`,
+    /// `validations[0]` or `target`.
+    pub location: String,
+    pub base: serde_json::Value,
+    pub ours: serde_json::Value,
+    pub theirs: serde_json::Value,
+}
+
+/// Merge `ours` and `theirs`, both edited from `base`.
+pub fn merge_scripts(base: &LoginScript, ours: &LoginScript, theirs: &LoginScript) -> MergeResult {
+    let mut conflicts = Vec::new();
+    let steps = merge_steps(
+        &base.steps,
+        &ours.steps,
+        &theirs.steps,
+        (&StepPath::root(), PathSegment::Steps),
+        &mut conflicts,
+    );
+
+    let mut validations = Vec::new();
+    for chunk in diff3(&base.validations, &ours.validations, &theirs.validations) {
+        match chunk {
+            Chunk::Resolved(resolved) => validations.extend_from_slice(resolved),
+            Chunk::Conflict { base, ours, theirs } => {
+                conflicts.push(conflict(
+                    format!("validations[{}]", validations.len()),
+                    base,
+                    ours,
+                    theirs,
+                ));
+                validations.extend_from_slice(ours);
+            }
+        }
+    }
+
+    let mut error_handlers = Vec::new();
+    for chunk in diff3(
+        &base.error_handlers,
+        &ours.error_handlers,
+        &theirs.error_handlers,
+    ) {
+        match chunk {
+            Chunk::Resolved(resolved) => error_handlers.extend_from_slice(resolved),
+            Chunk::Conflict {
+                base: [base],
+                ours: [ours],
+                theirs: [theirs],
+            } if base.name == ours.name && base.name == theirs.name => {
+                let handler = error_handlers.len();
+                let on_error = merge_steps(
+                    &base.on_error,
+                    &ours.on_error,
+                    &theirs.on_error,
+                    (&StepPath::root(), |index| PathSegment::OnError {
+                        handler,
+                        index,
+                    }),
+                    &mut conflicts,
+                );
+                error_handlers.push(automation_engine::ErrorHandler {
+                    name: ours.name.clone(),
+                    on_error,
+                });
+            }
+            Chunk::Conflict { base, ours, theirs } => {
+                conflicts.push(conflict(
+                    format!("error_handlers[{}]", error_handlers.len()),
+                    base,
+                    ours,
+                    theirs,
+                ));
+                error_handlers.extend_from_slice(ours);
+            }
+        }
+    }
+
+    let script = LoginScript {
+        meta: ours.meta.clone(),
+        target: merge_field(
+            "target",
+            &base.target,
+            &ours.target,
+            &theirs.target,
+            &mut conflicts,
+        ),
+        steps,
+        validations,
+        error_handlers,
+        token: merge_field(
+            "token",
+            &base.token,
+            &ours.token,
+            &theirs.token,
+            &mut conflicts,
+        ),
+        retry: merge_field(
+            "retry",
+            &base.retry,
+            &ours.retry,
+            &theirs.retry,
+            &mut conflicts,
+        ),
+    };
+    MergeResult { script, conflicts }
+}
+
+enum Chunk<'a, T> {
+    Resolved(&'a [T]),
+    Conflict {
+        base: &'a [T],
+        ours: &'a [T],
+        theirs: &'a [T],
+    },
+}
+
+/// Split the three lists into regions that merge cleanly and regions both
+/// sides changed differently.
+fn diff3<'a, T: PartialEq>(base: &'a [T], ours: &'a [T], theirs: &'a [T]) -> Vec<Chunk<'a, T>> {
+    let to_ours = matches(base, ours);
+    let to_theirs = matches(base, theirs);
+    let (mut i, mut j, mut k) = (0, 0, 0);
+    let mut chunks = Vec::new();
+    loop {
+        // Run of base items both sides kept in place.
+        let start = j;
+        while i < base.len() && to_ours[i] == Some(j) && to_theirs[i] == Some(k) {
+            i += 1;
+            j += 1;
+            k += 1;
+        }
+        if j > start {
+            chunks.push(Chunk::Resolved(&ours[start..j]));
+        }
+        if i == base.len() && j == ours.len() && k == theirs.len() {
+            break;
+        }
+        // Up to the next base item both sides still have.
+        let (end, ours_end, theirs_end) = (i..base.len())
+            .find_map(|l| Some((l, to_ours[l]?, to_theirs[l]?)))
+            .unwrap_or((base.len(), ours.len(), theirs.len()));
+        let (base, ours, theirs) = (&base[i..end], &ours[j..ours_end], &theirs[k..theirs_end]);
+        chunks.push(if ours == base {
+            Chunk::Resolved(theirs)
+        } else if theirs == base || ours == theirs {
+            Chunk::Resolved(ours)
+        } else {
+            Chunk::Conflict { base, ours, theirs }
+        });
+        (i, j, k) = (end, ours_end, theirs_end);
+    }
+    chunks
+}
+
+/// For each item of `base`, its position in `other` if it is part of their
+/// longest common subsequence.
+fn matches<T: PartialEq>(base: &[T], other: &[T]) -> Vec<Option<usize>> {
+    let mut positions = vec![None; base.len()];
+    for (i, j) in lcs(base, other) {
+        positions[i] = Some(j);
+    }
+    positions
+}
+
+fn merge_steps(
+    base: &[Step],
+    ours: &[Step],
+    theirs: &[Step],
+    at: (&StepPath, impl Fn(usize) -> PathSegment),
+    conflicts: &mut Vec<MergeConflict>,
+) -> Vec<Step> {
+    let mut merged = Vec::new();
+    for chunk in diff3(base, ours, theirs) {
+        match chunk {
+            Chunk::Resolved(resolved) => merged.extend_from_slice(resolved),
+            // Edits to neighbouring steps land in one region; when no step
+            // was added or removed, merge them one position at a time.
+            Chunk::Conflict { base, ours, theirs }
+                if base.len() == ours.len() && base.len() == theirs.len() =>
+            {
+                for ((base, ours), theirs) in base.iter().zip(ours).zip(theirs) {
+                    let path = at.0.child(at.1(merged.len()));
+                    merged.push(if ours == base {
+                        theirs.clone()
+                    } else if theirs == base || ours == theirs {
+                        ours.clone()
+                    } else if same_kind(base, ours) && same_kind(base, theirs) {
+                        merge_step(base, ours, theirs, &path, conflicts)
+                    } else {
+                        conflicts.push(conflict(path.to_string(), base, ours, theirs));
+                        ours.clone()
+                    });
+                }
+            }
+            Chunk::Conflict { base, ours, theirs } => {
+                let path = at.0.child(at.1(merged.len()));
+                conflicts.push(conflict(path.to_string(), base, ours, theirs));
+                merged.extend_from_slice(ours);
+            }
+        }
+    }
+    merged
+}
+
+/// Three edits of one step of the same kind: merge the step's arguments
+/// and, for steps with a body, the body.
+fn merge_step(
+    base: &Step,
+    ours: &Step,
+    theirs: &Step,
+    path: &StepPath,
+    conflicts: &mut Vec<MergeConflict>,
+) -> Step {
+    let header = if same_arguments(ours, base) {
+        theirs
+    } else if same_arguments(theirs, base) || same_arguments(ours, theirs) {
+        ours
+    } else {
+        conflicts.push(conflict(path.to_string(), base, ours, theirs));
+        ours
+    };
+    let mut body = |segment: fn(usize) -> PathSegment, pick: fn(&Step) -> &[Step]| {
+        merge_steps(
+            pick(base),
+            pick(ours),
+            pick(theirs),
+            (path, segment),
+            conflicts,
+        )
+    };
+    match header {
+        Step::Conditional { condition, .. } => Step::Conditional {
+            condition: condition.clone(),
+            on_true: body(PathSegment::OnTrue, |step| match step {
+                Step::Conditional { on_true, .. } => on_true,
+                _ => &[],
+            }),
+            on_false: body(PathSegment::OnFalse, |step| match step {
+                Step::Conditional { on_false, .. } => on_false,
+                _ => &[],
+            }),
+        },
+        Step::Loop { times, .. } => Step::Loop {
+            times: *times,
+            body: body(PathSegment::Body, step_body),
+        },
+        Step::Retry { policy, .. } => Step::Retry {
+            policy: policy.clone(),
+            body: body(PathSegment::Body, step_body),
+        },
+        // Without a body the header conflict above already covers it.
+        leaf => leaf.clone(),
+    }
+}
+
+fn step_body(step: &Step) -> &[Step] {
+    match step {
+        Step::Loop { body, .. } | Step::Retry { body, .. } => body,
+        _ => &[],
+    }
+}
+
+fn merge_field<T: Clone + PartialEq + Serialize>(
+    field: &str,
+    base: &T,
+    ours: &T,
+    theirs: &T,
+    conflicts: &mut Vec<MergeConflict>,
+) -> T {
+    if ours == base {
+        theirs.clone()
+    } else if theirs != base && ours != theirs {
+        conflicts.push(conflict(field.into(), base, ours, theirs));
+        ours.clone()
+    } else {
+        ours.clone()
+    }
+}
+
+fn conflict<T: Serialize + ?Sized>(
+    location: String,
+    base: &T,
+    ours: &T,
+    theirs: &T,
+) -> MergeConflict {
+    let value = |value: &T| serde_json::to_value(value).unwrap_or_default();
+    MergeConflict {
+        location,
+        base: value(base),
+        ours: value(ours),
+        theirs: value(theirs),
+    }
+}
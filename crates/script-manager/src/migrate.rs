@@ -0,0 +1,118 @@
+//! Upgrades older script documents to the current file format.
+//!
+//! A document is the JSON form of a script file (`format_version` plus the
+//! serialized `LoginScript`). Documents without `format_version` predate the
+//! file format and count as version 0. Each entry in [`MIGRATIONS`] lifts a
+//! document by exactly one version, so an old file walks the whole chain.
+//!
+//! Every migration has a fixture pair under
+//! `crates/script-manager/fixtures/migrations/<to>/` (`input.json`, the
+//! document before, and `expected.json`, the document after), checked by
+//! `tests/migrations.rs`.
+//!
+//! The chain is a stub so far: the only layout change since version 0 added
+//! `token` and `retry`, which deserialize with defaults, so v0 -> v1 only
+//! stamps the version. The first change that renames or restructures fields
+//! gets a real migration here.
+
+use crate::format::{ScriptFormatError, FORMAT_VERSION};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+/// One step of the chain, upgrading documents from `from` to `from + 1`.
+pub struct Migration {
+    pub from: u32,
+    pub description: &'static str,
+    pub apply: fn(&mut Map<String, Value>) -> Result<(), ScriptFormatError>,
+    /// Maps a field path of the upgraded document (e.g. `steps[2].Input`)
+    /// to the path the same field had before, so errors found after
+    /// migrating can point into the original file.
+    pub source_path: fn(&str) -> String,
+}
+
+/// Only ever append; entry `n` upgrades version `n` to `n + 1`.
+pub const MIGRATIONS: &[Migration] = &[Migration {
+    from: 0,
+    description: "stamp format_version",
+    apply: v0_to_v1,
+    source_path: unchanged,
+}];
+
+const _: () = assert!(MIGRATIONS.len() as u32 == FORMAT_VERSION);
+
+/// A migration that ran on a document.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MigrationRecord {
+    pub from: u32,
+    pub to: u32,
+    pub description: String,
+}
+
+/// `format_version` of `document`; 0 when absent.
+pub fn document_version(document: &Value) -> Result<u32, ScriptFormatError> {
+    match document.get("format_version") {
+        None | Some(Value::Null) => Ok(0),
+        Some(value) => value
+            .as_u64()
+            .and_then(|version| u32::try_from(version).ok())
+            .ok_or_else(|| {
+                ScriptFormatError::at(
+                    "format_version",
+                    format!("expected a version number, got {value}"),
+                )
+            }),
+    }
+}
+
+/// Bring `document` up to [`FORMAT_VERSION`], returning the migrations that
+/// ran (empty when it was already current).
+pub fn migrate(document: &mut Value) -> Result<Vec<MigrationRecord>, ScriptFormatError> {
+    let version = document_version(document)?;
+    if version > FORMAT_VERSION {
+        return Err(ScriptFormatError::at(
+            "format_version",
+            format!("format version {version} is newer than supported ({FORMAT_VERSION})"),
+        ));
+    }
+    let Value::Object(fields) = document else {
+        return Err(ScriptFormatError::at(
+            "",
+            "expected a mapping at the top level",
+        ));
+    };
+    let mut applied = Vec::new();
+    for migration in &MIGRATIONS[version as usize..] {
+        (migration.apply)(fields)?;
+        let to = migration.from + 1;
+        fields.insert("format_version".into(), to.into());
+        applied.push(MigrationRecord {
+            from: migration.from,
+            to,
+            description: migration.description.into(),
+        });
+    }
+    Ok(applied)
+}
+
+/// Path in a version `from` document of the field found at `path` once it
+/// was migrated to [`FORMAT_VERSION`].
+pub fn source_path(from: u32, path: &str) -> String {
+    MIGRATIONS
+        .iter()
+        .rev()
+        .filter(|migration| migration.from >= from)
+        .fold(path.to_string(), |path, migration| {
+            (migration.source_path)(&path)
+        })
+}
+
+/// `source_path` of migrations that move no fields.
+fn unchanged(path: &str) -> String {
+    path.to_string()
+}
+
+/// Version 0 is the bare `LoginScript` serialization saved before script
+/// files existed; it already has the version 1 layout.
+fn v0_to_v1(_fields: &mut Map<String, Value>) -> Result<(), ScriptFormatError> {
+    Ok(())
+}
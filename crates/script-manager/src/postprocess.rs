@@ -0,0 +1,218 @@
+//! Turns a raw recording into a script ready to run.
+//!
+//! [`script_from_recording`] drops the noise a person leaves behind while
+//! recording, puts a `WaitFor` in front of every interaction, infers the
+//! validations that tell a successful login from the page the recording
+//! ended on, and adds error handlers that clear common interruptions
+//! (cookie banners, dialogs, "not now" prompts).
+
+use crate::fields::{is_logout, FieldRole};
+use crate::recorder::wait_target;
+use crate::{build_login_script, RecordedPage, RecordedSession};
+use automation_engine::{Condition, ErrorHandler, LoginScript, Selector, Step, Validation};
+
+/// Build a script from `session`; `meta` is `(id, version)` as for
+/// [`build_login_script`].
+pub fn script_from_recording(session: &RecordedSession, meta: (&str, &str)) -> LoginScript {
+    let mut steps = with_waits(denoise(&session.steps));
+    let logout = result_page(session)
+        .and_then(|page| page.controls.iter().find(|control| is_logout(control)))
+        .and_then(|control| control.selector())
+        .as_ref()
+        .and_then(wait_target);
+    // Validations run right after the last step; give the page that
+    // follows the submit time to load.
+    if let Some(logout) = &logout {
+        steps.push(Step::WaitFor(logout.clone()));
+    }
+    let mut script = build_login_script(meta, session.target.clone(), steps);
+    script.validations = infer_validations(session, logout);
+    script.error_handlers = default_error_handlers();
+    script
+}
+
+/// The recorded actions without the recorder's waits and without noise:
+/// clicks that only focused a field typed into on the same page, repeated
+/// clicks on one element and a field filled in twice in a row. A
+/// navigation (marked by a recorded `WaitFor`) separates pages, so the same
+/// button clicked on two pages is kept twice.
+fn denoise(steps: &[Step]) -> Vec<Step> {
+    let mut kept: Vec<Step> = Vec::new();
+    for page in steps.split(|step| matches!(step, Step::WaitFor(_))) {
+        let fields: Vec<&Selector> = page
+            .iter()
+            .filter_map(|step| match step {
+                Step::Input { selector, .. } => Some(selector),
+                _ => None,
+            })
+            .collect();
+        let page_start = kept.len();
+        for step in page {
+            match (kept[page_start..].last(), step) {
+                (_, Step::Click(selector)) if fields.contains(&selector) => continue,
+                (Some(Step::Click(last)), Step::Click(selector)) if last == selector => continue,
+                (Some(Step::Input { selector: last, .. }), Step::Input { selector, .. })
+                    if last == selector =>
+                {
+                    kept.pop();
+                }
+                _ => {}
+            }
+            kept.push(step.clone());
+        }
+    }
+    kept
+}
+
+/// Wait for each interaction's element before acting on it.
+fn with_waits(steps: Vec<Step>) -> Vec<Step> {
+    let mut waited = Vec::with_capacity(steps.len() * 2);
+    for step in steps {
+        let target = match &step {
+            Step::Click(selector) | Step::Input { selector, .. } => wait_target(selector),
+            Step::Swipe { from, .. } => wait_target(from),
+            _ => None,
+        };
+        if let Some(selector) = target {
+            waited.push(Step::WaitFor(selector));
+        }
+        waited.push(step);
+    }
+    waited
+}
+
+/// The page the recording ended on, if it was reached after the last
+/// action; a recording stopped before the submit landed has none.
+fn result_page(session: &RecordedSession) -> Option<&RecordedPage> {
+    session
+        .pages
+        .last()
+        .filter(|page| page.step == session.steps.len() && page.step > 0)
+}
+
+/// Success checks, strongest first: the URL moved away from the login
+/// page, a logout control is shown. Without either, the password field
+/// having disappeared is the best sign left.
+fn infer_validations(session: &RecordedSession, logout: Option<Selector>) -> Vec<Validation> {
+    let mut validations = Vec::new();
+    let password = password_step(session);
+    if let (Some(login), Some(result)) = (login_page(session, password), result_page(session)) {
+        let (start, end) = (page_key(&login.url), page_key(&result.url));
+        if !start.is_empty() && !end.is_empty() && start != end {
+            validations.push(if start.contains(&end) {
+                Validation {
+                    description: format!("left {start}"),
+                    condition: Condition::Not(Box::new(Condition::UrlContains(start))),
+                }
+            } else {
+                Validation {
+                    description: format!("landed on {end}"),
+                    condition: Condition::UrlContains(end),
+                }
+            });
+        }
+    }
+    if let Some(logout) = logout {
+        validations.push(Validation {
+            description: "logout control is shown".into(),
+            condition: Condition::Exists(logout),
+        });
+    }
+    if validations.is_empty() {
+        let field = password.and_then(|step| match session.steps.get(step) {
+            Some(Step::Input { selector, .. }) => wait_target(selector),
+            _ => None,
+        });
+        if let Some(selector) = field {
+            validations.push(Validation {
+                description: "password field is gone".into(),
+                condition: Condition::Not(Box::new(Condition::Exists(selector))),
+            });
+        }
+    }
+    validations
+}
+
+fn password_step(session: &RecordedSession) -> Option<usize> {
+    session
+        .fields
+        .iter()
+        .rev()
+        .find(|field| field.role == FieldRole::Password)
+        .map(|field| field.step)
+}
+
+/// Page the password was typed on, else the first page recorded.
+fn login_page(session: &RecordedSession, password: Option<usize>) -> Option<&RecordedPage> {
+    password
+        .and_then(|step| session.pages.iter().rev().find(|page| page.step <= step))
+        .or_else(|| session.pages.first())
+}
+
+/// Host and path of a URL, without scheme, query, fragment or trailing
+/// slash; query strings carry per-session noise. Empty for native app
+/// screens, whose `package/activity` names no session can check.
+fn page_key(url: &str) -> String {
+    let Some((_, rest)) = url.split_once("://") else {
+        return String::new();
+    };
+    let end = rest.find(['?', '#']).unwrap_or(rest.len());
+    rest[..end].trim_end_matches('/').to_string()
+}
+
+/// Handlers that click away interruptions when present and do nothing
+/// otherwise. Selectors are XPath so every driver can evaluate them: `text()`
+/// matches web pages, `@text`/`@content-desc` match Android views.
+pub fn default_error_handlers() -> Vec<ErrorHandler> {
+    [
+        (
+            "dismiss-cookie-consent",
+            &[
+                "//*[@id='onetrust-accept-btn-handler']",
+                "//button[contains(text(),'Accept')]",
+                "//button[contains(text(),'同意')]",
+                "//button[contains(text(),'接受')]",
+            ][..],
+        ),
+        (
+            "close-dialog",
+            &[
+                "//*[@aria-label='Close']",
+                "//*[@aria-label='关闭']",
+                "//*[@content-desc='Close']",
+                "//*[@content-desc='关闭']",
+                "//button[text()='×']",
+            ][..],
+        ),
+        (
+            "skip-prompt",
+            &[
+                "//*[text()='Not now']",
+                "//*[@text='Not now']",
+                "//*[text()='以后再说']",
+                "//*[@text='以后再说']",
+                "//*[text()='稍后']",
+                "//*[@text='稍后']",
+                "//*[@text='跳过']",
+            ][..],
+        ),
+    ]
+    .into_iter()
+    .map(|(name, xpaths)| {
+        let selector = Selector::AnyOf(
+            xpaths
+                .iter()
+                .map(|xpath| Selector::XPath(xpath.to_string()))
+                .collect(),
+        );
+        ErrorHandler {
+            name: name.into(),
+            on_error: vec![Step::Conditional {
+                condition: Condition::Exists(selector.clone()),
+                on_true: vec![Step::Click(selector)],
+                on_false: vec![],
+            }],
+        }
+    })
+    .collect()
+}
@@ -0,0 +1,404 @@
+//! Turns interaction events captured on a page into recorded steps.
+//!
+//! [`RECORDER_JS`] is injected into the page being recorded and reports
+//! every click, keystroke, change and navigation as a JSON
+//! [`RecordedEvent`] through the [`RECORDER_BINDING`] binding. A
+//! [`Recorder`] folds those events into a [`RecordedSession`]: each action
+//! becomes a `Step` whose selector lists every usable candidate, keystrokes
+//! on one field collapse into a single `Input`, and the first action after
+//! a navigation is preceded by a `WaitFor` on its element. Username and
+//! password fields (see [`crate::fields`]) are recorded as vault
+//! references, so typed credentials never end up in the session.
+
+use crate::fields::{classify, is_button, is_logout, is_text_field, FieldRole, LoginField};
+use crate::RecordedSession;
+use automation_engine::{Selector, Step, ValueRef};
+use serde::{Deserialize, Serialize};
+
+/// Script injected into recorded pages; it can be loaded under node on its
+/// own to test the events it produces.
+pub const RECORDER_JS: &str = include_str!("../js/recorder.js");
+
+/// Name of the page binding `RECORDER_JS` reports events through.
+pub const RECORDER_BINDING: &str = "__aiHelperRecord";
+
+/// One interaction as reported by `RECORDER_JS`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum RecordedEvent {
+    Click {
+        element: ElementDescriptor,
+    },
+    /// Sent on every keystroke with the field's whole value so far.
+    Input {
+        element: ElementDescriptor,
+        value: String,
+    },
+    /// A committed value, e.g. a `<select>` choice or a field losing focus.
+    Change {
+        element: ElementDescriptor,
+        value: String,
+    },
+    /// A document load or single-page route change, reported again with
+    /// more controls once the page has rendered.
+    Navigation {
+        url: String,
+        /// Buttons and links visible on the page.
+        #[serde(default)]
+        controls: Vec<ElementDescriptor>,
+    },
+}
+
+/// A page the recording passed through.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordedPage {
+    pub url: String,
+    /// Index of the first step recorded on this page; equals the number of
+    /// steps when nothing was done on it.
+    pub step: usize,
+    pub controls: Vec<ElementDescriptor>,
+}
+
+/// What the page knew about the element an event targeted.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ElementDescriptor {
+    pub tag: String,
+    pub id: Option<String>,
+    pub name: Option<String>,
+    pub classes: Vec<String>,
+    pub aria_label: Option<String>,
+    /// Visible text, left out for long blocks of text.
+    pub text: Option<String>,
+    /// Absolute path from the document root.
+    pub xpath: Option<String>,
+    /// `type` attribute of inputs and buttons.
+    pub input_type: Option<String>,
+    pub autocomplete: Option<String>,
+    pub placeholder: Option<String>,
+    /// Text of the `<label>` attached to a form field.
+    pub label: Option<String>,
+    /// ARIA `role` attribute.
+    pub role: Option<String>,
+    /// Selectors worked out by the recorder itself, for elements the
+    /// attributes above do not address (native app views). Used instead of
+    /// the derived ones when present.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub candidates: Vec<Selector>,
+}
+
+impl ElementDescriptor {
+    /// Candidate selectors, most stable first: id, name, accessibility
+    /// label, visible text, classes, then the absolute XPath; or the
+    /// recorder's own `candidates`.
+    pub fn selectors(&self) -> Vec<Selector> {
+        if !self.candidates.is_empty() {
+            return self.candidates.clone();
+        }
+        let tag = if self.tag.is_empty() { "*" } else { &self.tag };
+        let mut candidates = Vec::new();
+        if let Some(id) = present(&self.id) {
+            candidates.push(Selector::Css(if is_identifier(id) {
+                format!("#{id}")
+            } else {
+                format!("[id=\"{}\"]", css_escape(id))
+            }));
+        }
+        if let Some(name) = present(&self.name) {
+            candidates.push(Selector::Css(format!(
+                "{tag}[name=\"{}\"]",
+                css_escape(name)
+            )));
+        }
+        if let Some(label) = present(&self.aria_label) {
+            candidates.push(Selector::AccessibilityId(label.to_string()));
+        }
+        if let Some(text) = present(&self.text) {
+            if !matches!(tag, "input" | "select" | "textarea") {
+                if let Some(literal) = xpath_literal(text) {
+                    candidates.push(Selector::XPath(format!("//{tag}[text()={literal}]")));
+                }
+            }
+        }
+        let classes: Vec<&str> = self
+            .classes
+            .iter()
+            .map(String::as_str)
+            .filter(|class| is_identifier(class))
+            .collect();
+        if !classes.is_empty() {
+            candidates.push(Selector::Css(format!("{tag}.{}", classes.join("."))));
+        }
+        if let Some(xpath) = present(&self.xpath) {
+            candidates.push(Selector::XPath(xpath.to_string()));
+        }
+        candidates.dedup();
+        candidates
+    }
+
+    /// The candidates as one selector: `AnyOf` when there are several,
+    /// `None` when the element offered nothing to select it by.
+    pub fn selector(&self) -> Option<Selector> {
+        let mut candidates = self.selectors();
+        match candidates.len() {
+            0 => None,
+            1 => candidates.pop(),
+            _ => Some(Selector::AnyOf(candidates)),
+        }
+    }
+
+    /// Whether both describe the same element. Classes and text change
+    /// while a user types (validation styles, counters), so the path
+    /// decides when both have one.
+    pub fn same_element(&self, other: &Self) -> bool {
+        match (&self.xpath, &other.xpath) {
+            (Some(a), Some(b)) => a == b,
+            _ => self.tag == other.tag && self.id == other.id && self.name == other.name,
+        }
+    }
+}
+
+/// Folds [`RecordedEvent`]s into a [`RecordedSession`].
+#[derive(Debug, Clone)]
+pub struct Recorder {
+    session: RecordedSession,
+    /// Credential id the vault references point at.
+    account: String,
+    /// The page changed since the last action, so the next one has to wait
+    /// for its element. Recording starts on a freshly loaded page.
+    navigated: bool,
+    /// Field of the `Input` step at the end of the session, and its role,
+    /// while its keystrokes keep arriving.
+    typing: Option<(ElementDescriptor, Option<FieldRole>)>,
+    /// Last `Input` step into a text field nothing identified; it becomes
+    /// the username when a password field follows.
+    unclassified: Option<usize>,
+}
+
+impl Recorder {
+    /// Records for the credential named after the session's target; see
+    /// [`Recorder::with_account`].
+    pub fn new(session: RecordedSession) -> Self {
+        Self {
+            account: session.target.name.clone(),
+            session,
+            navigated: true,
+            typing: None,
+            unclassified: None,
+        }
+    }
+
+    /// Point username and password fields at `account`'s vault entries
+    /// (`<account>.username`, `<account>.secret`).
+    pub fn with_account(mut self, account: impl Into<String>) -> Self {
+        self.account = account.into();
+        self
+    }
+
+    pub fn push(&mut self, event: RecordedEvent) {
+        match event {
+            RecordedEvent::Navigation { url, controls } => self.navigate(url, controls),
+            RecordedEvent::Click { element } => {
+                let selector = selector_of(&element);
+                self.click(element, selector);
+            }
+            RecordedEvent::Input { element, value } | RecordedEvent::Change { element, value } => {
+                let selector = selector_of(&element);
+                self.input(element, selector, value);
+            }
+        }
+    }
+
+    /// A new page (or screen) was shown.
+    pub(crate) fn navigate(&mut self, url: String, controls: Vec<ElementDescriptor>) {
+        tracing::debug!(%url, "recorded navigation");
+        self.navigated = true;
+        self.typing = None;
+        let step = self.session.steps.len();
+        match self.session.pages.last_mut() {
+            Some(page) if page.url == url && page.step == step => page.controls = controls,
+            _ => self.session.pages.push(RecordedPage {
+                url,
+                step,
+                controls,
+            }),
+        }
+    }
+
+    /// `element` was clicked; skipped when there is no selector for it.
+    pub(crate) fn click(&mut self, element: ElementDescriptor, selector: Option<Selector>) {
+        self.typing = None;
+        let Some(selector) = selector else {
+            return;
+        };
+        let step = self.act(selector, Step::Click);
+        // Without a telling label, the first button clicked after
+        // the password was typed is what submits the form.
+        let role = classify(&element).or_else(|| {
+            (is_button(&element)
+                && !is_logout(&element)
+                && self.has_role(FieldRole::Password)
+                && !self.has_role(FieldRole::Submit))
+            .then_some(FieldRole::Submit)
+        });
+        if role == Some(FieldRole::Submit) {
+            self.mark(step, FieldRole::Submit);
+        }
+    }
+
+    /// `element` now holds `value`; keystrokes on the field being typed
+    /// into update its `Input` step.
+    pub(crate) fn input(
+        &mut self,
+        element: ElementDescriptor,
+        selector: Option<Selector>,
+        value: String,
+    ) {
+        if let Some((_, role)) = self
+            .typing
+            .as_ref()
+            .filter(|(typing, _)| typing.same_element(&element))
+        {
+            // `typing` is only set right after its `Input` step.
+            let value = self.value(*role, value);
+            if let Some(Step::Input { value: last, .. }) = self.session.steps.last_mut() {
+                *last = value;
+            }
+            return;
+        }
+        let Some(selector) = selector else {
+            return;
+        };
+        let role = classify(&element).filter(|role| *role != FieldRole::Submit);
+        if role == Some(FieldRole::Password) && !self.has_role(FieldRole::Username) {
+            if let Some(step) = self.unclassified.take() {
+                self.assign(step, FieldRole::Username);
+            }
+        }
+        let value = self.value(role, value);
+        let step = self.act(selector, |selector| Step::Input { selector, value });
+        match role {
+            Some(role) => self.mark(step, role),
+            None if is_text_field(&element) => self.unclassified = Some(step),
+            None => {}
+        }
+        self.typing = Some((element, role));
+    }
+
+    /// Steps recorded so far.
+    pub fn session(&self) -> &RecordedSession {
+        &self.session
+    }
+
+    pub fn finish(self) -> RecordedSession {
+        self.session
+    }
+
+    /// Append the step (after a `WaitFor` when the page changed) and
+    /// return its index.
+    fn act(&mut self, selector: Selector, step: impl FnOnce(Selector) -> Step) -> usize {
+        if !self.session.selectors.contains(&selector) {
+            self.session.selectors.push(selector.clone());
+        }
+        if std::mem::take(&mut self.navigated) {
+            if let Some(target) = wait_target(&selector) {
+                self.session.steps.push(Step::WaitFor(target));
+            }
+        }
+        self.session.steps.push(step(selector));
+        self.session.steps.len() - 1
+    }
+
+    fn value(&self, role: Option<FieldRole>, typed: String) -> ValueRef {
+        match role.and_then(|role| role.vault_key(&self.account)) {
+            Some(key) => ValueRef::FromVault(key),
+            None => ValueRef::Literal(typed),
+        }
+    }
+
+    fn has_role(&self, role: FieldRole) -> bool {
+        self.session.fields.iter().any(|field| field.role == role)
+    }
+
+    fn mark(&mut self, step: usize, role: FieldRole) {
+        self.session.fields.push(LoginField { step, role });
+    }
+
+    /// Classify an `Input` step recorded earlier and swap its literal for
+    /// the vault reference.
+    fn assign(&mut self, step: usize, role: FieldRole) {
+        let value = self.value(Some(role), String::new());
+        if let Some(Step::Input { value: typed, .. }) = self.session.steps.get_mut(step) {
+            *typed = value;
+        }
+        self.mark(step, role);
+    }
+}
+
+impl Extend<RecordedEvent> for Recorder {
+    fn extend<I: IntoIterator<Item = RecordedEvent>>(&mut self, events: I) {
+        for event in events {
+            self.push(event);
+        }
+    }
+}
+
+/// What to wait for, or check the presence of, for `selector`: coordinates
+/// always "exist", so they are dropped from the candidates; `None` when
+/// nothing else is left.
+pub(crate) fn wait_target(selector: &Selector) -> Option<Selector> {
+    let mut candidates: Vec<Selector> = selector
+        .candidates()
+        .into_iter()
+        .filter(|candidate| !matches!(candidate, Selector::Coordinates { .. }))
+        .cloned()
+        .collect();
+    match candidates.len() {
+        0 => None,
+        1 => candidates.pop(),
+        _ => Some(Selector::AnyOf(candidates)),
+    }
+}
+
+fn selector_of(element: &ElementDescriptor) -> Option<Selector> {
+    let selector = element.selector();
+    if selector.is_none() {
+        tracing::warn!(
+            ?element,
+            "recorded element has no usable selector, skipping"
+        );
+    }
+    selector
+}
+
+/// Trimmed value, `None` when missing or blank.
+pub(crate) fn present(value: &Option<String>) -> Option<&str> {
+    value
+        .as_deref()
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+}
+
+/// Usable as-is after `#` or `.` in CSS.
+fn is_identifier(value: &str) -> bool {
+    let mut chars = value.chars();
+    chars
+        .next()
+        .is_some_and(|first| first.is_ascii_alphabetic() || first == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+fn css_escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// XPath 1.0 has no escapes; pick the quote the text does not contain.
+pub(crate) fn xpath_literal(text: &str) -> Option<String> {
+    if !text.contains('\'') {
+        Some(format!("'{text}'"))
+    } else if !text.contains('"') {
+        Some(format!("\"{text}\""))
+    } else {
+        None
+    }
+}
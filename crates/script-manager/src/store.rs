@@ -0,0 +1,577 @@
+//! Storage backends behind `ScriptManager`.
+//!
+//! `InMemoryStore` keeps everything in process; `SqliteStore` persists every
+//! version in a single SQLite file so scripts survive restarts. Versions of
+//! one id are always returned in the order they were saved.
+//!
+//! Each id also keeps a history: every save, plus every time a stored
+//! document written in an older file format was upgraded on load. A version
+//! can carry a test suite (see [`crate::harness`]).
+
+use crate::format::{self, LoadedScript};
+use crate::harness::TestSuite;
+use crate::migrate::MigrationRecord;
+use automation_engine::LoginScript;
+use rusqlite::{params, Connection, TransactionBehavior};
+use semver::Version;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+use std::sync::Mutex;
+
+pub trait ScriptStore: Send + Sync + fmt::Debug {
+    /// Append `script` as the newest version of its id; `changelog` becomes
+    /// the note of its history entry. Fails, without saving anything, when
+    /// the id already has a version of the same semver precedence.
+    fn save(&self, script: &LoginScript, changelog: Option<&str>) -> anyhow::Result<()>;
+
+    /// All versions of `id`, oldest first; empty when the id is unknown.
+    fn versions(&self, id: &str) -> anyhow::Result<Vec<LoginScript>>;
+
+    fn get(&self, id: &str, version: &str) -> anyhow::Result<Option<LoginScript>>;
+
+    /// Every stored script id, sorted.
+    fn ids(&self) -> anyhow::Result<Vec<String>>;
+
+    /// Ids whose most recently saved version targets the app named `target`.
+    fn ids_for_target(&self, target: &str) -> anyhow::Result<Vec<String>>;
+
+    /// History of `id`, oldest first.
+    fn history(&self, id: &str) -> anyhow::Result<Vec<HistoryEntry>>;
+
+    /// Add an entry that `save` does not write itself, e.g. the migrations
+    /// an imported file went through.
+    fn append_history(&self, entry: &HistoryEntry) -> anyhow::Result<()>;
+
+    /// Store the test suite of one version, replacing the previous one.
+    fn save_suite(&self, id: &str, version: &str, suite: &TestSuite) -> anyhow::Result<()>;
+
+    /// Test suite of one version; `None` when it has none.
+    fn suite(&self, id: &str, version: &str) -> anyhow::Result<Option<TestSuite>>;
+}
+
+/// Something that happened to one version of a script.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub id: String,
+    pub version: String,
+    pub kind: HistoryKind,
+    pub note: Option<String>,
+    /// Seconds since the Unix epoch.
+    pub at: i64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HistoryKind {
+    /// A new version was saved; the note is its changelog.
+    Saved,
+    /// The document was upgraded to a newer file format.
+    Migrated,
+}
+
+impl HistoryKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Saved => "saved",
+            Self::Migrated => "migrated",
+        }
+    }
+
+    fn parse(kind: &str) -> Option<Self> {
+        match kind {
+            "saved" => Some(Self::Saved),
+            "migrated" => Some(Self::Migrated),
+            _ => None,
+        }
+    }
+}
+
+impl HistoryEntry {
+    pub fn new(script: &LoginScript, kind: HistoryKind, note: Option<String>) -> Self {
+        Self {
+            id: script.meta.id.clone(),
+            version: script.meta.version.clone(),
+            kind,
+            note,
+            at: epoch_secs(),
+        }
+    }
+
+    /// Entry for one migration applied to `script`.
+    pub fn migrated(script: &LoginScript, migration: &MigrationRecord) -> Self {
+        Self::new(
+            script,
+            HistoryKind::Migrated,
+            Some(format!(
+                "format {} -> {}: {}",
+                migration.from, migration.to, migration.description
+            )),
+        )
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct InMemoryStore {
+    scripts: Mutex<HashMap<String, Vec<LoginScript>>>,
+    history: Mutex<Vec<HistoryEntry>>,
+    suites: Mutex<HashMap<(String, String), TestSuite>>,
+}
+
+impl InMemoryStore {
+    fn lock(&self) -> anyhow::Result<std::sync::MutexGuard<'_, HashMap<String, Vec<LoginScript>>>> {
+        self.scripts
+            .lock()
+            .map_err(|_| anyhow::anyhow!("script store poisoned"))
+    }
+
+    fn lock_history(&self) -> anyhow::Result<std::sync::MutexGuard<'_, Vec<HistoryEntry>>> {
+        self.history
+            .lock()
+            .map_err(|_| anyhow::anyhow!("script store poisoned"))
+    }
+
+    fn lock_suites(
+        &self,
+    ) -> anyhow::Result<std::sync::MutexGuard<'_, HashMap<(String, String), TestSuite>>> {
+        self.suites
+            .lock()
+            .map_err(|_| anyhow::anyhow!("script store poisoned"))
+    }
+}
+
+impl ScriptStore for InMemoryStore {
+    fn save(&self, script: &LoginScript, changelog: Option<&str>) -> anyhow::Result<()> {
+        let mut scripts = self.lock()?;
+        let versions = scripts.entry(script.meta.id.clone()).or_default();
+        check_new_version(script, versions.iter().map(|saved| &saved.meta.version))?;
+        versions.push(script.clone());
+        drop(scripts);
+        self.append_history(&HistoryEntry::new(
+            script,
+            HistoryKind::Saved,
+            changelog.map(str::to_string),
+        ))
+    }
+
+    fn versions(&self, id: &str) -> anyhow::Result<Vec<LoginScript>> {
+        Ok(self.lock()?.get(id).cloned().unwrap_or_default())
+    }
+
+    fn get(&self, id: &str, version: &str) -> anyhow::Result<Option<LoginScript>> {
+        Ok(self.lock()?.get(id).and_then(|versions| {
+            versions
+                .iter()
+                .find(|script| script.meta.version == version)
+                .cloned()
+        }))
+    }
+
+    fn ids(&self) -> anyhow::Result<Vec<String>> {
+        let mut ids: Vec<String> = self.lock()?.keys().cloned().collect();
+        ids.sort();
+        Ok(ids)
+    }
+
+    fn ids_for_target(&self, target: &str) -> anyhow::Result<Vec<String>> {
+        let mut ids: Vec<String> = self
+            .lock()?
+            .iter()
+            .filter(|(_, versions)| {
+                versions
+                    .last()
+                    .is_some_and(|script| script.target.name == target)
+            })
+            .map(|(id, _)| id.clone())
+            .collect();
+        ids.sort();
+        Ok(ids)
+    }
+
+    fn history(&self, id: &str) -> anyhow::Result<Vec<HistoryEntry>> {
+        Ok(self
+            .lock_history()?
+            .iter()
+            .filter(|entry| entry.id == id)
+            .cloned()
+            .collect())
+    }
+
+    fn append_history(&self, entry: &HistoryEntry) -> anyhow::Result<()> {
+        self.lock_history()?.push(entry.clone());
+        Ok(())
+    }
+
+    fn save_suite(&self, id: &str, version: &str, suite: &TestSuite) -> anyhow::Result<()> {
+        self.lock_suites()?
+            .insert((id.to_string(), version.to_string()), suite.clone());
+        Ok(())
+    }
+
+    fn suite(&self, id: &str, version: &str) -> anyhow::Result<Option<TestSuite>> {
+        Ok(self
+            .lock_suites()?
+            .get(&(id.to_string(), version.to_string()))
+            .cloned())
+    }
+}
+
+/// Schema steps, applied in order; `PRAGMA user_version` records how many
+/// have run. Only ever append to this list.
+const MIGRATIONS: &[&str] = &[
+    // 1: scripts and their versions.
+    "CREATE TABLE scripts (
+        id          TEXT PRIMARY KEY,
+        target_kind TEXT NOT NULL,
+        target_name TEXT NOT NULL,
+        updated_at  INTEGER NOT NULL
+    );
+    CREATE TABLE script_versions (
+        seq       INTEGER PRIMARY KEY AUTOINCREMENT,
+        id        TEXT NOT NULL REFERENCES scripts(id),
+        version   TEXT NOT NULL,
+        document  TEXT NOT NULL,
+        saved_at  INTEGER NOT NULL
+    );
+    CREATE INDEX script_versions_id_version ON script_versions (id, version);
+    CREATE INDEX scripts_target ON scripts (target_name, target_kind);",
+    // 2: per-id history, seeded with the saves made so far.
+    "CREATE TABLE script_history (
+        seq      INTEGER PRIMARY KEY AUTOINCREMENT,
+        id       TEXT NOT NULL REFERENCES scripts(id),
+        version  TEXT NOT NULL,
+        kind     TEXT NOT NULL,
+        note     TEXT,
+        at       INTEGER NOT NULL
+    );
+    CREATE INDEX script_history_id ON script_history (id);
+    INSERT INTO script_history (id, version, kind, note, at)
+        SELECT id, version, 'saved', NULL, saved_at FROM script_versions ORDER BY seq;",
+    // 3: test suites, one per version.
+    "CREATE TABLE script_tests (
+        id        TEXT NOT NULL REFERENCES scripts(id),
+        version   TEXT NOT NULL,
+        document  TEXT NOT NULL,
+        saved_at  INTEGER NOT NULL,
+        PRIMARY KEY (id, version)
+    );",
+    // 4: one row per (id, version). Rows that slipped past the old
+    // check-then-insert keep their first save.
+    "DELETE FROM script_versions WHERE seq NOT IN
+        (SELECT MIN(seq) FROM script_versions GROUP BY id, version);
+    DROP INDEX script_versions_id_version;
+    CREATE UNIQUE INDEX script_versions_id_version ON script_versions (id, version);",
+];
+
+/// Scripts persisted in SQLite. Each version is stored as its JSON document
+/// (see `format::Document`) next to the columns queries filter on.
+/// Documents in an older file format are upgraded in place the first time
+/// they are read.
+pub struct SqliteStore {
+    connection: Mutex<Connection>,
+}
+
+impl fmt::Debug for SqliteStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SqliteStore").finish_non_exhaustive()
+    }
+}
+
+impl SqliteStore {
+    /// Open (or create) the database at `path` and bring its schema up to
+    /// date.
+    pub fn open(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let connection = Connection::open(path)
+            .map_err(|err| anyhow::anyhow!("failed to open {}: {err}", path.display()))?;
+        Self::with_connection(connection)
+    }
+
+    /// Throw-away database, mostly for tests and previews.
+    pub fn open_in_memory() -> anyhow::Result<Self> {
+        Self::with_connection(Connection::open_in_memory()?)
+    }
+
+    fn with_connection(mut connection: Connection) -> anyhow::Result<Self> {
+        connection.pragma_update(None, "foreign_keys", true)?;
+        connection.pragma_update(None, "journal_mode", "WAL")?;
+        migrate(&mut connection)?;
+        Ok(Self {
+            connection: Mutex::new(connection),
+        })
+    }
+
+    /// Number of migrations applied to the open database.
+    pub fn schema_version(&self) -> anyhow::Result<usize> {
+        Ok(user_version(&*self.lock()?)?)
+    }
+
+    fn lock(&self) -> anyhow::Result<std::sync::MutexGuard<'_, Connection>> {
+        self.connection
+            .lock()
+            .map_err(|_| anyhow::anyhow!("script store poisoned"))
+    }
+
+    /// Run a query selecting `seq, document` from `script_versions`.
+    fn query_scripts(
+        &self,
+        sql: &str,
+        params: impl rusqlite::Params,
+    ) -> anyhow::Result<Vec<LoginScript>> {
+        let mut connection = self.lock()?;
+        let rows = connection
+            .prepare_cached(sql)?
+            .query_map(params, |row| {
+                Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut scripts = Vec::with_capacity(rows.len());
+        for (seq, document) in rows {
+            let loaded = decode(&document)?;
+            if !loaded.migrations.is_empty() {
+                upgrade(&mut connection, seq, &loaded)?;
+            }
+            scripts.push(loaded.script);
+        }
+        Ok(scripts)
+    }
+}
+
+/// Store the upgraded document for `seq` and note each migration in the
+/// history.
+fn upgrade(connection: &mut Connection, seq: i64, loaded: &LoadedScript) -> anyhow::Result<()> {
+    let script = &loaded.script;
+    tracing::info!(
+        id = %script.meta.id,
+        version = %script.meta.version,
+        migrations = loaded.migrations.len(),
+        "upgraded stored script to the current format"
+    );
+    let tx = connection.transaction()?;
+    tx.execute(
+        "UPDATE script_versions SET document = ?1 WHERE seq = ?2",
+        params![encode(script)?, seq],
+    )?;
+    for migration in &loaded.migrations {
+        insert_history(&tx, &HistoryEntry::migrated(script, migration))?;
+    }
+    tx.commit()?;
+    Ok(())
+}
+
+fn insert_history(connection: &Connection, entry: &HistoryEntry) -> anyhow::Result<()> {
+    connection.execute(
+        "INSERT INTO script_history (id, version, kind, note, at)
+         VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![
+            entry.id,
+            entry.version,
+            entry.kind.as_str(),
+            entry.note,
+            entry.at
+        ],
+    )?;
+    Ok(())
+}
+
+impl ScriptStore for SqliteStore {
+    fn save(&self, script: &LoginScript, changelog: Option<&str>) -> anyhow::Result<()> {
+        let document = encode(script)?;
+        let now = epoch_secs();
+        let mut connection = self.lock()?;
+        // Immediate, so no other connection saves between the check and the
+        // insert.
+        let tx = connection.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        let saved = tx
+            .prepare_cached("SELECT version FROM script_versions WHERE id = ?1")?
+            .query_map([&script.meta.id], |row| row.get::<_, String>(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+        check_new_version(script, saved.iter())?;
+        tx.execute(
+            "INSERT INTO scripts (id, target_kind, target_name, updated_at)
+             VALUES (?1, ?2, ?3, ?4)
+             ON CONFLICT (id) DO UPDATE SET
+                target_kind = excluded.target_kind,
+                target_name = excluded.target_name,
+                updated_at = excluded.updated_at",
+            params![
+                script.meta.id,
+                format!("{:?}", script.target.kind),
+                script.target.name,
+                now
+            ],
+        )?;
+        tx.execute(
+            "INSERT INTO script_versions (id, version, document, saved_at)
+             VALUES (?1, ?2, ?3, ?4)",
+            params![script.meta.id, script.meta.version, document, now],
+        )?;
+        insert_history(
+            &tx,
+            &HistoryEntry::new(script, HistoryKind::Saved, changelog.map(str::to_string)),
+        )?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn versions(&self, id: &str) -> anyhow::Result<Vec<LoginScript>> {
+        self.query_scripts(
+            "SELECT seq, document FROM script_versions WHERE id = ?1 ORDER BY seq",
+            [id],
+        )
+    }
+
+    fn get(&self, id: &str, version: &str) -> anyhow::Result<Option<LoginScript>> {
+        Ok(self
+            .query_scripts(
+                "SELECT seq, document FROM script_versions
+                 WHERE id = ?1 AND version = ?2 ORDER BY seq LIMIT 1",
+                [id, version],
+            )?
+            .pop())
+    }
+
+    fn ids(&self) -> anyhow::Result<Vec<String>> {
+        let connection = self.lock()?;
+        let mut statement = connection.prepare_cached("SELECT id FROM scripts ORDER BY id")?;
+        let ids = statement
+            .query_map([], |row| row.get(0))?
+            .collect::<Result<Vec<String>, _>>()?;
+        Ok(ids)
+    }
+
+    fn ids_for_target(&self, target: &str) -> anyhow::Result<Vec<String>> {
+        let connection = self.lock()?;
+        let mut statement = connection
+            .prepare_cached("SELECT id FROM scripts WHERE target_name = ?1 ORDER BY id")?;
+        let ids = statement
+            .query_map([target], |row| row.get(0))?
+            .collect::<Result<Vec<String>, _>>()?;
+        Ok(ids)
+    }
+
+    fn history(&self, id: &str) -> anyhow::Result<Vec<HistoryEntry>> {
+        let connection = self.lock()?;
+        let mut statement = connection.prepare_cached(
+            "SELECT id, version, kind, note, at FROM script_history WHERE id = ?1 ORDER BY seq",
+        )?;
+        let entries = statement
+            .query_map([id], |row| {
+                let kind = row.get::<_, String>(2)?;
+                Ok(HistoryEntry {
+                    id: row.get(0)?,
+                    version: row.get(1)?,
+                    kind: HistoryKind::parse(&kind).ok_or_else(|| {
+                        rusqlite::Error::FromSqlConversionFailure(
+                            2,
+                            rusqlite::types::Type::Text,
+                            format!("unknown history kind {kind:?}").into(),
+                        )
+                    })?,
+                    note: row.get(3)?,
+                    at: row.get(4)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(entries)
+    }
+
+    fn append_history(&self, entry: &HistoryEntry) -> anyhow::Result<()> {
+        insert_history(&*self.lock()?, entry)
+    }
+
+    fn save_suite(&self, id: &str, version: &str, suite: &TestSuite) -> anyhow::Result<()> {
+        self.lock()?.execute(
+            "INSERT INTO script_tests (id, version, document, saved_at)
+             VALUES (?1, ?2, ?3, ?4)
+             ON CONFLICT (id, version) DO UPDATE SET
+                document = excluded.document,
+                saved_at = excluded.saved_at",
+            params![id, version, serde_json::to_string(suite)?, epoch_secs()],
+        )?;
+        Ok(())
+    }
+
+    fn suite(&self, id: &str, version: &str) -> anyhow::Result<Option<TestSuite>> {
+        let connection = self.lock()?;
+        let mut statement = connection
+            .prepare_cached("SELECT document FROM script_tests WHERE id = ?1 AND version = ?2")?;
+        let mut rows = statement.query_map([id, version], |row| row.get::<_, String>(0))?;
+        rows.next()
+            .transpose()?
+            .map(|document| {
+                serde_json::from_str(&document)
+                    .map_err(|err| anyhow::anyhow!("corrupt stored test suite: {err}"))
+            })
+            .transpose()
+    }
+}
+
+/// Error when one of `saved` is the version of `script`: equal semver
+/// precedence (so `1.0.0+build.2` repeats `1.0.0`), or the same string for
+/// versions that do not parse.
+fn check_new_version<'a>(
+    script: &LoginScript,
+    saved: impl IntoIterator<Item = &'a String>,
+) -> anyhow::Result<()> {
+    let version = &script.meta.version;
+    let parsed = Version::parse(version).ok();
+    let duplicate = saved
+        .into_iter()
+        .find(|existing| match (&parsed, Version::parse(existing)) {
+            (Some(version), Ok(existing)) => existing.cmp_precedence(version).is_eq(),
+            _ => *existing == version,
+        });
+    match duplicate {
+        Some(existing) => Err(anyhow::anyhow!(
+            "script {} already has version {existing}",
+            script.meta.id
+        )),
+        None => Ok(()),
+    }
+}
+
+fn migrate(connection: &mut Connection) -> anyhow::Result<()> {
+    let applied = user_version(connection)?;
+    if applied > MIGRATIONS.len() {
+        return Err(anyhow::anyhow!(
+            "script database has schema version {applied}, newer than this build supports ({})",
+            MIGRATIONS.len()
+        ));
+    }
+    for (index, migration) in MIGRATIONS.iter().enumerate().skip(applied) {
+        let tx = connection.transaction()?;
+        tx.execute_batch(migration).map_err(|err| {
+            anyhow::anyhow!("script database migration {} failed: {err}", index + 1)
+        })?;
+        tx.pragma_update(None, "user_version", (index + 1) as i64)?;
+        tx.commit()?;
+    }
+    Ok(())
+}
+
+fn user_version(connection: &Connection) -> rusqlite::Result<usize> {
+    connection.query_row("PRAGMA user_version", [], |row| {
+        row.get::<_, i64>(0).map(|version| version as usize)
+    })
+}
+
+fn encode(script: &LoginScript) -> anyhow::Result<String> {
+    Ok(serde_json::to_string(&format::Document::new(script))?)
+}
+
+fn decode(document: &str) -> anyhow::Result<LoadedScript> {
+    serde_json::from_str(document)
+        .map_err(|err| anyhow::anyhow!("corrupt stored script: {err}"))
+        .and_then(|document| {
+            format::read_document(document)
+                .map_err(|err| anyhow::anyhow!("corrupt stored script: {err}"))
+        })
+}
+
+fn epoch_secs() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs() as i64)
+}
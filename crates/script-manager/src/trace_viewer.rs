@@ -0,0 +1,152 @@
+//! Read-only access to trace bundles written by the automation engine.
+
+use automation_engine::trace::{TraceStep, TRACE_FORMAT_VERSION, TRACE_MANIFEST};
+use automation_engine::TraceManifest;
+use std::path::{Component, Path, PathBuf};
+
+/// A trace bundle directory loaded from disk. Artifacts are read lazily, one
+/// step at a time.
+#[derive(Debug, Clone)]
+pub struct TraceBundle {
+    root: PathBuf,
+    manifest: TraceManifest,
+}
+
+/// One step of a bundle together with its artifacts.
+#[derive(Debug, Clone)]
+pub struct TraceFrame<'a> {
+    pub step: &'a TraceStep,
+    pub screenshot: Option<Vec<u8>>,
+    pub snapshot: Option<String>,
+}
+
+impl TraceBundle {
+    pub fn open(root: impl Into<PathBuf>) -> anyhow::Result<Self> {
+        let root = root.into();
+        let raw = std::fs::read(root.join(TRACE_MANIFEST)).map_err(|err| {
+            anyhow::anyhow!("cannot read trace manifest in {}: {err}", root.display())
+        })?;
+        let manifest: TraceManifest = serde_json::from_slice(&raw)?;
+        if manifest.format_version > TRACE_FORMAT_VERSION {
+            return Err(anyhow::anyhow!(
+                "trace format {} is newer than supported {TRACE_FORMAT_VERSION}",
+                manifest.format_version
+            ));
+        }
+        Ok(Self { root, manifest })
+    }
+
+    pub fn manifest(&self) -> &TraceManifest {
+        &self.manifest
+    }
+
+    pub fn len(&self) -> usize {
+        self.manifest.steps.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.manifest.steps.is_empty()
+    }
+
+    /// Index of the first step that reported an error.
+    pub fn first_failure(&self) -> Option<usize> {
+        self.manifest
+            .steps
+            .iter()
+            .position(|step| step.error.is_some())
+    }
+
+    /// Load the step at `index` with its screenshot and snapshot.
+    pub fn frame(&self, index: usize) -> anyhow::Result<TraceFrame<'_>> {
+        let step = self
+            .manifest
+            .steps
+            .get(index)
+            .ok_or_else(|| anyhow::anyhow!("trace has no step {index}"))?;
+        let screenshot = match &step.screenshot {
+            Some(relative) => Some(std::fs::read(self.artifact_path(relative)?)?),
+            None => None,
+        };
+        let snapshot = match &step.snapshot {
+            Some(relative) => Some(std::fs::read_to_string(self.artifact_path(relative)?)?),
+            None => None,
+        };
+        Ok(TraceFrame {
+            step,
+            screenshot,
+            snapshot,
+        })
+    }
+
+    pub fn cursor(&self) -> TraceCursor<'_> {
+        TraceCursor {
+            bundle: self,
+            position: 0,
+        }
+    }
+
+    /// Manifests are user-shareable files; keep artifact paths inside the
+    /// bundle.
+    fn artifact_path(&self, relative: &str) -> anyhow::Result<PathBuf> {
+        let path = Path::new(relative);
+        if !path
+            .components()
+            .all(|part| matches!(part, Component::Normal(_)))
+        {
+            return Err(anyhow::anyhow!(
+                "artifact path {relative} escapes the bundle"
+            ));
+        }
+        Ok(self.root.join(path))
+    }
+}
+
+/// Step-by-step walker over a bundle, e.g. for a replay view.
+#[derive(Debug, Clone)]
+pub struct TraceCursor<'a> {
+    bundle: &'a TraceBundle,
+    position: usize,
+}
+
+impl<'a> TraceCursor<'a> {
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    pub fn current(&self) -> Option<&'a TraceStep> {
+        self.bundle.manifest.steps.get(self.position)
+    }
+
+    /// Move to the next step; `false` at the end.
+    pub fn advance(&mut self) -> bool {
+        if self.position + 1 < self.bundle.len() {
+            self.position += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Move to the previous step; `false` at the start.
+    pub fn back(&mut self) -> bool {
+        if self.position > 0 {
+            self.position -= 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn seek(&mut self, index: usize) -> anyhow::Result<()> {
+        if index >= self.bundle.len() {
+            return Err(anyhow::anyhow!("trace has no step {index}"));
+        }
+        self.position = index;
+        Ok(())
+    }
+
+    /// Load artifacts of the current step.
+    pub fn load(&self) -> anyhow::Result<TraceFrame<'a>> {
+        self.bundle.frame(self.position)
+    }
+}
@@ -0,0 +1,147 @@
+//! Native recordings replayed from the accessibility event log in
+//! `fixtures/accessibility`.
+
+use automation_engine::{Selector, Step, TargetApp, TargetAppKind, ValueRef};
+use script_manager::{
+    AccessibilityEvent, AccessibilityEventSource, AccessibilityRecorder, FieldRole, JsonEventLog,
+    ScriptManager,
+};
+use std::path::{Path, PathBuf};
+
+fn fixture(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("fixtures/accessibility")
+        .join(name)
+}
+
+fn recorder() -> AccessibilityRecorder {
+    let recorder = ScriptManager::default()
+        .recorder(TargetApp {
+            kind: TargetAppKind::Android,
+            name: "mail".into(),
+            version: None,
+            endpoint: Some("com.example.mail".into()),
+        })
+        .with_account("alice");
+    AccessibilityRecorder::new(recorder)
+}
+
+fn xpath(expression: &str) -> Selector {
+    Selector::XPath(expression.into())
+}
+
+#[test]
+fn a_logged_login_becomes_steps() {
+    let mut log = JsonEventLog::open(&fixture("login.jsonl")).unwrap();
+    let mut recorder = recorder();
+    recorder.record(&mut log).unwrap();
+    assert_eq!(log.next_event().unwrap(), None);
+    let session = recorder.finish();
+
+    let username = [
+        Selector::AccessibilityId("username".into()),
+        xpath("/hierarchy/android.widget.FrameLayout/android.widget.EditText[1]"),
+    ];
+    // The password field came without id or path: its bounds are all
+    // there is, as an XPath attribute and as a tap position.
+    let password = Selector::AnyOf(vec![
+        xpath("//android.widget.EditText[@bounds='[40,560][1040,680]']"),
+        Selector::Coordinates { x: 540, y: 620 },
+    ]);
+    let banner = xpath("//android.view.View[@bounds='[0,200][1080,400]']");
+    assert_eq!(
+        session.steps,
+        [
+            Step::WaitFor(Selector::AnyOf(username.to_vec())),
+            // Both text changes of the field end up in one step.
+            Step::Input {
+                selector: Selector::AnyOf(
+                    username
+                        .iter()
+                        .cloned()
+                        .chain([Selector::Coordinates { x: 540, y: 460 }])
+                        .collect()
+                ),
+                value: ValueRef::FromVault("alice.username".into()),
+            },
+            Step::Input {
+                selector: password,
+                value: ValueRef::FromVault("alice.secret".into()),
+            },
+            Step::Click(Selector::AnyOf(vec![
+                Selector::AccessibilityId("login".into()),
+                xpath("//android.widget.Button[@text='Log in']"),
+                xpath("/hierarchy/android.widget.FrameLayout/android.widget.Button"),
+                Selector::Coordinates { x: 540, y: 1060 },
+            ])),
+            // Coordinates always "exist", so the wait uses the bounds.
+            Step::WaitFor(banner.clone()),
+            Step::Click(Selector::AnyOf(vec![
+                banner,
+                Selector::Coordinates { x: 540, y: 300 },
+            ])),
+        ]
+    );
+    let roles: Vec<(usize, FieldRole)> = session
+        .fields
+        .iter()
+        .map(|field| (field.step, field.role))
+        .collect();
+    assert_eq!(
+        roles,
+        [
+            (1, FieldRole::Username),
+            (2, FieldRole::Password),
+            (3, FieldRole::Submit)
+        ]
+    );
+    let pages: Vec<(&str, usize, usize)> = session
+        .pages
+        .iter()
+        .map(|page| (page.url.as_str(), page.step, page.controls.len()))
+        .collect();
+    assert_eq!(
+        pages,
+        [
+            ("com.example.mail/.LoginActivity", 0, 1),
+            ("com.example.mail/.InboxActivity", 4, 2)
+        ]
+    );
+    // Typed passwords never reach the session.
+    assert!(!serde_json::to_string(&session).unwrap().contains('•'));
+}
+
+#[test]
+fn logs_can_be_one_json_array() {
+    let lines = std::fs::read_to_string(fixture("login.jsonl")).unwrap();
+    let events: Vec<AccessibilityEvent> = lines
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    let array = serde_json::to_string_pretty(&events).unwrap();
+
+    let mut from_lines = recorder();
+    from_lines
+        .record(&mut JsonEventLog::parse(&lines).unwrap())
+        .unwrap();
+    let mut from_array = recorder();
+    from_array
+        .record(&mut JsonEventLog::parse(&array).unwrap())
+        .unwrap();
+    assert_eq!(from_array.session().steps, from_lines.session().steps);
+    assert_eq!(from_array.session().steps.len(), 6);
+}
+
+#[test]
+fn bad_lines_are_reported_with_their_number() {
+    let text = "{\"type\":\"view_clicked\",\"node\":{}}\n\n{\"type\":\"scrolled\"}\n";
+    let err = JsonEventLog::parse(text).unwrap_err();
+    assert!(
+        err.to_string()
+            .starts_with("invalid accessibility event on line 3:"),
+        "{err}"
+    );
+    let missing = JsonEventLog::open(&fixture("missing.jsonl")).unwrap_err();
+    assert!(missing.to_string().contains("missing.jsonl"), "{missing}");
+}
@@ -0,0 +1,310 @@
+//! `diff_scripts`: added, removed, moved and modified steps, selector
+//! changes and changes inside step bodies.
+
+use automation_engine::{
+    Condition, ErrorHandler, LoginScript, PathSegment, ScriptMeta, Selector, Step, StepPath,
+    TargetApp, TargetAppKind, Validation, ValueRef,
+};
+use script_manager::{diff_scripts, HandlerChange, SelectorChange, StepChange, ValidationChange};
+
+fn script(version: &str, steps: Vec<Step>) -> LoginScript {
+    LoginScript {
+        meta: ScriptMeta {
+            id: "mail".into(),
+            version: version.into(),
+            author: None,
+            created_at: None,
+            updated_at: None,
+        },
+        target: TargetApp {
+            kind: TargetAppKind::Web,
+            name: "mail".into(),
+            version: None,
+            endpoint: None,
+        },
+        steps,
+        validations: Vec::new(),
+        error_handlers: Vec::new(),
+        token: None,
+        retry: None,
+    }
+}
+
+fn css(selector: &str) -> Selector {
+    Selector::Css(selector.into())
+}
+
+fn click(selector: &str) -> Step {
+    Step::Click(css(selector))
+}
+
+fn input(selector: &str, text: &str) -> Step {
+    Step::Input {
+        selector: css(selector),
+        value: ValueRef::Literal(text.into()),
+    }
+}
+
+fn path(segments: &[PathSegment]) -> StepPath {
+    segments
+        .iter()
+        .fold(StepPath::root(), |path, segment| path.child(*segment))
+}
+
+fn step(index: usize) -> StepPath {
+    path(&[PathSegment::Steps(index)])
+}
+
+#[test]
+fn identical_scripts_have_no_changes() {
+    let before = script("1.0.0", vec![click("#login")]);
+    let diff = diff_scripts(&before, &before);
+    assert!(diff.is_empty());
+    assert_eq!(diff.to_string(), "--- 1.0.0\n+++ 1.0.0\n");
+}
+
+#[test]
+fn steps_are_added_removed_and_moved() {
+    let before = script(
+        "1.0.0",
+        vec![click("#cookies"), Step::SleepMs(500), click("#login")],
+    );
+    let after = script(
+        "1.1.0",
+        vec![click("#login"), click("#cookies"), click("#remember")],
+    );
+
+    let diff = diff_scripts(&before, &after);
+    assert_eq!(
+        diff.steps,
+        [
+            StepChange::Moved {
+                from: step(0),
+                to: step(1),
+                step: click("#cookies"),
+            },
+            StepChange::Removed {
+                path: step(1),
+                step: Step::SleepMs(500),
+            },
+            StepChange::Added {
+                path: step(2),
+                step: click("#remember"),
+            },
+        ]
+    );
+    assert_eq!(
+        diff.to_string(),
+        "--- 1.0.0\n+++ 1.1.0\n\
+         > steps[0] -> steps[1]: {\"Click\":{\"Css\":\"#cookies\"}}\n\
+         - steps[1]: {\"SleepMs\":500}\n\
+         + steps[2]: {\"Click\":{\"Css\":\"#remember\"}}\n"
+    );
+}
+
+#[test]
+fn modified_steps_list_their_selector_changes() {
+    let before = script(
+        "1.0.0",
+        vec![
+            click("#start"),
+            input("#user", "alice"),
+            input("#pass", "hunter2"),
+            click("#login"),
+        ],
+    );
+    let after = script(
+        "1.1.0",
+        vec![
+            click("#start"),
+            input("#email", "alice"),
+            input("#pass", "hunter3"),
+            Step::Click(Selector::AnyOf(vec![css("#login"), css("#signin")])),
+        ],
+    );
+
+    let diff = diff_scripts(&before, &after);
+    let summary: Vec<_> = diff
+        .steps
+        .iter()
+        .map(|change| match change {
+            StepChange::Modified {
+                from,
+                to,
+                selectors,
+                ..
+            } => (from.clone(), to.clone(), selectors.clone()),
+            other => panic!("unexpected change {other:?}"),
+        })
+        .collect();
+    assert_eq!(
+        summary,
+        [
+            (
+                step(1),
+                step(1),
+                vec![SelectorChange {
+                    field: "selector".into(),
+                    before: css("#user"),
+                    after: css("#email"),
+                }]
+            ),
+            // Only the typed value changed.
+            (step(2), step(2), Vec::new()),
+            (
+                step(3),
+                step(3),
+                vec![SelectorChange {
+                    field: "selector".into(),
+                    before: css("#login"),
+                    after: Selector::AnyOf(vec![css("#login"), css("#signin")]),
+                }]
+            ),
+        ]
+    );
+    let text = diff.to_string();
+    assert!(text.contains(
+        "~ steps[1] -> steps[1]: selector {\"Css\":\"#user\"} -> {\"Css\":\"#email\"}\n"
+    ));
+    assert!(text.contains("~ steps[2] -> steps[2]: {\"Input\""));
+}
+
+#[test]
+fn changes_inside_bodies_are_reported_at_their_own_path() {
+    let banner = Condition::Exists(css("#banner"));
+    let before = script(
+        "1.0.0",
+        vec![
+            click("#start"),
+            Step::Conditional {
+                condition: banner.clone(),
+                on_true: vec![click("#dismiss")],
+                on_false: vec![click("#skip")],
+            },
+            Step::Loop {
+                times: 2,
+                body: vec![click("#next")],
+            },
+        ],
+    );
+    let after = script(
+        "1.1.0",
+        vec![
+            click("#start"),
+            Step::Conditional {
+                condition: banner,
+                on_true: vec![click("#dismiss"), click("#confirm")],
+                on_false: Vec::new(),
+            },
+            Step::Loop {
+                times: 3,
+                body: vec![click("#continue")],
+            },
+        ],
+    );
+
+    let diff = diff_scripts(&before, &after);
+    assert_eq!(
+        diff.steps,
+        [
+            // The unchanged condition itself is not reported.
+            StepChange::Added {
+                path: path(&[PathSegment::Steps(1), PathSegment::OnTrue(1)]),
+                step: click("#confirm"),
+            },
+            StepChange::Removed {
+                path: path(&[PathSegment::Steps(1), PathSegment::OnFalse(0)]),
+                step: click("#skip"),
+            },
+            StepChange::Modified {
+                from: step(2),
+                to: step(2),
+                before: before.steps[2].clone(),
+                after: after.steps[2].clone(),
+                selectors: Vec::new(),
+            },
+            StepChange::Modified {
+                from: path(&[PathSegment::Steps(2), PathSegment::Body(0)]),
+                to: path(&[PathSegment::Steps(2), PathSegment::Body(0)]),
+                before: click("#next"),
+                after: click("#continue"),
+                selectors: vec![SelectorChange {
+                    field: "selector".into(),
+                    before: css("#next"),
+                    after: css("#continue"),
+                }],
+            },
+        ]
+    );
+}
+
+#[test]
+fn validations_handlers_and_fields_are_compared_by_name() {
+    let mut before = script("1.0.0", Vec::new());
+    before.validations = vec![
+        Validation {
+            description: "inbox".into(),
+            condition: Condition::UrlContains("/inbox".into()),
+        },
+        Validation {
+            description: "avatar".into(),
+            condition: Condition::Exists(css("#avatar")),
+        },
+    ];
+    before.error_handlers = vec![
+        ErrorHandler {
+            name: "popup".into(),
+            on_error: vec![click("#close")],
+        },
+        ErrorHandler {
+            name: "captcha".into(),
+            on_error: Vec::new(),
+        },
+    ];
+    let mut after = before.clone();
+    after.meta.version = "1.1.0".into();
+    after.validations[0].condition = Condition::UrlContains("/mail".into());
+    after.validations.remove(1);
+    after.error_handlers[0].on_error = vec![click("#dismiss")];
+    after.error_handlers[1].name = "otp".into();
+    after.target.endpoint = Some("https://mail.example".into());
+
+    let diff = diff_scripts(&before, &after);
+    assert_eq!(
+        diff.validations,
+        [
+            ValidationChange::Modified {
+                index: 0,
+                before: before.validations[0].clone(),
+                after: after.validations[0].clone(),
+            },
+            ValidationChange::Removed {
+                index: 1,
+                validation: before.validations[1].clone(),
+            },
+        ]
+    );
+    assert_eq!(
+        diff.handlers,
+        [
+            HandlerChange::Removed {
+                index: 1,
+                handler: before.error_handlers[1].clone(),
+            },
+            HandlerChange::Added {
+                index: 1,
+                handler: after.error_handlers[1].clone(),
+            },
+        ]
+    );
+    let on_error = path(&[PathSegment::OnError {
+        handler: 0,
+        index: 0,
+    }]);
+    assert!(matches!(
+        diff.steps.as_slice(),
+        [StepChange::Modified { from, to, .. }] if *from == on_error && *to == on_error
+    ));
+    let fields: Vec<&str> = diff.fields.iter().map(|f| f.field.as_str()).collect();
+    assert_eq!(fields, ["target"]);
+}
@@ -0,0 +1,48 @@
+//! `fields::classify` on single elements.
+
+use script_manager::fields::classify;
+use script_manager::{ElementDescriptor, FieldRole};
+
+fn text_field(name: &str, placeholder: Option<&str>) -> ElementDescriptor {
+    ElementDescriptor {
+        tag: "input".into(),
+        name: Some(name.into()),
+        placeholder: placeholder.map(str::to_string),
+        ..ElementDescriptor::default()
+    }
+}
+
+#[test]
+fn one_time_code_fields_hold_no_credentials() {
+    for (name, placeholder) in [
+        ("smsCode", None),
+        ("otp_login", None),
+        ("user-verification", None),
+        ("account", Some("Search accounts")),
+        ("phone", Some("请输入验证码")),
+        ("login_captcha", Some("")),
+    ] {
+        assert_eq!(
+            classify(&text_field(name, placeholder)),
+            None,
+            "{name} {placeholder:?}"
+        );
+    }
+}
+
+#[test]
+fn words_containing_excluded_ones_still_count() {
+    for (name, placeholder, role) in [
+        ("user_postcode", None, FieldRole::Username),
+        ("barcodeLogin", None, FieldRole::Username),
+        ("hotpot-account", None, FieldRole::Username),
+        ("pwd", Some("Researcher password"), FieldRole::Password),
+        ("mobile", Some("请输入手机号"), FieldRole::Username),
+    ] {
+        assert_eq!(
+            classify(&text_field(name, placeholder)),
+            Some(role),
+            "{name} {placeholder:?}"
+        );
+    }
+}
@@ -0,0 +1,248 @@
+//! Script files: error positions for files in an older format, and the JSON
+//! Schema in `docs/schema` against what `write_script` produces.
+
+use automation_engine::{
+    AutomationContext, Backoff, CaptchaKind, Condition, ErrorHandler, ErrorKind, LoginScript,
+    RetryPolicy, Selector, Step, TargetApp, TargetAppKind, TokenRule, TokenSource, Validation,
+    ValueRef,
+};
+use script_manager::format::JSON_SCHEMA;
+use script_manager::{build_login_script, read_script, write_script, ScriptFormat};
+use serde_json::Value;
+
+/// Version 0 file (no `format_version`) whose second step has a number
+/// where the selector belongs, on line 9.
+const OLD_JSON: &str = r##"{
+  "meta": { "id": "mail", "version": "1.0.0", "author": null,
+            "created_at": null, "updated_at": null },
+  "target": { "kind": "Web", "name": "mail", "version": null,
+              "endpoint": "https://mail.example.com" },
+  "steps": [
+    { "WaitFor": { "Css": "#user" } },
+    { "Click":
+        42 }
+  ],
+  "validations": [],
+  "error_handlers": []
+}
+"##;
+
+const OLD_YAML: &str = r##"meta:
+  id: mail
+  version: 1.0.0
+  author: null
+  created_at: null
+  updated_at: null
+target:
+  kind: Web
+  name: mail
+  version: null
+  endpoint: https://mail.example.com
+steps:
+  - WaitFor:
+      Css: "#user"
+  - Click: 42
+validations: []
+error_handlers: []
+"##;
+
+#[test]
+fn errors_in_old_files_keep_their_position() {
+    let err = read_script(OLD_JSON, ScriptFormat::Json).unwrap_err();
+    assert_eq!(err.path, "steps[1].Click");
+    assert_eq!(err.line, Some(9), "{err}");
+    assert!(err.column.is_some());
+
+    let err = read_script(OLD_YAML, ScriptFormat::Yaml).unwrap_err();
+    assert_eq!(err.path, "steps[1].Click");
+    assert_eq!(err.line, Some(15), "{err}");
+    assert!(err.column.is_some());
+}
+
+#[test]
+fn version_errors_point_at_format_version() {
+    let valid = OLD_JSON.replace("42", r##"{ "Css": "#go" }"##);
+    assert!(read_script(&valid, ScriptFormat::Json).is_ok());
+
+    let newer = valid.replace("\"steps\"", "\"format_version\": 9,\n  \"steps\"");
+    let err = read_script(&newer, ScriptFormat::Json).unwrap_err();
+    assert_eq!(err.path, "format_version");
+    assert_eq!(err.line, Some(6), "{err}");
+
+    let missing = valid.replace("\"validations\": [],", "");
+    let err = read_script(&missing, ScriptFormat::Json).unwrap_err();
+    assert!(err.message.contains("validations"), "{err}");
+}
+
+/// Scripts that between them use every step, selector, condition, value,
+/// token source and retry setting.
+fn examples() -> Vec<LoginScript> {
+    let web = TargetApp {
+        kind: TargetAppKind::Web,
+        name: "mail".into(),
+        version: Some("2024.1".into()),
+        endpoint: Some("https://mail.example.com/login".into()),
+    };
+    let mut web_login = build_login_script(
+        ("mail-web", "1.2.0"),
+        web,
+        vec![
+            Step::WaitFor(Selector::AnyOf(vec![
+                Selector::Css("#user".into()),
+                Selector::XPath("//input[@name='user']".into()),
+            ])),
+            Step::Input {
+                selector: Selector::Css("#user".into()),
+                value: ValueRef::FromVault("mail.username".into()),
+            },
+            Step::Input {
+                selector: Selector::Css("#pass".into()),
+                value: ValueRef::Literal("hunter2".into()),
+            },
+            Step::Conditional {
+                condition: Condition::And(vec![
+                    Condition::Exists(Selector::Css("#captcha".into())),
+                    Condition::Not(Box::new(Condition::UrlContains("/inbox".into()))),
+                ]),
+                on_true: vec![Step::Captcha {
+                    kind: CaptchaKind::Image,
+                    challenge: Selector::Css("#captcha img".into()),
+                    response: Selector::Css("#captcha input".into()),
+                }],
+                on_false: Vec::new(),
+            },
+            Step::Retry {
+                policy: RetryPolicy {
+                    max_attempts: 2,
+                    retry_on: vec![ErrorKind::Timeout, ErrorKind::Network],
+                    backoff: Backoff::default(),
+                },
+                body: vec![Step::Click(Selector::Css("button[type=submit]".into()))],
+            },
+            Step::SleepMs(500),
+        ],
+    );
+    web_login.meta.author = Some("ops".into());
+    web_login.meta.created_at = Some("2024-05-01T08:00:00Z".into());
+    web_login.validations = vec![Validation {
+        description: "inbox or welcome".into(),
+        condition: Condition::Or(vec![
+            Condition::UrlContains("/inbox".into()),
+            Condition::TextEquals {
+                selector: Selector::Css("h1".into()),
+                expected: "Welcome".into(),
+            },
+        ]),
+    }];
+    web_login.error_handlers = vec![ErrorHandler {
+        name: "cookie banner".into(),
+        on_error: vec![Step::Click(Selector::Css("#accept".into()))],
+    }];
+    web_login.token = Some(TokenRule {
+        source: TokenSource::Cookie("SID".into()),
+        ttl_secs: Some(3_600),
+    });
+    web_login.retry = Some(RetryPolicy::default());
+
+    let android = TargetApp {
+        kind: TargetAppKind::Hybrid,
+        name: "mail".into(),
+        version: None,
+        endpoint: Some("com.example.mail".into()),
+    };
+    let mut app_login = build_login_script(
+        ("mail-app", "0.3.1-beta.1"),
+        android,
+        vec![
+            Step::Loop {
+                times: 3,
+                body: vec![Step::Swipe {
+                    from: Selector::Coordinates { x: 900, y: 1200 },
+                    to: Selector::Coordinates { x: 100, y: 1200 },
+                    duration_ms: 300,
+                }],
+            },
+            Step::Click(Selector::AccessibilityId("Get started".into())),
+            Step::Click(Selector::Image("buttons/login.png".into())),
+            Step::SwitchContext(AutomationContext::WebView(Some("login".into()))),
+            Step::Captcha {
+                kind: CaptchaKind::Slider,
+                challenge: Selector::Css(".slider".into()),
+                response: Selector::Css(".slider-handle".into()),
+            },
+            Step::SwitchContext(AutomationContext::WebView(None)),
+            Step::SwitchContext(AutomationContext::Native),
+            Step::Captcha {
+                kind: CaptchaKind::OtpPush,
+                challenge: Selector::AccessibilityId("otp".into()),
+                response: Selector::AccessibilityId("otp".into()),
+            },
+        ],
+    );
+    app_login.token = Some(TokenRule {
+        source: TokenSource::SharedPreference {
+            file: "auth".into(),
+            key: "session_token".into(),
+        },
+        ttl_secs: None,
+    });
+
+    let mut others: Vec<LoginScript> = [
+        TokenSource::LocalStorage("access_token".into()),
+        TokenSource::ResponseHeader("Authorization".into()),
+        TokenSource::ElementText(Selector::Css("#token".into())),
+    ]
+    .into_iter()
+    .enumerate()
+    .map(|(i, source)| {
+        let mut script = build_login_script(
+            ("token", &format!("1.0.{i}")),
+            TargetApp {
+                kind: [
+                    TargetAppKind::Web,
+                    TargetAppKind::Ios,
+                    TargetAppKind::Android,
+                ][i]
+                    .clone(),
+                name: "token".into(),
+                version: None,
+                endpoint: None,
+            },
+            Vec::new(),
+        );
+        script.token = Some(TokenRule {
+            source,
+            ttl_secs: None,
+        });
+        script
+    })
+    .collect();
+    others.extend([web_login, app_login]);
+    others
+}
+
+#[test]
+fn written_scripts_match_the_json_schema() {
+    let schema: Value = serde_json::from_str(JSON_SCHEMA).unwrap();
+    let validator = jsonschema::validator_for(&schema).unwrap();
+    for script in examples() {
+        for format in [ScriptFormat::Json, ScriptFormat::Yaml] {
+            let text = write_script(&script, format).unwrap();
+            let document: Value = match format {
+                ScriptFormat::Json => serde_json::from_str(&text).unwrap(),
+                ScriptFormat::Yaml => serde_saphyr::from_str(&text).unwrap(),
+            };
+            let errors: Vec<String> = validator
+                .iter_errors(&document)
+                .map(|err| format!("{}: {err}", err.instance_path))
+                .collect();
+            assert!(
+                errors.is_empty(),
+                "{} {format:?} does not match the schema:\n{}\n{text}",
+                script.meta.id,
+                errors.join("\n")
+            );
+            assert_eq!(read_script(&text, format).unwrap().script, script);
+        }
+    }
+}
@@ -0,0 +1,96 @@
+//! Runs the suite in `fixtures/harness/` against its script on fake pages.
+
+use automation_engine::{ExecutionEventKind, StepPath};
+use script_manager::{
+    parse_script, parse_suite, run_suite, write_suite, Assertion, FakeAction, ScriptFormat,
+    ScriptManager, SqliteStore, SuiteReport, TestSuite,
+};
+use std::path::Path;
+use std::sync::Arc;
+
+fn fixture(name: &str) -> String {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("fixtures/harness")
+        .join(name);
+    std::fs::read_to_string(&path).unwrap_or_else(|err| panic!("{}: {err}", path.display()))
+}
+
+fn suite() -> TestSuite {
+    parse_suite(&fixture("login.tests.yaml"), ScriptFormat::Yaml).unwrap()
+}
+
+async fn run() -> SuiteReport {
+    let script = parse_script(&fixture("login.yaml"), ScriptFormat::Yaml).unwrap();
+    run_suite(&script, &suite()).await
+}
+
+fn path(raw: &str) -> StepPath {
+    raw.parse().unwrap()
+}
+
+#[tokio::test]
+async fn passing_cases_check_steps_branches_and_inputs() {
+    let report = run().await;
+    let [happy, wrong_password, _] = report.cases.as_slice() else {
+        panic!("unexpected cases {report}");
+    };
+
+    assert!(happy.passed(), "{report}");
+    assert!(happy.outcome.success);
+    assert_eq!(happy.final_page, "inbox");
+    assert_eq!(
+        happy.actions.last(),
+        Some(&FakeAction::Navigated {
+            from: "login".into(),
+            to: "inbox".into(),
+        })
+    );
+    assert!(happy.events.iter().any(|event| matches!(
+        event,
+        ExecutionEventKind::BranchTaken { path: taken, condition: true } if *taken == path("steps[0]")
+    )));
+
+    assert!(wrong_password.passed(), "{report}");
+    assert!(!wrong_password.outcome.success);
+    assert_eq!(wrong_password.final_page, "login");
+}
+
+#[tokio::test]
+async fn unmet_assertions_are_reported() {
+    let report = run().await;
+    assert_eq!((report.passed(), report.failed()), (2, 1), "{report}");
+
+    let case = &report.cases[2];
+    let expected = &suite().cases[2].assertions;
+    assert_eq!(case.failures.len(), expected.len(), "{report}");
+    for (failure, assertion) in case.failures.iter().zip(expected) {
+        let mentions = match assertion {
+            Assertion::Outcome { .. } => "succeed".to_string(),
+            Assertion::Entered { value, .. } => value.clone(),
+            Assertion::Branch { path, .. } | Assertion::StepRan { path } => path.to_string(),
+            other => panic!("unexpected assertion {other:?}"),
+        };
+        assert!(failure.contains(&mentions), "{failure:?} for {assertion:?}");
+    }
+    assert!(report
+        .to_string()
+        .contains("FAIL expectations that do not hold"));
+}
+
+#[tokio::test]
+async fn suites_are_stored_with_their_version() {
+    let manager = ScriptManager::with_store(Arc::new(SqliteStore::open_in_memory().unwrap()));
+    manager
+        .save(parse_script(&fixture("login.yaml"), ScriptFormat::Yaml).unwrap())
+        .unwrap();
+    let suite = suite();
+    assert!(manager.save_tests("mail", "9.9.9", &suite).is_err());
+    manager.save_tests("mail", "1.0.0", &suite).unwrap();
+    assert_eq!(manager.tests("mail", "1.0.0").unwrap(), suite);
+
+    let written = write_suite(&suite, ScriptFormat::Yaml).unwrap();
+    assert_eq!(parse_suite(&written, ScriptFormat::Yaml).unwrap(), suite);
+
+    let report = manager.run_tests("mail", None).await.unwrap();
+    assert_eq!((report.passed(), report.failed()), (2, 1), "{report}");
+}
@@ -0,0 +1,300 @@
+//! `merge_scripts`: clean merges, conflicts, and edits merged inside the
+//! bodies of steps both sides changed.
+
+use automation_engine::{
+    Backoff, Condition, ErrorKind, LoginScript, RetryPolicy, ScriptMeta, Selector, Step, TargetApp,
+    TargetAppKind, Validation,
+};
+use script_manager::{merge_scripts, ScriptManager};
+use serde_json::json;
+
+fn script(version: &str, steps: Vec<Step>) -> LoginScript {
+    LoginScript {
+        meta: ScriptMeta {
+            id: "mail".into(),
+            version: version.into(),
+            author: None,
+            created_at: None,
+            updated_at: None,
+        },
+        target: TargetApp {
+            kind: TargetAppKind::Web,
+            name: "mail".into(),
+            version: None,
+            endpoint: None,
+        },
+        steps,
+        validations: Vec::new(),
+        error_handlers: Vec::new(),
+        token: None,
+        retry: None,
+    }
+}
+
+fn click(selector: &str) -> Step {
+    Step::Click(Selector::Css(selector.into()))
+}
+
+/// Copy of `base` as `version` with its steps replaced.
+fn edit(base: &LoginScript, version: &str, steps: Vec<Step>) -> LoginScript {
+    LoginScript {
+        meta: ScriptMeta {
+            version: version.into(),
+            ..base.meta.clone()
+        },
+        steps,
+        ..base.clone()
+    }
+}
+
+fn retry(max_attempts: u32) -> Option<RetryPolicy> {
+    Some(RetryPolicy {
+        max_attempts,
+        retry_on: vec![ErrorKind::Timeout],
+        backoff: Backoff::default(),
+    })
+}
+
+#[test]
+fn edits_to_different_parts_merge_cleanly() {
+    let base = script(
+        "1.0.0",
+        vec![
+            click("#cookies"),
+            click("#user"),
+            click("#pass"),
+            click("#login"),
+        ],
+    );
+    // Ours replaces the first step and appends one; theirs drops the
+    // password step, adds a validation and a retry policy. As in diff3,
+    // the edits need an untouched step between them.
+    let ours = edit(
+        &base,
+        "1.1.0",
+        vec![
+            click("#accept"),
+            click("#user"),
+            click("#pass"),
+            click("#login"),
+            click("#remember"),
+        ],
+    );
+    let mut theirs = edit(
+        &base,
+        "1.2.0",
+        vec![click("#cookies"), click("#user"), click("#login")],
+    );
+    theirs.validations.push(Validation {
+        description: "inbox".into(),
+        condition: Condition::UrlContains("/inbox".into()),
+    });
+    theirs.retry = retry(3);
+
+    let merged = merge_scripts(&base, &ours, &theirs);
+    assert!(merged.is_clean(), "{:?}", merged.conflicts);
+    assert_eq!(merged.script.meta.version, "1.1.0");
+    assert_eq!(
+        merged.script.steps,
+        [
+            click("#accept"),
+            click("#user"),
+            click("#login"),
+            click("#remember")
+        ]
+    );
+    assert_eq!(merged.script.validations, theirs.validations);
+    assert_eq!(merged.script.retry, retry(3));
+}
+
+#[test]
+fn the_same_edit_on_both_sides_is_taken_once() {
+    let base = script("1.0.0", vec![click("#login")]);
+    let ours = edit(&base, "1.1.0", vec![click("#signin"), click("#remember")]);
+    let theirs = edit(&base, "1.2.0", ours.steps.clone());
+
+    let merged = merge_scripts(&base, &ours, &theirs);
+    assert!(merged.is_clean());
+    assert_eq!(merged.script.steps, ours.steps);
+}
+
+#[test]
+fn different_edits_to_one_step_conflict_and_keep_ours() {
+    let base = script(
+        "1.0.0",
+        vec![click("#start"), click("#login"), click("#done")],
+    );
+    let mut ours = edit(
+        &base,
+        "1.1.0",
+        vec![click("#start"), click("#signin"), click("#done")],
+    );
+    let mut theirs = edit(
+        &base,
+        "1.2.0",
+        vec![click("#start"), click("#log-in"), click("#finish")],
+    );
+    ours.retry = retry(2);
+    theirs.retry = retry(5);
+
+    let merged = merge_scripts(&base, &ours, &theirs);
+    // Theirs alone touched the last step, so it merges; the retry policy
+    // conflicts as a field.
+    assert_eq!(
+        merged.script.steps,
+        [click("#start"), click("#signin"), click("#finish")]
+    );
+    assert_eq!(merged.script.retry, retry(2));
+    let locations: Vec<&str> = merged
+        .conflicts
+        .iter()
+        .map(|conflict| conflict.location.as_str())
+        .collect();
+    assert_eq!(locations, ["steps[1]", "retry"]);
+    let step = &merged.conflicts[0];
+    assert_eq!(step.base, json!({ "Click": { "Css": "#login" } }));
+    assert_eq!(step.ours, json!({ "Click": { "Css": "#signin" } }));
+    assert_eq!(step.theirs, json!({ "Click": { "Css": "#log-in" } }));
+}
+
+#[test]
+fn inserting_different_steps_at_one_place_conflicts_as_a_region() {
+    let base = script("1.0.0", vec![click("#user"), click("#login")]);
+    let ours = edit(
+        &base,
+        "1.1.0",
+        vec![click("#user"), click("#pass"), click("#login")],
+    );
+    let theirs = edit(
+        &base,
+        "1.2.0",
+        vec![
+            click("#user"),
+            click("#otp"),
+            click("#code"),
+            click("#login"),
+        ],
+    );
+
+    let merged = merge_scripts(&base, &ours, &theirs);
+    assert_eq!(merged.script.steps, ours.steps);
+    assert_eq!(merged.conflicts.len(), 1);
+    let conflict = &merged.conflicts[0];
+    assert_eq!(conflict.location, "steps[1]");
+    assert_eq!(conflict.base, json!([]));
+    assert_eq!(
+        conflict.theirs,
+        json!([{ "Click": { "Css": "#otp" } }, { "Click": { "Css": "#code" } }])
+    );
+}
+
+#[test]
+fn bodies_of_a_conditional_both_sides_edited_merge_recursively() {
+    let banner = Condition::Exists(Selector::Css("#banner".into()));
+    let conditional =
+        |condition: &Condition, on_true: Vec<Step>, on_false: Vec<Step>| Step::Conditional {
+            condition: condition.clone(),
+            on_true,
+            on_false,
+        };
+    let base = script(
+        "1.0.0",
+        vec![conditional(
+            &banner,
+            vec![click("#dismiss"), click("#close")],
+            vec![click("#skip")],
+        )],
+    );
+    // Ours edits the first branch step and the condition, theirs the
+    // second branch step and the other branch.
+    let popup = Condition::Exists(Selector::Css("#popup".into()));
+    let ours = edit(
+        &base,
+        "1.1.0",
+        vec![conditional(
+            &popup,
+            vec![click("#accept"), click("#close")],
+            vec![click("#skip")],
+        )],
+    );
+    let theirs = edit(
+        &base,
+        "1.2.0",
+        vec![conditional(
+            &banner,
+            vec![click("#dismiss"), click("#x")],
+            vec![click("#skip"), click("#next")],
+        )],
+    );
+
+    let merged = merge_scripts(&base, &ours, &theirs);
+    assert!(merged.is_clean(), "{:?}", merged.conflicts);
+    assert_eq!(
+        merged.script.steps,
+        [conditional(
+            &popup,
+            vec![click("#accept"), click("#x")],
+            vec![click("#skip"), click("#next")],
+        )]
+    );
+
+    // Both editing the same branch step conflicts at that step only.
+    let theirs = edit(
+        &base,
+        "1.2.0",
+        vec![conditional(
+            &banner,
+            vec![click("#ok"), click("#x")],
+            vec![click("#skip")],
+        )],
+    );
+    let merged = merge_scripts(&base, &ours, &theirs);
+    let locations: Vec<&str> = merged
+        .conflicts
+        .iter()
+        .map(|conflict| conflict.location.as_str())
+        .collect();
+    assert_eq!(locations, ["steps[0].on_true[0]"]);
+    assert_eq!(
+        merged.script.steps,
+        [conditional(
+            &popup,
+            vec![click("#accept"), click("#x")],
+            vec![click("#skip")],
+        )]
+    );
+}
+
+#[test]
+fn the_manager_merges_stored_versions() {
+    let manager = ScriptManager::default();
+    let base = script("1.0.0", vec![click("#user"), click("#login")]);
+    manager.save(base.clone()).unwrap();
+    manager
+        .save(edit(
+            &base,
+            "1.1.0",
+            vec![click("#cookies"), click("#user"), click("#login")],
+        ))
+        .unwrap();
+    manager
+        .save(edit(
+            &base,
+            "1.2.0",
+            vec![click("#user"), click("#login"), click("#remember")],
+        ))
+        .unwrap();
+
+    let merged = manager.merge("mail", "1.0.0", "1.1.0", "1.2.0").unwrap();
+    assert!(merged.is_clean());
+    assert_eq!(
+        merged.script.steps,
+        [
+            click("#cookies"),
+            click("#user"),
+            click("#login"),
+            click("#remember")
+        ]
+    );
+    assert!(manager.merge("mail", "1.0.0", "1.1.0", "9.9.9").is_err());
+}
@@ -0,0 +1,71 @@
+//! Runs every migration fixture pair under `fixtures/migrations/<to>/`
+//! through the migration that produces version `<to>`, then the whole chain.
+
+use script_manager::format::read_document;
+use script_manager::migrate::{document_version, migrate, MIGRATIONS};
+use script_manager::FORMAT_VERSION;
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+
+/// Fixture directories with the version they migrate to, oldest first.
+fn fixtures() -> Vec<(u32, PathBuf)> {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/migrations");
+    let mut fixtures: Vec<_> = std::fs::read_dir(&root)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.is_dir())
+        .map(|path| {
+            let to = path.file_name().unwrap().to_str().unwrap().parse().unwrap();
+            (to, path)
+        })
+        .collect();
+    fixtures.sort();
+    fixtures
+}
+
+fn load(path: &Path) -> Value {
+    let text = std::fs::read_to_string(path).unwrap();
+    serde_json::from_str(&text).unwrap_or_else(|err| panic!("{}: {err}", path.display()))
+}
+
+#[test]
+fn every_migration_has_a_fixture() {
+    let versions: Vec<u32> = fixtures().into_iter().map(|(to, _)| to).collect();
+    let expected: Vec<u32> = MIGRATIONS
+        .iter()
+        .map(|migration| migration.from + 1)
+        .collect();
+    assert_eq!(versions, expected);
+}
+
+#[test]
+fn each_migration_turns_input_into_expected() {
+    for (to, dir) in fixtures() {
+        let mut document = load(&dir.join("input.json"));
+        let expected = load(&dir.join("expected.json"));
+        assert_eq!(
+            document_version(&document).unwrap(),
+            to - 1,
+            "{}",
+            dir.display()
+        );
+        let Value::Object(fields) = &mut document else {
+            panic!("{}: input is not a mapping", dir.display());
+        };
+        (MIGRATIONS[to as usize - 1].apply)(fields).unwrap();
+        fields.insert("format_version".into(), to.into());
+        assert_eq!(document, expected, "{}", dir.display());
+    }
+}
+
+#[test]
+fn inputs_migrate_to_current_scripts() {
+    for (to, dir) in fixtures() {
+        let mut document = load(&dir.join("input.json"));
+        let applied = migrate(&mut document).unwrap();
+        assert_eq!(applied.len() as u32, FORMAT_VERSION - (to - 1));
+        assert_eq!(document_version(&document).unwrap(), FORMAT_VERSION);
+        assert!(migrate(&mut document).unwrap().is_empty());
+        read_document(document).unwrap_or_else(|err| panic!("{}: {err}", dir.display()));
+    }
+}
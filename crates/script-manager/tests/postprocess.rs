@@ -0,0 +1,255 @@
+//! `script_from_recording`: noise removal, inferred success validations and
+//! the default error handlers.
+
+use automation_engine::{Condition, Selector, Step, TargetApp, TargetAppKind, ValueRef};
+use script_manager::postprocess::default_error_handlers;
+use script_manager::{
+    script_from_recording, ElementDescriptor, RecordedEvent, RecordedSession, Recorder,
+    ScriptManager,
+};
+
+fn record(events: Vec<RecordedEvent>) -> RecordedSession {
+    let mut recorder: Recorder = ScriptManager::default()
+        .recorder(TargetApp {
+            kind: TargetAppKind::Web,
+            name: "mail".into(),
+            version: None,
+            endpoint: None,
+        })
+        .with_account("alice");
+    for event in events {
+        recorder.push(event);
+    }
+    recorder.finish()
+}
+
+fn element(tag: &str, id: &str, input_type: Option<&str>) -> ElementDescriptor {
+    ElementDescriptor {
+        tag: tag.into(),
+        id: Some(id.into()),
+        input_type: input_type.map(Into::into),
+        ..ElementDescriptor::default()
+    }
+}
+
+fn username() -> ElementDescriptor {
+    element("input", "user", Some("email"))
+}
+
+fn password() -> ElementDescriptor {
+    element("input", "password", Some("password"))
+}
+
+fn navigation(url: &str, controls: Vec<ElementDescriptor>) -> RecordedEvent {
+    RecordedEvent::Navigation {
+        url: url.into(),
+        controls,
+    }
+}
+
+fn click(element: &ElementDescriptor) -> RecordedEvent {
+    RecordedEvent::Click {
+        element: element.clone(),
+    }
+}
+
+fn typed(element: &ElementDescriptor, value: &str) -> RecordedEvent {
+    RecordedEvent::Input {
+        element: element.clone(),
+        value: value.into(),
+    }
+}
+
+fn css(selector: &str) -> Selector {
+    Selector::Css(selector.into())
+}
+
+fn vault(key: &str) -> ValueRef {
+    ValueRef::FromVault(key.into())
+}
+
+/// Username, password and submit typed on `login`, then the browser lands
+/// on `landed` (when given) showing `controls`.
+fn login(login: &str, landed: Option<(&str, Vec<ElementDescriptor>)>) -> RecordedSession {
+    let submit = element("button", "go", None);
+    let mut events = vec![
+        navigation(login, Vec::new()),
+        typed(&username(), "alice"),
+        typed(&password(), "hunter2"),
+        click(&submit),
+    ];
+    if let Some((url, controls)) = landed {
+        events.push(navigation(url, controls));
+    }
+    record(events)
+}
+
+#[test]
+fn noise_is_dropped_and_every_action_waits_for_its_element() {
+    let submit = element("button", "login", None);
+    let next = element("button", "next", None);
+    let session = record(vec![
+        navigation("https://mail.example/login", Vec::new()),
+        // Clicks that only focus a field, the username typed twice in a
+        // row, and a double click on the submit button.
+        click(&username()),
+        typed(&username(), "al"),
+        click(&username()),
+        typed(&username(), "alice"),
+        click(&password()),
+        typed(&password(), "hunter2"),
+        click(&submit),
+        click(&submit),
+        // The same button on two pages is two actions.
+        navigation("https://mail.example/welcome/1", Vec::new()),
+        click(&next),
+        navigation("https://mail.example/welcome/2", Vec::new()),
+        click(&next),
+    ]);
+    assert_eq!(session.steps.len(), 13);
+
+    let script = script_from_recording(&session, ("mail-login", "1.0.0"));
+    assert_eq!(script.meta.id, "mail-login");
+    assert_eq!(
+        script.steps,
+        [
+            Step::WaitFor(css("#user")),
+            Step::Input {
+                selector: css("#user"),
+                value: vault("alice.username"),
+            },
+            Step::WaitFor(css("#password")),
+            Step::Input {
+                selector: css("#password"),
+                value: vault("alice.secret"),
+            },
+            Step::WaitFor(css("#login")),
+            Step::Click(css("#login")),
+            Step::WaitFor(css("#next")),
+            Step::Click(css("#next")),
+            Step::WaitFor(css("#next")),
+            Step::Click(css("#next")),
+        ]
+    );
+}
+
+#[test]
+fn landing_elsewhere_with_a_logout_control_validates_both() {
+    let logout = element("button", "logout", None);
+    let session = login(
+        "https://mail.example/login?next=%2Finbox",
+        Some(("https://mail.example/inbox/#unread", vec![logout])),
+    );
+    let script = script_from_recording(&session, ("mail-login", "1.0.0"));
+
+    // The validations run right after the logout control has shown up.
+    assert_eq!(script.steps.last(), Some(&Step::WaitFor(css("#logout"))));
+    let validations: Vec<(&str, &Condition)> = script
+        .validations
+        .iter()
+        .map(|validation| (validation.description.as_str(), &validation.condition))
+        .collect();
+    assert_eq!(
+        validations,
+        [
+            (
+                "landed on mail.example/inbox",
+                &Condition::UrlContains("mail.example/inbox".into())
+            ),
+            (
+                "logout control is shown",
+                &Condition::Exists(css("#logout"))
+            ),
+        ]
+    );
+}
+
+#[test]
+fn landing_on_a_parent_path_validates_leaving_the_login_page() {
+    let session = login(
+        "https://shop.example/account/login",
+        Some(("https://shop.example/account/", Vec::new())),
+    );
+    let script = script_from_recording(&session, ("shop-login", "1.0.0"));
+
+    assert_eq!(script.steps.last(), Some(&Step::Click(css("#go"))));
+    assert_eq!(script.validations.len(), 1);
+    assert_eq!(
+        script.validations[0].description,
+        "left shop.example/account/login"
+    );
+    // "shop.example/account" is part of the login URL, so only leaving
+    // the login page tells them apart.
+    assert_eq!(
+        script.validations[0].condition,
+        Condition::Not(Box::new(Condition::UrlContains(
+            "shop.example/account/login".into()
+        )))
+    );
+}
+
+#[test]
+fn without_a_result_page_the_password_field_has_to_go() {
+    // Stopped before the submit landed; a native screen name tells nothing
+    // either.
+    for landed in [None, Some(("com.example.mail/.InboxActivity", Vec::new()))] {
+        let url = match landed {
+            None => "https://mail.example/login",
+            Some(_) => "com.example.mail/.LoginActivity",
+        };
+        let script = script_from_recording(&login(url, landed), ("mail-login", "1.0.0"));
+        assert_eq!(script.validations.len(), 1, "{url}");
+        assert_eq!(script.validations[0].description, "password field is gone");
+        assert_eq!(
+            script.validations[0].condition,
+            Condition::Not(Box::new(Condition::Exists(css("#password"))))
+        );
+    }
+
+    // Nothing typed into a password field: no validation to infer.
+    let session = record(vec![
+        navigation("https://mail.example/login", Vec::new()),
+        typed(&username(), "alice"),
+    ]);
+    assert!(script_from_recording(&session, ("mail-login", "1.0.0"))
+        .validations
+        .is_empty());
+}
+
+#[test]
+fn default_handlers_click_interruptions_away_only_when_present() {
+    let handlers = default_error_handlers();
+    let names: Vec<&str> = handlers
+        .iter()
+        .map(|handler| handler.name.as_str())
+        .collect();
+    assert_eq!(
+        names,
+        ["dismiss-cookie-consent", "close-dialog", "skip-prompt"]
+    );
+    for handler in &handlers {
+        let [Step::Conditional {
+            condition: Condition::Exists(selector),
+            on_true,
+            on_false,
+        }] = handler.on_error.as_slice()
+        else {
+            panic!("unexpected handler {handler:?}");
+        };
+        assert_eq!(on_true, &[Step::Click(selector.clone())]);
+        assert!(on_false.is_empty());
+        // XPath is the one selector kind web and Android drivers share.
+        let Selector::AnyOf(candidates) = selector else {
+            panic!("unexpected selector {selector:?}");
+        };
+        assert!(candidates.len() > 1);
+        assert!(candidates
+            .iter()
+            .all(|candidate| matches!(candidate, Selector::XPath(_))));
+    }
+    let skip = format!("{:?}", handlers[2]);
+    assert!(skip.contains("@text='Not now'") && skip.contains("text()='Not now'"));
+
+    let script = script_from_recording(&login("https://mail.example/login", None), ("m", "1"));
+    assert_eq!(script.error_handlers, handlers);
+}
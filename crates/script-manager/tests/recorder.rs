@@ -0,0 +1,336 @@
+//! `Recorder`: keystroke merging, candidate selectors, waits after
+//! navigations, and the events `RECORDER_JS` produces under node.
+
+use automation_engine::{Selector, Step, TargetApp, TargetAppKind, ValueRef};
+use script_manager::{
+    ElementDescriptor, FieldRole, RecordedEvent, RecordedSession, Recorder, ScriptManager,
+};
+use std::path::Path;
+use std::process::Command;
+
+fn recorder() -> Recorder {
+    ScriptManager::default().recorder(TargetApp {
+        kind: TargetAppKind::Web,
+        name: "mail".into(),
+        version: None,
+        endpoint: Some("https://mail.example/login".into()),
+    })
+}
+
+fn field(id: &str) -> ElementDescriptor {
+    ElementDescriptor {
+        tag: "input".into(),
+        id: Some(id.into()),
+        ..ElementDescriptor::default()
+    }
+}
+
+fn button(id: &str) -> ElementDescriptor {
+    ElementDescriptor {
+        tag: "button".into(),
+        id: Some(id.into()),
+        ..ElementDescriptor::default()
+    }
+}
+
+fn css(selector: &str) -> Selector {
+    Selector::Css(selector.into())
+}
+
+fn navigation(url: &str) -> RecordedEvent {
+    RecordedEvent::Navigation {
+        url: url.into(),
+        controls: Vec::new(),
+    }
+}
+
+fn typed(element: &ElementDescriptor, value: &str) -> RecordedEvent {
+    RecordedEvent::Input {
+        element: element.clone(),
+        value: value.into(),
+    }
+}
+
+fn click(element: &ElementDescriptor) -> RecordedEvent {
+    RecordedEvent::Click {
+        element: element.clone(),
+    }
+}
+
+fn literal(selector: &str, text: &str) -> Step {
+    Step::Input {
+        selector: css(selector),
+        value: ValueRef::Literal(text.into()),
+    }
+}
+
+#[test]
+fn keystrokes_on_one_field_become_one_input() {
+    let city = field("city");
+    // Validation styles come and go while typing; the path still matches.
+    let styled = ElementDescriptor {
+        classes: vec!["invalid".into()],
+        xpath: Some("/html/body/form/input".into()),
+        ..city.clone()
+    };
+    let styled_again = ElementDescriptor {
+        classes: vec!["valid".into()],
+        ..styled.clone()
+    };
+    let mut recorder = recorder();
+    recorder.extend([
+        typed(&city, "B"),
+        typed(&city, "Be"),
+        typed(&city, "Berlin"),
+        click(&button("next")),
+        typed(&styled, "M"),
+        typed(&styled_again, "Munich"),
+        RecordedEvent::Change {
+            element: styled_again.clone(),
+            value: "Munich".into(),
+        },
+    ]);
+
+    let session = recorder.finish();
+    assert_eq!(
+        session.steps[1..],
+        [
+            literal("#city", "Berlin"),
+            Step::Click(css("#next")),
+            Step::Input {
+                selector: styled.selector().unwrap(),
+                value: ValueRef::Literal("Munich".into()),
+            },
+        ]
+    );
+}
+
+#[test]
+fn selectors_list_every_usable_candidate() {
+    let login = ElementDescriptor {
+        tag: "button".into(),
+        id: Some("login-btn".into()),
+        name: Some("do\"login".into()),
+        classes: vec!["btn".into(), "2col".into(), "primary".into()],
+        aria_label: Some("Log in".into()),
+        text: Some("Log in".into()),
+        xpath: Some("/html/body/form/button[2]".into()),
+        ..ElementDescriptor::default()
+    };
+    assert_eq!(
+        login.selector(),
+        Some(Selector::AnyOf(vec![
+            css("#login-btn"),
+            css("button[name=\"do\\\"login\"]"),
+            Selector::AccessibilityId("Log in".into()),
+            Selector::XPath("//button[text()='Log in']".into()),
+            css("button.btn.primary"),
+            Selector::XPath("/html/body/form/button[2]".into()),
+        ]))
+    );
+
+    // Ids that are not CSS identifiers are matched as attributes; text
+    // quoting both ways cannot be an XPath literal; field text is a value.
+    let odd = ElementDescriptor {
+        tag: "span".into(),
+        id: Some("1st".into()),
+        text: Some(r#"it's "odd""#.into()),
+        ..ElementDescriptor::default()
+    };
+    assert_eq!(odd.selector(), Some(css("[id=\"1st\"]")));
+    let filled = ElementDescriptor {
+        text: Some("alice".into()),
+        ..field("user")
+    };
+    assert_eq!(filled.selector(), Some(css("#user")));
+
+    // Elements without anything to select them by are not recorded.
+    let mut recorder = recorder();
+    let blank = ElementDescriptor {
+        tag: "div".into(),
+        id: Some("  ".into()),
+        ..ElementDescriptor::default()
+    };
+    assert_eq!(blank.selector(), None);
+    recorder.push(click(&blank));
+    assert!(recorder.session().steps.is_empty());
+}
+
+#[test]
+fn the_first_action_on_a_new_page_waits_for_its_element() {
+    let native = ElementDescriptor {
+        candidates: vec![
+            Selector::Coordinates { x: 10, y: 20 },
+            Selector::AccessibilityId("Continue".into()),
+        ],
+        ..ElementDescriptor::default()
+    };
+    let mut recorder = recorder();
+    recorder.extend([
+        click(&button("cookies")),
+        click(&button("start")),
+        navigation("https://mail.example/login"),
+        // Reported again once rendered: still the same page.
+        RecordedEvent::Navigation {
+            url: "https://mail.example/login".into(),
+            controls: vec![button("login")],
+        },
+        typed(&field("city"), "Berlin"),
+        navigation("app://welcome"),
+        click(&native),
+    ]);
+
+    let session = recorder.finish();
+    assert_eq!(
+        session.steps,
+        [
+            Step::WaitFor(css("#cookies")),
+            Step::Click(css("#cookies")),
+            Step::Click(css("#start")),
+            Step::WaitFor(css("#city")),
+            literal("#city", "Berlin"),
+            // Coordinates always "exist", so only the label is waited for.
+            Step::WaitFor(Selector::AccessibilityId("Continue".into())),
+            Step::Click(native.selector().unwrap()),
+        ]
+    );
+    let pages: Vec<(&str, usize, usize)> = session
+        .pages
+        .iter()
+        .map(|page| (page.url.as_str(), page.step, page.controls.len()))
+        .collect();
+    assert_eq!(
+        pages,
+        [
+            ("https://mail.example/login", 3, 1),
+            ("app://welcome", 5, 0)
+        ]
+    );
+}
+
+/// `node`, unless it is not installed.
+fn node() -> Option<Command> {
+    match Command::new("node").arg("--version").output() {
+        Ok(output) if output.status.success() => Some(Command::new("node")),
+        _ => {
+            eprintln!("node not found, skipping the recorder.js tests");
+            None
+        }
+    }
+}
+
+fn js_dir() -> std::path::PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("js")
+}
+
+#[test]
+fn recorder_js_passes_its_node_tests() {
+    let Some(mut node) = node() else {
+        return;
+    };
+    let output = node
+        .arg("--test")
+        .arg(js_dir().join("recorder.test.js"))
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stdout)
+    );
+}
+
+/// Logs in on the fake login page of `recorder.test.js` and prints each
+/// reported event as a JSON line.
+const LOGIN_UNDER_NODE: &str = r#"
+const recorder = require("./recorder.js");
+const { el, page } = require("./fake_dom.js");
+const user = el("input", { id: "user", name: "email", autocomplete: "username" });
+const pass = el("input", { id: "pass", type: "password" });
+const submit = el("button", { type: "submit" }, el("span", {}, "Log in"));
+const tab = page(
+  "https://mail.example/login",
+  el("body", {}, el("form", {}, el("div", {}, user), el("div", {}, pass), submit))
+);
+recorder.install(tab, (event) => console.log(JSON.stringify(event)));
+for (const value of ["a", "al", "alice"]) {
+  user.value = value;
+  tab.fire("input", user);
+}
+pass.value = "hunter2";
+tab.fire("input", pass);
+tab.fire("click", submit.children[0]);
+tab.history.pushState(null, "", "/inbox");
+"#;
+
+#[test]
+fn events_of_the_injected_script_feed_the_recorder() {
+    let Some(mut node) = node() else {
+        return;
+    };
+    let output = node
+        .current_dir(js_dir())
+        .args(["-e", LOGIN_UNDER_NODE])
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let events: Vec<RecordedEvent> = String::from_utf8(output.stdout)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap_or_else(|err| panic!("{line}: {err}")))
+        .collect();
+    assert_eq!(events.len(), 7);
+
+    let mut recorder = recorder().with_account("alice");
+    recorder.extend(events);
+    let RecordedSession {
+        steps,
+        fields,
+        pages,
+        ..
+    } = recorder.finish();
+    let user = Selector::AnyOf(vec![
+        css("#user"),
+        css("input[name=\"email\"]"),
+        Selector::XPath("/html/body/form/div[1]/input".into()),
+    ]);
+    assert_eq!(
+        steps,
+        [
+            Step::WaitFor(user.clone()),
+            Step::Input {
+                selector: user,
+                value: ValueRef::FromVault("alice.username".into()),
+            },
+            Step::Input {
+                selector: Selector::AnyOf(vec![
+                    css("#pass"),
+                    Selector::XPath("/html/body/form/div[2]/input".into()),
+                ]),
+                value: ValueRef::FromVault("alice.secret".into()),
+            },
+            Step::Click(Selector::AnyOf(vec![
+                Selector::XPath("//button[text()='Log in']".into()),
+                Selector::XPath("/html/body/form/button".into()),
+            ])),
+        ]
+    );
+    let roles: Vec<(usize, FieldRole)> = fields.iter().map(|f| (f.step, f.role)).collect();
+    assert_eq!(
+        roles,
+        [
+            (1, FieldRole::Username),
+            (2, FieldRole::Password),
+            (3, FieldRole::Submit)
+        ]
+    );
+    let urls: Vec<&str> = pages.iter().map(|page| page.url.as_str()).collect();
+    assert_eq!(
+        urls,
+        ["https://mail.example/login", "https://mail.example/inbox"]
+    );
+}
@@ -0,0 +1,126 @@
+//! `SqliteStore` keeps one row per script version, also when several
+//! connections save at once.
+
+use automation_engine::{LoginScript, ScriptMeta, TargetApp, TargetAppKind};
+use rusqlite::Connection;
+use script_manager::{ScriptManager, ScriptStore, SqliteStore};
+use std::path::PathBuf;
+use std::sync::{Arc, Barrier};
+
+fn script(version: &str) -> LoginScript {
+    LoginScript {
+        meta: ScriptMeta {
+            id: "mail".into(),
+            version: version.into(),
+            author: None,
+            created_at: None,
+            updated_at: None,
+        },
+        target: TargetApp {
+            kind: TargetAppKind::Web,
+            name: "mail".into(),
+            version: None,
+            endpoint: None,
+        },
+        steps: Vec::new(),
+        validations: Vec::new(),
+        error_handlers: Vec::new(),
+        token: None,
+        retry: None,
+    }
+}
+
+/// Fresh database file under the system temp directory.
+fn database(name: &str) -> PathBuf {
+    let path =
+        std::env::temp_dir().join(format!("script-manager-{name}-{}.db", std::process::id()));
+    for suffix in ["", "-wal", "-shm"] {
+        let _ = std::fs::remove_file(format!("{}{suffix}", path.display()));
+    }
+    path
+}
+
+#[test]
+fn the_same_version_is_stored_once() {
+    let store = SqliteStore::open_in_memory().unwrap();
+    store.save(&script("1.0.0"), None).unwrap();
+    assert!(store.save(&script("1.0.0"), None).is_err());
+    store.save(&script("1.0.1"), None).unwrap();
+
+    let versions: Vec<String> = store
+        .versions("mail")
+        .unwrap()
+        .into_iter()
+        .map(|script| script.meta.version)
+        .collect();
+    assert_eq!(versions, ["1.0.0", "1.0.1"]);
+    assert_eq!(store.history("mail").unwrap().len(), 2);
+}
+
+#[test]
+fn upgrading_drops_duplicate_rows() {
+    let path = database("duplicates");
+    let store = SqliteStore::open(&path).unwrap();
+    let schema = store.schema_version().unwrap();
+    store.save(&script("1.0.0"), None).unwrap();
+    drop(store);
+
+    // Roll the file back to schema 3, where (id, version) was not unique,
+    // and sneak in a second copy of 1.0.0.
+    let connection = Connection::open(&path).unwrap();
+    connection
+        .execute_batch(
+            "DROP INDEX script_versions_id_version;
+             CREATE INDEX script_versions_id_version ON script_versions (id, version);
+             INSERT INTO script_versions (id, version, document, saved_at)
+                 SELECT id, version, document, saved_at + 1 FROM script_versions;
+             PRAGMA user_version = 3;",
+        )
+        .unwrap();
+    drop(connection);
+
+    let store = SqliteStore::open(&path).unwrap();
+    assert_eq!(store.schema_version().unwrap(), schema);
+    assert_eq!(store.versions("mail").unwrap().len(), 1);
+    assert!(store.save(&script("1.0.0"), None).is_err());
+    drop(store);
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn build_metadata_does_not_make_a_new_version() {
+    let store = SqliteStore::open_in_memory().unwrap();
+    store.save(&script("1.0.0"), None).unwrap();
+    let err = store.save(&script("1.0.0+build.2"), None).unwrap_err();
+    assert_eq!(err.to_string(), "script mail already has version 1.0.0");
+    assert_eq!(store.versions("mail").unwrap().len(), 1);
+}
+
+#[test]
+fn concurrent_publishes_of_one_version_save_it_once() {
+    let path = database("concurrent");
+    SqliteStore::open(&path).unwrap();
+    let barrier = Arc::new(Barrier::new(4));
+    let saves: Vec<_> = (0..4)
+        .map(|build| {
+            let (path, barrier) = (path.clone(), barrier.clone());
+            std::thread::spawn(move || {
+                let manager = ScriptManager::open(&path).unwrap();
+                barrier.wait();
+                manager.save(script(&format!("2.0.0+build.{build}")))
+            })
+        })
+        .collect();
+    let saved = saves
+        .into_iter()
+        .map(|save| save.join().unwrap())
+        .filter(Result::is_ok)
+        .count();
+
+    assert_eq!(saved, 1);
+    let store = SqliteStore::open(&path).unwrap();
+    assert_eq!(store.versions("mail").unwrap().len(), 1);
+    assert_eq!(store.history("mail").unwrap().len(), 1);
+    drop(store);
+    let _ = std::fs::remove_file(&path);
+}
@@ -0,0 +1,263 @@
+//! Trace bundles written by a real engine run, read back through
+//! `TraceBundle`.
+
+use async_trait::async_trait;
+use automation_engine::trace::{SnapshotFormat, UiSnapshot, TRACE_MANIFEST};
+use automation_engine::{
+    AutomationDriver, AutomationEngine, Condition, DriverSession, ExecutionEventKind, LoginScript,
+    NoopCaptcha, ScriptMeta, Selector, Step, StepPath, TargetApp, TargetAppKind, TraceConfig,
+    TraceMode, ValueRef,
+};
+use script_manager::TraceBundle;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+const TOKEN: &str = "sess-7f3a9c21";
+
+/// Login page whose only missing element is `#gone`; every screenshot
+/// carries the number of actions taken so far.
+struct Page;
+
+struct PageSession {
+    actions: u8,
+}
+
+#[async_trait]
+impl AutomationDriver for Page {
+    fn name(&self) -> &'static str {
+        "page"
+    }
+
+    fn supports(&self, target: &TargetApp) -> bool {
+        target.kind == TargetAppKind::Web
+    }
+
+    async fn open_session(&self, _target: &TargetApp) -> anyhow::Result<Box<dyn DriverSession>> {
+        Ok(Box::new(PageSession { actions: 0 }))
+    }
+}
+
+#[async_trait]
+impl DriverSession for PageSession {
+    async fn exists(&mut self, selector: &Selector) -> anyhow::Result<bool> {
+        Ok(*selector != Selector::Css("#gone".into()))
+    }
+
+    async fn click(&mut self, selector: &Selector) -> anyhow::Result<()> {
+        if !self.exists(selector).await? {
+            anyhow::bail!("no element {selector:?}");
+        }
+        self.actions += 1;
+        Ok(())
+    }
+
+    async fn input(&mut self, _selector: &Selector, _text: &str) -> anyhow::Result<()> {
+        self.actions += 1;
+        Ok(())
+    }
+
+    async fn swipe(&mut self, _: &Selector, _: &Selector, _: u64) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    async fn text(&mut self, _selector: &Selector) -> anyhow::Result<Option<String>> {
+        Ok(None)
+    }
+
+    async fn capture(&mut self, _selector: &Selector) -> anyhow::Result<Vec<u8>> {
+        Ok(Vec::new())
+    }
+
+    async fn screenshot(&mut self) -> anyhow::Result<Vec<u8>> {
+        Ok(vec![0x89, b'P', b'N', b'G', self.actions])
+    }
+
+    async fn snapshot(&mut self) -> anyhow::Result<Option<UiSnapshot>> {
+        Ok(Some(UiSnapshot {
+            format: SnapshotFormat::Html,
+            content: format!("<body data-actions=\"{}\"></body>", self.actions),
+        }))
+    }
+
+    async fn session_token(&mut self) -> anyhow::Result<Option<String>> {
+        Ok(Some(TOKEN.into()))
+    }
+}
+
+fn script(id: &str, steps: Vec<Step>) -> LoginScript {
+    LoginScript {
+        meta: ScriptMeta {
+            id: id.into(),
+            version: "1.0.0".into(),
+            author: None,
+            created_at: None,
+            updated_at: None,
+        },
+        target: TargetApp {
+            kind: TargetAppKind::Web,
+            name: "mail".into(),
+            version: None,
+            endpoint: None,
+        },
+        steps,
+        validations: Vec::new(),
+        error_handlers: Vec::new(),
+        token: None,
+        retry: None,
+    }
+}
+
+fn css(selector: &str) -> Selector {
+    Selector::Css(selector.into())
+}
+
+fn scratch(name: &str) -> PathBuf {
+    let directory =
+        std::env::temp_dir().join(format!("trace-viewer-{name}-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&directory);
+    directory
+}
+
+/// Run `script` with traces in `directory` and return the bundle written.
+async fn traced_run(directory: &Path, mode: TraceMode, script: &LoginScript) -> Option<PathBuf> {
+    let mut engine = AutomationEngine::new(vec![Arc::new(Page)], Arc::new(NoopCaptcha));
+    engine.set_trace_config(TraceConfig {
+        mode,
+        directory: directory.to_path_buf(),
+        skip_screenshots: false,
+    });
+    let mut events = engine.subscribe();
+    engine.run(script).await.unwrap();
+    std::iter::from_fn(|| events.try_recv().ok()).find_map(|event| match event.kind {
+        ExecutionEventKind::TraceSaved { directory } => Some(PathBuf::from(directory)),
+        _ => None,
+    })
+}
+
+#[tokio::test]
+async fn a_bundle_replays_every_step_without_the_token() {
+    let directory = scratch("replay");
+    let login = script(
+        "mail-login",
+        vec![
+            Step::Input {
+                selector: css("#user"),
+                value: ValueRef::Literal("alice".into()),
+            },
+            Step::Conditional {
+                condition: Condition::Exists(css("#remember")),
+                on_true: vec![Step::Click(css("#remember"))],
+                on_false: vec![],
+            },
+            Step::Click(css("#login")),
+        ],
+    );
+    let root = traced_run(&directory, TraceMode::Always, &login)
+        .await
+        .expect("no trace written");
+
+    // The token stays out of the bundle; only the fact that there was one
+    // is kept.
+    let raw = std::fs::read_to_string(root.join(TRACE_MANIFEST)).unwrap();
+    assert!(!raw.contains(TOKEN), "{raw}");
+    let bundle = TraceBundle::open(&root).unwrap();
+    let manifest = bundle.manifest();
+    assert_eq!(manifest.script_id, "mail-login");
+    assert_eq!(manifest.driver, "page");
+    assert!(manifest.outcome.success && manifest.outcome.has_token);
+    assert_eq!(bundle.first_failure(), None);
+
+    // The conditional is traced through the click it ran.
+    let paths: Vec<(String, &str)> = manifest
+        .steps
+        .iter()
+        .map(|step| (step.path.to_string(), step.step.as_str()))
+        .collect();
+    assert_eq!(
+        paths,
+        [
+            ("steps[0]".to_string(), "input"),
+            ("steps[1].on_true[0]".to_string(), "click"),
+            ("steps[2]".to_string(), "click"),
+        ]
+    );
+    let mut cursor = bundle.cursor();
+    for actions in 1..=3u8 {
+        let frame = cursor.load().unwrap();
+        assert_eq!(frame.step.sequence, usize::from(actions - 1));
+        assert_eq!(
+            frame.screenshot,
+            Some(vec![0x89, b'P', b'N', b'G', actions])
+        );
+        assert_eq!(
+            frame.snapshot,
+            Some(format!("<body data-actions=\"{actions}\"></body>"))
+        );
+        assert_eq!(cursor.advance(), actions < 3);
+    }
+    cursor.seek(1).unwrap();
+    assert_eq!(
+        cursor.current().map(|step| &step.path),
+        Some(&"steps[1].on_true[0]".parse::<StepPath>().unwrap())
+    );
+    assert!(cursor.back() && !cursor.back());
+    assert!(cursor.seek(3).is_err());
+
+    std::fs::remove_dir_all(&directory).unwrap();
+}
+
+#[tokio::test]
+async fn failed_runs_point_at_the_failing_step() {
+    let directory = scratch("failure");
+    let passing = script("mail-login", vec![Step::Click(css("#login"))]);
+    assert_eq!(
+        traced_run(&directory, TraceMode::OnFailure, &passing).await,
+        None
+    );
+
+    let failing = script(
+        "mail-login",
+        vec![Step::Click(css("#login")), Step::Click(css("#gone"))],
+    );
+    let root = traced_run(&directory, TraceMode::OnFailure, &failing)
+        .await
+        .expect("no trace written");
+    let bundle = TraceBundle::open(&root).unwrap();
+    assert!(!bundle.manifest().outcome.success);
+    assert!(!bundle.manifest().outcome.has_token);
+    assert_eq!(bundle.len(), 2);
+    assert_eq!(bundle.first_failure(), Some(1));
+    let frame = bundle.frame(1).unwrap();
+    assert!(frame.step.error.as_deref().unwrap().contains("#gone"));
+
+    std::fs::remove_dir_all(&directory).unwrap();
+}
+
+#[tokio::test]
+async fn edited_manifests_cannot_reach_outside_the_bundle() {
+    let directory = scratch("escape");
+    let login = script("mail-login", vec![Step::Click(css("#login"))]);
+    let root = traced_run(&directory, TraceMode::Always, &login)
+        .await
+        .unwrap();
+    let manifest = root.join(TRACE_MANIFEST);
+    let raw = std::fs::read_to_string(&manifest).unwrap();
+
+    std::fs::write(
+        &manifest,
+        raw.replace("steps/0000.png", "../../../etc/passwd"),
+    )
+    .unwrap();
+    let err = TraceBundle::open(&root).unwrap().frame(0).unwrap_err();
+    assert!(err.to_string().contains("escapes the bundle"), "{err}");
+
+    std::fs::write(
+        &manifest,
+        raw.replace("\"format_version\": 1", "\"format_version\": 99"),
+    )
+    .unwrap();
+    let err = TraceBundle::open(&root).unwrap_err();
+    assert!(err.to_string().contains("newer than supported"), "{err}");
+
+    std::fs::remove_dir_all(&directory).unwrap();
+}
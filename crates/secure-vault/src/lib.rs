@@ -3,9 +3,11 @@
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fmt;
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, Mutex, MutexGuard};
 use thiserror::Error;
 
+/// Clones share the same underlying store.
+#[derive(Clone)]
 pub struct CredentialVault {
     provider: Arc<dyn KeyProvider + Send + Sync>,
     store: Arc<Mutex<HashMap<String, CredentialBlob>>>,
@@ -38,31 +40,84 @@ impl CredentialVault {
     /// Persist an entry using the derived key. Encryption is mocked for now
     /// but the flow matches a real implementation (derive key -> seal bytes).
     pub fn store(&self, entry: CredentialEntry) -> Result<(), VaultError> {
-        let key = self.provider.derive_key(&entry.id)?;
-        let sealed = CredentialBlob {
-            ciphertext: serde_json::to_vec(&entry)
-                .map_err(|e| VaultError::Internal(e.to_string()))?,
-            key_alias: key.alias,
-        };
-
-        let mut guard = self
-            .store
-            .lock()
-            .map_err(|_| VaultError::Internal("vault lock poisoned".into()))?;
+        let sealed = self.seal(&entry)?;
+        let mut guard = self.lock()?;
         guard.insert(entry.id.clone(), sealed);
         Ok(())
     }
 
+    /// Whether an entry exists, without decrypting it.
+    pub fn contains(&self, id: &str) -> bool {
+        self.store
+            .lock()
+            .map(|guard| guard.contains_key(id))
+            .unwrap_or(false)
+    }
+
     /// Fetch and "decrypt" an entry; errors if missing or corrupted.
     pub fn fetch(&self, id: &str) -> Result<CredentialEntry, VaultError> {
-        let guard = self
-            .store
-            .lock()
-            .map_err(|_| VaultError::Internal("vault lock poisoned".into()))?;
+        let guard = self.lock()?;
+        let blob = guard
+            .get(id)
+            .ok_or_else(|| VaultError::NotFound(id.to_string()))?;
+        Self::open(blob)
+    }
+
+    /// Replace the session token of an existing credential. The entry is
+    /// read and written back under one lock, so a concurrent `store` of the
+    /// same credential is never undone.
+    pub fn store_token(
+        &self,
+        id: &str,
+        token: String,
+        expires_at: Option<u64>,
+    ) -> Result<(), VaultError> {
+        let mut guard = self.lock()?;
         let blob = guard
             .get(id)
             .ok_or_else(|| VaultError::NotFound(id.to_string()))?;
+        let mut entry = Self::open(blob)?;
+        entry.token = Some(token);
+        entry.token_expires_at = expires_at;
+        let sealed = self.seal(&entry)?;
+        guard.insert(entry.id, sealed);
+        Ok(())
+    }
+
+    /// Stored token with its expiry if it stays valid for at least
+    /// `margin_secs` after `now` (epoch seconds). Tokens without a known
+    /// expiry are never reused.
+    pub fn valid_token(
+        &self,
+        id: &str,
+        now: u64,
+        margin_secs: u64,
+    ) -> Result<Option<(String, u64)>, VaultError> {
+        let entry = self.fetch(id)?;
+        Ok(match (entry.token, entry.token_expires_at) {
+            (Some(token), Some(expires_at)) if expires_at > now.saturating_add(margin_secs) => {
+                Some((token, expires_at))
+            }
+            _ => None,
+        })
+    }
+
+    fn lock(&self) -> Result<MutexGuard<'_, HashMap<String, CredentialBlob>>, VaultError> {
+        self.store
+            .lock()
+            .map_err(|_| VaultError::Internal("vault lock poisoned".into()))
+    }
+
+    fn seal(&self, entry: &CredentialEntry) -> Result<CredentialBlob, VaultError> {
+        let key = self.provider.derive_key(&entry.id)?;
+        Ok(CredentialBlob {
+            ciphertext: serde_json::to_vec(entry)
+                .map_err(|e| VaultError::Internal(e.to_string()))?,
+            key_alias: key.alias,
+        })
+    }
 
+    fn open(blob: &CredentialBlob) -> Result<CredentialEntry, VaultError> {
         serde_json::from_slice::<CredentialEntry>(&blob.ciphertext)
             .map_err(|e| VaultError::Internal(e.to_string()))
     }
@@ -91,6 +146,9 @@ pub struct CredentialEntry {
     pub secret: String,
     pub token: Option<String>,
     pub metadata: Option<String>,
+    /// Expiry of `token` in seconds since the Unix epoch.
+    #[serde(default)]
+    pub token_expires_at: Option<u64>,
 }
 
 #[allow(dead_code)]
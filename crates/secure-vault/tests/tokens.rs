@@ -0,0 +1,118 @@
+//! Session tokens cached next to the credentials: expiry checks and
+//! concurrent updates.
+
+use secure_vault::{CredentialEntry, CredentialVault, VaultError};
+use std::sync::{Arc, Barrier};
+use std::thread;
+
+const NOW: u64 = 1_000_000;
+
+fn entry(secret: &str) -> CredentialEntry {
+    CredentialEntry {
+        id: "alice".into(),
+        username: "alice".into(),
+        secret: secret.into(),
+        token: None,
+        metadata: None,
+        token_expires_at: None,
+    }
+}
+
+#[test]
+fn valid_token_needs_a_known_expiry_beyond_the_margin() {
+    let vault = CredentialVault::default();
+    vault.store(entry("hunter2")).unwrap();
+    assert_eq!(vault.valid_token("alice", NOW, 60).unwrap(), None);
+
+    vault
+        .store_token("alice", "tok".into(), Some(NOW + 100))
+        .unwrap();
+    assert_eq!(
+        vault.valid_token("alice", NOW, 60).unwrap(),
+        Some(("tok".into(), NOW + 100))
+    );
+    // Expiring within the margin, or already expired, is not valid.
+    assert_eq!(vault.valid_token("alice", NOW, 100).unwrap(), None);
+    assert_eq!(vault.valid_token("alice", NOW + 200, 0).unwrap(), None);
+
+    vault.store_token("alice", "tok".into(), None).unwrap();
+    assert_eq!(vault.valid_token("alice", NOW, 0).unwrap(), None);
+    assert_eq!(vault.fetch("alice").unwrap().secret, "hunter2");
+
+    assert!(matches!(
+        vault.valid_token("bob", NOW, 0),
+        Err(VaultError::NotFound(_))
+    ));
+    assert!(matches!(
+        vault.store_token("bob", "tok".into(), None),
+        Err(VaultError::NotFound(_))
+    ));
+}
+
+#[test]
+fn token_updates_do_not_undo_a_concurrent_store() {
+    let vault = CredentialVault::default();
+    vault.store(entry("old-secret")).unwrap();
+    let start = Arc::new(Barrier::new(3));
+
+    let writers: Vec<_> = (0..2)
+        .map(|writer| {
+            let (vault, start) = (vault.clone(), start.clone());
+            thread::spawn(move || {
+                start.wait();
+                for n in 0..500 {
+                    let token = format!("tok-{writer}-{n}");
+                    vault.store_token("alice", token, Some(NOW + n)).unwrap();
+                }
+            })
+        })
+        .collect();
+    start.wait();
+    vault.store(entry("new-secret")).unwrap();
+    for writer in writers {
+        writer.join().unwrap();
+    }
+
+    let stored = vault.fetch("alice").unwrap();
+    assert_eq!(stored.secret, "new-secret");
+    // Whichever token write came last, token and expiry belong together.
+    if let (Some(token), Some(expires_at)) = (stored.token, stored.token_expires_at) {
+        assert!(
+            token.ends_with(&format!("-{}", expires_at - NOW)),
+            "{token}"
+        );
+    }
+}
+
+#[test]
+fn readers_see_each_token_with_its_own_expiry() {
+    let vault = CredentialVault::default();
+    vault.store(entry("hunter2")).unwrap();
+    vault
+        .store_token("alice", "tok-0".into(), Some(NOW))
+        .unwrap();
+
+    let writer = {
+        let vault = vault.clone();
+        thread::spawn(move || {
+            for n in 1..=500 {
+                vault
+                    .store_token("alice", format!("tok-{n}"), Some(NOW + n))
+                    .unwrap();
+            }
+        })
+    };
+    let mut last = 0;
+    while !writer.is_finished() {
+        if let Some((token, expires_at)) = vault.valid_token("alice", NOW, 0).unwrap() {
+            assert_eq!(token, format!("tok-{}", expires_at - NOW));
+            assert!(expires_at >= last, "token went back in time");
+            last = expires_at;
+        }
+    }
+    writer.join().unwrap();
+    assert_eq!(
+        vault.valid_token("alice", NOW, 0).unwrap(),
+        Some(("tok-500".into(), NOW + 500))
+    );
+}